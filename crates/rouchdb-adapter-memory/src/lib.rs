@@ -1,18 +1,25 @@
 use std::collections::{BTreeMap, HashMap};
-use std::sync::Arc;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
 use async_trait::async_trait;
-use md5::{Digest, Md5};
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use rouchdb_core::adapter::Adapter;
+use rouchdb_core::digest::{compute_attachment_digest, verify_digest};
 use rouchdb_core::document::*;
 use rouchdb_core::error::{Result, RouchError};
-use rouchdb_core::merge::{collect_conflicts, is_deleted, merge_tree, winning_rev};
+use rouchdb_core::merge::{
+    collect_conflicts, is_deleted, is_editable_rev, merge_tree, select_open_revs,
+};
+use rouchdb_core::mime;
+use rouchdb_core::rev::compute_rev;
 use rouchdb_core::rev_tree::{
-    NodeOpts, RevPath, RevStatus, RevTree, build_path_from_revs, collect_leaves, find_rev_ancestry,
-    rev_exists,
+    NodeOpts, RevPath, RevStatus, RevTree, build_path_from_revs, collect_leaves, recent_revs,
+    remove_leaf, rev_exists, revisions_for, revs_info_for,
 };
 
 const DEFAULT_REV_LIMIT: u64 = 1000;
@@ -21,18 +28,96 @@ const DEFAULT_REV_LIMIT: u64 = 1000;
 // Internal storage types
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct StoredDoc {
+    #[serde(with = "flat_rev_tree")]
     rev_tree: RevTree,
+    /// Winning revision of `rev_tree` and whether it is deleted, kept in
+    /// step by `set_rev_tree` so reads don't walk the tree.
+    #[serde(skip)]
+    winner: Option<Revision>,
+    #[serde(skip)]
+    deleted: bool,
     /// Map from "pos-hash" to the document data at that revision.
     rev_data: HashMap<String, serde_json::Value>,
     /// Map from "pos-hash" to the deleted flag at that revision.
     rev_deleted: HashMap<String, bool>,
+    /// Map from "pos-hash" to that revision's attachment stubs. Revisions
+    /// without attachments have no entry.
+    rev_attachments: HashMap<String, HashMap<String, AttachmentMeta>>,
     /// Current sequence number for this document.
     seq: u64,
 }
 
-#[derive(Debug)]
+impl StoredDoc {
+    fn set_rev_tree(&mut self, rev_tree: RevTree) {
+        self.rev_tree = rev_tree;
+        self.refresh_winner();
+    }
+
+    /// Recompute the cached winner after `rev_tree` changed.
+    fn refresh_winner(&mut self) {
+        let leaves = collect_leaves(&self.rev_tree);
+        let winner = leaves.first();
+        self.winner = winner.map(|l| Revision::new(l.pos, l.hash.clone()));
+        self.deleted = winner.is_some_and(|l| l.deleted);
+    }
+
+    fn winning_rev(&self) -> Option<Revision> {
+        self.winner.clone()
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    /// Drop stored bodies for revisions pruned from the tree by stemming.
+    fn forget_revs(&mut self, stemmed: &[String]) {
+        if stemmed.is_empty() {
+            return;
+        }
+        let is_stemmed = |rev: &String| {
+            rev.split_once('-')
+                .is_some_and(|(_, hash)| stemmed.iter().any(|s| s == hash))
+        };
+        self.rev_data.retain(|rev, _| !is_stemmed(rev));
+        self.rev_deleted.retain(|rev, _| !is_stemmed(rev));
+        self.rev_attachments.retain(|rev, _| !is_stemmed(rev));
+    }
+
+    /// Attachment stubs of a revision.
+    fn attachments_at(&self, rev: &str) -> HashMap<String, AttachmentMeta> {
+        self.rev_attachments.get(rev).cloned().unwrap_or_default()
+    }
+
+    fn set_attachments(&mut self, rev: &str, stubs: HashMap<String, AttachmentMeta>) {
+        if !stubs.is_empty() {
+            self.rev_attachments.insert(rev.to_string(), stubs);
+        }
+    }
+}
+
+/// Move the bytes of inline attachments into the digest-keyed store and
+/// return stubs for all of them.
+fn store_attachments(
+    store: &mut HashMap<String, Vec<u8>>,
+    attachments: HashMap<String, AttachmentMeta>,
+) -> HashMap<String, AttachmentMeta> {
+    attachments
+        .into_iter()
+        .map(|(name, mut meta)| {
+            if let Some(data) = meta.data.take() {
+                meta.digest = compute_attachment_digest(&data);
+                meta.length = data.len() as u64;
+                store.insert(meta.digest.clone(), data);
+            }
+            meta.stub = true;
+            (name, meta)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
 struct Inner {
     name: String,
     /// Documents keyed by ID.
@@ -66,29 +151,330 @@ impl MemoryAdapter {
             })),
         }
     }
+
+    /// An independent copy of this adapter: documents, revision trees,
+    /// changes log, sequence counter, local documents and attachments.
+    ///
+    /// Unlike `clone()`, which shares the store, writes to the fork and
+    /// the original do not affect each other afterwards. A write in progress
+    /// finishes first; no lock is held across an await, so the wait is
+    /// short.
+    pub fn fork(&self) -> MemoryAdapter {
+        let inner = self.inner.read().unwrap().clone();
+        Self {
+            inner: Arc::new(RwLock::new(inner)),
+        }
+    }
+
+    /// Dump the whole store to JSON: documents with their revision trees,
+    /// the changes log and sequence counter, local documents, and
+    /// attachment data (Base64).
+    pub async fn snapshot(&self) -> serde_json::Value {
+        serde_json::to_value(self.take_snapshot().await).expect("snapshot serializes")
+    }
+
+    /// Rebuild an adapter from the output of `snapshot()`.
+    pub fn restore(value: serde_json::Value) -> Result<Self> {
+        Self::from_snapshot(serde_json::from_value(value)?)
+    }
+
+    /// Write the whole store to `path` as newline-delimited JSON.
+    ///
+    /// The file is written next to `path` and renamed into place, so a crash
+    /// mid-save leaves the previous file intact.
+    pub async fn save_to_path(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+        let snapshot = self.take_snapshot().await;
+        tokio::task::spawn_blocking(move || write_snapshot(snapshot, &path))
+            .await
+            .map_err(|e| RouchError::DatabaseError(format!("save failed: {}", e)))?
+    }
+
+    /// Load an adapter from a file written by `save_to_path`.
+    ///
+    /// This reads the file with blocking I/O; from async code, call it
+    /// through `tokio::task::spawn_blocking`.
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut lines = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                lines.push(serde_json::from_str(&line)?);
+            }
+        }
+        Self::from_snapshot(Snapshot::from_lines(lines)?)
+    }
+
+    async fn take_snapshot(&self) -> Snapshot {
+        use base64::Engine;
+
+        let inner = self.inner.read().unwrap();
+        Snapshot {
+            name: inner.name.clone(),
+            update_seq: inner.update_seq,
+            docs: inner.docs.clone(),
+            changes: inner.changes.clone(),
+            local_docs: inner.local_docs.clone(),
+            attachments: inner
+                .attachments
+                .iter()
+                .map(|(digest, data)| {
+                    let encoded = base64::engine::general_purpose::STANDARD.encode(data);
+                    (digest.clone(), encoded)
+                })
+                .collect(),
+        }
+    }
+
+    fn from_snapshot(snapshot: Snapshot) -> Result<Self> {
+        use base64::Engine;
+
+        let attachments = snapshot
+            .attachments
+            .into_iter()
+            .map(|(digest, encoded)| {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(&encoded)
+                    .map_err(|e| RouchError::BadRequest(format!("attachment {}: {}", digest, e)))?;
+                Ok((digest, data))
+            })
+            .collect::<Result<_>>()?;
+
+        let mut docs = snapshot.docs;
+        for doc in docs.values_mut() {
+            doc.refresh_winner();
+        }
+
+        Ok(Self {
+            inner: Arc::new(RwLock::new(Inner {
+                name: snapshot.name,
+                docs,
+                update_seq: snapshot.update_seq,
+                changes: snapshot.changes,
+                local_docs: snapshot.local_docs,
+                attachments,
+            })),
+        })
+    }
+}
+
+/// Serialized form of `Inner`, with attachment bytes as Base64 strings.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    name: String,
+    update_seq: u64,
+    docs: HashMap<String, StoredDoc>,
+    changes: BTreeMap<u64, (String, bool)>,
+    local_docs: HashMap<String, serde_json::Value>,
+    attachments: HashMap<String, String>,
+}
+
+/// Serde adapter writing a `RevTree` as flat node lists, each node pointing
+/// at its parent. A nested tree would exceed serde_json's 128-level limit
+/// on reading after about 60 edits.
+mod flat_rev_tree {
+    use rouchdb_core::rev_tree::{NodeOpts, RevNode, RevPath, RevStatus, RevTree};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct FlatPath {
+        pos: u64,
+        /// Depth-first, so every parent precedes its children.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        nodes: Vec<FlatNode>,
+        /// Nested form written by earlier versions; read, never written.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tree: Option<RevNode>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct FlatNode {
+        hash: String,
+        status: RevStatus,
+        deleted: bool,
+        /// Index of the parent in `nodes`; `None` for the root.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent: Option<usize>,
+    }
+
+    pub fn serialize<S: Serializer>(tree: &RevTree, s: S) -> Result<S::Ok, S::Error> {
+        let paths: Vec<FlatPath> = tree
+            .iter()
+            .map(|path| {
+                let mut nodes = Vec::new();
+                let mut stack = vec![(&path.tree, None)];
+                while let Some((node, parent)) = stack.pop() {
+                    let index = nodes.len();
+                    nodes.push(FlatNode {
+                        hash: node.hash.clone(),
+                        status: node.status.clone(),
+                        deleted: node.opts.deleted,
+                        parent,
+                    });
+                    // Reversed so the first child comes out first
+                    stack.extend(node.children.iter().rev().map(|c| (c, Some(index))));
+                }
+                FlatPath {
+                    pos: path.pos,
+                    nodes,
+                    tree: None,
+                }
+            })
+            .collect();
+        paths.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<RevTree, D::Error> {
+        Vec::<FlatPath>::deserialize(d)?
+            .into_iter()
+            .map(|path| {
+                let tree = match path.tree {
+                    Some(tree) => tree,
+                    None => unflatten(path.nodes)
+                        .ok_or_else(|| serde::de::Error::custom("malformed revision tree"))?,
+                };
+                Ok(RevPath {
+                    pos: path.pos,
+                    tree,
+                })
+            })
+            .collect()
+    }
+
+    /// Attach nodes to their parents from the back, leaving only the root.
+    fn unflatten(nodes: Vec<FlatNode>) -> Option<RevNode> {
+        let parents: Vec<Option<usize>> = nodes.iter().map(|n| n.parent).collect();
+        let mut built: Vec<Option<RevNode>> = nodes
+            .into_iter()
+            .map(|n| {
+                Some(RevNode {
+                    hash: n.hash,
+                    status: n.status,
+                    opts: NodeOpts { deleted: n.deleted },
+                    children: Vec::new(),
+                })
+            })
+            .collect();
+        for (i, parent) in parents.iter().enumerate().skip(1).rev() {
+            let child = built[i].take()?;
+            let parent = built.get_mut(parent.filter(|p| *p < i)?)?.as_mut()?;
+            parent.children.insert(0, child);
+        }
+        built.into_iter().next()?
+    }
+}
+
+/// Write `snapshot` to a temporary file next to `path`, then rename it
+/// into place. The temporary file is removed if anything fails.
+fn write_snapshot(snapshot: Snapshot, path: &Path) -> Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp = PathBuf::from(tmp_name);
+
+    let written = write_lines(snapshot, &tmp).and_then(|()| Ok(std::fs::rename(&tmp, path)?));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written
+}
+
+fn write_lines(snapshot: Snapshot, path: &Path) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    for line in snapshot.into_lines() {
+        serde_json::to_writer(&mut out, &line)?;
+        out.write_all(b"\n")?;
+    }
+    let file = out.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// One line of a `save_to_path` file. The header comes first; the other
+/// records may follow in any order.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum SnapshotLine {
+    Header { name: String, update_seq: u64 },
+    Doc { id: String, doc: StoredDoc },
+    Change { seq: u64, id: String, deleted: bool },
+    Local { id: String, body: serde_json::Value },
+    Attachment { digest: String, data: String },
+}
+
+impl Snapshot {
+    fn into_lines(self) -> impl Iterator<Item = SnapshotLine> {
+        let header = SnapshotLine::Header {
+            name: self.name,
+            update_seq: self.update_seq,
+        };
+        std::iter::once(header)
+            .chain(
+                self.docs
+                    .into_iter()
+                    .map(|(id, doc)| SnapshotLine::Doc { id, doc }),
+            )
+            .chain(
+                self.changes
+                    .into_iter()
+                    .map(|(seq, (id, deleted))| SnapshotLine::Change { seq, id, deleted }),
+            )
+            .chain(
+                self.local_docs
+                    .into_iter()
+                    .map(|(id, body)| SnapshotLine::Local { id, body }),
+            )
+            .chain(
+                self.attachments
+                    .into_iter()
+                    .map(|(digest, data)| SnapshotLine::Attachment { digest, data }),
+            )
+    }
+
+    fn from_lines(lines: Vec<SnapshotLine>) -> Result<Self> {
+        let mut lines = lines.into_iter();
+        let Some(SnapshotLine::Header { name, update_seq }) = lines.next() else {
+            return Err(RouchError::BadRequest(
+                "memory store file must start with a header".into(),
+            ));
+        };
+        let mut snapshot = Snapshot {
+            name,
+            update_seq,
+            docs: HashMap::new(),
+            changes: BTreeMap::new(),
+            local_docs: HashMap::new(),
+            attachments: HashMap::new(),
+        };
+        for line in lines {
+            match line {
+                SnapshotLine::Header { .. } => {
+                    return Err(RouchError::BadRequest(
+                        "memory store file has more than one header".into(),
+                    ));
+                }
+                SnapshotLine::Doc { id, doc } => {
+                    snapshot.docs.insert(id, doc);
+                }
+                SnapshotLine::Change { seq, id, deleted } => {
+                    snapshot.changes.insert(seq, (id, deleted));
+                }
+                SnapshotLine::Local { id, body } => {
+                    snapshot.local_docs.insert(id, body);
+                }
+                SnapshotLine::Attachment { digest, data } => {
+                    snapshot.attachments.insert(digest, data);
+                }
+            }
+        }
+        Ok(snapshot)
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Helper functions
 // ---------------------------------------------------------------------------
 
-/// Generate a revision hash from the document content.
-fn generate_rev_hash(
-    doc_data: &serde_json::Value,
-    deleted: bool,
-    prev_rev: Option<&str>,
-) -> String {
-    let mut hasher = Md5::new();
-    // Include the previous revision in the hash for determinism
-    if let Some(prev) = prev_rev {
-        hasher.update(prev.as_bytes());
-    }
-    hasher.update(if deleted { b"1" } else { b"0" });
-    let serialized = serde_json::to_string(doc_data).unwrap_or_default();
-    hasher.update(serialized.as_bytes());
-    format!("{:x}", hasher.finalize())
-}
-
 fn rev_string(pos: u64, hash: &str) -> String {
     format!("{}-{}", pos, hash)
 }
@@ -103,15 +489,6 @@ fn parse_rev(rev_str: &str) -> Result<(u64, String)> {
     Ok((pos, hash.to_string()))
 }
 
-fn compute_attachment_digest(data: &[u8]) -> String {
-    let mut hasher = Md5::new();
-    hasher.update(data);
-    let hash = hasher.finalize();
-    use base64::Engine;
-    let b64 = base64::engine::general_purpose::STANDARD.encode(hash);
-    format!("md5-{}", b64)
-}
-
 // ---------------------------------------------------------------------------
 // Adapter implementation
 // ---------------------------------------------------------------------------
@@ -119,25 +496,39 @@ fn compute_attachment_digest(data: &[u8]) -> String {
 #[async_trait]
 impl Adapter for MemoryAdapter {
     async fn info(&self) -> Result<DbInfo> {
-        let inner = self.inner.read().await;
-        let doc_count = inner
-            .docs
-            .values()
-            .filter(|d| {
-                // Count only non-deleted documents
-                !is_deleted(&d.rev_tree)
-            })
-            .count() as u64;
+        let inner = self.inner.read().unwrap();
+        let doc_del_count = inner.docs.values().filter(|d| d.is_deleted()).count() as u64;
+        let doc_count = inner.docs.len() as u64 - doc_del_count;
 
         Ok(DbInfo {
             db_name: inner.name.clone(),
             doc_count,
+            doc_del_count,
             update_seq: Seq::Num(inner.update_seq),
+            sizes: None,
         })
     }
 
+    async fn exists(&self, id: &str) -> Result<bool> {
+        let inner = self.inner.read().unwrap();
+        Ok(inner
+            .docs
+            .get(id)
+            .is_some_and(|stored| stored.winner.is_some() && !stored.is_deleted()))
+    }
+
+    async fn winning_rev(&self, id: &str) -> Result<Revision> {
+        let inner = self.inner.read().unwrap();
+        inner
+            .docs
+            .get(id)
+            .filter(|stored| !stored.is_deleted())
+            .and_then(|stored| stored.winning_rev())
+            .ok_or_else(|| RouchError::NotFound(id.to_string()))
+    }
+
     async fn get(&self, id: &str, opts: GetOptions) -> Result<Document> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
         let stored = inner
             .docs
             .get(id)
@@ -148,7 +539,8 @@ impl Adapter for MemoryAdapter {
             rev_str.clone()
         } else {
             // Use the winning revision
-            let winner = winning_rev(&stored.rev_tree)
+            let winner = stored
+                .winning_rev()
                 .ok_or_else(|| RouchError::NotFound(id.to_string()))?;
             winner.to_string()
         };
@@ -162,12 +554,12 @@ impl Adapter for MemoryAdapter {
             }
         }
 
-        // Get the data for this revision
+        // Get the data for this revision; compaction may have dropped it
         let data = stored
             .rev_data
             .get(&target_rev)
             .cloned()
-            .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
+            .ok_or_else(|| RouchError::NotFound(format!("{} at {}", id, target_rev)))?;
 
         let deleted = stored
             .rev_deleted
@@ -185,10 +577,10 @@ impl Adapter for MemoryAdapter {
 
         let mut doc = Document {
             id: id.to_string(),
-            rev: Some(rev),
+            rev: Some(rev.clone()),
             deleted,
             data,
-            attachments: HashMap::new(),
+            attachments: stored.attachments_at(&target_rev),
         };
 
         // Add conflicts if requested
@@ -208,48 +600,52 @@ impl Adapter for MemoryAdapter {
             }
         }
 
-        // Add revs_info if requested
-        if opts.revs_info {
-            use rouchdb_core::rev_tree::traverse_rev_tree;
-            let mut revs_info = Vec::new();
-            traverse_rev_tree(&stored.rev_tree, |node_pos, node, _root_pos| {
-                let rev_str = format!("{}-{}", node_pos, node.hash);
-                let status = if node.opts.deleted {
-                    "deleted"
-                } else {
-                    match node.status {
-                        rouchdb_core::rev_tree::RevStatus::Available => "available",
-                        rouchdb_core::rev_tree::RevStatus::Missing => "missing",
-                    }
-                };
-                revs_info.push(RevInfo {
-                    rev: rev_str,
-                    status: status.to_string(),
-                });
-            });
-            // Sort by pos descending (newest first)
-            revs_info.sort_by(|a, b| {
-                let a_pos: u64 = a.rev.split('-').next().unwrap_or("0").parse().unwrap_or(0);
-                let b_pos: u64 = b.rev.split('-').next().unwrap_or("0").parse().unwrap_or(0);
-                b_pos.cmp(&a_pos)
-            });
-            if let serde_json::Value::Object(ref mut map) = doc.data {
-                map.insert(
-                    "_revs_info".to_string(),
-                    serde_json::to_value(&revs_info).unwrap(),
-                );
-            }
+        // Ancestry of the returned revision, newest first
+        if opts.revs
+            && let Some(revs) = revisions_for(&stored.rev_tree, &rev)
+            && let serde_json::Value::Object(ref mut map) = doc.data
+        {
+            map.insert("_revisions".to_string(), serde_json::to_value(revs)?);
+        }
+
+        // The returned revision's branch with each node's status
+        if opts.revs_info
+            && let Some(info) = revs_info_for(&stored.rev_tree, &rev)
+            && let serde_json::Value::Object(ref mut map) = doc.data
+        {
+            map.insert("_revs_info".to_string(), serde_json::to_value(info)?);
         }
 
         Ok(doc)
     }
 
+    async fn get_open_revs(&self, id: &str, open_revs: OpenRevs) -> Result<Vec<Document>> {
+        let revs = {
+            let inner = self.inner.read().unwrap();
+            let stored = inner
+                .docs
+                .get(id)
+                .ok_or_else(|| RouchError::NotFound(id.to_string()))?;
+            select_open_revs(&stored.rev_tree, &open_revs)?
+        };
+
+        let mut docs = Vec::with_capacity(revs.len());
+        for rev in revs {
+            let opts = GetOptions {
+                rev: Some(rev.to_string()),
+                ..Default::default()
+            };
+            docs.push(self.get(id, opts).await?);
+        }
+        Ok(docs)
+    }
+
     async fn bulk_docs(
         &self,
         docs: Vec<Document>,
         opts: BulkDocsOptions,
     ) -> Result<Vec<DocResult>> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
         let mut results = Vec::with_capacity(docs.len());
 
         for doc in docs {
@@ -265,7 +661,7 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn all_docs(&self, opts: AllDocsOptions) -> Result<AllDocsResponse> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
 
         // Collect all doc IDs sorted
         let mut doc_ids: Vec<&String> = inner.docs.keys().collect();
@@ -288,41 +684,23 @@ impl Adapter for MemoryAdapter {
 
         for key in &target_keys {
             // Apply key range filters if no specific keys were given
-            if opts.keys.is_none() && opts.key.is_none() {
-                if let Some(ref start) = opts.start_key
-                    && ((!opts.descending && key.as_str() < start.as_str())
-                        || (opts.descending && key.as_str() > start.as_str()))
-                {
-                    continue;
-                }
-                if let Some(ref end) = opts.end_key {
-                    if opts.inclusive_end {
-                        if (!opts.descending && key.as_str() > end.as_str())
-                            || (opts.descending && key.as_str() < end.as_str())
-                        {
-                            continue;
-                        }
-                    } else if (!opts.descending && key.as_str() >= end.as_str())
-                        || (opts.descending && key.as_str() <= end.as_str())
-                    {
-                        continue;
-                    }
-                }
+            if opts.keys.is_none() && opts.key.is_none() && !opts.in_key_range(key) {
+                continue;
             }
 
             if let Some(stored) = inner.docs.get(key.as_str()) {
-                let winner = match winning_rev(&stored.rev_tree) {
+                let winner = match stored.winning_rev() {
                     Some(w) => w,
                     None => continue,
                 };
-                let deleted = is_deleted(&stored.rev_tree);
+                let deleted = stored.is_deleted();
 
                 // Skip deleted docs unless specific keys were requested
-                if deleted && opts.keys.is_none() {
+                if deleted && opts.keys.is_none() && !opts.include_deleted {
                     continue;
                 }
 
-                let doc_json = if opts.include_docs && !deleted {
+                let doc_json = if opts.include_docs && (!deleted || opts.include_deleted) {
                     let rev_str = winner.to_string();
                     stored.rev_data.get(&rev_str).map(|data| {
                         let mut obj = match data {
@@ -331,6 +709,9 @@ impl Adapter for MemoryAdapter {
                         };
                         obj.insert("_id".into(), serde_json::Value::String(key.clone()));
                         obj.insert("_rev".into(), serde_json::Value::String(rev_str));
+                        if deleted {
+                            obj.insert("_deleted".into(), serde_json::Value::Bool(true));
+                        }
                         // Include conflicts if requested
                         if opts.conflicts {
                             let conflicts = collect_conflicts(&stored.rev_tree);
@@ -392,23 +773,17 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn changes(&self, opts: ChangesOptions) -> Result<ChangesResponse> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
 
         let mut results = Vec::new();
 
-        // Iterate changes after `since`
-        let range = (opts.since.as_num() + 1)..;
+        // Iterate changes after `since`, or before it when descending
+        let since = opts.since.as_num();
         let iter: Box<dyn Iterator<Item = (&u64, &(String, bool))>> = if opts.descending {
-            Box::new(
-                inner
-                    .changes
-                    .range(range)
-                    .collect::<Vec<_>>()
-                    .into_iter()
-                    .rev(),
-            )
+            let end = if since == 0 { u64::MAX } else { since };
+            Box::new(inner.changes.range(..end).rev())
         } else {
-            Box::new(inner.changes.range(range))
+            Box::new(inner.changes.range((since + 1)..))
         };
 
         for (seq, (doc_id, deleted)) in iter {
@@ -421,7 +796,7 @@ impl Adapter for MemoryAdapter {
 
             let stored = inner.docs.get(doc_id);
             let rev_str = stored
-                .and_then(|s| winning_rev(&s.rev_tree))
+                .and_then(|s| s.winning_rev())
                 .map(|r| r.to_string())
                 .unwrap_or_default();
 
@@ -449,7 +824,6 @@ impl Adapter for MemoryAdapter {
                 if let Some(s) = stored {
                     collect_leaves(&s.rev_tree)
                         .iter()
-                        .filter(|l| !s.rev_deleted.get(&l.rev_string()).copied().unwrap_or(false))
                         .map(|l| ChangeRev {
                             rev: l.rev_string(),
                         })
@@ -502,7 +876,7 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn revs_diff(&self, revs: HashMap<String, Vec<String>>) -> Result<RevsDiffResponse> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
         let mut results = HashMap::new();
 
         for (doc_id, rev_list) in revs {
@@ -525,8 +899,9 @@ impl Adapter for MemoryAdapter {
                     if let Some(stored) = stored {
                         let leaves = collect_leaves(&stored.rev_tree);
                         for leaf in &leaves {
-                            if leaf.pos < pos {
-                                possible_ancestors.push(leaf.rev_string());
+                            let anc = leaf.rev_string();
+                            if leaf.pos < pos && !possible_ancestors.contains(&anc) {
+                                possible_ancestors.push(anc);
                             }
                         }
                     }
@@ -548,7 +923,7 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn bulk_get(&self, docs: Vec<BulkGetItem>) -> Result<BulkGetResponse> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
         let mut results = Vec::new();
 
         for item in docs {
@@ -559,7 +934,7 @@ impl Adapter for MemoryAdapter {
                     let rev_str = if let Some(ref rev) = item.rev {
                         rev.clone()
                     } else {
-                        match winning_rev(&stored.rev_tree) {
+                        match stored.winning_rev() {
                             Some(w) => w.to_string(),
                             None => {
                                 bulk_docs.push(BulkGetDoc {
@@ -591,18 +966,16 @@ impl Adapter for MemoryAdapter {
                         if deleted {
                             obj.insert("_deleted".into(), serde_json::Value::Bool(true));
                         }
+                        if let Some(stubs) = stored.rev_attachments.get(&rev_str) {
+                            obj.insert("_attachments".into(), serde_json::to_value(stubs)?);
+                        }
 
                         // Include _revisions for replication
-                        if let Ok((pos, ref hash)) = parse_rev(&rev_str)
-                            && let Some(ancestry) = find_rev_ancestry(&stored.rev_tree, pos, hash)
+                        if let Ok((pos, hash)) = parse_rev(&rev_str)
+                            && let Some(revs) =
+                                revisions_for(&stored.rev_tree, &Revision::new(pos, hash))
                         {
-                            obj.insert(
-                                "_revisions".into(),
-                                serde_json::json!({
-                                    "start": pos,
-                                    "ids": ancestry
-                                }),
-                            );
+                            obj.insert("_revisions".into(), serde_json::to_value(revs)?);
                         }
 
                         bulk_docs.push(BulkGetDoc {
@@ -654,7 +1027,7 @@ impl Adapter for MemoryAdapter {
         let digest = compute_attachment_digest(&data);
         let length = data.len() as u64;
 
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
 
         // Store the attachment data
         inner.attachments.insert(digest.clone(), data);
@@ -666,7 +1039,8 @@ impl Adapter for MemoryAdapter {
             .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
 
         // Verify the rev matches
-        let winner = winning_rev(&stored.rev_tree)
+        let winner = stored
+            .winning_rev()
             .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
         if winner.to_string() != rev {
             return Err(RouchError::Conflict);
@@ -680,24 +1054,24 @@ impl Adapter for MemoryAdapter {
             .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
 
         // Build updated document with attachment metadata
-        let att_meta = AttachmentMeta {
-            content_type: content_type.to_string(),
-            digest: digest.clone(),
-            length,
-            stub: true,
-            data: None,
-        };
+        let mut attachments = stored.attachments_at(rev);
+        attachments.insert(
+            att_id.to_string(),
+            AttachmentMeta {
+                content_type: mime::or_default(content_type).to_string(),
+                digest: digest.clone(),
+                length,
+                stub: true,
+                data: None,
+            },
+        );
 
         let doc = Document {
             id: doc_id.to_string(),
             rev: Some(winner.clone()),
             deleted: false,
             data: doc_data.clone(),
-            attachments: {
-                let mut atts = HashMap::new();
-                atts.insert(att_id.to_string(), att_meta);
-                atts
-            },
+            attachments,
         };
 
         // Process as a normal edit
@@ -711,7 +1085,7 @@ impl Adapter for MemoryAdapter {
         att_id: &str,
         opts: GetAttachmentOptions,
     ) -> Result<Vec<u8>> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
 
         let stored = inner
             .docs
@@ -721,34 +1095,38 @@ impl Adapter for MemoryAdapter {
         let rev_str = if let Some(ref rev) = opts.rev {
             rev.clone()
         } else {
-            winning_rev(&stored.rev_tree)
+            stored
+                .winning_rev()
                 .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?
                 .to_string()
         };
 
-        // Look for attachment metadata in the doc data
-        // For now, look up by digest in our attachment store
-        // We'd need to track which attachments belong to which doc/rev
-        // For simplicity, search through our attachment map
-        let _data = stored.rev_data.get(&rev_str);
+        let not_found = || RouchError::NotFound(format!("attachment {}/{}", doc_id, att_id));
+        let meta = stored
+            .rev_attachments
+            .get(&rev_str)
+            .and_then(|atts| atts.get(att_id))
+            .ok_or_else(not_found)?;
 
-        // TODO: proper attachment tracking per revision
-        Err(RouchError::NotFound(format!(
-            "attachment {}/{}",
-            doc_id, att_id
-        )))
+        let data = inner
+            .attachments
+            .get(&meta.digest)
+            .cloned()
+            .ok_or_else(not_found)?;
+        verify_digest(&opts, &data, &meta.digest)?;
+        opts.slice(data)
     }
 
     async fn remove_attachment(&self, doc_id: &str, att_id: &str, rev: &str) -> Result<DocResult> {
-        let _ = att_id; // attachment tracking is simplified in memory adapter
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
 
         let stored = inner
             .docs
             .get(doc_id)
             .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
 
-        let winner = winning_rev(&stored.rev_tree)
+        let winner = stored
+            .winning_rev()
             .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
         if winner.to_string() != rev {
             return Err(RouchError::Conflict);
@@ -760,13 +1138,21 @@ impl Adapter for MemoryAdapter {
             .cloned()
             .unwrap_or(serde_json::Value::Object(serde_json::Map::new()));
 
+        let mut attachments = stored.attachments_at(rev);
+        if attachments.remove(att_id).is_none() {
+            return Err(RouchError::NotFound(format!(
+                "attachment {}/{}",
+                doc_id, att_id
+            )));
+        }
+
         // Create a new revision (attachment removal is a document update)
         let doc = Document {
             id: doc_id.to_string(),
             rev: Some(winner.clone()),
             deleted: false,
             data: doc_data,
-            attachments: HashMap::new(),
+            attachments,
         };
 
         let result = process_doc_new_edits(&mut inner, doc);
@@ -774,7 +1160,7 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn get_local(&self, id: &str) -> Result<serde_json::Value> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
         inner
             .local_docs
             .get(id)
@@ -783,13 +1169,13 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn put_local(&self, id: &str, doc: serde_json::Value) -> Result<()> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
         inner.local_docs.insert(id.to_string(), doc);
         Ok(())
     }
 
     async fn remove_local(&self, id: &str) -> Result<()> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
         inner
             .local_docs
             .remove(id)
@@ -798,23 +1184,49 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn compact(&self) -> Result<()> {
-        let mut inner = self.inner.write().await;
+        self.compact_with_opts(CompactOptions::default()).await
+    }
+
+    async fn compact_with_opts(&self, opts: CompactOptions) -> Result<()> {
+        let mut inner = self.inner.write().unwrap();
 
         for stored in inner.docs.values_mut() {
-            let leaves = collect_leaves(&stored.rev_tree);
-            let leaf_revs: std::collections::HashSet<String> =
-                leaves.iter().map(|l| l.rev_string()).collect();
+            let keep = recent_revs(&stored.rev_tree, opts.retain_revs);
 
-            // Remove data for non-leaf revisions
-            stored.rev_data.retain(|k, _| leaf_revs.contains(k));
-            stored.rev_deleted.retain(|k, _| leaf_revs.contains(k));
+            // Remove data for revisions too far behind every leaf
+            stored.rev_data.retain(|k, _| keep.contains(k));
+            stored.rev_deleted.retain(|k, _| keep.contains(k));
+            stored.rev_attachments.retain(|k, _| keep.contains(k));
         }
 
+        // Drop attachment bodies no remaining revision points to
+        let referenced: std::collections::HashSet<String> = inner
+            .docs
+            .values()
+            .flat_map(|s| s.rev_attachments.values())
+            .flat_map(|atts| atts.values().map(|a| a.digest.clone()))
+            .collect();
+        inner
+            .attachments
+            .retain(|digest, _| referenced.contains(digest));
+
         Ok(())
     }
 
+    async fn needs_compaction(&self) -> Result<bool> {
+        let inner = self.inner.read().unwrap();
+        let (mut live, mut dead) = (0, 0);
+        for stored in inner.docs.values() {
+            let keep = recent_revs(&stored.rev_tree, 1);
+            let kept = stored.rev_data.keys().filter(|k| keep.contains(*k)).count();
+            live += kept;
+            dead += stored.rev_data.len() - kept;
+        }
+        Ok(dead > live)
+    }
+
     async fn destroy(&self) -> Result<()> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
         inner.docs.clear();
         inner.changes.clear();
         inner.local_docs.clear();
@@ -824,43 +1236,53 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn purge(&self, req: HashMap<String, Vec<String>>) -> Result<PurgeResponse> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
         let mut purged = HashMap::new();
-        let mut docs_to_remove = Vec::new();
 
         for (doc_id, revs) in req {
+            let Some(stored) = inner.docs.get_mut(&doc_id) else {
+                continue;
+            };
             let mut purged_revs = Vec::new();
-            if let Some(stored) = inner.docs.get_mut(&doc_id) {
-                for rev_str in &revs {
-                    if stored.rev_data.remove(rev_str).is_some() {
-                        stored.rev_deleted.remove(rev_str);
-                        purged_revs.push(rev_str.clone());
-
-                        // Also prune the revision from the rev_tree so that
-                        // winning_rev(), collect_conflicts(), and replication
-                        // don't reference purged revisions.
-                        if let Some((pos, hash)) = rev_str.split_once('-')
-                            && let Ok(pos) = pos.parse::<u64>()
-                        {
-                            prune_leaf_from_tree(&mut stored.rev_tree, pos, hash);
-                        }
-                    }
+            for rev_str in &revs {
+                let Ok((pos, hash)) = parse_rev(rev_str) else {
+                    continue;
+                };
+                // Prune the leaf (and the ancestors only it used) from the
+                // rev_tree so that winning_rev(), collect_conflicts(), and
+                // replication don't reference it.
+                let pruned = remove_leaf(&mut stored.rev_tree, pos, &hash);
+                let had_body = stored.rev_data.contains_key(rev_str);
+                for rev in pruned.iter().chain([rev_str]) {
+                    stored.rev_data.remove(rev);
+                    stored.rev_deleted.remove(rev);
+                    stored.rev_attachments.remove(rev);
                 }
-                // Remove empty rev_tree paths after pruning
-                stored.rev_tree.retain(|p| !is_tree_empty(&p.tree));
-
-                if stored.rev_data.is_empty() {
-                    docs_to_remove.push((doc_id.clone(), stored.seq));
+                if !pruned.is_empty() || had_body {
+                    purged_revs.push(rev_str.clone());
                 }
             }
-            if !purged_revs.is_empty() {
-                purged.insert(doc_id, purged_revs);
+            if purged_revs.is_empty() {
+                continue;
             }
-        }
-
-        for (doc_id, seq) in docs_to_remove {
-            inner.changes.remove(&seq);
-            inner.docs.remove(&doc_id);
+            stored.refresh_winner();
+
+            let old_seq = stored.seq;
+            let gone = stored.rev_tree.is_empty() || stored.rev_data.is_empty();
+            let deleted = stored.is_deleted();
+            inner.changes.remove(&old_seq);
+            inner.update_seq += 1;
+            if gone {
+                inner.docs.remove(&doc_id);
+            } else {
+                // The winner may have changed; record the doc at a new seq.
+                let seq = inner.update_seq;
+                inner.changes.insert(seq, (doc_id.clone(), deleted));
+                if let Some(stored) = inner.docs.get_mut(&doc_id) {
+                    stored.seq = seq;
+                }
+            }
+            purged.insert(doc_id, purged_revs);
         }
 
         Ok(PurgeResponse {
@@ -870,7 +1292,7 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn get_security(&self) -> Result<SecurityDocument> {
-        let inner = self.inner.read().await;
+        let inner = self.inner.read().unwrap();
         match inner.local_docs.get("_security") {
             Some(val) => serde_json::from_value(val.clone())
                 .map_err(|e| RouchError::DatabaseError(e.to_string())),
@@ -879,7 +1301,7 @@ impl Adapter for MemoryAdapter {
     }
 
     async fn put_security(&self, doc: SecurityDocument) -> Result<()> {
-        let mut inner = self.inner.write().await;
+        let mut inner = self.inner.write().unwrap();
         let val = serde_json::to_value(&doc)?;
         inner.local_docs.insert("_security".to_string(), val);
         Ok(())
@@ -899,34 +1321,30 @@ fn process_doc_new_edits(inner: &mut Inner, doc: Document) -> DocResult {
 
     let existing = inner.docs.get(&doc_id);
 
-    // Check for conflicts: if the doc has a _rev, it must match the winning rev
+    // Check for conflicts: if the doc has a _rev, it must be the winner or a conflicting leaf
     if let Some(stored) = existing {
-        let winner = winning_rev(&stored.rev_tree);
+        let winner = stored.winning_rev();
 
         match (&doc.rev, &winner) {
-            (Some(provided_rev), Some(current_winner)) => {
-                if provided_rev.to_string() != current_winner.to_string() {
-                    return DocResult {
-                        ok: false,
-                        id: doc_id,
-                        rev: None,
-                        error: Some("conflict".into()),
-                        reason: Some("Document update conflict".into()),
-                    };
-                }
+            (Some(provided_rev), Some(_)) if !is_editable_rev(&stored.rev_tree, provided_rev) => {
+                return DocResult {
+                    ok: false,
+                    id: doc_id,
+                    rev: None,
+                    error: Some("conflict".into()),
+                    reason: Some("Document update conflict".into()),
+                };
             }
-            (None, Some(_)) => {
-                // Trying to create a doc that already exists (and isn't deleted)
-                if !is_deleted(&stored.rev_tree) {
-                    return DocResult {
-                        ok: false,
-                        id: doc_id,
-                        rev: None,
-                        error: Some("conflict".into()),
-                        reason: Some("Document update conflict".into()),
-                    };
-                }
-                // If winner is deleted, allow creating a new doc at the same ID
+            // Trying to create a doc that already exists (and isn't deleted).
+            // If the winner is deleted, creating a new doc at the same ID is allowed.
+            (None, Some(_)) if !stored.is_deleted() => {
+                return DocResult {
+                    ok: false,
+                    id: doc_id,
+                    rev: None,
+                    error: Some("conflict".into()),
+                    reason: Some("Document update conflict".into()),
+                };
             }
             _ => {}
         }
@@ -942,9 +1360,9 @@ fn process_doc_new_edits(inner: &mut Inner, doc: Document) -> DocResult {
     }
 
     // Generate new revision
-    let new_pos = doc.rev.as_ref().map(|r| r.pos + 1).unwrap_or(1);
-    let prev_rev_str = doc.rev.as_ref().map(|r| r.to_string());
-    let new_hash = generate_rev_hash(&doc.data, doc.deleted, prev_rev_str.as_deref());
+    let new_rev = compute_rev(doc.rev.as_ref(), &doc.data, doc.deleted, &doc.attachments);
+    let new_pos = new_rev.pos;
+    let new_hash = new_rev.hash;
     let new_rev_str = rev_string(new_pos, &new_hash);
 
     // Build the revision path for merging
@@ -965,7 +1383,8 @@ fn process_doc_new_edits(inner: &mut Inner, doc: Document) -> DocResult {
     // Merge into existing tree or create new one
     let existing_tree = existing.map(|s| s.rev_tree.clone()).unwrap_or_default();
 
-    let (merged_tree, _merge_result) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    let (merged_tree, _merge_result, stemmed) =
+        merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
 
     // Update sequence
     inner.update_seq += 1;
@@ -976,20 +1395,27 @@ fn process_doc_new_edits(inner: &mut Inner, doc: Document) -> DocResult {
         inner.changes.remove(&existing.seq);
     }
 
+    let stubs = store_attachments(&mut inner.attachments, doc.attachments);
+
     // Store or update the document
     let stored = inner
         .docs
         .entry(doc_id.clone())
         .or_insert_with(|| StoredDoc {
             rev_tree: Vec::new(),
+            winner: None,
+            deleted: false,
             rev_data: HashMap::new(),
             rev_deleted: HashMap::new(),
+            rev_attachments: HashMap::new(),
             seq: 0,
         });
 
-    stored.rev_tree = merged_tree;
+    stored.set_rev_tree(merged_tree);
+    stored.forget_revs(&stemmed);
     stored.rev_data.insert(new_rev_str.clone(), doc.data);
     stored.rev_deleted.insert(new_rev_str.clone(), doc.deleted);
+    stored.set_attachments(&new_rev_str, stubs);
     stored.seq = seq;
 
     // Record in changes
@@ -1072,7 +1498,8 @@ fn process_doc_replication(inner: &mut Inner, mut doc: Document) -> DocResult {
         .map(|s| s.rev_tree.clone())
         .unwrap_or_default();
 
-    let (merged_tree, _merge_result) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    let (merged_tree, _merge_result, stemmed) =
+        merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
 
     // Update sequence
     inner.update_seq += 1;
@@ -1084,20 +1511,26 @@ fn process_doc_replication(inner: &mut Inner, mut doc: Document) -> DocResult {
     }
 
     let is_doc_deleted = is_deleted(&merged_tree);
+    let stubs = store_attachments(&mut inner.attachments, doc.attachments);
 
     let stored = inner
         .docs
         .entry(doc_id.clone())
         .or_insert_with(|| StoredDoc {
             rev_tree: Vec::new(),
+            winner: None,
+            deleted: false,
             rev_data: HashMap::new(),
             rev_deleted: HashMap::new(),
+            rev_attachments: HashMap::new(),
             seq: 0,
         });
 
-    stored.rev_tree = merged_tree;
+    stored.set_rev_tree(merged_tree);
+    stored.forget_revs(&stemmed);
     stored.rev_data.insert(rev_str.clone(), doc.data);
     stored.rev_deleted.insert(rev_str.clone(), doc.deleted);
+    stored.set_attachments(&rev_str, stubs);
     stored.seq = seq;
 
     inner.changes.insert(seq, (doc_id.clone(), is_doc_deleted));
@@ -1111,41 +1544,6 @@ fn process_doc_replication(inner: &mut Inner, mut doc: Document) -> DocResult {
     }
 }
 
-// ---------------------------------------------------------------------------
-// Rev-tree pruning helpers for purge
-// ---------------------------------------------------------------------------
-
-use rouchdb_core::rev_tree::RevNode;
-
-/// Remove a specific leaf node from the rev tree. If the node at (pos, hash)
-/// is a leaf (no children), it's removed from its parent's children list.
-fn prune_leaf_from_tree(tree: &mut RevTree, target_pos: u64, target_hash: &str) {
-    for path in tree.iter_mut() {
-        prune_leaf_from_node(&mut path.tree, path.pos, target_pos, target_hash);
-    }
-}
-
-/// Recursively remove a matching leaf node from the subtree.
-/// Returns true if the node at this level should be removed (it matched and was a leaf).
-fn prune_leaf_from_node(node: &mut RevNode, current_pos: u64, target_pos: u64, target_hash: &str) {
-    // Remove matching children that are leaves
-    node.children.retain(|child| {
-        let child_pos = current_pos + 1;
-        !(child_pos == target_pos && child.hash == target_hash && child.children.is_empty())
-    });
-
-    // Recurse into remaining children
-    for child in node.children.iter_mut() {
-        prune_leaf_from_node(child, current_pos + 1, target_pos, target_hash);
-    }
-}
-
-/// Check if a rev tree node is effectively empty (no children and no useful data).
-/// Used to clean up orphaned root paths after leaf pruning.
-fn is_tree_empty(node: &RevNode) -> bool {
-    node.children.is_empty() && node.hash.is_empty()
-}
-
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -1194,6 +1592,433 @@ mod tests {
         assert!(fetched.rev.is_some());
     }
 
+    #[tokio::test]
+    async fn fork_is_independent() {
+        let put = |db: &MemoryAdapter, id: &str| {
+            let doc = Document {
+                id: id.into(),
+                rev: None,
+                deleted: false,
+                data: serde_json::json!({}),
+                attachments: HashMap::new(),
+            };
+            let db = db.clone();
+            async move {
+                db.bulk_docs(vec![doc], BulkDocsOptions::new())
+                    .await
+                    .unwrap();
+            }
+        };
+        let ids = |db: &MemoryAdapter| {
+            let db = db.clone();
+            async move {
+                db.all_docs(AllDocsOptions::new())
+                    .await
+                    .unwrap()
+                    .rows
+                    .into_iter()
+                    .map(|row| row.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        let db = new_db().await;
+        put(&db, "base").await;
+        let fork = db.fork();
+        put(&db, "original").await;
+        put(&fork, "forked").await;
+
+        assert_eq!(ids(&db).await, ["base", "original"]);
+        assert_eq!(ids(&fork).await, ["base", "forked"]);
+        // Both continue from the shared sequence
+        assert_eq!(db.info().await.unwrap().update_seq, Seq::Num(2));
+        assert_eq!(fork.info().await.unwrap().update_seq, Seq::Num(2));
+        let changes = fork.changes(ChangesOptions::default()).await.unwrap();
+        let changed: Vec<&str> = changes.results.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(changed, ["base", "forked"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fork_waits_for_a_running_write() {
+        let db = new_db().await;
+        let (locked, wait_locked) = std::sync::mpsc::channel();
+        // Holds the store mid-write, as a write on another thread would
+        let writer = {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                let mut inner = db.inner.write().unwrap();
+                locked.send(()).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                inner.update_seq += 1;
+            })
+        };
+        wait_locked.recv().unwrap();
+
+        let fork = db.fork();
+        assert_eq!(fork.info().await.unwrap().update_seq, Seq::Num(1));
+        writer.join().unwrap();
+
+        // Forking alongside real writes never sees half of one
+        let writes = {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for i in 0..50 {
+                    let doc = Document {
+                        id: format!("doc{}", i),
+                        rev: None,
+                        deleted: false,
+                        data: serde_json::json!({}),
+                        attachments: HashMap::new(),
+                    };
+                    db.bulk_docs(vec![doc], BulkDocsOptions::new())
+                        .await
+                        .unwrap();
+                }
+            })
+        };
+        while !writes.is_finished() {
+            let fork = db.fork();
+            let info = fork.info().await.unwrap();
+            assert_eq!(info.update_seq, Seq::Num(info.doc_count + 1));
+            tokio::task::yield_now().await;
+        }
+        writes.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshot_restore_preserves_store() {
+        let db = new_db().await;
+        for hash in ["aaa", "bbb"] {
+            let doc = Document {
+                id: "doc1".into(),
+                rev: Some(Revision::new(1, hash.into())),
+                deleted: false,
+                data: serde_json::json!({"branch": hash}),
+                attachments: HashMap::new(),
+            };
+            db.bulk_docs(vec![doc], BulkDocsOptions::replication())
+                .await
+                .unwrap();
+        }
+        let r = db
+            .bulk_docs(
+                vec![Document {
+                    id: "doc2".into(),
+                    rev: None,
+                    deleted: false,
+                    data: serde_json::json!({"n": 2}),
+                    attachments: HashMap::new(),
+                }],
+                BulkDocsOptions::new(),
+            )
+            .await
+            .unwrap();
+        db.put_attachment(
+            "doc2",
+            "a.txt",
+            r[0].rev.as_ref().unwrap(),
+            b"hi".to_vec(),
+            "text/plain",
+        )
+        .await
+        .unwrap();
+        db.put_local("ck", serde_json::json!({"seq": 3}))
+            .await
+            .unwrap();
+
+        // Round-trip through text, as a fixture file would
+        let text = db.snapshot().await.to_string();
+        let restored = MemoryAdapter::restore(serde_json::from_str(&text).unwrap()).unwrap();
+
+        let rows = |r: AllDocsResponse| -> Vec<(String, String)> {
+            r.rows
+                .into_iter()
+                .map(|row| (row.id, row.value.rev))
+                .collect()
+        };
+        assert_eq!(
+            rows(db.all_docs(AllDocsOptions::new()).await.unwrap()),
+            rows(restored.all_docs(AllDocsOptions::new()).await.unwrap())
+        );
+
+        let changes = |r: ChangesResponse| -> Vec<(Seq, String)> {
+            r.results.into_iter().map(|c| (c.seq, c.id)).collect()
+        };
+        assert_eq!(
+            changes(db.changes(ChangesOptions::default()).await.unwrap()),
+            changes(restored.changes(ChangesOptions::default()).await.unwrap())
+        );
+        assert_eq!(restored.info().await.unwrap().update_seq, Seq::Num(4));
+
+        let opts = GetOptions {
+            conflicts: true,
+            ..Default::default()
+        };
+        let doc = restored.get("doc1", opts).await.unwrap();
+        assert_eq!(doc.rev.unwrap().to_string(), "1-bbb");
+        assert_eq!(doc.data["_conflicts"], serde_json::json!(["1-aaa"]));
+
+        let data = restored
+            .get_attachment("doc2", "a.txt", GetAttachmentOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(data, b"hi");
+        assert_eq!(restored.get_local("ck").await.unwrap()["seq"], 3);
+    }
+
+    #[tokio::test]
+    async fn save_and_load_from_path() {
+        let db = new_db().await;
+        let docs = (0..5)
+            .map(|i| Document {
+                id: format!("doc{}", i),
+                rev: None,
+                deleted: false,
+                data: serde_json::json!({"n": i}),
+                attachments: HashMap::new(),
+            })
+            .collect();
+        db.bulk_docs(docs, BulkDocsOptions::new()).await.unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.ndjson");
+        db.save_to_path(&path).await.unwrap();
+        // Saved again over the first file, via the temp file and rename
+        db.save_to_path(&path).await.unwrap();
+        assert!(!dir.path().join("store.ndjson.tmp").exists());
+
+        // A save that can't be renamed into place leaves no temp file
+        let taken = dir.path().join("taken");
+        std::fs::create_dir_all(taken.join("inside")).unwrap();
+        assert!(db.save_to_path(&taken).await.is_err());
+        assert!(!dir.path().join("taken.tmp").exists());
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with(r#"{"type":"header""#));
+        assert!(
+            text.lines()
+                .all(|l| serde_json::from_str::<serde_json::Value>(l).is_ok())
+        );
+
+        let loaded = MemoryAdapter::load_from_path(&path).unwrap();
+        assert_eq!(loaded.info().await.unwrap().doc_count, 5);
+        for i in 0..5 {
+            let id = format!("doc{}", i);
+            let original = db.get(&id, GetOptions::default()).await.unwrap();
+            let copy = loaded.get(&id, GetOptions::default()).await.unwrap();
+            assert_eq!(copy.data, original.data);
+            assert_eq!(copy.rev, original.rev);
+        }
+    }
+
+    #[tokio::test]
+    async fn long_histories_survive_save_and_restore() {
+        let db = new_db().await;
+        let mut rev = None;
+        for i in 0..150 {
+            let doc = Document {
+                id: "doc1".into(),
+                rev,
+                deleted: false,
+                data: serde_json::json!({"i": i}),
+                attachments: HashMap::new(),
+            };
+            let results = db
+                .bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap();
+            rev = Some(results[0].rev.clone().unwrap().parse().unwrap());
+        }
+        let check = |db: MemoryAdapter| async move {
+            let opts = GetOptions {
+                revs: true,
+                ..Default::default()
+            };
+            let doc = db.get("doc1", opts).await.unwrap();
+            assert_eq!(doc.rev.unwrap().pos, 150);
+            assert_eq!(doc.data["_revisions"]["ids"].as_array().unwrap().len(), 150);
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.ndjson");
+        db.save_to_path(&path).await.unwrap();
+        check(MemoryAdapter::load_from_path(&path).unwrap()).await;
+
+        let text = serde_json::to_string(&db.snapshot().await).unwrap();
+        let value = serde_json::from_str(&text).unwrap();
+        check(MemoryAdapter::restore(value).unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn cached_winner_tracks_every_edit() {
+        let db = new_db().await;
+        let check = |db: &MemoryAdapter| {
+            let db = db.clone();
+            async move {
+                let inner = db.inner.read().unwrap();
+                let stored = &inner.docs["doc1"];
+                assert_eq!(
+                    stored.winning_rev(),
+                    rouchdb_core::merge::winning_rev(&stored.rev_tree)
+                );
+                assert_eq!(stored.is_deleted(), is_deleted(&stored.rev_tree));
+                stored.winning_rev().unwrap()
+            }
+        };
+        let edit = |rev: Option<Revision>, deleted: bool| Document {
+            id: "doc1".into(),
+            rev,
+            deleted,
+            data: serde_json::json!({"deleted": deleted}),
+            attachments: HashMap::new(),
+        };
+
+        db.bulk_docs(vec![edit(None, false)], BulkDocsOptions::new())
+            .await
+            .unwrap();
+        let first = check(&db).await;
+        db.bulk_docs(
+            vec![edit(Some(first.clone()), false)],
+            BulkDocsOptions::new(),
+        )
+        .await
+        .unwrap();
+        let second = check(&db).await;
+        assert_eq!(second.pos, 2);
+
+        // A replicated branch that sorts higher takes over as winner
+        let branch = Document {
+            rev: Some(Revision::new(2, "ffffffffffffffffffffffffffffffff".into())),
+            ..edit(None, false)
+        };
+        db.bulk_docs(vec![branch], BulkDocsOptions::replication())
+            .await
+            .unwrap();
+        let winner = check(&db).await;
+        assert_ne!(winner, second);
+
+        db.bulk_docs(
+            vec![edit(Some(winner.clone()), true)],
+            BulkDocsOptions::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(check(&db).await, second);
+
+        let mut req = HashMap::new();
+        req.insert("doc1".to_string(), vec![second.to_string()]);
+        db.purge(req).await.unwrap();
+        assert_ne!(check(&db).await, second);
+
+        let restored = MemoryAdapter::restore(db.snapshot().await).unwrap();
+        check(&restored).await;
+    }
+
+    #[tokio::test]
+    async fn purge_conflict_branch_shrinks_conflicts() {
+        let db = new_db().await;
+        for hash in ["aaa", "bbb", "ccc"] {
+            let doc = Document {
+                id: "doc1".into(),
+                rev: Some(Revision::new(1, hash.into())),
+                deleted: false,
+                data: serde_json::json!({"branch": hash}),
+                attachments: HashMap::new(),
+            };
+            db.bulk_docs(vec![doc], BulkDocsOptions::replication())
+                .await
+                .unwrap();
+        }
+        let conflicts = |db: &MemoryAdapter| {
+            let db = db.clone();
+            async move {
+                let inner = db.inner.read().unwrap();
+                collect_conflicts(&inner.docs["doc1"].rev_tree).len()
+            }
+        };
+        assert_eq!(conflicts(&db).await, 2);
+        let seq_before = db.info().await.unwrap().update_seq.as_num();
+
+        let mut req = HashMap::new();
+        req.insert("doc1".to_string(), vec!["1-aaa".to_string()]);
+        let resp = db.purge(req).await.unwrap();
+        assert_eq!(resp.purged["doc1"], vec!["1-aaa".to_string()]);
+        assert_eq!(conflicts(&db).await, 1);
+        assert!(db.info().await.unwrap().update_seq.as_num() > seq_before);
+
+        // The surviving doc reappears in the changes feed at the new seq.
+        let changes = db.changes(ChangesOptions::default()).await.unwrap();
+        assert_eq!(changes.results.len(), 1);
+        assert_eq!(changes.results[0].seq.as_num(), resp.purge_seq.unwrap());
+    }
+
+    #[tokio::test]
+    async fn purge_only_leaf_drops_history() {
+        let db = new_db().await;
+        let mut revs = Vec::new();
+        for i in 0..3 {
+            let doc = Document {
+                id: "doc1".into(),
+                rev: revs.last().map(|r: &String| r.parse().unwrap()),
+                deleted: false,
+                data: serde_json::json!({"i": i}),
+                attachments: HashMap::new(),
+            };
+            let results = db
+                .bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap();
+            revs.push(results[0].rev.clone().unwrap());
+        }
+
+        let mut req = HashMap::new();
+        req.insert("doc1".to_string(), vec![revs[2].clone()]);
+        let resp = db.purge(req).await.unwrap();
+        assert_eq!(resp.purged["doc1"], vec![revs[2].clone()]);
+
+        assert!(db.get("doc1", GetOptions::default()).await.is_err());
+        for rev in &revs {
+            let opts = GetOptions {
+                rev: Some(rev.clone()),
+                ..Default::default()
+            };
+            assert!(db.get("doc1", opts).await.is_err());
+        }
+        assert!(db.inner.read().unwrap().docs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stemmed_revisions_drop_their_bodies() {
+        let db = new_db().await;
+
+        let mut rev: Option<Revision> = None;
+        let mut first_rev = String::new();
+        for i in 0..=DEFAULT_REV_LIMIT {
+            let doc = Document {
+                id: "doc1".into(),
+                rev: rev.clone(),
+                deleted: false,
+                data: serde_json::json!({"i": i}),
+                attachments: HashMap::new(),
+            };
+            let results = db
+                .bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap();
+            let new_rev = results[0].rev.clone().unwrap();
+            if i == 0 {
+                first_rev = new_rev.clone();
+            }
+            rev = Some(new_rev.parse().unwrap());
+        }
+
+        let inner = db.inner.read().unwrap();
+        let stored = &inner.docs["doc1"];
+        assert!(!stored.rev_data.contains_key(&first_rev));
+        assert!(!stored.rev_deleted.contains_key(&first_rev));
+        assert_eq!(stored.rev_data.len() as u64, DEFAULT_REV_LIMIT);
+    }
+
     #[tokio::test]
     async fn update_document() {
         let db = new_db().await;
@@ -1534,4 +2359,42 @@ mod tests {
         assert!(result.results[0].docs[0].ok.is_some());
         assert!(result.results[1].docs[0].error.is_some());
     }
+
+    #[tokio::test]
+    async fn corrupted_attachment_fails_verified_read() {
+        let db = new_db().await;
+        let doc = Document {
+            id: "doc".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        };
+        let rev = db
+            .bulk_docs(vec![doc], BulkDocsOptions::new())
+            .await
+            .unwrap()[0]
+            .rev
+            .clone()
+            .unwrap();
+        db.put_attachment("doc", "a.txt", &rev, b"hello".to_vec(), "text/plain")
+            .await
+            .unwrap();
+        for data in db.inner.write().unwrap().attachments.values_mut() {
+            *data = b"jello".to_vec();
+        }
+
+        let err = db
+            .get_attachment("doc", "a.txt", GetAttachmentOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RouchError::DatabaseError(ref m) if m.contains("digest mismatch")));
+
+        let unchecked = GetAttachmentOptions {
+            verify: false,
+            ..Default::default()
+        };
+        let data = db.get_attachment("doc", "a.txt", unchecked).await.unwrap();
+        assert_eq!(data, b"jello");
+    }
 }
@@ -0,0 +1,265 @@
+//! Chunked attachment storage.
+//!
+//! Attachment bytes are stored as rows of at most
+//! [`crate::ATTACHMENT_CHUNK_LEN`] bytes, keyed by attachment and chunk
+//! index, so streaming an attachment in or out holds one chunk in memory
+//! at a time. Each chunk is sealed on its own. Every chunk but the last is
+//! full, which lets a ranged read start at the chunk holding its first
+//! byte.
+//!
+//! Files written before chunking keep whole attachments in the
+//! `attachments` table. Those are read from there until the attachment is
+//! next written.
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use md5::{Digest, Md5};
+use redb::{ReadTransaction, TableHandle, WriteTransaction};
+use tokio::io::{AsyncRead, ReadBuf};
+
+use rouchdb_core::error::{Result, RouchError};
+
+use crate::seal::Sealer;
+use crate::{ATTACHMENT_CHUNK_LEN as CHUNK_LEN, ATTACHMENT_CHUNK_TABLE, ATTACHMENT_TABLE};
+
+fn db_err(e: impl ToString) -> RouchError {
+    RouchError::DatabaseError(e.to_string())
+}
+
+/// Store `data` as the attachment under `key`, replacing any previous
+/// bytes.
+pub(crate) fn write(txn: &WriteTransaction, sealer: &Sealer, key: &str, data: &[u8]) -> Result<()> {
+    clear(txn, key)?;
+    if data.is_empty() {
+        // One empty chunk, so the attachment still has a row
+        return write_chunk(txn, sealer, key, 0, &[]);
+    }
+    for (index, chunk) in data.chunks(CHUNK_LEN).enumerate() {
+        write_chunk(txn, sealer, key, index as u64, chunk)?;
+    }
+    Ok(())
+}
+
+/// Remove the bytes stored under `key`, chunked or not.
+pub(crate) fn clear(txn: &WriteTransaction, key: &str) -> Result<()> {
+    let mut chunks = txn.open_table(ATTACHMENT_CHUNK_TABLE).map_err(db_err)?;
+    chunks
+        .retain_in((key, 0)..=(key, u64::MAX), |_, _| false)
+        .map_err(db_err)?;
+    let mut whole = txn.open_table(ATTACHMENT_TABLE).map_err(db_err)?;
+    whole.remove(key).map_err(db_err)?;
+    Ok(())
+}
+
+/// Store chunk `index` of the attachment under `key`. Callers write
+/// chunks in order after a [`clear`], each full but the last.
+pub(crate) fn write_chunk(
+    txn: &WriteTransaction,
+    sealer: &Sealer,
+    key: &str,
+    index: u64,
+    chunk: &[u8],
+) -> Result<()> {
+    let sealed = sealer.seal(
+        ATTACHMENT_CHUNK_TABLE.name(),
+        &[key.as_bytes(), &index.to_be_bytes()],
+        chunk.to_vec(),
+    )?;
+    let mut chunks = txn.open_table(ATTACHMENT_CHUNK_TABLE).map_err(db_err)?;
+    chunks
+        .insert((key, index), sealed.as_slice())
+        .map_err(db_err)?;
+    Ok(())
+}
+
+/// The whole attachment stored under `key`, if any.
+pub(crate) fn read(txn: &ReadTransaction, sealer: &Sealer, key: &str) -> Result<Option<Vec<u8>>> {
+    let Some(mut reader) = ChunkReader::new(txn, sealer, key, 0, u64::MAX, None)? else {
+        return Ok(None);
+    };
+    // A whole attachment is loaded by `new`
+    let mut data = std::mem::take(&mut reader.chunk);
+    while let Some(chunk) = reader.next_chunk()? {
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+type ChunkTable = redb::ReadOnlyTable<(&'static str, u64), &'static [u8]>;
+
+/// Reads an attachment one stored chunk at a time.
+pub(crate) struct ChunkReader {
+    /// `None` once every chunk has been read or for a whole attachment,
+    /// which is loaded up front.
+    table: Option<ChunkTable>,
+    sealer: Sealer,
+    key: String,
+    next_index: u64,
+    /// Offset into the attachment of the next chunk's first byte.
+    next_offset: u64,
+    start: u64,
+    end: u64,
+    /// The expected digest and the hash of the bytes read so far, when
+    /// checking.
+    verify: Option<(String, Md5)>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl ChunkReader {
+    /// A reader over bytes `start..end` of the attachment under `key`, or
+    /// `None` if nothing is stored there. With `verify` set to the
+    /// attachment's digest, every chunk is hashed, even outside the range,
+    /// and a mismatch fails the read that reaches the end.
+    pub(crate) fn new(
+        txn: &ReadTransaction,
+        sealer: &Sealer,
+        key: &str,
+        start: u64,
+        end: u64,
+        verify: Option<String>,
+    ) -> Result<Option<Self>> {
+        let mut reader = Self {
+            table: None,
+            sealer: sealer.clone(),
+            key: key.to_string(),
+            next_index: 0,
+            next_offset: 0,
+            start,
+            end,
+            verify: verify.map(|digest| (digest, Md5::new())),
+            chunk: Vec::new(),
+            pos: 0,
+        };
+
+        // Files from before chunking may not have the table at all
+        let table = match txn.open_table(ATTACHMENT_CHUNK_TABLE) {
+            Ok(table) => Some(table),
+            Err(redb::TableError::TableDoesNotExist(_)) => None,
+            Err(e) => return Err(db_err(e)),
+        };
+        if let Some(table) = table
+            && table.get((key, 0)).map_err(db_err)?.is_some()
+        {
+            if reader.verify.is_none() {
+                reader.next_index = start / CHUNK_LEN as u64;
+                reader.next_offset = reader.next_index * CHUNK_LEN as u64;
+            }
+            reader.table = Some(table);
+            return Ok(Some(reader));
+        }
+
+        let whole = txn.open_table(ATTACHMENT_TABLE).map_err(db_err)?;
+        let Some(guard) = whole.get(key).map_err(db_err)? else {
+            return Ok(None);
+        };
+        let data = sealer
+            .open(ATTACHMENT_TABLE.name(), &[key.as_bytes()], guard.value())?
+            .into_owned();
+        reader.hash(&data);
+        reader.next_offset = data.len() as u64;
+        reader.chunk = reader.visible(0, data);
+        Ok(Some(reader))
+    }
+
+    /// Bytes held in memory, at most one chunk for chunked attachments.
+    #[cfg(test)]
+    pub(crate) fn buffered(&self) -> usize {
+        self.chunk.capacity()
+    }
+
+    /// The in-range bytes of the next stored chunk, or `None` at the end.
+    /// The digest is checked by the caller once this returns `None`.
+    fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            let Some(table) = self.table.as_ref() else {
+                return Ok(None);
+            };
+            if self.verify.is_none() && self.next_offset >= self.end {
+                self.table = None;
+                return Ok(None);
+            }
+            let stored = table
+                .get((self.key.as_str(), self.next_index))
+                .map_err(db_err)?
+                .map(|guard| {
+                    let place = [self.key.as_bytes(), &self.next_index.to_be_bytes()];
+                    self.sealer
+                        .open(ATTACHMENT_CHUNK_TABLE.name(), &place, guard.value())
+                        .map(|c| c.into_owned())
+                })
+                .transpose()?;
+            let Some(data) = stored else {
+                self.table = None;
+                return Ok(None);
+            };
+            self.hash(&data);
+            let offset = self.next_offset;
+            self.next_index += 1;
+            self.next_offset += data.len() as u64;
+            let visible = self.visible(offset, data);
+            if !visible.is_empty() {
+                return Ok(Some(visible));
+            }
+        }
+    }
+
+    fn hash(&mut self, data: &[u8]) {
+        if let Some((_, ref mut hasher)) = self.verify {
+            hasher.update(data);
+        }
+    }
+
+    /// The part of `data`, found at `offset`, inside the requested range.
+    fn visible(&self, offset: u64, mut data: Vec<u8>) -> Vec<u8> {
+        let len = data.len() as u64;
+        let from = self.start.saturating_sub(offset).min(len) as usize;
+        let to = self.end.saturating_sub(offset).min(len) as usize;
+        data.truncate(to.max(from));
+        data.drain(..from);
+        data
+    }
+
+    fn check_digest(&mut self) -> Result<()> {
+        if let Some((digest, hasher)) = self.verify.take()
+            && digest.starts_with("md5-")
+            && rouchdb_core::digest::digest_string(hasher.finalize().as_slice()) != digest
+        {
+            return Err(RouchError::DatabaseError(
+                "attachment digest mismatch".into(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl AsyncRead for ChunkReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.pos == this.chunk.len() {
+            // Drop the spent chunk before loading the next one
+            this.chunk = Vec::new();
+            this.pos = 0;
+            match this.next_chunk() {
+                Ok(Some(chunk)) => this.chunk = chunk,
+                Ok(None) => {
+                    if let Err(e) = this.check_digest() {
+                        return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e)));
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) => return Poll::Ready(Err(io::Error::other(e))),
+            }
+        }
+        let n = buf.remaining().min(this.chunk.len() - this.pos);
+        buf.put_slice(&this.chunk[this.pos..this.pos + n]);
+        this.pos += n;
+        Poll::Ready(Ok(()))
+    }
+}
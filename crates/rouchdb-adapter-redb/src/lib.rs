@@ -1,23 +1,38 @@
-use std::collections::HashMap;
+mod attachments;
+mod read_only;
+mod seal;
+
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use async_trait::async_trait;
+use futures_util::StreamExt;
 use md5::{Digest, Md5};
-use redb::{Database, ReadableTable, TableDefinition};
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition, TableHandle};
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use rouchdb_core::adapter::Adapter;
+use rouchdb_core::adapter::{Adapter, AllDocsStream, AttachmentReader};
+use rouchdb_core::digest::{compute_attachment_digest, digest_string, verify_digest};
 use rouchdb_core::document::*;
 use rouchdb_core::error::{Result, RouchError};
-use rouchdb_core::merge::{collect_conflicts, is_deleted, merge_tree, winning_rev};
+use rouchdb_core::merge::{
+    collect_conflicts, is_deleted, is_editable_rev, merge_tree, select_open_revs, winning_rev,
+};
+use rouchdb_core::mime;
+use rouchdb_core::rev::compute_rev;
 use rouchdb_core::rev_tree::{
     NodeOpts, RevNode, RevPath, RevStatus, RevTree, build_path_from_revs, collect_leaves,
-    find_rev_ancestry, rev_exists,
+    recent_revs, remove_leaf, rev_exists, revisions_for, revs_info_for, traverse_rev_tree,
 };
 
+use crate::attachments::ChunkReader;
+use crate::seal::Sealer;
+
 const DEFAULT_REV_LIMIT: u64 = 1000;
 
 // ---------------------------------------------------------------------------
@@ -36,12 +51,29 @@ const CHANGES_TABLE: TableDefinition<u64, &[u8]> = TableDefinition::new("changes
 /// Local documents: local_id -> serialized JSON
 const LOCAL_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("local_docs");
 
-/// Attachments: digest -> raw bytes
+/// Whole attachments written before chunked storage: "doc_id\0att_id" ->
+/// raw bytes. Read until the attachment is next written.
 const ATTACHMENT_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("attachments");
 
+/// Attachment chunks: ("doc_id\0att_id", chunk index) -> raw bytes
+const ATTACHMENT_CHUNK_TABLE: TableDefinition<(&str, u64), &[u8]> =
+    TableDefinition::new("attachment_chunks");
+
+/// Attachments are stored, and streamed out of storage, in chunks of this
+/// many bytes.
+pub const ATTACHMENT_CHUNK_LEN: usize = 64 * 1024;
+
 /// Metadata table: key -> value
 const META_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("metadata");
 
+/// Persisted map/reduce view indexes: view name -> serialized JSON header
+const VIEW_INDEX_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("view_indexes");
+
+/// Rows of persisted view indexes: (view name, doc_id) -> serialized JSON
+/// map output
+const VIEW_INDEX_ROW_TABLE: TableDefinition<(&str, &str), &[u8]> =
+    TableDefinition::new("view_index_rows");
+
 // ---------------------------------------------------------------------------
 // Serializable records
 // ---------------------------------------------------------------------------
@@ -50,12 +82,57 @@ const META_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("metadata"
 struct DocRecord {
     rev_tree: Vec<SerializedRevPath>,
     seq: u64,
+    /// The winning revision, computed when the record is written so reads
+    /// don't rebuild and walk the tree. Records written before it was
+    /// stored don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    winner: Option<WinnerRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WinnerRecord {
+    pos: u64,
+    hash: String,
+    deleted: bool,
+}
+
+impl DocRecord {
+    fn new(tree: &RevTree, seq: u64) -> Self {
+        Self {
+            rev_tree: rev_tree_to_serialized(tree),
+            seq,
+            winner: winning_rev(tree).map(|rev| WinnerRecord {
+                pos: rev.pos,
+                hash: rev.hash,
+                deleted: is_deleted(tree),
+            }),
+        }
+    }
+
+    /// The winning revision and whether it is a deletion, falling back to
+    /// the tree for records without a stored winner.
+    fn winner(&self) -> Option<(Revision, bool)> {
+        match self.winner {
+            Some(ref w) => Some((Revision::new(w.pos, w.hash.clone()), w.deleted)),
+            None => {
+                let tree = serialized_to_rev_tree(&self.rev_tree);
+                winning_rev(&tree).map(|rev| (rev, is_deleted(&tree)))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct SerializedRevPath {
     pos: u64,
-    tree: SerializedRevNode,
+    /// The path's nodes depth-first, each pointing at its parent. Kept flat
+    /// because serde_json refuses input nested more than 128 levels deep,
+    /// which a nested tree reaches after about 60 edits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    nodes: Vec<SerializedRevNode>,
+    /// Nested form written by earlier versions; read, never written.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tree: Option<NestedRevNode>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -63,7 +140,17 @@ struct SerializedRevNode {
     hash: String,
     status: String,
     deleted: bool,
-    children: Vec<SerializedRevNode>,
+    /// Index of the parent in `nodes`; `None` for the root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NestedRevNode {
+    hash: String,
+    status: String,
+    deleted: bool,
+    children: Vec<NestedRevNode>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -99,47 +186,83 @@ struct MetaRecord {
 
 fn rev_tree_to_serialized(tree: &RevTree) -> Vec<SerializedRevPath> {
     tree.iter()
-        .map(|path| SerializedRevPath {
-            pos: path.pos,
-            tree: rev_node_to_serialized(&path.tree),
+        .map(|path| {
+            let mut nodes = Vec::new();
+            let mut stack = vec![(&path.tree, None)];
+            while let Some((node, parent)) = stack.pop() {
+                let index = nodes.len();
+                nodes.push(SerializedRevNode {
+                    hash: node.hash.clone(),
+                    status: match node.status {
+                        RevStatus::Available => "available".into(),
+                        RevStatus::Missing => "missing".into(),
+                    },
+                    deleted: node.opts.deleted,
+                    parent,
+                });
+                // Reversed so the first child comes out first
+                stack.extend(node.children.iter().rev().map(|c| (c, Some(index))));
+            }
+            SerializedRevPath {
+                pos: path.pos,
+                nodes,
+                tree: None,
+            }
         })
         .collect()
 }
 
-fn rev_node_to_serialized(node: &RevNode) -> SerializedRevNode {
-    SerializedRevNode {
-        hash: node.hash.clone(),
-        status: match node.status {
-            RevStatus::Available => "available".into(),
-            RevStatus::Missing => "missing".into(),
-        },
-        deleted: node.opts.deleted,
-        children: node.children.iter().map(rev_node_to_serialized).collect(),
-    }
-}
-
 fn serialized_to_rev_tree(paths: &[SerializedRevPath]) -> RevTree {
     paths
         .iter()
-        .map(|p| RevPath {
-            pos: p.pos,
-            tree: serialized_to_rev_node(&p.tree),
+        .filter_map(|p| {
+            let tree = match p.tree {
+                Some(ref nested) => nested_to_rev_node(nested),
+                None => flat_to_rev_node(&p.nodes)?,
+            };
+            Some(RevPath { pos: p.pos, tree })
         })
         .collect()
 }
 
-fn serialized_to_rev_node(node: &SerializedRevNode) -> RevNode {
+/// Rebuild a path from its depth-first node list. Parents always precede
+/// their children, so attaching from the back leaves only the root.
+fn flat_to_rev_node(nodes: &[SerializedRevNode]) -> Option<RevNode> {
+    let mut built: Vec<Option<RevNode>> = nodes
+        .iter()
+        .map(|n| {
+            Some(RevNode {
+                hash: n.hash.clone(),
+                status: rev_status(&n.status),
+                opts: NodeOpts { deleted: n.deleted },
+                children: Vec::new(),
+            })
+        })
+        .collect();
+    for (i, n) in nodes.iter().enumerate().skip(1).rev() {
+        let child = built[i].take()?;
+        let parent = built.get_mut(n.parent.filter(|p| *p < i)?)?.as_mut()?;
+        parent.children.insert(0, child);
+    }
+    built.into_iter().next()?
+}
+
+fn nested_to_rev_node(node: &NestedRevNode) -> RevNode {
     RevNode {
         hash: node.hash.clone(),
-        status: if node.status == "available" {
-            RevStatus::Available
-        } else {
-            RevStatus::Missing
-        },
+        status: rev_status(&node.status),
         opts: NodeOpts {
             deleted: node.deleted,
         },
-        children: node.children.iter().map(serialized_to_rev_node).collect(),
+        children: node.children.iter().map(nested_to_rev_node).collect(),
+    }
+}
+
+fn rev_status(status: &str) -> RevStatus {
+    if status == "available" {
+        RevStatus::Available
+    } else {
+        RevStatus::Missing
     }
 }
 
@@ -147,24 +270,82 @@ fn rev_data_key(doc_id: &str, rev_str: &str) -> String {
     format!("{}\0{}", doc_id, rev_str)
 }
 
+fn encode_rev_data(sealer: &Sealer, key: &str, rd: &RevDataRecord) -> Result<Vec<u8>> {
+    sealer.seal(
+        REV_DATA_TABLE.name(),
+        &[key.as_bytes()],
+        serde_json::to_vec(rd)?,
+    )
+}
+
+fn decode_rev_data(sealer: &Sealer, key: &str, bytes: &[u8]) -> Result<RevDataRecord> {
+    let plain = sealer.open(REV_DATA_TABLE.name(), &[key.as_bytes()], bytes)?;
+    Ok(serde_json::from_slice(&plain)?)
+}
+
 // ---------------------------------------------------------------------------
 // Adapter
 // ---------------------------------------------------------------------------
 
+/// Options for `RedbAdapter::open_with_opts`.
+#[derive(Debug, Clone, Default)]
+pub struct RedbOptions {
+    /// Compact in the background after every this many document writes.
+    /// `None` leaves compaction to explicit `compact()` calls.
+    pub auto_compact_every: Option<u64>,
+}
+
 /// Persistent adapter backed by `redb`.
+///
+/// Clones share the open file.
+#[derive(Clone)]
 pub struct RedbAdapter {
-    db: Arc<Database>,
+    /// `None` once `close` has released the file.
+    db: Arc<std::sync::RwLock<Option<Arc<Database>>>>,
     name: String,
     /// Lock for write serialization (redb handles transactions, but we need
     /// to serialize our read-modify-write sequences).
     write_lock: Arc<RwLock<()>>,
+    /// Opened with `open_read_only`: every write fails with `Forbidden`.
+    read_only: bool,
+    auto_compact_every: Option<u64>,
+    /// Documents written through this handle, for `auto_compact_every`.
+    writes: Arc<AtomicU64>,
+    /// Set while a background compaction runs, so they never overlap.
+    compacting: Arc<AtomicBool>,
+    /// Encrypts stored values when opened with `open_encrypted`.
+    sealer: Sealer,
 }
 
 impl RedbAdapter {
     /// Open or create a database at the given path.
     pub fn open(path: impl AsRef<Path>, name: &str) -> Result<Self> {
-        let db = Database::create(path.as_ref())
-            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        Self::open_with_opts(path, name, RedbOptions::default())
+    }
+
+    /// Open or create a database at the given path with `opts`.
+    pub fn open_with_opts(path: impl AsRef<Path>, name: &str, opts: RedbOptions) -> Result<Self> {
+        Self::create(path.as_ref(), name, opts, Sealer::default())
+    }
+
+    /// Open or create a database whose bodies, attachments, local documents
+    /// and view indexes are encrypted with `key` (ChaCha20-Poly1305).
+    ///
+    /// The first encrypted open of a new file records a check value; after
+    /// that the file only opens with the same key, and plain `open` refuses
+    /// it. An existing unencrypted file can't be opened this way.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: impl AsRef<Path>, name: &str, key: &[u8; 32]) -> Result<Self> {
+        Self::create(
+            path.as_ref(),
+            name,
+            RedbOptions::default(),
+            Sealer::new(key),
+        )
+    }
+
+    fn create(path: &Path, name: &str, opts: RedbOptions, sealer: Sealer) -> Result<Self> {
+        let db = Database::create(path).map_err(|e| RouchError::DatabaseError(e.to_string()))?;
 
         // Initialize tables
         {
@@ -188,16 +369,25 @@ impl RedbAdapter {
                 write_txn
                     .open_table(ATTACHMENT_TABLE)
                     .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+                write_txn
+                    .open_table(ATTACHMENT_CHUNK_TABLE)
+                    .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+                write_txn
+                    .open_table(VIEW_INDEX_TABLE)
+                    .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+                write_txn
+                    .open_table(VIEW_INDEX_ROW_TABLE)
+                    .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
             }
             {
                 let mut meta = write_txn
                     .open_table(META_TABLE)
                     .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
-                if meta
+                let fresh = meta
                     .get("meta")
                     .map_err(|e| RouchError::DatabaseError(e.to_string()))?
-                    .is_none()
-                {
+                    .is_none();
+                if fresh {
                     let record = MetaRecord {
                         update_seq: 0,
                         db_uuid: Uuid::new_v4().to_string(),
@@ -206,6 +396,8 @@ impl RedbAdapter {
                     meta.insert("meta", bytes.as_slice())
                         .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
                 }
+                check_key(&mut meta, &sealer, fresh)?;
+                check_format(&mut meta)?;
             }
             write_txn
                 .commit()
@@ -213,15 +405,123 @@ impl RedbAdapter {
         }
 
         Ok(Self {
-            db: Arc::new(db),
+            db: Arc::new(std::sync::RwLock::new(Some(Arc::new(db)))),
             name: name.to_string(),
             write_lock: Arc::new(RwLock::new(())),
+            read_only: false,
+            auto_compact_every: opts.auto_compact_every,
+            writes: Arc::new(AtomicU64::new(0)),
+            compacting: Arc::new(AtomicBool::new(false)),
+            sealer,
         })
     }
 
+    /// Open an existing redb database for reading only.
+    ///
+    /// The file is read in place under a shared lock, so any number of
+    /// read-only handles can share it, but not with a writer: this fails
+    /// with an `Io` error of kind `WouldBlock` while the file is open for
+    /// writing, and `open` fails while a read-only handle holds it. Writes
+    /// return `RouchError::Forbidden`.
+    pub fn open_read_only(path: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let backend = read_only::SharedFileBackend::open(path.as_ref())?;
+        let db = Database::builder()
+            .create_with_backend(backend)
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        let adapter = Self {
+            db: Arc::new(std::sync::RwLock::new(Some(Arc::new(db)))),
+            name: name.to_string(),
+            write_lock: Arc::new(RwLock::new(())),
+            read_only: true,
+            auto_compact_every: None,
+            writes: Arc::new(AtomicU64::new(0)),
+            compacting: Arc::new(AtomicBool::new(false)),
+            sealer: Sealer::default(),
+        };
+        // Fail now rather than on first read if this isn't a RouchDB file
+        adapter.read_meta()?;
+        adapter.check_format_readable()?;
+        if adapter.is_encrypted_file()? {
+            return Err(RouchError::Forbidden("database is encrypted".into()));
+        }
+        Ok(adapter)
+    }
+
+    /// The open redb handle, or an error after `close`.
+    fn db(&self) -> Result<Arc<Database>> {
+        self.db
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| RouchError::DatabaseError("database is closed".into()))
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(RouchError::Forbidden("read-only".into()));
+        }
+        Ok(())
+    }
+
+    /// Count `n` committed document writes and start a background
+    /// compaction each time the total passes a multiple of
+    /// `auto_compact_every`, unless one is still running. Compaction waits
+    /// for the write lock like any write; reads are not blocked. Outside a
+    /// Tokio runtime there is nowhere to run it, so it is skipped until a
+    /// later threshold is crossed on one.
+    fn count_writes(&self, n: u64) {
+        let Some(every) = self.auto_compact_every.filter(|every| *every > 0) else {
+            return;
+        };
+        let before = self.writes.fetch_add(n, Ordering::Relaxed);
+        if (before + n) / every <= before / every {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        if !self.compacting.swap(true, Ordering::AcqRel) {
+            let adapter = self.clone();
+            runtime.spawn(async move {
+                // Nobody to report to; the next run retries
+                let _ = adapter.compact().await;
+                adapter.compacting.store(false, Ordering::Release);
+            });
+        }
+    }
+
+    fn is_encrypted_file(&self) -> Result<bool> {
+        let read_txn = self
+            .db()?
+            .begin_read()
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        let table = read_txn
+            .open_table(META_TABLE)
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        let entry = table
+            .get(KEY_CHECK_ENTRY)
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        Ok(entry.is_some())
+    }
+
+    /// `check_format` for a handle that can't record the version.
+    fn check_format_readable(&self) -> Result<()> {
+        let read_txn = self
+            .db()?
+            .begin_read()
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        let table = read_txn
+            .open_table(META_TABLE)
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        let entry = table
+            .get(FORMAT_VERSION_ENTRY)
+            .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
+        format_supported(entry.map(|g| g.value().to_vec()))
+    }
+
     fn read_meta(&self) -> Result<MetaRecord> {
         let read_txn = self
-            .db
+            .db()?
             .begin_read()
             .map_err(|e| RouchError::DatabaseError(e.to_string()))?;
         let table = read_txn
@@ -236,32 +536,26 @@ impl RedbAdapter {
     }
 }
 
-fn generate_rev_hash(
-    doc_data: &serde_json::Value,
-    deleted: bool,
-    prev_rev: Option<&str>,
-) -> String {
-    let mut hasher = Md5::new();
-    if let Some(prev) = prev_rev {
-        hasher.update(prev.as_bytes());
-    }
-    hasher.update(if deleted { b"1" } else { b"0" });
-    let serialized = serde_json::to_string(doc_data).unwrap_or_default();
-    hasher.update(serialized.as_bytes());
-    format!("{:x}", hasher.finalize())
-}
-
 fn attachment_key(doc_id: &str, att_id: &str) -> String {
     format!("{}\0{}", doc_id, att_id)
 }
 
-fn compute_attachment_digest(data: &[u8]) -> String {
-    let mut hasher = Md5::new();
-    hasher.update(data);
-    let hash = hasher.finalize();
-    use base64::Engine;
-    let b64 = base64::engine::general_purpose::STANDARD.encode(hash);
-    format!("md5-{}", b64)
+fn attachment_stubs(
+    records: &HashMap<String, AttachmentRecord>,
+) -> HashMap<String, AttachmentMeta> {
+    records
+        .iter()
+        .map(|(name, r)| {
+            let stub = AttachmentMeta {
+                content_type: r.content_type.clone(),
+                digest: r.digest.clone(),
+                length: r.length,
+                stub: true,
+                data: None,
+            };
+            (name.clone(), stub)
+        })
+        .collect()
 }
 
 fn parse_rev(rev_str: &str) -> Result<(u64, String)> {
@@ -280,20 +574,130 @@ macro_rules! db_err {
     };
 }
 
+/// Metadata entry holding the sealed `seal::KEY_CHECK` of encrypted files.
+const KEY_CHECK_ENTRY: &str = "key_check";
+
+/// Make sure `sealer` fits the file: encrypted files open only with their
+/// key, unencrypted ones only without one. A fresh file opened with a key
+/// records the check value.
+fn check_key(meta: &mut redb::Table<&str, &[u8]>, sealer: &Sealer, fresh: bool) -> Result<()> {
+    let stored = db_err!(meta.get(KEY_CHECK_ENTRY))?.map(|g| g.value().to_vec());
+    match stored {
+        Some(stored)
+            if sealer.is_encrypted()
+                && sealer
+                    .open(META_TABLE.name(), &[KEY_CHECK_ENTRY.as_bytes()], &stored)
+                    .ok()
+                    .as_deref()
+                    != Some(seal::KEY_CHECK) =>
+        {
+            return Err(RouchError::Forbidden("wrong encryption key".into()));
+        }
+        Some(_) if sealer.is_encrypted() => {}
+        Some(_) => {
+            return Err(RouchError::Forbidden(
+                "database is encrypted; open it with open_encrypted".into(),
+            ));
+        }
+        None if sealer.is_encrypted() && !fresh => {
+            return Err(RouchError::BadRequest(
+                "database was created without encryption".into(),
+            ));
+        }
+        None if sealer.is_encrypted() => {
+            let check = sealer.seal(
+                META_TABLE.name(),
+                &[KEY_CHECK_ENTRY.as_bytes()],
+                seal::KEY_CHECK.to_vec(),
+            )?;
+            db_err!(meta.insert(KEY_CHECK_ENTRY, check.as_slice()))?;
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// Metadata entry holding the on-disk format version, as a decimal string.
+const FORMAT_VERSION_ENTRY: &str = "format_version";
+
+/// Version of the record layout this build writes:
+///
+/// - 1 (no entry): rev trees stored as nested `tree` objects.
+/// - 2: rev trees stored as flat `nodes` lists. Version 1 files are still
+///   read, and their rev trees are rewritten flat as documents change.
+const FORMAT_VERSION: u64 = 2;
+
+/// Refuse files written by a newer build, whose records this one may
+/// misread, and record the version this build writes.
+fn check_format(meta: &mut redb::Table<&str, &[u8]>) -> Result<()> {
+    let stored = db_err!(meta.get(FORMAT_VERSION_ENTRY))?.map(|g| g.value().to_vec());
+    format_supported(stored)?;
+    let version = FORMAT_VERSION.to_string();
+    db_err!(meta.insert(FORMAT_VERSION_ENTRY, version.as_bytes()))?;
+    Ok(())
+}
+
+fn format_supported(stored: Option<Vec<u8>>) -> Result<()> {
+    let version = match stored {
+        Some(bytes) => std::str::from_utf8(&bytes)
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .ok_or_else(|| RouchError::DatabaseError("unreadable format version".into()))?,
+        None => 1,
+    };
+    if version > FORMAT_VERSION {
+        return Err(RouchError::BadRequest(format!(
+            "database uses format version {version}; this build reads up to {FORMAT_VERSION}"
+        )));
+    }
+    Ok(())
+}
+
+/// Keys of the revision bodies a compaction keeping `retain_revs` per
+/// branch removes: too far behind every leaf, or of docs that are gone.
+fn stale_bodies(
+    doc_table: &impl ReadableTable<&'static str, &'static [u8]>,
+    rev_table: &impl ReadableTable<&'static str, &'static [u8]>,
+    retain_revs: u64,
+) -> Result<Vec<String>> {
+    let mut keep = HashMap::new();
+    for entry in db_err!(doc_table.iter())? {
+        let entry = db_err!(entry)?;
+        let record: DocRecord = serde_json::from_slice(entry.1.value())?;
+        let tree = serialized_to_rev_tree(&record.rev_tree);
+        keep.insert(entry.0.value().to_string(), recent_revs(&tree, retain_revs));
+    }
+
+    let mut stale = Vec::new();
+    for entry in db_err!(rev_table.iter())? {
+        let entry = db_err!(entry)?;
+        let key = entry.0.value();
+        let kept = key
+            .rsplit_once('\0')
+            .is_some_and(|(id, rev)| keep.get(id).is_some_and(|revs| revs.contains(rev)));
+        if !kept {
+            stale.push(key.to_string());
+        }
+    }
+    Ok(stale)
+}
+
 #[async_trait]
 impl Adapter for RedbAdapter {
     async fn info(&self) -> Result<DbInfo> {
         let meta = self.read_meta()?;
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let table = db_err!(read_txn.open_table(DOC_TABLE))?;
 
         let mut doc_count = 0u64;
+        let mut doc_del_count = 0u64;
         let iter = db_err!(table.iter())?;
         for entry in iter {
             let entry = db_err!(entry)?;
             let record: DocRecord = serde_json::from_slice(entry.1.value())?;
-            let tree = serialized_to_rev_tree(&record.rev_tree);
-            if !is_deleted(&tree) {
+            if record.winner().is_some_and(|(_, deleted)| deleted) {
+                doc_del_count += 1;
+            } else {
                 doc_count += 1;
             }
         }
@@ -301,50 +705,94 @@ impl Adapter for RedbAdapter {
         Ok(DbInfo {
             db_name: self.name.clone(),
             doc_count,
+            doc_del_count,
             update_seq: Seq::Num(meta.update_seq),
+            sizes: None,
         })
     }
 
+    async fn exists(&self, id: &str) -> Result<bool> {
+        let read_txn = db_err!(self.db()?.begin_read())?;
+        let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
+        let Some(guard) = db_err!(doc_table.get(id))? else {
+            return Ok(false);
+        };
+        let record: DocRecord = serde_json::from_slice(guard.value())?;
+        Ok(record.winner().is_some_and(|(_, deleted)| !deleted))
+    }
+
+    async fn winning_rev(&self, id: &str) -> Result<Revision> {
+        let read_txn = db_err!(self.db()?.begin_read())?;
+        let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
+        let guard =
+            db_err!(doc_table.get(id))?.ok_or_else(|| RouchError::NotFound(id.to_string()))?;
+        let record: DocRecord = serde_json::from_slice(guard.value())?;
+        record
+            .winner()
+            .filter(|(_, deleted)| !deleted)
+            .map(|(rev, _)| rev)
+            .ok_or_else(|| RouchError::NotFound(id.to_string()))
+    }
+
     async fn get(&self, id: &str, opts: GetOptions) -> Result<Document> {
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
         let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
 
         let guard =
             db_err!(doc_table.get(id))?.ok_or_else(|| RouchError::NotFound(id.to_string()))?;
         let record: DocRecord = serde_json::from_slice(guard.value())?;
-        let tree = serialized_to_rev_tree(&record.rev_tree);
+        // Plain reads only need the stored winner
+        let tree = if opts.revs || opts.revs_info || opts.conflicts {
+            serialized_to_rev_tree(&record.rev_tree)
+        } else {
+            RevTree::new()
+        };
 
         let target_rev = if let Some(ref rev_str) = opts.rev {
             rev_str.clone()
         } else {
-            winning_rev(&tree)
+            record
+                .winner()
                 .ok_or_else(|| RouchError::NotFound(id.to_string()))?
+                .0
                 .to_string()
         };
 
         let key = rev_data_key(id, &target_rev);
         let rev_guard = db_err!(rev_table.get(key.as_str()))?;
 
-        let (data, deleted) = if let Some(guard) = rev_guard {
-            let rd: RevDataRecord = serde_json::from_slice(guard.value())?;
-            (rd.data, rd.deleted)
-        } else {
-            (serde_json::Value::Object(serde_json::Map::new()), false)
-        };
+        // Compaction may have dropped the body of an old revision
+        let guard =
+            rev_guard.ok_or_else(|| RouchError::NotFound(format!("{} at {}", id, target_rev)))?;
+        let rd = decode_rev_data(&self.sealer, &key, guard.value())?;
+        let (data, deleted, attachments) = (rd.data, rd.deleted, attachment_stubs(&rd.attachments));
 
         if deleted && opts.rev.is_none() {
             return Err(RouchError::NotFound(id.to_string()));
         }
 
         let (pos, hash) = parse_rev(&target_rev)?;
+        let rev = Revision::new(pos, hash);
+
+        // Ancestry of the returned revision, newest first
+        let revisions = if opts.revs {
+            revisions_for(&tree, &rev)
+        } else {
+            None
+        };
+        let revs_info = if opts.revs_info {
+            revs_info_for(&tree, &rev)
+        } else {
+            None
+        };
 
         let mut doc = Document {
             id: id.to_string(),
-            rev: Some(Revision::new(pos, hash)),
+            rev: Some(rev),
             deleted,
             data,
-            attachments: HashMap::new(),
+            attachments,
         };
 
         if opts.conflicts {
@@ -360,16 +808,49 @@ impl Adapter for RedbAdapter {
             }
         }
 
+        if let Some(revs) = revisions
+            && let serde_json::Value::Object(ref mut map) = doc.data
+        {
+            map.insert("_revisions".into(), serde_json::to_value(revs)?);
+        }
+        if let Some(info) = revs_info
+            && let serde_json::Value::Object(ref mut map) = doc.data
+        {
+            map.insert("_revs_info".into(), serde_json::to_value(info)?);
+        }
+
         Ok(doc)
     }
 
+    async fn get_open_revs(&self, id: &str, open_revs: OpenRevs) -> Result<Vec<Document>> {
+        let revs = {
+            let read_txn = db_err!(self.db()?.begin_read())?;
+            let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
+            let guard =
+                db_err!(doc_table.get(id))?.ok_or_else(|| RouchError::NotFound(id.to_string()))?;
+            let record: DocRecord = serde_json::from_slice(guard.value())?;
+            select_open_revs(&serialized_to_rev_tree(&record.rev_tree), &open_revs)?
+        };
+
+        let mut docs = Vec::with_capacity(revs.len());
+        for rev in revs {
+            let opts = GetOptions {
+                rev: Some(rev.to_string()),
+                ..Default::default()
+            };
+            docs.push(self.get(id, opts).await?);
+        }
+        Ok(docs)
+    }
+
     async fn bulk_docs(
         &self,
         docs: Vec<Document>,
         opts: BulkDocsOptions,
     ) -> Result<Vec<DocResult>> {
+        self.check_writable()?;
         let _lock = self.write_lock.write().await;
-        let write_txn = db_err!(self.db.begin_write())?;
+        let write_txn = db_err!(self.db()?.begin_write())?;
 
         let mut results = Vec::with_capacity(docs.len());
 
@@ -385,14 +866,20 @@ impl Adapter for RedbAdapter {
             let mut rev_table = db_err!(write_txn.open_table(REV_DATA_TABLE))?;
             let mut changes_table = db_err!(write_txn.open_table(CHANGES_TABLE))?;
 
+            let process = if opts.new_edits {
+                process_doc_new_edits
+            } else {
+                process_doc_replication
+            };
             for doc in docs {
-                let result = process_doc(
+                let result = process(
+                    &self.sealer,
                     &mut doc_table,
                     &mut rev_table,
                     &mut changes_table,
+                    &write_txn,
                     &mut meta,
                     doc,
-                    opts.new_edits,
                 )?;
                 results.push(result);
             }
@@ -406,12 +893,13 @@ impl Adapter for RedbAdapter {
         }
 
         db_err!(write_txn.commit())?;
+        self.count_writes(results.iter().filter(|r| r.ok).count() as u64);
 
         Ok(results)
     }
 
     async fn all_docs(&self, opts: AllDocsOptions) -> Result<AllDocsResponse> {
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
         let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
 
@@ -422,34 +910,17 @@ impl Adapter for RedbAdapter {
             let entry = db_err!(entry)?;
             let doc_id = entry.0.value().to_string();
             let record: DocRecord = serde_json::from_slice(entry.1.value())?;
-            let tree = serialized_to_rev_tree(&record.rev_tree);
-
-            let winner = match winning_rev(&tree) {
-                Some(w) => w,
-                None => continue,
+            let Some((winner, deleted)) = record.winner() else {
+                continue;
             };
-            let deleted = is_deleted(&tree);
 
-            if deleted && opts.keys.is_none() {
+            if deleted && opts.keys.is_none() && !opts.include_deleted {
                 continue;
             }
 
             // Apply key range filters
-            if opts.keys.is_none() && opts.key.is_none() {
-                if let Some(ref start) = opts.start_key
-                    && doc_id.as_str() < start.as_str()
-                {
-                    continue;
-                }
-                if let Some(ref end) = opts.end_key {
-                    if opts.inclusive_end {
-                        if doc_id.as_str() > end.as_str() {
-                            continue;
-                        }
-                    } else if doc_id.as_str() >= end.as_str() {
-                        continue;
-                    }
-                }
+            if opts.keys.is_none() && opts.key.is_none() && !opts.in_key_range(&doc_id) {
+                continue;
             }
 
             if let Some(ref key) = opts.key
@@ -464,32 +935,14 @@ impl Adapter for RedbAdapter {
                 continue;
             }
 
-            let doc_json = if opts.include_docs && !deleted {
-                let rev_str = winner.to_string();
-                let key = rev_data_key(&doc_id, &rev_str);
-                db_err!(rev_table.get(key.as_str()))?.map(|guard| {
-                    let rd: RevDataRecord = serde_json::from_slice(guard.value()).unwrap();
-                    let mut obj = match rd.data {
-                        serde_json::Value::Object(m) => m,
-                        _ => serde_json::Map::new(),
-                    };
-                    obj.insert("_id".into(), serde_json::Value::String(doc_id.clone()));
-                    obj.insert("_rev".into(), serde_json::Value::String(rev_str));
-                    serde_json::Value::Object(obj)
-                })
-            } else {
-                None
-            };
-
-            rows.push(AllDocsRow {
-                id: doc_id.clone(),
-                key: doc_id,
-                value: AllDocsRowValue {
-                    rev: winner.to_string(),
-                    deleted: if deleted { Some(true) } else { None },
-                },
-                doc: doc_json,
-            });
+            rows.push(all_docs_row(
+                &self.sealer,
+                &rev_table,
+                doc_id,
+                &winner,
+                deleted,
+                opts.include_docs && (!deleted || opts.include_deleted),
+            )?);
         }
 
         if opts.descending {
@@ -520,16 +973,52 @@ impl Adapter for RedbAdapter {
         })
     }
 
+    fn all_docs_stream(&self, opts: AllDocsOptions) -> AllDocsStream<'_> {
+        if opts.keys.is_some() {
+            // Explicit keys are already a bounded set; no range to walk.
+            let rows = async move {
+                match self.all_docs(opts).await {
+                    Ok(response) => response.rows.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                }
+            };
+            return Box::pin(futures_util::stream::once(rows).flat_map(futures_util::stream::iter));
+        }
+
+        let cursor = match self
+            .db()
+            .and_then(|db| AllDocsCursor::open(&db, self.sealer.clone(), opts))
+        {
+            Ok(cursor) => cursor,
+            Err(e) => return Box::pin(futures_util::stream::once(async { Err(e) })),
+        };
+        Box::pin(futures_util::stream::unfold(cursor, |mut cursor| async {
+            let row = cursor.next_row().transpose()?;
+            Some((row, cursor))
+        }))
+    }
+
     async fn changes(&self, opts: ChangesOptions) -> Result<ChangesResponse> {
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let changes_table = db_err!(read_txn.open_table(CHANGES_TABLE))?;
         let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
         let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
 
         let mut results = Vec::new();
 
-        let start = opts.since.as_num() + 1;
-        let iter = db_err!(changes_table.range(start..))?;
+        // Changes after `since`, or before it when descending
+        let since = opts.since.as_num();
+        let iter = if opts.descending {
+            let end = if since == 0 { u64::MAX } else { since };
+            db_err!(changes_table.range(..end))?
+        } else {
+            db_err!(changes_table.range((since + 1)..))?
+        };
+        let iter: Box<dyn Iterator<Item = _>> = if opts.descending {
+            Box::new(iter.rev())
+        } else {
+            Box::new(iter)
+        };
 
         let entries: Vec<_> = iter
             .filter_map(|e| e.ok())
@@ -541,13 +1030,7 @@ impl Adapter for RedbAdapter {
             })
             .collect();
 
-        let iter: Box<dyn Iterator<Item = &(u64, ChangeRecord)>> = if opts.descending {
-            Box::new(entries.iter().rev())
-        } else {
-            Box::new(entries.iter())
-        };
-
-        for (seq, change) in iter {
+        for (seq, change) in &entries {
             if let Some(ref doc_ids) = opts.doc_ids
                 && !doc_ids.contains(&change.doc_id)
             {
@@ -557,29 +1040,30 @@ impl Adapter for RedbAdapter {
             let rev_str = db_err!(doc_table.get(change.doc_id.as_str()))?
                 .and_then(|guard| {
                     let record: DocRecord = serde_json::from_slice(guard.value()).ok()?;
-                    let tree = serialized_to_rev_tree(&record.rev_tree);
-                    winning_rev(&tree).map(|r| r.to_string())
+                    record.winner().map(|(rev, _)| rev.to_string())
                 })
                 .unwrap_or_default();
 
             let doc = if opts.include_docs && !rev_str.is_empty() {
                 let key = rev_data_key(&change.doc_id, &rev_str);
-                db_err!(rev_table.get(key.as_str()))?.map(|guard| {
-                    let rd: RevDataRecord = serde_json::from_slice(guard.value()).unwrap();
-                    let mut obj = match rd.data {
-                        serde_json::Value::Object(m) => m,
-                        _ => serde_json::Map::new(),
-                    };
-                    obj.insert(
-                        "_id".into(),
-                        serde_json::Value::String(change.doc_id.clone()),
-                    );
-                    obj.insert("_rev".into(), serde_json::Value::String(rev_str.clone()));
-                    if change.deleted {
-                        obj.insert("_deleted".into(), serde_json::Value::Bool(true));
-                    }
-                    serde_json::Value::Object(obj)
-                })
+                db_err!(rev_table.get(key.as_str()))?
+                    .map(|guard| -> Result<serde_json::Value> {
+                        let rd = decode_rev_data(&self.sealer, &key, guard.value())?;
+                        let mut obj = match rd.data {
+                            serde_json::Value::Object(m) => m,
+                            _ => serde_json::Map::new(),
+                        };
+                        obj.insert(
+                            "_id".into(),
+                            serde_json::Value::String(change.doc_id.clone()),
+                        );
+                        obj.insert("_rev".into(), serde_json::Value::String(rev_str.clone()));
+                        if change.deleted {
+                            obj.insert("_deleted".into(), serde_json::Value::Bool(true));
+                        }
+                        Ok(serde_json::Value::Object(obj))
+                    })
+                    .transpose()?
             } else {
                 None
             };
@@ -648,7 +1132,7 @@ impl Adapter for RedbAdapter {
     }
 
     async fn revs_diff(&self, revs: HashMap<String, Vec<String>>) -> Result<RevsDiffResponse> {
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
 
         let mut results = HashMap::new();
@@ -701,7 +1185,7 @@ impl Adapter for RedbAdapter {
     }
 
     async fn bulk_get(&self, docs: Vec<BulkGetItem>) -> Result<BulkGetResponse> {
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
         let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
 
@@ -718,8 +1202,8 @@ impl Adapter for RedbAdapter {
                     let rev_str = if let Some(ref rev) = item.rev {
                         rev.clone()
                     } else {
-                        match winning_rev(&tree) {
-                            Some(w) => w.to_string(),
+                        match record.winner() {
+                            Some((w, _)) => w.to_string(),
                             None => {
                                 bulk_docs.push(BulkGetDoc {
                                     ok: None,
@@ -741,7 +1225,7 @@ impl Adapter for RedbAdapter {
 
                     let key = rev_data_key(&item.id, &rev_str);
                     if let Some(rev_guard) = db_err!(rev_table.get(key.as_str()))? {
-                        let rd: RevDataRecord = serde_json::from_slice(rev_guard.value())?;
+                        let rd = decode_rev_data(&self.sealer, &key, rev_guard.value())?;
                         let mut obj = match rd.data {
                             serde_json::Value::Object(m) => m,
                             _ => serde_json::Map::new(),
@@ -751,18 +1235,18 @@ impl Adapter for RedbAdapter {
                         if rd.deleted {
                             obj.insert("_deleted".into(), serde_json::Value::Bool(true));
                         }
+                        if !rd.attachments.is_empty() {
+                            obj.insert(
+                                "_attachments".into(),
+                                serde_json::to_value(attachment_stubs(&rd.attachments))?,
+                            );
+                        }
 
                         // Include _revisions for replication
-                        if let Ok((pos, ref hash)) = parse_rev(&rev_str)
-                            && let Some(ancestry) = find_rev_ancestry(&tree, pos, hash)
+                        if let Ok((pos, hash)) = parse_rev(&rev_str)
+                            && let Some(revs) = revisions_for(&tree, &Revision::new(pos, hash))
                         {
-                            obj.insert(
-                                "_revisions".into(),
-                                serde_json::json!({
-                                    "start": pos,
-                                    "ids": ancestry
-                                }),
-                            );
+                            obj.insert("_revisions".into(), serde_json::to_value(revs)?);
                         }
 
                         bulk_docs.push(BulkGetDoc {
@@ -811,105 +1295,67 @@ impl Adapter for RedbAdapter {
         data: Vec<u8>,
         content_type: &str,
     ) -> Result<DocResult> {
-        let digest = compute_attachment_digest(&data);
-        let length = data.len() as u64;
+        self.check_writable()?;
+        let record = AttachmentRecord {
+            content_type: mime::or_default(content_type).to_string(),
+            digest: compute_attachment_digest(&data),
+            length: data.len() as u64,
+        };
         let _lock = self.write_lock.write().await;
-        let write_txn = db_err!(self.db.begin_write())?;
+        let write_txn = db_err!(self.db()?.begin_write())?;
 
-        let result = {
-            // Store the raw attachment data
-            let mut att_table = db_err!(write_txn.open_table(ATTACHMENT_TABLE))?;
-            let att_key = attachment_key(doc_id, att_id);
-            db_err!(att_table.insert(att_key.as_str(), data.as_slice()))?;
+        let att_key = attachment_key(doc_id, att_id);
+        attachments::write(&write_txn, &self.sealer, &att_key, &data)?;
+        let result = attach(&self.sealer, &write_txn, doc_id, att_id, rev, record)?;
 
-            // Load existing doc and verify rev
-            let mut doc_table = db_err!(write_txn.open_table(DOC_TABLE))?;
-            let mut rev_table = db_err!(write_txn.open_table(REV_DATA_TABLE))?;
-            let mut changes_table = db_err!(write_txn.open_table(CHANGES_TABLE))?;
+        db_err!(write_txn.commit())?;
+        Ok(result)
+    }
 
-            let existing_record: Option<DocRecord> = {
-                let existing = db_err!(doc_table.get(doc_id))?;
-                existing
-                    .as_ref()
-                    .and_then(|g| serde_json::from_slice(g.value()).ok())
-            };
+    async fn put_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        rev: &str,
+        content_type: &str,
+        mut reader: AttachmentReader,
+    ) -> Result<DocResult> {
+        self.check_writable()?;
+        let _lock = self.write_lock.write().await;
+        let write_txn = db_err!(self.db()?.begin_write())?;
 
-            let record = existing_record.ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
-            let tree = serialized_to_rev_tree(&record.rev_tree);
-            let winner =
-                winning_rev(&tree).ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
-            if winner.to_string() != rev {
-                return Err(RouchError::Conflict);
+        let att_key = attachment_key(doc_id, att_id);
+        attachments::clear(&write_txn, &att_key)?;
+        let mut hasher = Md5::new();
+        let mut length = 0;
+        let mut chunk = vec![0u8; ATTACHMENT_CHUNK_LEN];
+        for index in 0.. {
+            // Fill the chunk so every stored chunk but the last is full
+            let mut filled = 0;
+            while filled < chunk.len() {
+                let n = reader.read(&mut chunk[filled..]).await?;
+                if n == 0 {
+                    break;
+                }
+                filled += n;
             }
+            if filled == 0 && index > 0 {
+                break;
+            }
+            hasher.update(&chunk[..filled]);
+            attachments::write_chunk(&write_txn, &self.sealer, &att_key, index, &chunk[..filled])?;
+            length += filled as u64;
+            if filled < chunk.len() {
+                break;
+            }
+        }
 
-            // Load current rev data to preserve existing attachments
-            let rev_key = rev_data_key(doc_id, rev);
-            let rd: RevDataRecord = db_err!(rev_table.get(rev_key.as_str()))?
-                .map(|g| serde_json::from_slice(g.value()).unwrap())
-                .unwrap_or(RevDataRecord {
-                    data: serde_json::Value::Object(serde_json::Map::new()),
-                    deleted: false,
-                    attachments: HashMap::new(),
-                });
-
-            // Build updated attachment map
-            let mut attachments = rd.attachments;
-            attachments.insert(
-                att_id.to_string(),
-                AttachmentRecord {
-                    content_type: content_type.to_string(),
-                    digest,
-                    length,
-                },
-            );
-
-            // Build a Document and process as normal edit
-            let doc = Document {
-                id: doc_id.to_string(),
-                rev: Some(winner),
-                deleted: false,
-                data: rd.data,
-                attachments: attachments
-                    .iter()
-                    .map(|(k, v)| {
-                        (
-                            k.clone(),
-                            AttachmentMeta {
-                                content_type: v.content_type.clone(),
-                                digest: v.digest.clone(),
-                                length: v.length,
-                                stub: true,
-                                data: None,
-                            },
-                        )
-                    })
-                    .collect(),
-            };
-
-            let mut meta = {
-                let meta_table = db_err!(write_txn.open_table(META_TABLE))?;
-                let guard = db_err!(meta_table.get("meta"))?.unwrap();
-                serde_json::from_slice::<MetaRecord>(guard.value())?
-            };
-
-            let result = process_doc_new_edits_with_attachments(
-                &mut doc_table,
-                &mut rev_table,
-                &mut changes_table,
-                &mut meta,
-                doc,
-                attachments,
-            )?;
-
-            // Save updated metadata
-            {
-                let mut meta_table = db_err!(write_txn.open_table(META_TABLE))?;
-                let meta_bytes = serde_json::to_vec(&meta)?;
-                db_err!(meta_table.insert("meta", meta_bytes.as_slice()))?;
-            }
-
-            result
+        let record = AttachmentRecord {
+            content_type: mime::or_default(content_type).to_string(),
+            digest: digest_string(&hasher.finalize()),
+            length,
         };
+        let result = attach(&self.sealer, &write_txn, doc_id, att_id, rev, record)?;
 
         db_err!(write_txn.commit())?;
         Ok(result)
@@ -921,65 +1367,51 @@ impl Adapter for RedbAdapter {
         att_id: &str,
         opts: GetAttachmentOptions,
     ) -> Result<Vec<u8>> {
-        let read_txn = db_err!(self.db.begin_read())?;
-
-        // Verify the document and revision exist, and the attachment is tracked
-        let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
-        let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
-
-        let record: DocRecord = db_err!(doc_table.get(doc_id))?
-            .map(|g| serde_json::from_slice(g.value()).unwrap())
-            .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
-
-        let tree = serialized_to_rev_tree(&record.rev_tree);
-        let rev_str = if let Some(ref rev) = opts.rev {
-            rev.clone()
-        } else {
-            winning_rev(&tree)
-                .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?
-                .to_string()
-        };
+        let read_txn = db_err!(self.db()?.begin_read())?;
+        let meta = attachment_record(&read_txn, &self.sealer, doc_id, att_id, opts.rev.as_deref())?;
 
-        // Check that the attachment exists in this revision's metadata
-        let rev_key = rev_data_key(doc_id, &rev_str);
-        let rd: RevDataRecord = db_err!(rev_table.get(rev_key.as_str()))?
-            .map(|g| serde_json::from_slice(g.value()).unwrap())
+        let att_key = attachment_key(doc_id, att_id);
+        let data = attachments::read(&read_txn, &self.sealer, &att_key)?
             .ok_or_else(|| RouchError::NotFound(format!("attachment {}/{}", doc_id, att_id)))?;
+        verify_digest(&opts, &data, &meta.digest)?;
+        opts.slice(data)
+    }
 
-        if !rd.attachments.contains_key(att_id) {
-            return Err(RouchError::NotFound(format!(
-                "attachment {}/{}",
-                doc_id, att_id
-            )));
-        }
+    async fn get_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        opts: GetAttachmentOptions,
+    ) -> Result<AttachmentReader> {
+        let read_txn = db_err!(self.db()?.begin_read())?;
+        let meta = attachment_record(&read_txn, &self.sealer, doc_id, att_id, opts.rev.as_deref())?;
+        let (start, end) = opts.byte_range(meta.length)?;
 
-        // Fetch raw bytes
-        let att_table = db_err!(read_txn.open_table(ATTACHMENT_TABLE))?;
         let att_key = attachment_key(doc_id, att_id);
-        let guard = db_err!(att_table.get(att_key.as_str()))?
+        let verify = opts.verify.then_some(meta.digest);
+        let reader = ChunkReader::new(&read_txn, &self.sealer, &att_key, start, end, verify)?
             .ok_or_else(|| RouchError::NotFound(format!("attachment {}/{}", doc_id, att_id)))?;
-
-        Ok(guard.value().to_vec())
+        Ok(Box::new(reader))
     }
 
     async fn remove_attachment(&self, doc_id: &str, att_id: &str, rev: &str) -> Result<DocResult> {
+        self.check_writable()?;
         let _lock = self.write_lock.write().await;
-        let write_txn = db_err!(self.db.begin_write())?;
+        let write_txn = db_err!(self.db()?.begin_write())?;
 
         let result = {
             let mut doc_table = db_err!(write_txn.open_table(DOC_TABLE))?;
             let mut rev_table = db_err!(write_txn.open_table(REV_DATA_TABLE))?;
             let mut changes_table = db_err!(write_txn.open_table(CHANGES_TABLE))?;
-            let mut att_table = db_err!(write_txn.open_table(ATTACHMENT_TABLE))?;
 
             // Load existing doc and verify rev
             let record: DocRecord = db_err!(doc_table.get(doc_id))?
                 .map(|g| serde_json::from_slice(g.value()).unwrap())
                 .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
 
-            let tree = serialized_to_rev_tree(&record.rev_tree);
-            let winner =
-                winning_rev(&tree).ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
+            let (winner, _) = record
+                .winner()
+                .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
             if winner.to_string() != rev {
                 return Err(RouchError::Conflict);
             }
@@ -987,7 +1419,8 @@ impl Adapter for RedbAdapter {
             // Load current rev data
             let rev_key = rev_data_key(doc_id, rev);
             let rd: RevDataRecord = db_err!(rev_table.get(rev_key.as_str()))?
-                .map(|g| serde_json::from_slice(g.value()).unwrap())
+                .map(|g| decode_rev_data(&self.sealer, &rev_key, g.value()))
+                .transpose()?
                 .unwrap_or(RevDataRecord {
                     data: serde_json::Value::Object(serde_json::Map::new()),
                     deleted: false,
@@ -996,10 +1429,14 @@ impl Adapter for RedbAdapter {
 
             // Remove attachment from metadata and storage
             let mut attachments = rd.attachments;
-            attachments.remove(att_id);
+            if attachments.remove(att_id).is_none() {
+                return Err(RouchError::NotFound(format!(
+                    "attachment {}/{}",
+                    doc_id, att_id
+                )));
+            }
 
-            let att_key = attachment_key(doc_id, att_id);
-            let _ = db_err!(att_table.remove(att_key.as_str()));
+            attachments::clear(&write_txn, &attachment_key(doc_id, att_id))?;
 
             // Create a new revision without the attachment
             let doc = Document {
@@ -1007,21 +1444,7 @@ impl Adapter for RedbAdapter {
                 rev: Some(winner),
                 deleted: false,
                 data: rd.data,
-                attachments: attachments
-                    .iter()
-                    .map(|(k, v)| {
-                        (
-                            k.clone(),
-                            AttachmentMeta {
-                                content_type: v.content_type.clone(),
-                                digest: v.digest.clone(),
-                                length: v.length,
-                                stub: true,
-                                data: None,
-                            },
-                        )
-                    })
-                    .collect(),
+                attachments: attachment_stubs(&attachments),
             };
 
             let mut meta = {
@@ -1031,6 +1454,7 @@ impl Adapter for RedbAdapter {
             };
 
             let result = process_doc_new_edits_with_attachments(
+                &self.sealer,
                 &mut doc_table,
                 &mut rev_table,
                 &mut changes_table,
@@ -1052,21 +1476,131 @@ impl Adapter for RedbAdapter {
         Ok(result)
     }
 
+    async fn purge(&self, req: HashMap<String, Vec<String>>) -> Result<PurgeResponse> {
+        self.check_writable()?;
+        let _lock = self.write_lock.write().await;
+        let write_txn = db_err!(self.db()?.begin_write())?;
+        let mut purged = HashMap::new();
+
+        let update_seq = {
+            let mut doc_table = db_err!(write_txn.open_table(DOC_TABLE))?;
+            let mut rev_table = db_err!(write_txn.open_table(REV_DATA_TABLE))?;
+            let mut changes_table = db_err!(write_txn.open_table(CHANGES_TABLE))?;
+            let mut meta_table = db_err!(write_txn.open_table(META_TABLE))?;
+            let mut meta: MetaRecord = {
+                let guard = db_err!(meta_table.get("meta"))?.unwrap();
+                serde_json::from_slice(guard.value())?
+            };
+
+            for (doc_id, revs) in req {
+                let record: Option<DocRecord> = db_err!(doc_table.get(doc_id.as_str()))?
+                    .and_then(|g| serde_json::from_slice(g.value()).ok());
+                let Some(record) = record else {
+                    continue;
+                };
+                let mut tree = serialized_to_rev_tree(&record.rev_tree);
+
+                let mut purged_revs = Vec::new();
+                for rev_str in &revs {
+                    let Ok((pos, hash)) = parse_rev(rev_str) else {
+                        continue;
+                    };
+                    // Ancestors only this leaf used go with it, bodies too.
+                    let pruned = remove_leaf(&mut tree, pos, &hash);
+                    for rev in &pruned {
+                        db_err!(rev_table.remove(rev_data_key(&doc_id, rev).as_str()))?;
+                    }
+                    let key = rev_data_key(&doc_id, rev_str);
+                    let had_body = db_err!(rev_table.remove(key.as_str()))?.is_some();
+                    if !pruned.is_empty() || had_body {
+                        purged_revs.push(rev_str.clone());
+                    }
+                }
+                if purged_revs.is_empty() {
+                    continue;
+                }
+
+                let mut gone = true;
+                for leaf in collect_leaves(&tree) {
+                    let key = rev_data_key(&doc_id, &leaf.rev_string());
+                    if db_err!(rev_table.get(key.as_str()))?.is_some() {
+                        gone = false;
+                        break;
+                    }
+                }
+
+                db_err!(changes_table.remove(record.seq))?;
+                meta.update_seq += 1;
+                if gone {
+                    // Drop any interior bodies left behind along with the doc.
+                    let mut keys = Vec::new();
+                    traverse_rev_tree(&tree, |pos, node, _| {
+                        keys.push(rev_data_key(&doc_id, &format!("{}-{}", pos, node.hash)));
+                    });
+                    for key in keys {
+                        db_err!(rev_table.remove(key.as_str()))?;
+                    }
+                    db_err!(doc_table.remove(doc_id.as_str()))?;
+                } else {
+                    // The winner may have changed; record the doc at a new seq.
+                    let seq = meta.update_seq;
+                    let new_record = DocRecord::new(&tree, seq);
+                    let doc_bytes = serde_json::to_vec(&new_record)?;
+                    db_err!(doc_table.insert(doc_id.as_str(), doc_bytes.as_slice()))?;
+                    let change = ChangeRecord {
+                        doc_id: doc_id.clone(),
+                        deleted: is_deleted(&tree),
+                    };
+                    let change_bytes = serde_json::to_vec(&change)?;
+                    db_err!(changes_table.insert(seq, change_bytes.as_slice()))?;
+                }
+                purged.insert(doc_id, purged_revs);
+            }
+
+            let meta_bytes = serde_json::to_vec(&meta)?;
+            db_err!(meta_table.insert("meta", meta_bytes.as_slice()))?;
+            meta.update_seq
+        };
+
+        // Purged docs leave no trace in the changes feed, so view indexes
+        // can't drop their rows incrementally; rebuild them from scratch.
+        if !purged.is_empty() {
+            let _ = db_err!(write_txn.delete_table(VIEW_INDEX_TABLE))?;
+            let _ = db_err!(write_txn.delete_table(VIEW_INDEX_ROW_TABLE))?;
+            db_err!(write_txn.open_table(VIEW_INDEX_TABLE))?;
+            db_err!(write_txn.open_table(VIEW_INDEX_ROW_TABLE))?;
+        }
+
+        db_err!(write_txn.commit())?;
+        Ok(PurgeResponse {
+            purge_seq: Some(update_seq),
+            purged,
+        })
+    }
+
     async fn get_local(&self, id: &str) -> Result<serde_json::Value> {
-        let read_txn = db_err!(self.db.begin_read())?;
+        let read_txn = db_err!(self.db()?.begin_read())?;
         let table = db_err!(read_txn.open_table(LOCAL_TABLE))?;
         let guard = db_err!(table.get(id))?
             .ok_or_else(|| RouchError::NotFound(format!("_local/{}", id)))?;
-        let value: serde_json::Value = serde_json::from_slice(guard.value())?;
+        let plain = self
+            .sealer
+            .open(LOCAL_TABLE.name(), &[id.as_bytes()], guard.value())?;
+        let value: serde_json::Value = serde_json::from_slice(&plain)?;
         Ok(value)
     }
 
     async fn put_local(&self, id: &str, doc: serde_json::Value) -> Result<()> {
+        self.check_writable()?;
         let _lock = self.write_lock.write().await;
-        let write_txn = db_err!(self.db.begin_write())?;
+        let write_txn = db_err!(self.db()?.begin_write())?;
         {
             let mut table = db_err!(write_txn.open_table(LOCAL_TABLE))?;
-            let bytes = serde_json::to_vec(&doc)?;
+            let bytes = self.sealer.seal(
+                LOCAL_TABLE.name(),
+                &[id.as_bytes()],
+                serde_json::to_vec(&doc)?,
+            )?;
             db_err!(table.insert(id, bytes.as_slice()))?;
         }
         db_err!(write_txn.commit())?;
@@ -1074,8 +1608,9 @@ impl Adapter for RedbAdapter {
     }
 
     async fn remove_local(&self, id: &str) -> Result<()> {
+        self.check_writable()?;
         let _lock = self.write_lock.write().await;
-        let write_txn = db_err!(self.db.begin_write())?;
+        let write_txn = db_err!(self.db()?.begin_write())?;
         {
             let mut table = db_err!(write_txn.open_table(LOCAL_TABLE))?;
             db_err!(table.remove(id))?
@@ -1085,14 +1620,155 @@ impl Adapter for RedbAdapter {
         Ok(())
     }
 
+    async fn get_view_index(&self, name: &str) -> Result<Option<StoredViewIndex>> {
+        let read_txn = db_err!(self.db()?.begin_read())?;
+        // Files written before view indexes existed don't have the tables.
+        let (headers, rows) = match (
+            read_txn.open_table(VIEW_INDEX_TABLE),
+            read_txn.open_table(VIEW_INDEX_ROW_TABLE),
+        ) {
+            (Ok(headers), Ok(rows)) => (headers, rows),
+            (Err(redb::TableError::TableDoesNotExist(_)), _)
+            | (_, Err(redb::TableError::TableDoesNotExist(_))) => return Ok(None),
+            (Err(e), _) | (_, Err(e)) => return Err(RouchError::DatabaseError(e.to_string())),
+        };
+        let Some(guard) = db_err!(headers.get(name))? else {
+            return Ok(None);
+        };
+        let mut index = StoredViewIndex {
+            header: serde_json::from_slice(&self.sealer.open(
+                VIEW_INDEX_TABLE.name(),
+                &[name.as_bytes()],
+                guard.value(),
+            )?)?,
+            rows: BTreeMap::new(),
+        };
+        for entry in db_err!(rows.range((name, "")..))? {
+            let (key, value) = db_err!(entry)?;
+            let (index_name, doc_id) = key.value();
+            if index_name != name {
+                break;
+            }
+            let row = serde_json::from_slice(&self.sealer.open(
+                VIEW_INDEX_ROW_TABLE.name(),
+                &[name.as_bytes(), doc_id.as_bytes()],
+                value.value(),
+            )?)?;
+            index.rows.insert(doc_id.to_string(), row);
+        }
+        Ok(Some(index))
+    }
+
+    async fn update_view_index(&self, name: &str, update: ViewIndexUpdate) -> Result<()> {
+        // Read-only handles rebuild the index in memory on every query
+        // rather than failing the query.
+        if self.read_only {
+            return Ok(());
+        }
+        let _lock = self.write_lock.write().await;
+        let write_txn = db_err!(self.db()?.begin_write())?;
+        {
+            let mut headers = db_err!(write_txn.open_table(VIEW_INDEX_TABLE))?;
+            let bytes = self.sealer.seal(
+                VIEW_INDEX_TABLE.name(),
+                &[name.as_bytes()],
+                serde_json::to_vec(&update.header)?,
+            )?;
+            db_err!(headers.insert(name, bytes.as_slice()))?;
+
+            let mut rows = db_err!(write_txn.open_table(VIEW_INDEX_ROW_TABLE))?;
+            if update.reset {
+                let mut stale = Vec::new();
+                for entry in db_err!(rows.range((name, "")..))? {
+                    let (key, _) = db_err!(entry)?;
+                    let (index_name, doc_id) = key.value();
+                    if index_name != name {
+                        break;
+                    }
+                    stale.push(doc_id.to_string());
+                }
+                for doc_id in stale {
+                    db_err!(rows.remove((name, doc_id.as_str())))?;
+                }
+            }
+            for (doc_id, row) in update.rows {
+                match row {
+                    Some(row) => {
+                        let bytes = self.sealer.seal(
+                            VIEW_INDEX_ROW_TABLE.name(),
+                            &[name.as_bytes(), doc_id.as_bytes()],
+                            serde_json::to_vec(&row)?,
+                        )?;
+                        db_err!(rows.insert((name, doc_id.as_str()), bytes.as_slice()))?;
+                    }
+                    None => {
+                        db_err!(rows.remove((name, doc_id.as_str())))?;
+                    }
+                }
+            }
+        }
+        db_err!(write_txn.commit())?;
+        Ok(())
+    }
+
     async fn compact(&self) -> Result<()> {
-        // TODO: remove non-leaf revision data
+        self.compact_with_opts(CompactOptions::default()).await
+    }
+
+    async fn compact_with_opts(&self, opts: CompactOptions) -> Result<()> {
+        self.check_writable()?;
+        let _lock = self.write_lock.write().await;
+        let write_txn = db_err!(self.db()?.begin_write())?;
+        {
+            let doc_table = db_err!(write_txn.open_table(DOC_TABLE))?;
+            let mut rev_table = db_err!(write_txn.open_table(REV_DATA_TABLE))?;
+            let stale = stale_bodies(&doc_table, &rev_table, opts.retain_revs)?;
+            for key in stale {
+                db_err!(rev_table.remove(key.as_str()))?;
+            }
+        }
+        db_err!(write_txn.commit())?;
+        Ok(())
+    }
+
+    async fn needs_compaction(&self) -> Result<bool> {
+        let read_txn = db_err!(self.db()?.begin_read())?;
+        let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
+        let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
+        let dead = stale_bodies(&doc_table, &rev_table, 1)?.len() as u64;
+        let live = db_err!(rev_table.len())? - dead;
+        Ok(dead > live)
+    }
+
+    async fn backup_to(&self, path: &Path) -> Result<()> {
+        if path.exists() {
+            return Err(RouchError::BadRequest(format!(
+                "backup target {} already exists",
+                path.display()
+            )));
+        }
+        // Built in a temporary file beside the target and renamed into
+        // place once complete; on any error the temporary file is dropped,
+        // and with it removed
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let tmp = tempfile::Builder::new()
+            .prefix(".backup")
+            .tempfile_in(dir)?;
+        let source = self.db()?;
+        let target = db_err!(Database::builder().create_file(tmp.reopen()?))?;
+        copy_database(&source, &target)?;
+        drop(target);
+        tmp.persist_noclobber(path).map_err(|e| e.error)?;
         Ok(())
     }
 
     async fn destroy(&self) -> Result<()> {
+        self.check_writable()?;
         let _lock = self.write_lock.write().await;
-        let write_txn = db_err!(self.db.begin_write())?;
+        let write_txn = db_err!(self.db()?.begin_write())?;
 
         // Delete all tables in O(1) instead of draining entries one by one.
         let _ = db_err!(write_txn.delete_table(DOC_TABLE))?;
@@ -1100,6 +1776,9 @@ impl Adapter for RedbAdapter {
         let _ = db_err!(write_txn.delete_table(CHANGES_TABLE))?;
         let _ = db_err!(write_txn.delete_table(LOCAL_TABLE))?;
         let _ = db_err!(write_txn.delete_table(ATTACHMENT_TABLE))?;
+        let _ = db_err!(write_txn.delete_table(ATTACHMENT_CHUNK_TABLE))?;
+        let _ = db_err!(write_txn.delete_table(VIEW_INDEX_TABLE))?;
+        let _ = db_err!(write_txn.delete_table(VIEW_INDEX_ROW_TABLE))?;
 
         // Recreate empty tables so subsequent operations don't fail.
         db_err!(write_txn.open_table(DOC_TABLE))?;
@@ -1107,6 +1786,9 @@ impl Adapter for RedbAdapter {
         db_err!(write_txn.open_table(CHANGES_TABLE))?;
         db_err!(write_txn.open_table(LOCAL_TABLE))?;
         db_err!(write_txn.open_table(ATTACHMENT_TABLE))?;
+        db_err!(write_txn.open_table(ATTACHMENT_CHUNK_TABLE))?;
+        db_err!(write_txn.open_table(VIEW_INDEX_TABLE))?;
+        db_err!(write_txn.open_table(VIEW_INDEX_ROW_TABLE))?;
 
         // Reset metadata
         {
@@ -1122,33 +1804,402 @@ impl Adapter for RedbAdapter {
         db_err!(write_txn.commit())?;
         Ok(())
     }
+
+    async fn close(&self) -> Result<()> {
+        // Every write commits before returning, so there is nothing to
+        // flush; wait out in-flight writes and let go of the file. Open
+        // streams keep their own handle until they finish.
+        let _lock = self.write_lock.write().await;
+        self.db.write().unwrap().take();
+        Ok(())
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Document processing (shared by bulk_docs)
 // ---------------------------------------------------------------------------
 
-fn process_doc(
-    doc_table: &mut redb::Table<&str, &[u8]>,
+/// Build the `all_docs` row for a document's winning revision.
+fn all_docs_row(
+    sealer: &Sealer,
+    rev_table: &impl ReadableTable<&'static str, &'static [u8]>,
+    doc_id: String,
+    winner: &Revision,
+    deleted: bool,
+    include_docs: bool,
+) -> Result<AllDocsRow> {
+    let rev_str = winner.to_string();
+    let doc = if include_docs {
+        let key = rev_data_key(&doc_id, &rev_str);
+        match db_err!(rev_table.get(key.as_str()))? {
+            Some(guard) => {
+                let rd = decode_rev_data(sealer, &key, guard.value())?;
+                let mut obj = match rd.data {
+                    serde_json::Value::Object(m) => m,
+                    _ => serde_json::Map::new(),
+                };
+                obj.insert("_id".into(), serde_json::Value::String(doc_id.clone()));
+                obj.insert("_rev".into(), serde_json::Value::String(rev_str.clone()));
+                if deleted {
+                    obj.insert("_deleted".into(), serde_json::Value::Bool(true));
+                }
+                Some(serde_json::Value::Object(obj))
+            }
+            None => None,
+        }
+    } else {
+        None
+    };
+
+    Ok(AllDocsRow {
+        id: doc_id.clone(),
+        key: doc_id,
+        value: AllDocsRowValue {
+            rev: rev_str,
+            deleted: if deleted { Some(true) } else { None },
+        },
+        doc,
+    })
+}
+
+/// Walks the document table for `all_docs_stream`, one row at a time.
+///
+/// The table range is opened once on a read transaction, so the stream
+/// sees a consistent snapshot and never collects the table.
+struct AllDocsCursor {
+    range: redb::Range<'static, &'static str, &'static [u8]>,
+    rev_table: redb::ReadOnlyTable<&'static str, &'static [u8]>,
+    sealer: Sealer,
+    opts: AllDocsOptions,
+    skipped: u64,
+    emitted: u64,
+}
+
+impl AllDocsCursor {
+    fn open(db: &Database, sealer: Sealer, opts: AllDocsOptions) -> Result<Self> {
+        use std::ops::Bound;
+
+        let read_txn = db_err!(db.begin_read())?;
+        let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
+        let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
+
+        let (lower, upper) = match opts.key.as_deref() {
+            Some(key) => (Bound::Included(key), Bound::Included(key)),
+            None => {
+                let start = opts
+                    .start_key
+                    .as_deref()
+                    .map_or(Bound::Unbounded, Bound::Included);
+                let end = match opts.end_key.as_deref() {
+                    Some(end) if opts.inclusive_end => Bound::Included(end),
+                    Some(end) => Bound::Excluded(end),
+                    None => Bound::Unbounded,
+                };
+                // Descending walks from `start_key` down to `end_key`
+                if opts.descending {
+                    (end, start)
+                } else {
+                    (start, end)
+                }
+            }
+        };
+        let range = db_err!(doc_table.range::<&str>((lower, upper)))?;
+
+        Ok(Self {
+            range,
+            rev_table,
+            sealer,
+            opts,
+            skipped: 0,
+            emitted: 0,
+        })
+    }
+
+    fn next_row(&mut self) -> Result<Option<AllDocsRow>> {
+        if self.opts.limit.is_some_and(|l| self.emitted >= l) {
+            return Ok(None);
+        }
+        loop {
+            let entry = if self.opts.descending {
+                self.range.next_back()
+            } else {
+                self.range.next()
+            };
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+            let entry = db_err!(entry)?;
+            let record: DocRecord = serde_json::from_slice(entry.1.value())?;
+            let Some((winner, deleted)) = record.winner() else {
+                continue;
+            };
+            if deleted && !self.opts.include_deleted {
+                continue;
+            }
+            let doc_id = entry.0.value().to_string();
+            if self.opts.key.is_none() && self.opts.outside_doc_id_bounds(&doc_id) {
+                continue;
+            }
+            if self.skipped < self.opts.skip {
+                self.skipped += 1;
+                continue;
+            }
+
+            self.emitted += 1;
+            let row = all_docs_row(
+                &self.sealer,
+                &self.rev_table,
+                doc_id,
+                &winner,
+                deleted,
+                self.opts.include_docs,
+            )?;
+            return Ok(Some(row));
+        }
+    }
+}
+
+/// Copy every table of `source`, as of one read transaction, into the
+/// empty database `target`. Writers carry on meanwhile.
+fn copy_database(source: &Database, target: &Database) -> Result<()> {
+    let read_txn = db_err!(source.begin_read())?;
+    let write_txn = db_err!(target.begin_write())?;
+    for table in [
+        DOC_TABLE,
+        REV_DATA_TABLE,
+        LOCAL_TABLE,
+        ATTACHMENT_TABLE,
+        META_TABLE,
+        VIEW_INDEX_TABLE,
+    ] {
+        // Files written before view indexes existed don't have the table
+        let source = match read_txn.open_table(table) {
+            Ok(source) => source,
+            Err(redb::TableError::TableDoesNotExist(_)) => continue,
+            Err(e) => return Err(RouchError::DatabaseError(e.to_string())),
+        };
+        let mut copy = db_err!(write_txn.open_table(table))?;
+        for entry in db_err!(source.iter())? {
+            let (key, value) = db_err!(entry)?;
+            db_err!(copy.insert(key.value(), value.value()))?;
+        }
+    }
+    {
+        let source = db_err!(read_txn.open_table(CHANGES_TABLE))?;
+        let mut copy = db_err!(write_txn.open_table(CHANGES_TABLE))?;
+        for entry in db_err!(source.iter())? {
+            let (key, value) = db_err!(entry)?;
+            db_err!(copy.insert(key.value(), value.value()))?;
+        }
+    }
+    // Read-only handles on older files may lack these tables
+    match read_txn.open_table(ATTACHMENT_CHUNK_TABLE) {
+        Ok(source) => {
+            let mut copy = db_err!(write_txn.open_table(ATTACHMENT_CHUNK_TABLE))?;
+            for entry in db_err!(source.iter())? {
+                let (key, value) = db_err!(entry)?;
+                db_err!(copy.insert(key.value(), value.value()))?;
+            }
+        }
+        Err(redb::TableError::TableDoesNotExist(_)) => {}
+        Err(e) => return Err(RouchError::DatabaseError(e.to_string())),
+    }
+    match read_txn.open_table(VIEW_INDEX_ROW_TABLE) {
+        Ok(source) => {
+            let mut copy = db_err!(write_txn.open_table(VIEW_INDEX_ROW_TABLE))?;
+            for entry in db_err!(source.iter())? {
+                let (key, value) = db_err!(entry)?;
+                db_err!(copy.insert(key.value(), value.value()))?;
+            }
+        }
+        Err(redb::TableError::TableDoesNotExist(_)) => {}
+        Err(e) => return Err(RouchError::DatabaseError(e.to_string())),
+    }
+    db_err!(write_txn.commit())?;
+    Ok(())
+}
+
+/// Delete the stored bodies of revisions that `merge_tree` stemmed away.
+///
+/// `stem` only reports hashes, so their positions are looked up in the
+/// tree as it was before the merge.
+fn remove_stemmed_rev_data(
     rev_table: &mut redb::Table<&str, &[u8]>,
-    changes_table: &mut redb::Table<u64, &[u8]>,
-    meta: &mut MetaRecord,
-    doc: Document,
-    new_edits: bool,
+    doc_id: &str,
+    old_tree: &RevTree,
+    stemmed: &[String],
+) -> Result<()> {
+    if stemmed.is_empty() {
+        return Ok(());
+    }
+    let mut keys = Vec::new();
+    traverse_rev_tree(old_tree, |pos, node, _| {
+        if stemmed.contains(&node.hash) {
+            keys.push(rev_data_key(doc_id, &format!("{}-{}", pos, node.hash)));
+        }
+    });
+    for key in keys {
+        db_err!(rev_table.remove(key.as_str()))?;
+    }
+    Ok(())
+}
+
+/// Record `record` as attachment `att_id` of `doc_id` in a new revision on
+/// top of `rev`, which must be the winner. The bytes are already stored.
+fn attach(
+    sealer: &Sealer,
+    write_txn: &redb::WriteTransaction,
+    doc_id: &str,
+    att_id: &str,
+    rev: &str,
+    record: AttachmentRecord,
 ) -> Result<DocResult> {
-    if new_edits {
-        process_doc_new_edits(doc_table, rev_table, changes_table, meta, doc)
-    } else {
-        process_doc_replication(doc_table, rev_table, changes_table, meta, doc)
+    // Load existing doc and verify rev
+    let mut doc_table = db_err!(write_txn.open_table(DOC_TABLE))?;
+    let mut rev_table = db_err!(write_txn.open_table(REV_DATA_TABLE))?;
+    let mut changes_table = db_err!(write_txn.open_table(CHANGES_TABLE))?;
+
+    let existing_record: Option<DocRecord> = {
+        let existing = db_err!(doc_table.get(doc_id))?;
+        existing
+            .as_ref()
+            .and_then(|g| serde_json::from_slice(g.value()).ok())
+    };
+
+    let existing = existing_record.ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
+    let (winner, _) = existing
+        .winner()
+        .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
+    if winner.to_string() != rev {
+        return Err(RouchError::Conflict);
+    }
+
+    // Load current rev data to preserve existing attachments
+    let rev_key = rev_data_key(doc_id, rev);
+    let rd: RevDataRecord = db_err!(rev_table.get(rev_key.as_str()))?
+        .map(|g| decode_rev_data(sealer, &rev_key, g.value()))
+        .transpose()?
+        .unwrap_or(RevDataRecord {
+            data: serde_json::Value::Object(serde_json::Map::new()),
+            deleted: false,
+            attachments: HashMap::new(),
+        });
+
+    // Build updated attachment map
+    let mut attachments = rd.attachments;
+    attachments.insert(att_id.to_string(), record);
+
+    // Build a Document and process as normal edit
+    let doc = Document {
+        id: doc_id.to_string(),
+        rev: Some(winner),
+        deleted: false,
+        data: rd.data,
+        attachments: attachment_stubs(&attachments),
+    };
+
+    let mut meta = {
+        let meta_table = db_err!(write_txn.open_table(META_TABLE))?;
+        let guard = db_err!(meta_table.get("meta"))?.unwrap();
+        serde_json::from_slice::<MetaRecord>(guard.value())?
+    };
+
+    let result = process_doc_new_edits_with_attachments(
+        sealer,
+        &mut doc_table,
+        &mut rev_table,
+        &mut changes_table,
+        &mut meta,
+        doc,
+        attachments,
+    )?;
+
+    // Save updated metadata
+    let mut meta_table = db_err!(write_txn.open_table(META_TABLE))?;
+    let meta_bytes = serde_json::to_vec(&meta)?;
+    db_err!(meta_table.insert("meta", meta_bytes.as_slice()))?;
+
+    Ok(result)
+}
+
+/// The metadata of attachment `att_id` in revision `rev` of `doc_id`, the
+/// winner if `None`.
+fn attachment_record(
+    read_txn: &redb::ReadTransaction,
+    sealer: &Sealer,
+    doc_id: &str,
+    att_id: &str,
+    rev: Option<&str>,
+) -> Result<AttachmentRecord> {
+    // Verify the document and revision exist, and the attachment is tracked
+    let doc_table = db_err!(read_txn.open_table(DOC_TABLE))?;
+    let rev_table = db_err!(read_txn.open_table(REV_DATA_TABLE))?;
+
+    let record: DocRecord = db_err!(doc_table.get(doc_id))?
+        .map(|g| serde_json::from_slice(g.value()).unwrap())
+        .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?;
+
+    let rev_str = match rev {
+        Some(rev) => rev.to_string(),
+        None => record
+            .winner()
+            .ok_or_else(|| RouchError::NotFound(doc_id.to_string()))?
+            .0
+            .to_string(),
+    };
+
+    // Check that the attachment exists in this revision's metadata
+    let rev_key = rev_data_key(doc_id, &rev_str);
+    let mut rd: RevDataRecord = db_err!(rev_table.get(rev_key.as_str()))?
+        .map(|g| decode_rev_data(sealer, &rev_key, g.value()))
+        .transpose()?
+        .ok_or_else(|| RouchError::NotFound(format!("attachment {}/{}", doc_id, att_id)))?;
+
+    rd.attachments
+        .remove(att_id)
+        .ok_or_else(|| RouchError::NotFound(format!("attachment {}/{}", doc_id, att_id)))
+}
+
+/// Store the bytes of inline attachments and return the metadata records
+/// for all of a revision's attachments.
+fn store_attachments(
+    sealer: &Sealer,
+    write_txn: &redb::WriteTransaction,
+    doc_id: &str,
+    attachments: HashMap<String, AttachmentMeta>,
+) -> Result<HashMap<String, AttachmentRecord>> {
+    let mut records = HashMap::with_capacity(attachments.len());
+    for (name, meta) in attachments {
+        let record = match meta.data {
+            Some(data) => {
+                let key = attachment_key(doc_id, &name);
+                let record = AttachmentRecord {
+                    content_type: meta.content_type,
+                    digest: compute_attachment_digest(&data),
+                    length: data.len() as u64,
+                };
+                attachments::write(write_txn, sealer, &key, &data)?;
+                record
+            }
+            None => AttachmentRecord {
+                content_type: meta.content_type,
+                digest: meta.digest,
+                length: meta.length,
+            },
+        };
+        records.insert(name, record);
     }
+    Ok(records)
 }
 
 fn process_doc_new_edits(
+    sealer: &Sealer,
     doc_table: &mut redb::Table<&str, &[u8]>,
     rev_table: &mut redb::Table<&str, &[u8]>,
     changes_table: &mut redb::Table<u64, &[u8]>,
+    write_txn: &redb::WriteTransaction,
     meta: &mut MetaRecord,
-    doc: Document,
+    mut doc: Document,
 ) -> Result<DocResult> {
     let doc_id = if doc.id.is_empty() {
         Uuid::new_v4().to_string()
@@ -1174,27 +2225,23 @@ fn process_doc_new_edits(
         let tree = serialized_to_rev_tree(&record.rev_tree);
         let winner = winning_rev(&tree);
         match (&doc.rev, &winner) {
-            (Some(provided_rev), Some(current_winner)) => {
-                if provided_rev.to_string() != current_winner.to_string() {
-                    return Ok(DocResult {
-                        ok: false,
-                        id: doc_id,
-                        rev: None,
-                        error: Some("conflict".into()),
-                        reason: Some("Document update conflict".into()),
-                    });
-                }
+            (Some(provided_rev), Some(_)) if !is_editable_rev(&tree, provided_rev) => {
+                return Ok(DocResult {
+                    ok: false,
+                    id: doc_id,
+                    rev: None,
+                    error: Some("conflict".into()),
+                    reason: Some("Document update conflict".into()),
+                });
             }
-            (None, Some(_)) => {
-                if !is_deleted(&tree) {
-                    return Ok(DocResult {
-                        ok: false,
-                        id: doc_id,
-                        rev: None,
-                        error: Some("conflict".into()),
-                        reason: Some("Document update conflict".into()),
-                    });
-                }
+            (None, Some(_)) if !is_deleted(&tree) => {
+                return Ok(DocResult {
+                    ok: false,
+                    id: doc_id,
+                    rev: None,
+                    error: Some("conflict".into()),
+                    reason: Some("Document update conflict".into()),
+                });
             }
             _ => {}
         }
@@ -1209,9 +2256,9 @@ fn process_doc_new_edits(
     }
 
     // Generate new revision
-    let new_pos = doc.rev.as_ref().map(|r| r.pos + 1).unwrap_or(1);
-    let prev_rev_str = doc.rev.as_ref().map(|r| r.to_string());
-    let new_hash = generate_rev_hash(&doc.data, doc.deleted, prev_rev_str.as_deref());
+    let new_rev = compute_rev(doc.rev.as_ref(), &doc.data, doc.deleted, &doc.attachments);
+    let new_pos = new_rev.pos;
+    let new_hash = new_rev.hash;
     let new_rev_str = format!("{}-{}", new_pos, new_hash);
 
     let mut rev_hashes = vec![new_hash.clone()];
@@ -1227,7 +2274,8 @@ fn process_doc_new_edits(
         RevStatus::Available,
     );
 
-    let (merged_tree, _) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    let (merged_tree, _, stemmed) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    remove_stemmed_rev_data(rev_table, &doc_id, &existing_tree, &stemmed)?;
 
     // Update sequence
     meta.update_seq += 1;
@@ -1239,21 +2287,24 @@ fn process_doc_new_edits(
     }
 
     // Save doc record
-    let new_record = DocRecord {
-        rev_tree: rev_tree_to_serialized(&merged_tree),
-        seq,
-    };
+    let new_record = DocRecord::new(&merged_tree, seq);
     let doc_bytes = serde_json::to_vec(&new_record)?;
     db_err!(doc_table.insert(doc_id.as_str(), doc_bytes.as_slice()))?;
 
     // Save rev data
+    let attachments = store_attachments(
+        sealer,
+        write_txn,
+        &doc_id,
+        std::mem::take(&mut doc.attachments),
+    )?;
     let rd = RevDataRecord {
         data: doc.data,
         deleted: doc.deleted,
-        attachments: HashMap::new(),
+        attachments,
     };
-    let rev_bytes = serde_json::to_vec(&rd)?;
     let key = rev_data_key(&doc_id, &new_rev_str);
+    let rev_bytes = encode_rev_data(sealer, &key, &rd)?;
     db_err!(rev_table.insert(key.as_str(), rev_bytes.as_slice()))?;
 
     // Save change
@@ -1275,6 +2326,7 @@ fn process_doc_new_edits(
 
 /// Like `process_doc_new_edits` but also stores attachment metadata in the rev data.
 fn process_doc_new_edits_with_attachments(
+    sealer: &Sealer,
     doc_table: &mut redb::Table<&str, &[u8]>,
     rev_table: &mut redb::Table<&str, &[u8]>,
     changes_table: &mut redb::Table<u64, &[u8]>,
@@ -1297,9 +2349,9 @@ fn process_doc_new_edits_with_attachments(
         .unwrap_or_default();
 
     // Generate new revision
-    let new_pos = doc.rev.as_ref().map(|r| r.pos + 1).unwrap_or(1);
-    let prev_rev_str = doc.rev.as_ref().map(|r| r.to_string());
-    let new_hash = generate_rev_hash(&doc.data, doc.deleted, prev_rev_str.as_deref());
+    let new_rev = compute_rev(doc.rev.as_ref(), &doc.data, doc.deleted, &doc.attachments);
+    let new_pos = new_rev.pos;
+    let new_hash = new_rev.hash;
     let new_rev_str = format!("{}-{}", new_pos, new_hash);
 
     let mut rev_hashes = vec![new_hash.clone()];
@@ -1315,7 +2367,8 @@ fn process_doc_new_edits_with_attachments(
         RevStatus::Available,
     );
 
-    let (merged_tree, _) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    let (merged_tree, _, stemmed) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    remove_stemmed_rev_data(rev_table, &doc_id, &existing_tree, &stemmed)?;
 
     meta.update_seq += 1;
     let seq = meta.update_seq;
@@ -1324,10 +2377,7 @@ fn process_doc_new_edits_with_attachments(
         let _ = db_err!(changes_table.remove(record.seq));
     }
 
-    let new_record = DocRecord {
-        rev_tree: rev_tree_to_serialized(&merged_tree),
-        seq,
-    };
+    let new_record = DocRecord::new(&merged_tree, seq);
     let doc_bytes = serde_json::to_vec(&new_record)?;
     db_err!(doc_table.insert(doc_id.as_str(), doc_bytes.as_slice()))?;
 
@@ -1337,8 +2387,8 @@ fn process_doc_new_edits_with_attachments(
         deleted: doc.deleted,
         attachments,
     };
-    let rev_bytes = serde_json::to_vec(&rd)?;
     let key = rev_data_key(&doc_id, &new_rev_str);
+    let rev_bytes = encode_rev_data(sealer, &key, &rd)?;
     db_err!(rev_table.insert(key.as_str(), rev_bytes.as_slice()))?;
 
     let change = ChangeRecord {
@@ -1358,9 +2408,11 @@ fn process_doc_new_edits_with_attachments(
 }
 
 fn process_doc_replication(
+    sealer: &Sealer,
     doc_table: &mut redb::Table<&str, &[u8]>,
     rev_table: &mut redb::Table<&str, &[u8]>,
     changes_table: &mut redb::Table<u64, &[u8]>,
+    write_txn: &redb::WriteTransaction,
     meta: &mut MetaRecord,
     mut doc: Document,
 ) -> Result<DocResult> {
@@ -1432,7 +2484,8 @@ fn process_doc_replication(
         map.remove("_revisions");
     }
 
-    let (merged_tree, _) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    let (merged_tree, _, stemmed) = merge_tree(&existing_tree, &new_path, DEFAULT_REV_LIMIT);
+    remove_stemmed_rev_data(rev_table, &doc_id, &existing_tree, &stemmed)?;
 
     meta.update_seq += 1;
     let seq = meta.update_seq;
@@ -1443,20 +2496,23 @@ fn process_doc_replication(
 
     let doc_deleted = is_deleted(&merged_tree);
 
-    let new_record = DocRecord {
-        rev_tree: rev_tree_to_serialized(&merged_tree),
-        seq,
-    };
+    let new_record = DocRecord::new(&merged_tree, seq);
     let doc_bytes = serde_json::to_vec(&new_record)?;
     db_err!(doc_table.insert(doc_id.as_str(), doc_bytes.as_slice()))?;
 
+    let attachments = store_attachments(
+        sealer,
+        write_txn,
+        &doc_id,
+        std::mem::take(&mut doc.attachments),
+    )?;
     let rd = RevDataRecord {
         data: doc.data,
         deleted: doc.deleted,
-        attachments: HashMap::new(),
+        attachments,
     };
-    let rev_bytes = serde_json::to_vec(&rd)?;
     let key = rev_data_key(&doc_id, &rev_str);
+    let rev_bytes = encode_rev_data(sealer, &key, &rd)?;
     db_err!(rev_table.insert(key.as_str(), rev_bytes.as_slice()))?;
 
     let change = ChangeRecord {
@@ -1491,6 +2547,57 @@ mod tests {
         (dir, adapter)
     }
 
+    #[tokio::test]
+    async fn auto_compaction_counts_committed_writes_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = RedbOptions {
+            auto_compact_every: Some(2),
+        };
+        let db = RedbAdapter::open_with_opts(dir.path().join("test.redb"), "test", opts).unwrap();
+        let doc = |id: &str| Document {
+            id: id.into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        };
+
+        // A compaction is already running: crossing the threshold starts
+        // no second one, which would clear the flag when done
+        db.compacting.store(true, Ordering::Release);
+        db.bulk_docs(vec![doc("a"), doc("b")], BulkDocsOptions::new())
+            .await
+            .unwrap();
+        assert_eq!(db.writes.load(Ordering::Relaxed), 2);
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(db.compacting.load(Ordering::Acquire));
+
+        // Conflicts are not writes
+        let results = db
+            .bulk_docs(vec![doc("a"), doc("c")], BulkDocsOptions::new())
+            .await
+            .unwrap();
+        assert!(!results[0].ok);
+        assert_eq!(db.writes.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn auto_compaction_is_skipped_outside_a_runtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = RedbOptions {
+            auto_compact_every: Some(2),
+        };
+        let db = RedbAdapter::open_with_opts(dir.path().join("test.redb"), "test", opts).unwrap();
+
+        // Writes driven by another executor cross the threshold with no
+        // Tokio runtime to spawn on
+        db.count_writes(3);
+        assert_eq!(db.writes.load(Ordering::Relaxed), 3);
+        assert!(!db.compacting.load(Ordering::Acquire));
+    }
+
     #[tokio::test]
     async fn info_empty() {
         let (_dir, db) = temp_db();
@@ -1587,6 +2694,44 @@ mod tests {
         assert_eq!(changes.results.len(), 3);
     }
 
+    #[tokio::test]
+    async fn changes_descending() {
+        let (_dir, db) = temp_db();
+
+        for i in 0..5 {
+            let doc = Document {
+                id: format!("doc{}", i),
+                rev: None,
+                deleted: false,
+                data: serde_json::json!({"i": i}),
+                attachments: HashMap::new(),
+            };
+            db.bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap();
+        }
+
+        let seqs = |since| {
+            let db = &db;
+            async move {
+                db.changes(ChangesOptions {
+                    since,
+                    descending: true,
+                    ..Default::default()
+                })
+                .await
+                .unwrap()
+                .results
+                .into_iter()
+                .map(|e| e.seq.as_num())
+                .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(seqs(Seq::default()).await, vec![5, 4, 3, 2, 1]);
+        assert_eq!(seqs(Seq::Num(3)).await, vec![2, 1]);
+    }
+
     #[tokio::test]
     async fn all_docs_sorted() {
         let (_dir, db) = temp_db();
@@ -1604,10 +2749,188 @@ mod tests {
                 .unwrap();
         }
 
-        let result = db.all_docs(AllDocsOptions::new()).await.unwrap();
-        assert_eq!(result.rows[0].id, "alice");
-        assert_eq!(result.rows[1].id, "bob");
-        assert_eq!(result.rows[2].id, "charlie");
+        let result = db.all_docs(AllDocsOptions::new()).await.unwrap();
+        assert_eq!(result.rows[0].id, "alice");
+        assert_eq!(result.rows[1].id, "bob");
+        assert_eq!(result.rows[2].id, "charlie");
+    }
+
+    #[tokio::test]
+    async fn all_docs_stream_honors_range_options() {
+        let (_dir, db) = temp_db();
+
+        for name in ["alice", "bob", "carol", "dave", "erin"] {
+            let doc = Document {
+                id: name.into(),
+                rev: None,
+                deleted: false,
+                data: serde_json::json!({"name": name}),
+                attachments: HashMap::new(),
+            };
+            db.bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap();
+        }
+
+        let ids = |opts| async {
+            db.all_docs_stream(opts)
+                .map(|row| row.unwrap().id)
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let range = ids(AllDocsOptions {
+            start_key: Some("bob".into()),
+            end_key: Some("dave".into()),
+            ..AllDocsOptions::new()
+        })
+        .await;
+        assert_eq!(range, vec!["bob", "carol", "dave"]);
+
+        let exclusive = ids(AllDocsOptions {
+            start_key: Some("bob".into()),
+            end_key: Some("dave".into()),
+            inclusive_end: false,
+            ..AllDocsOptions::default()
+        })
+        .await;
+        assert_eq!(exclusive, vec!["bob", "carol"]);
+
+        let paged = ids(AllDocsOptions {
+            descending: true,
+            skip: 1,
+            limit: Some(2),
+            ..AllDocsOptions::new()
+        })
+        .await;
+        assert_eq!(paged, vec!["dave", "carol"]);
+
+        let mut with_docs = db.all_docs_stream(AllDocsOptions {
+            key: Some("erin".into()),
+            include_docs: true,
+            ..AllDocsOptions::new()
+        });
+        let row = with_docs.next().await.unwrap().unwrap();
+        assert_eq!(row.doc.unwrap()["name"], "erin");
+        assert!(with_docs.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn close_releases_the_file() {
+        let (dir, db) = temp_db();
+        let doc = Document {
+            id: "doc1".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({"name": "Alice"}),
+            attachments: HashMap::new(),
+        };
+        db.bulk_docs(vec![doc], BulkDocsOptions::new())
+            .await
+            .unwrap();
+
+        db.close().await.unwrap();
+        assert!(matches!(
+            db.get("doc1", GetOptions::default()).await,
+            Err(RouchError::DatabaseError(_))
+        ));
+
+        // The lock is gone, so the same path reopens in-process
+        let reopened = RedbAdapter::open(dir.path().join("test.redb"), "test").unwrap();
+        let doc = reopened.get("doc1", GetOptions::default()).await.unwrap();
+        assert_eq!(doc.data["name"], "Alice");
+    }
+
+    #[tokio::test]
+    async fn read_only_handles_share_a_file() {
+        let (dir, writer) = temp_db();
+        let doc = Document {
+            id: "doc1".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({"name": "Alice"}),
+            attachments: HashMap::new(),
+        };
+        writer
+            .bulk_docs(vec![doc], BulkDocsOptions::new())
+            .await
+            .unwrap();
+        writer.close().await.unwrap();
+
+        // Two readers open the same file at once
+        let path = dir.path().join("test.redb");
+        let before = std::fs::read(&path).unwrap();
+        let r1 = RedbAdapter::open_read_only(&path, "test").unwrap();
+        let r2 = RedbAdapter::open_read_only(&path, "test").unwrap();
+        for reader in [&r1, &r2] {
+            let fetched = reader.get("doc1", GetOptions::default()).await.unwrap();
+            assert_eq!(fetched.data["name"], "Alice");
+            assert_eq!(
+                reader
+                    .all_docs(AllDocsOptions::new())
+                    .await
+                    .unwrap()
+                    .rows
+                    .len(),
+                1
+            );
+            let changes = reader.changes(ChangesOptions::default()).await.unwrap();
+            assert_eq!(changes.results.len(), 1);
+        }
+
+        let write = Document {
+            id: "doc2".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        };
+        let err = r1
+            .bulk_docs(vec![write], BulkDocsOptions::new())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RouchError::Forbidden(ref r) if r == "read-only"));
+        assert!(matches!(
+            r2.put_local("ck", serde_json::json!({})).await,
+            Err(RouchError::Forbidden(_))
+        ));
+        assert!(matches!(r2.compact().await, Err(RouchError::Forbidden(_))));
+
+        // Readers leave the file as they found it, and a writer gets it back
+        // once they are gone
+        assert!(RedbAdapter::open(&path, "test").is_err());
+        drop((r1, r2));
+        assert_eq!(std::fs::read(&path).unwrap(), before);
+        let db = RedbAdapter::open(&path, "test").unwrap();
+        db.put_local("ck", serde_json::json!({})).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_only_open_waits_for_the_writer() {
+        let (dir, db) = temp_db();
+        let path = dir.path().join("test.redb");
+        let doc = Document {
+            id: "doc1".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({"n": 1}),
+            attachments: HashMap::new(),
+        };
+        db.bulk_docs(vec![doc], BulkDocsOptions::new())
+            .await
+            .unwrap();
+
+        // The writer's exclusive lock keeps readers out
+        let err = RedbAdapter::open_read_only(&path, "test").err().unwrap();
+        assert!(
+            matches!(err, RouchError::Io(ref e) if e.kind() == std::io::ErrorKind::WouldBlock),
+            "{err:?}"
+        );
+
+        db.close().await.unwrap();
+        let reader = RedbAdapter::open_read_only(&path, "test").unwrap();
+        let fetched = reader.get("doc1", GetOptions::default()).await.unwrap();
+        assert_eq!(fetched.data["n"], 1);
     }
 
     #[tokio::test]
@@ -1934,8 +3257,278 @@ mod tests {
         assert!(fetched.data.get("_revisions").is_none());
     }
 
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn encrypted_values_are_ciphertext_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sealed.redb");
+        let key = [7u8; 32];
+
+        let db = RedbAdapter::open_encrypted(&path, "test", &key).unwrap();
+        let mut atts = HashMap::new();
+        atts.insert(
+            "note.txt".to_string(),
+            AttachmentMeta {
+                content_type: "text/plain".into(),
+                digest: String::new(),
+                length: 0,
+                stub: false,
+                data: Some(b"attachment-plaintext-marker".to_vec()),
+            },
+        );
+        let doc = Document {
+            id: "doc1".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({"secret": "body-plaintext-marker"}),
+            attachments: atts,
+        };
+        db.bulk_docs(vec![doc], BulkDocsOptions::new())
+            .await
+            .unwrap();
+        db.close().await.unwrap();
+
+        let raw = std::fs::read(&path).unwrap();
+        for marker in [
+            &b"body-plaintext-marker"[..],
+            b"attachment-plaintext-marker",
+        ] {
+            assert!(!raw.windows(marker.len()).any(|w| w == marker));
+        }
+
+        let db = RedbAdapter::open_encrypted(&path, "test", &key).unwrap();
+        let doc = db.get("doc1", GetOptions::default()).await.unwrap();
+        assert_eq!(doc.data["secret"], "body-plaintext-marker");
+        let data = db
+            .get_attachment("doc1", "note.txt", GetAttachmentOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(data, b"attachment-plaintext-marker");
+        db.close().await.unwrap();
+
+        let wrong = RedbAdapter::open_encrypted(&path, "test", &[8u8; 32]);
+        assert!(matches!(wrong, Err(RouchError::Forbidden(_))));
+        let plain = RedbAdapter::open(&path, "test");
+        assert!(matches!(plain, Err(RouchError::Forbidden(_))));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn sealed_values_only_open_in_their_own_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = RedbAdapter::open_encrypted(dir.path().join("sealed.redb"), "test", &[7u8; 32])
+            .unwrap();
+        let doc = |id: &str, secret: &str| Document {
+            id: id.into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({ "secret": secret }),
+            attachments: HashMap::new(),
+        };
+        let results = db
+            .bulk_docs(
+                vec![doc("a", "for a"), doc("b", "for b")],
+                BulkDocsOptions::new(),
+            )
+            .await
+            .unwrap();
+        let key = |i: usize| rev_data_key(&results[i].id, results[i].rev.as_ref().unwrap());
+
+        // Swap a's sealed body into b's row
+        let write_txn = db.db().unwrap().begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(REV_DATA_TABLE).unwrap();
+            let sealed = table
+                .get(key(0).as_str())
+                .unwrap()
+                .unwrap()
+                .value()
+                .to_vec();
+            table.insert(key(1).as_str(), sealed.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        assert_eq!(
+            db.get("a", GetOptions::default()).await.unwrap().data["secret"],
+            "for a"
+        );
+        let err = db.get("b", GetOptions::default()).await.unwrap_err();
+        assert!(
+            matches!(err, RouchError::DatabaseError(ref m) if m.contains("decryption failed")),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn long_histories_round_trip() {
+        let (_dir, db) = temp_db();
+        let mut rev = None;
+        for v in 0..200 {
+            let doc = Document {
+                id: "doc".into(),
+                rev,
+                deleted: false,
+                data: serde_json::json!({"v": v}),
+                attachments: HashMap::new(),
+            };
+            let result = db
+                .bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap()
+                .remove(0);
+            assert!(result.ok, "edit {v}: {:?}", result.reason);
+            rev = Some(result.rev.unwrap().parse().unwrap());
+        }
+
+        let opts = GetOptions {
+            revs: true,
+            ..Default::default()
+        };
+        let doc = db.get("doc", opts).await.unwrap();
+        assert_eq!(doc.data["v"], 199);
+        assert_eq!(doc.data["_revisions"]["ids"].as_array().unwrap().len(), 200);
+    }
+
+    #[test]
+    fn deep_nested_rev_trees_hit_the_recursion_limit() {
+        // 200 edits of one doc in the nested form earlier versions wrote
+        let mut node = serde_json::json!({"hash": "h199", "status": "available",
+            "deleted": false, "children": []});
+        for i in (0..199).rev() {
+            node = serde_json::json!({"hash": format!("h{i}"), "status": "missing",
+                "deleted": false, "children": [node]});
+        }
+        let nested = serde_json::json!({"rev_tree": [{"pos": 1, "tree": node}], "seq": 1});
+        let text = serde_json::to_string(&nested).unwrap();
+        assert!(serde_json::from_str::<DocRecord>(&text).is_err());
+
+        // The flat form of the same tree reads back
+        let chain: RevTree = vec![RevPath {
+            pos: 1,
+            tree: (0..200)
+                .rev()
+                .fold(None, |child: Option<RevNode>, i| {
+                    Some(RevNode {
+                        hash: format!("h{i}"),
+                        status: RevStatus::Missing,
+                        opts: NodeOpts::default(),
+                        children: child.into_iter().collect(),
+                    })
+                })
+                .unwrap(),
+        }];
+        let flat = serde_json::to_vec(&DocRecord::new(&chain, 1)).unwrap();
+        let reread: DocRecord = serde_json::from_slice(&flat).unwrap();
+        assert_eq!(
+            serde_json::to_value(serialized_to_rev_tree(&reread.rev_tree)).unwrap(),
+            serde_json::to_value(&chain).unwrap()
+        );
+    }
+
+    #[test]
+    fn newer_format_versions_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("future.redb");
+        RedbAdapter::open(&path, "future").unwrap();
+        {
+            let db = Database::create(&path).unwrap();
+            let txn = db.begin_write().unwrap();
+            {
+                let mut meta = txn.open_table(META_TABLE).unwrap();
+                assert_eq!(
+                    meta.get(FORMAT_VERSION_ENTRY).unwrap().unwrap().value(),
+                    FORMAT_VERSION.to_string().as_bytes()
+                );
+                meta.insert(FORMAT_VERSION_ENTRY, b"99".as_slice()).unwrap();
+            }
+            txn.commit().unwrap();
+        }
+        for err in [
+            RedbAdapter::open(&path, "future").err(),
+            RedbAdapter::open_read_only(&path, "future").err(),
+        ] {
+            assert!(matches!(err, Some(RouchError::BadRequest(ref m)) if m.contains("99")));
+        }
+    }
+
+    #[tokio::test]
+    async fn doc_records_store_the_winner() {
+        let (_dir, db) = temp_db();
+        let doc = |rev: Option<Revision>, deleted: bool| Document {
+            id: "doc".into(),
+            rev,
+            deleted,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        };
+        let rev = db
+            .bulk_docs(vec![doc(None, false)], BulkDocsOptions::new())
+            .await
+            .unwrap()[0]
+            .rev
+            .clone()
+            .unwrap();
+        db.bulk_docs(
+            vec![doc(Some(rev.parse().unwrap()), true)],
+            BulkDocsOptions::new(),
+        )
+        .await
+        .unwrap();
+
+        let raw = |db: &RedbAdapter| -> DocRecord {
+            let read_txn = db.db().unwrap().begin_read().unwrap();
+            let table = read_txn.open_table(DOC_TABLE).unwrap();
+            serde_json::from_slice(table.get("doc").unwrap().unwrap().value()).unwrap()
+        };
+        let record = raw(&db);
+        let tree = serialized_to_rev_tree(&record.rev_tree);
+        let stored = record.winner.clone().unwrap();
+        assert_eq!(
+            Revision::new(stored.pos, stored.hash),
+            winning_rev(&tree).unwrap()
+        );
+        assert!(stored.deleted);
+
+        // Records from before the winner was stored fall back to the tree
+        let mut old = record;
+        old.winner = None;
+        let write_txn = db.db().unwrap().begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(DOC_TABLE).unwrap();
+            let bytes = serde_json::to_vec(&old).unwrap();
+            table.insert("doc", bytes.as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+        assert!(!db.exists("doc").await.unwrap());
+        assert_eq!(db.info().await.unwrap().doc_del_count, 1);
+    }
+
+    #[test]
+    fn nested_rev_trees_still_read() {
+        // 1-a -> {2-b, 2-c}, as earlier versions stored it
+        let json = r#"{"rev_tree": [{"pos": 1, "tree": {"hash": "a", "status": "missing",
+            "deleted": false, "children": [
+                {"hash": "b", "status": "available", "deleted": false, "children": []},
+                {"hash": "c", "status": "available", "deleted": true, "children": []}
+            ]}}], "seq": 3}"#;
+        let record: DocRecord = serde_json::from_str(json).unwrap();
+        let tree = serialized_to_rev_tree(&record.rev_tree);
+
+        // Written back flat, it reads as the same tree
+        let flat = serde_json::to_vec(&DocRecord::new(&tree, 3)).unwrap();
+        let reread: DocRecord = serde_json::from_slice(&flat).unwrap();
+        assert!(reread.rev_tree[0].tree.is_none());
+        assert_eq!(
+            serde_json::to_value(serialized_to_rev_tree(&reread.rev_tree)).unwrap(),
+            serde_json::to_value(&tree).unwrap()
+        );
+        assert_eq!(tree[0].tree.status, RevStatus::Missing);
+        assert_eq!(tree[0].tree.children[0].hash, "b");
+        assert!(tree[0].tree.children[1].opts.deleted);
+    }
+
     #[tokio::test]
-    async fn compact_is_noop() {
+    async fn compact_empty_db() {
         let (_dir, db) = temp_db();
         db.compact().await.unwrap();
     }
@@ -1988,10 +3581,397 @@ mod tests {
         assert_eq!(fetched.data["_conflicts"].as_array().unwrap().len(), 1);
     }
 
+    #[tokio::test]
+    async fn purge_conflict_branch() {
+        let (_dir, db) = temp_db();
+
+        for (hash, branch) in [("aaa", "a"), ("bbb", "b")] {
+            let doc = Document {
+                id: "doc1".into(),
+                rev: Some(Revision::new(1, hash.into())),
+                deleted: false,
+                data: serde_json::json!({"branch": branch}),
+                attachments: HashMap::new(),
+            };
+            db.bulk_docs(vec![doc], BulkDocsOptions::replication())
+                .await
+                .unwrap();
+        }
+        let seq_before = db.info().await.unwrap().update_seq.as_num();
+
+        let mut req = HashMap::new();
+        req.insert("doc1".to_string(), vec!["1-aaa".to_string()]);
+        let resp = db.purge(req).await.unwrap();
+        assert_eq!(resp.purged["doc1"], vec!["1-aaa".to_string()]);
+        assert!(db.info().await.unwrap().update_seq.as_num() > seq_before);
+
+        let fetched = db
+            .get(
+                "doc1",
+                GetOptions {
+                    conflicts: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(fetched.rev.unwrap().to_string(), "1-bbb");
+        assert!(fetched.data.get("_conflicts").is_none());
+
+        // Purging the last leaf removes the document entirely.
+        let mut req = HashMap::new();
+        req.insert("doc1".to_string(), vec!["1-bbb".to_string()]);
+        db.purge(req).await.unwrap();
+        assert!(db.get("doc1", GetOptions::default()).await.is_err());
+        let changes = db.changes(ChangesOptions::default()).await.unwrap();
+        assert!(changes.results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn purge_only_leaf_drops_history() {
+        let (_dir, db) = temp_db();
+        let mut revs = Vec::new();
+        for i in 0..3 {
+            let doc = Document {
+                id: "doc1".into(),
+                rev: revs.last().map(|r: &String| r.parse().unwrap()),
+                deleted: false,
+                data: serde_json::json!({"i": i}),
+                attachments: HashMap::new(),
+            };
+            let results = db
+                .bulk_docs(vec![doc], BulkDocsOptions::new())
+                .await
+                .unwrap();
+            revs.push(results[0].rev.clone().unwrap());
+        }
+
+        let mut req = HashMap::new();
+        req.insert("doc1".to_string(), vec![revs[2].clone()]);
+        let resp = db.purge(req).await.unwrap();
+        assert_eq!(resp.purged["doc1"], vec![revs[2].clone()]);
+
+        assert!(db.get("doc1", GetOptions::default()).await.is_err());
+        for rev in &revs {
+            let opts = GetOptions {
+                rev: Some(rev.clone()),
+                ..Default::default()
+            };
+            assert!(db.get("doc1", opts).await.is_err());
+        }
+        let read_txn = db.db().unwrap().begin_read().unwrap();
+        let table = read_txn.open_table(REV_DATA_TABLE).unwrap();
+        assert_eq!(table.len().unwrap(), 0);
+    }
+
     #[tokio::test]
     async fn remove_local_nonexistent() {
         let (_dir, db) = temp_db();
         let result = db.remove_local("nope").await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn corrupted_attachment_fails_verified_read() {
+        let (_dir, db) = temp_db();
+        let doc = Document {
+            id: "doc".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        };
+        let rev = db
+            .bulk_docs(vec![doc], BulkDocsOptions::new())
+            .await
+            .unwrap()[0]
+            .rev
+            .clone()
+            .unwrap();
+        db.put_attachment("doc", "a.txt", &rev, b"hello".to_vec(), "text/plain")
+            .await
+            .unwrap();
+
+        // Flip the stored bytes behind the adapter's back
+        let handle = db.db().unwrap();
+        let write_txn = handle.begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(ATTACHMENT_CHUNK_TABLE).unwrap();
+            let key = attachment_key("doc", "a.txt");
+            table
+                .insert((key.as_str(), 0), b"jello".as_slice())
+                .unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        let err = db
+            .get_attachment("doc", "a.txt", GetAttachmentOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RouchError::DatabaseError(ref m) if m.contains("digest mismatch")));
+
+        let unchecked = GetAttachmentOptions {
+            verify: false,
+            ..Default::default()
+        };
+        let data = db.get_attachment("doc", "a.txt", unchecked).await.unwrap();
+        assert_eq!(data, b"jello");
+    }
+
+    async fn doc_with_attachment(db: &RedbAdapter, data: Vec<u8>) -> String {
+        let rev = db
+            .bulk_docs(
+                vec![Document {
+                    id: "doc".into(),
+                    rev: None,
+                    deleted: false,
+                    data: serde_json::json!({}),
+                    attachments: HashMap::new(),
+                }],
+                BulkDocsOptions::new(),
+            )
+            .await
+            .unwrap()[0]
+            .rev
+            .clone()
+            .unwrap();
+        let reader: AttachmentReader = Box::new(std::io::Cursor::new(data));
+        db.put_attachment_stream("doc", "big.bin", &rev, "", reader)
+            .await
+            .unwrap()
+            .rev
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn failed_backups_leave_no_file() {
+        let (dir, db) = temp_db();
+        db.close().await.unwrap();
+
+        let backup = dir.path().join("backup.redb");
+        assert!(db.backup_to(&backup).await.is_err());
+        let names: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["test.redb"]);
+    }
+
+    #[tokio::test]
+    async fn stemmed_revisions_drop_their_bodies() {
+        let (_dir, db) = temp_db();
+        let hash = |i: u64| format!("{:032x}", i);
+        let replicated = |pos: u64, data| Document {
+            id: "doc1".into(),
+            rev: Some(Revision::new(pos, hash(pos))),
+            deleted: false,
+            data,
+            attachments: HashMap::new(),
+        };
+
+        db.bulk_docs(
+            vec![replicated(1, serde_json::json!({"v": 1}))],
+            BulkDocsOptions::replication(),
+        )
+        .await
+        .unwrap();
+
+        // One revision past the limit, descending from the first
+        let newest = DEFAULT_REV_LIMIT + 1;
+        let ids: Vec<String> = (1..=newest).rev().map(hash).collect();
+        let history = serde_json::json!({"v": 2, "_revisions": {"start": newest, "ids": ids}});
+        db.bulk_docs(
+            vec![replicated(newest, history)],
+            BulkDocsOptions::replication(),
+        )
+        .await
+        .unwrap();
+
+        let read_txn = db.db().unwrap().begin_read().unwrap();
+        let table = read_txn.open_table(REV_DATA_TABLE).unwrap();
+        let first = rev_data_key("doc1", &format!("1-{}", hash(1)));
+        assert!(table.get(first.as_str()).unwrap().is_none());
+        let latest = rev_data_key("doc1", &format!("{}-{}", newest, hash(newest)));
+        assert!(table.get(latest.as_str()).unwrap().is_some());
+        assert_eq!(
+            db.get("doc1", GetOptions::default()).await.unwrap().data["v"],
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn attachment_streams_hold_one_chunk() {
+        use tokio::io::AsyncReadExt;
+
+        let (_dir, db) = temp_db();
+        let size = 3 * ATTACHMENT_CHUNK_LEN + 100;
+        let body: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        doc_with_attachment(&db, body.clone()).await;
+
+        let key = attachment_key("doc", "big.bin");
+        let read_txn = db.db().unwrap().begin_read().unwrap();
+        {
+            let table = read_txn.open_table(ATTACHMENT_CHUNK_TABLE).unwrap();
+            let lens: Vec<usize> = table
+                .range((key.as_str(), 0)..=(key.as_str(), u64::MAX))
+                .unwrap()
+                .map(|row| row.unwrap().1.value().len())
+                .collect();
+            let full = ATTACHMENT_CHUNK_LEN;
+            assert_eq!(lens, vec![full, full, full, 100]);
+        }
+
+        let record = attachment_record(&read_txn, &db.sealer, "doc", "big.bin", None).unwrap();
+        let mut reader = ChunkReader::new(
+            &read_txn,
+            &db.sealer,
+            &key,
+            0,
+            size as u64,
+            Some(record.digest),
+        )
+        .unwrap()
+        .unwrap();
+        let mut buf = vec![0u8; 4 * ATTACHMENT_CHUNK_LEN];
+        let mut read = Vec::new();
+        loop {
+            let n = reader.read(&mut buf).await.unwrap();
+            assert!(reader.buffered() <= ATTACHMENT_CHUNK_LEN);
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(read, body);
+
+        // A range across chunk boundaries skips the chunks before it
+        let start = ATTACHMENT_CHUNK_LEN as u64 + 10;
+        let end = 3 * ATTACHMENT_CHUNK_LEN as u64 + 5;
+        let opts = GetAttachmentOptions {
+            range: Some((start, Some(end))),
+            verify: false,
+            ..Default::default()
+        };
+        let mut part = Vec::new();
+        db.get_attachment_stream("doc", "big.bin", opts)
+            .await
+            .unwrap()
+            .read_to_end(&mut part)
+            .await
+            .unwrap();
+        assert_eq!(part, body[start as usize..=end as usize]);
+    }
+
+    #[tokio::test]
+    async fn attachment_streams_check_the_digest() {
+        use tokio::io::AsyncReadExt;
+
+        let (_dir, db) = temp_db();
+        doc_with_attachment(&db, vec![7; ATTACHMENT_CHUNK_LEN + 1]).await;
+
+        let write_txn = db.db().unwrap().begin_write().unwrap();
+        {
+            let mut table = write_txn.open_table(ATTACHMENT_CHUNK_TABLE).unwrap();
+            let key = attachment_key("doc", "big.bin");
+            table.insert((key.as_str(), 1), b"8".as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        let mut reader = db
+            .get_attachment_stream("doc", "big.bin", GetAttachmentOptions::default())
+            .await
+            .unwrap();
+        let err = reader.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("digest mismatch"));
+    }
+
+    #[tokio::test]
+    async fn whole_attachments_from_older_files_are_still_read() {
+        use tokio::io::AsyncReadExt;
+
+        let (_dir, db) = temp_db();
+        let rev = doc_with_attachment(&db, b"hello".to_vec()).await;
+
+        // Store the bytes the way files from before chunking did
+        let key = attachment_key("doc", "big.bin");
+        let write_txn = db.db().unwrap().begin_write().unwrap();
+        attachments::clear(&write_txn, &key).unwrap();
+        {
+            let mut table = write_txn.open_table(ATTACHMENT_TABLE).unwrap();
+            table.insert(key.as_str(), b"hello".as_slice()).unwrap();
+        }
+        write_txn.commit().unwrap();
+
+        let data = db
+            .get_attachment("doc", "big.bin", GetAttachmentOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(data, b"hello");
+        let mut streamed = Vec::new();
+        db.get_attachment_stream("doc", "big.bin", GetAttachmentOptions::default())
+            .await
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .await
+            .unwrap();
+        assert_eq!(streamed, b"hello");
+
+        // Writing the attachment again moves it to chunks
+        db.put_attachment("doc", "big.bin", &rev, b"bye".to_vec(), "")
+            .await
+            .unwrap();
+        let read_txn = db.db().unwrap().begin_read().unwrap();
+        let table = read_txn.open_table(ATTACHMENT_TABLE).unwrap();
+        assert!(table.get(key.as_str()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn view_index_updates_touch_only_listed_rows() {
+        let (_dir, db) = temp_db();
+        let update = |rows: Vec<(&str, Option<serde_json::Value>)>, reset| ViewIndexUpdate {
+            header: serde_json::json!({"seq": 1}),
+            rows: rows
+                .into_iter()
+                .map(|(id, row)| (id.to_string(), row))
+                .collect(),
+            reset,
+        };
+        assert!(db.get_view_index("a").await.unwrap().is_none());
+
+        db.update_view_index(
+            "a",
+            update(vec![("x", Some(1.into())), ("y", Some(2.into()))], true),
+        )
+        .await
+        .unwrap();
+        db.update_view_index("b", update(vec![("x", Some(9.into()))], true))
+            .await
+            .unwrap();
+        db.update_view_index("a", update(vec![("x", None), ("z", Some(3.into()))], false))
+            .await
+            .unwrap();
+
+        let a = db.get_view_index("a").await.unwrap().unwrap();
+        assert_eq!(a.header, serde_json::json!({"seq": 1}));
+        let rows: Vec<_> = a.rows.into_iter().collect();
+        assert_eq!(
+            rows,
+            vec![("y".to_string(), 2.into()), ("z".to_string(), 3.into())]
+        );
+
+        // A reset drops that index's rows and no other's
+        db.update_view_index("a", update(vec![], true))
+            .await
+            .unwrap();
+        assert!(
+            db.get_view_index("a")
+                .await
+                .unwrap()
+                .unwrap()
+                .rows
+                .is_empty()
+        );
+        assert_eq!(db.get_view_index("b").await.unwrap().unwrap().rows.len(), 1);
+    }
 }
@@ -0,0 +1,146 @@
+//! Read-only access to a redb file.
+//!
+//! redb 2 has no read-only mode: opening a file takes an exclusive lock and
+//! rewrites its header (a recovery flag set on open and cleared on close).
+//! A read-only handle instead opens the file through `SharedFileBackend`,
+//! which takes a shared lock, reads the file in place and keeps whatever
+//! redb writes in memory, so the file itself is never modified. Any number
+//! of shared locks can be held at once, but not alongside the exclusive
+//! lock of a writer, which is what keeps the file from changing underneath
+//! a reader.
+
+use std::collections::HashMap;
+use std::fs::{File, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Mutex;
+
+use redb::StorageBackend;
+
+/// Granularity of the in-memory copy of written bytes.
+const BLOCK: u64 = 4096;
+
+/// A redb backend over a shared-locked file whose writes stay in memory.
+pub(crate) struct SharedFileBackend {
+    state: Mutex<Overlay>,
+}
+
+struct Overlay {
+    file: File,
+    /// Length redb sees, which `set_len` may change.
+    len: u64,
+    /// Bytes of the file still visible; anything past them reads as zeros.
+    readable: u64,
+    /// Blocks redb wrote, each `BLOCK` bytes long.
+    blocks: HashMap<u64, Vec<u8>>,
+}
+
+impl SharedFileBackend {
+    /// Open `path` under a shared lock. Fails with `WouldBlock` while a
+    /// writer has the file open.
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        file.try_lock_shared().map_err(|e| match e {
+            TryLockError::WouldBlock => {
+                io::Error::new(io::ErrorKind::WouldBlock, "database is open for writing")
+            }
+            TryLockError::Error(e) => e,
+        })?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            state: Mutex::new(Overlay {
+                file,
+                len,
+                readable: len,
+                blocks: HashMap::new(),
+            }),
+        })
+    }
+}
+
+impl Overlay {
+    /// Append `len` bytes of the file at `offset` to `out`, zero-filled
+    /// past the readable part.
+    fn read_file(&mut self, offset: u64, len: usize, out: &mut Vec<u8>) -> io::Result<()> {
+        let start = out.len();
+        let available = self.readable.saturating_sub(offset).min(len as u64);
+        if available > 0 {
+            self.file.seek(SeekFrom::Start(offset))?;
+            (&self.file).take(available).read_to_end(out)?;
+        }
+        out.resize(start + len, 0);
+        Ok(())
+    }
+
+    fn block_mut(&mut self, block: u64) -> io::Result<&mut Vec<u8>> {
+        if !self.blocks.contains_key(&block) {
+            let mut data = Vec::with_capacity(BLOCK as usize);
+            self.read_file(block * BLOCK, BLOCK as usize, &mut data)?;
+            self.blocks.insert(block, data);
+        }
+        Ok(self
+            .blocks
+            .get_mut(&block)
+            .expect("block was just inserted"))
+    }
+}
+
+impl std::fmt::Debug for SharedFileBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedFileBackend").finish_non_exhaustive()
+    }
+}
+
+impl StorageBackend for SharedFileBackend {
+    fn len(&self) -> io::Result<u64> {
+        Ok(self.state.lock().unwrap().len)
+    }
+
+    fn read(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let mut out = Vec::with_capacity(len);
+        let end = offset + len as u64;
+        let mut pos = offset;
+        while pos < end {
+            let within = pos % BLOCK;
+            let take = (BLOCK - within).min(end - pos) as usize;
+            match state.blocks.get(&(pos / BLOCK)) {
+                Some(block) => {
+                    out.extend_from_slice(&block[within as usize..within as usize + take])
+                }
+                None => state.read_file(pos, take, &mut out)?,
+            }
+            pos += take as u64;
+        }
+        Ok(out)
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.len = len;
+        state.readable = state.readable.min(len);
+        state.blocks.retain(|block, _| block * BLOCK < len);
+        if let Some(block) = state.blocks.get_mut(&(len / BLOCK)) {
+            block[(len % BLOCK) as usize..].fill(0);
+        }
+        Ok(())
+    }
+
+    fn sync_data(&self, _eventual: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let mut written = 0;
+        while written < data.len() {
+            let pos = offset + written as u64;
+            let within = (pos % BLOCK) as usize;
+            let take = (BLOCK as usize - within).min(data.len() - written);
+            let block = state.block_mut(pos / BLOCK)?;
+            block[within..within + take].copy_from_slice(&data[written..written + take]);
+            written += take;
+        }
+        Ok(())
+    }
+}
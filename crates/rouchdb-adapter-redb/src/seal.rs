@@ -0,0 +1,116 @@
+//! Encryption of stored values.
+//!
+//! A database opened with `RedbAdapter::open_encrypted` seals every
+//! revision body, attachment, local document and view index with
+//! ChaCha20-Poly1305 before it reaches redb. Each value is stored as a
+//! random 12-byte nonce followed by the ciphertext and tag. The table
+//! name and row key are authenticated with it, so a value copied to
+//! another row fails to open there. Document IDs, revision trees and the
+//! changes feed stay in plaintext so lookups can work without decrypting.
+//!
+//! Without a key (or without the `encryption` feature) values pass
+//! through unchanged.
+
+use std::borrow::Cow;
+
+use rouchdb_core::error::Result;
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+#[cfg(feature = "encryption")]
+use rouchdb_core::error::RouchError;
+
+/// Value stored in the metadata table so a wrong key fails on open rather
+/// than on the first read.
+pub(crate) const KEY_CHECK: &[u8] = b"rouchdb";
+
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone, Default)]
+pub(crate) struct Sealer {
+    #[cfg(feature = "encryption")]
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+impl Sealer {
+    #[cfg(feature = "encryption")]
+    pub(crate) fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Some(ChaCha20Poly1305::new(key.into())),
+        }
+    }
+
+    pub(crate) fn is_encrypted(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.cipher.is_some() {
+            return true;
+        }
+        false
+    }
+
+    /// The bytes to store for `plain` in row `key` of `table`. Keys made
+    /// of several parts, such as tuples, pass each part.
+    pub(crate) fn seal(&self, table: &str, key: &[&[u8]], plain: Vec<u8>) -> Result<Vec<u8>> {
+        #[cfg(feature = "encryption")]
+        if let Some(ref cipher) = self.cipher {
+            let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+            let payload = Payload {
+                msg: &plain,
+                aad: &place(table, key),
+            };
+            let sealed = cipher
+                .encrypt(&nonce, payload)
+                .map_err(|_| RouchError::DatabaseError("encryption failed".into()))?;
+            let mut out = nonce.to_vec();
+            out.extend_from_slice(&sealed);
+            return Ok(out);
+        }
+        let _ = (table, key);
+        Ok(plain)
+    }
+
+    /// The plaintext of `bytes` stored in row `key` of `table`. Fails if
+    /// they were sealed under a different key, for another row, or have
+    /// been tampered with.
+    pub(crate) fn open<'a>(
+        &self,
+        table: &str,
+        key: &[&[u8]],
+        bytes: &'a [u8],
+    ) -> Result<Cow<'a, [u8]>> {
+        #[cfg(feature = "encryption")]
+        if let Some(ref cipher) = self.cipher {
+            let failed =
+                || RouchError::DatabaseError("decryption failed: wrong key or corrupt data".into());
+            if bytes.len() < NONCE_LEN {
+                return Err(failed());
+            }
+            let (nonce, sealed) = bytes.split_at(NONCE_LEN);
+            let payload = Payload {
+                msg: sealed,
+                aad: &place(table, key),
+            };
+            let plain = cipher
+                .decrypt(Nonce::from_slice(nonce), payload)
+                .map_err(|_| failed())?;
+            return Ok(Cow::Owned(plain));
+        }
+        let _ = (table, key);
+        Ok(Cow::Borrowed(bytes))
+    }
+}
+
+/// The associated data binding a value to its row: the table name and
+/// each key part, length-prefixed so different splits can't collide.
+#[cfg(feature = "encryption")]
+fn place(table: &str, key: &[&[u8]]) -> Vec<u8> {
+    let mut aad = Vec::new();
+    for part in std::iter::once(table.as_bytes()).chain(key.iter().copied()) {
+        aad.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        aad.extend_from_slice(part);
+    }
+    aad
+}
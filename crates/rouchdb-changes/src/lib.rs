@@ -4,10 +4,14 @@
 /// and supports:
 /// - One-shot mode: fetch changes since a sequence and return
 /// - Live/continuous mode: keep polling for new changes
-/// - Filtering by document IDs
+/// - Filtering by document IDs or Mango selector
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll, ready};
 use std::time::Duration;
 
+use futures_core::Stream;
 use tokio::sync::{broadcast, mpsc};
 use tokio_util::sync::CancellationToken;
 
@@ -33,6 +37,8 @@ pub enum ChangesEvent {
     Paused,
     /// The stream resumed fetching after being paused.
     Active,
+    /// Keep-alive tick emitted while waiting, at the `heartbeat` interval.
+    Heartbeat,
 }
 use rouchdb_core::error::Result;
 
@@ -43,6 +49,16 @@ pub struct ChangeNotification {
     pub doc_id: String,
 }
 
+/// What a `ChangeReceiver` got from the broadcast channel.
+#[derive(Debug, Clone)]
+pub enum ChangeSignal {
+    /// A change was written.
+    Notification(ChangeNotification),
+    /// The receiver fell behind and this many notifications were dropped.
+    /// Re-fetch from the last known sequence to catch up.
+    Lagged(u64),
+}
+
 /// A sender for change notifications. Adapters use this to notify listeners
 /// when documents are written.
 #[derive(Debug, Clone)]
@@ -74,16 +90,12 @@ pub struct ChangeReceiver {
 }
 
 impl ChangeReceiver {
-    pub async fn recv(&mut self) -> Option<ChangeNotification> {
-        loop {
-            match self.rx.recv().await {
-                Ok(notification) => return Some(notification),
-                Err(broadcast::error::RecvError::Lagged(_)) => {
-                    // Missed some messages, continue receiving
-                    continue;
-                }
-                Err(broadcast::error::RecvError::Closed) => return None,
-            }
+    /// Wait for the next signal. Returns `None` once every sender is gone.
+    pub async fn recv(&mut self) -> Option<ChangeSignal> {
+        match self.rx.recv().await {
+            Ok(notification) => Some(ChangeSignal::Notification(notification)),
+            Err(broadcast::error::RecvError::Lagged(n)) => Some(ChangeSignal::Lagged(n)),
+            Err(broadcast::error::RecvError::Closed) => None,
         }
     }
 }
@@ -97,18 +109,38 @@ pub struct ChangesStreamOptions {
     pub doc_ids: Option<Vec<String>>,
     pub selector: Option<serde_json::Value>,
     pub limit: Option<u64>,
+    /// Return the newest changes first; with `since`, only those before it.
+    /// One-shot only: live streams always follow the feed forwards.
+    pub descending: bool,
     /// Include conflicting revisions per change event.
     pub conflicts: bool,
     /// Changes style: `MainOnly` (default) or `AllDocs`.
     pub style: ChangesStyle,
-    /// A filter function applied post-fetch to each change event.
+    /// A filter function applied post-fetch to each change event. Like the
+    /// selector, it runs before `limit` is counted.
     pub filter: Option<ChangesFilter>,
     /// Polling interval for live mode when no broadcast channel is available.
     pub poll_interval: Duration,
     /// How long to keep the connection open before closing in live mode.
     pub timeout: Option<Duration>,
+    /// End a live stream once this long passes without a new change, even
+    /// if polls or notifications keep arriving.
+    pub idle_timeout: Option<Duration>,
     /// Interval for heartbeat signals in live mode (prevents connection timeout).
+    ///
+    /// While waiting for changes, the stream wakes at this interval and
+    /// yields `ChangesEvent::Heartbeat` from `next_event()` /
+    /// `live_changes_events()`. Also forwarded to the adapter as
+    /// `ChangesOptions::heartbeat`.
     pub heartbeat: Option<Duration>,
+    /// Keep a live stream going when a fetch fails, e.g. a dropped HTTP
+    /// `_changes` request. The fetch is retried from the last seen seq
+    /// after a backoff that starts at `poll_interval` and doubles up to
+    /// 30 seconds; events the retried feed replays are skipped. Only
+    /// transient errors (transport failures and 5xx responses) are
+    /// retried; others, such as a 401, 403 or 404, end the stream as they
+    /// do without it.
+    pub reconnect: bool,
 }
 
 impl Default for ChangesStreamOptions {
@@ -120,12 +152,15 @@ impl Default for ChangesStreamOptions {
             doc_ids: None,
             selector: None,
             limit: None,
+            descending: false,
             conflicts: false,
             style: ChangesStyle::default(),
             filter: None,
             poll_interval: Duration::from_millis(500),
             timeout: None,
+            idle_timeout: None,
             heartbeat: None,
+            reconnect: false,
         }
     }
 }
@@ -139,56 +174,103 @@ impl std::fmt::Debug for ChangesStreamOptions {
             .field("doc_ids", &self.doc_ids)
             .field("selector", &self.selector)
             .field("limit", &self.limit)
+            .field("descending", &self.descending)
             .field("conflicts", &self.conflicts)
             .field("style", &self.style)
             .field("filter", &self.filter.as_ref().map(|_| "<fn>"))
             .field("poll_interval", &self.poll_interval)
             .field("timeout", &self.timeout)
+            .field("idle_timeout", &self.idle_timeout)
             .field("heartbeat", &self.heartbeat)
+            .field("reconnect", &self.reconnect)
             .finish()
     }
 }
 
+/// Keep only the events that match the selector and pass the filter.
+///
+/// The selector is passed down to the adapter as well, but only the HTTP
+/// adapter evaluates it server-side; local adapters return every change, so
+/// it is always re-checked here. Documents are fetched for evaluation and
+/// stripped again if the caller did not ask for them.
+fn apply_filters(results: &mut Vec<ChangeEvent>, opts: &ChangesStreamOptions) {
+    if let Some(ref selector) = opts.selector {
+        results.retain(|event| {
+            event
+                .doc
+                .as_ref()
+                .is_some_and(|doc| rouchdb_query::matches_selector(doc, selector))
+        });
+        if !opts.include_docs {
+            for event in results.iter_mut() {
+                event.doc = None;
+            }
+        }
+    }
+    if let Some(ref filter) = opts.filter {
+        results.retain(|event| filter(event));
+    }
+}
+
+/// The limit to ask the adapter for. Filtered feeds are read whole, since
+/// a limit applied before filtering would cut off later matches.
+fn fetch_limit(opts: &ChangesStreamOptions, limit: Option<u64>) -> Option<u64> {
+    match opts.selector.is_some() || opts.filter.is_some() {
+        true => None,
+        false => limit,
+    }
+}
+
 /// Fetch changes from an adapter in one-shot mode.
 pub async fn get_changes(
     adapter: &dyn Adapter,
     opts: ChangesStreamOptions,
 ) -> Result<Vec<ChangeEvent>> {
-    let filter = opts.filter.clone();
     let changes_opts = ChangesOptions {
-        since: opts.since,
-        limit: opts.limit,
-        descending: false,
-        include_docs: opts.include_docs,
+        since: opts.since.clone(),
+        limit: fetch_limit(&opts, opts.limit),
+        descending: opts.descending,
+        include_docs: opts.include_docs || opts.selector.is_some(),
         live: false,
-        doc_ids: opts.doc_ids,
+        doc_ids: opts.doc_ids.clone(),
+        selector: opts.selector.clone(),
         conflicts: opts.conflicts,
-        style: opts.style,
-        ..Default::default()
+        style: opts.style.clone(),
+        heartbeat: opts.heartbeat,
     };
 
-    let response = adapter.changes(changes_opts).await?;
-    let results = if let Some(f) = filter {
-        response.results.into_iter().filter(|e| f(e)).collect()
-    } else {
-        response.results
-    };
-    Ok(results)
+    let mut response = adapter.changes(changes_opts).await?;
+    apply_filters(&mut response.results, &opts);
+    if let Some(limit) = opts.limit {
+        response.results.truncate(limit as usize);
+    }
+    Ok(response.results)
 }
 
-/// A live changes stream that yields change events as they happen.
-///
-/// In live mode, after fetching existing changes, it waits for
-/// notifications via a broadcast channel or polls at regular intervals.
-pub struct LiveChangesStream {
+/// Longest wait between reconnection attempts.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// State machine behind `LiveChangesStream`, owned by the in-flight future
+/// while a fetch or wait is pending.
+struct StreamCore {
     adapter: Arc<dyn Adapter>,
     receiver: Option<ChangeReceiver>,
     opts: ChangesStreamOptions,
     last_seq: Seq,
+    /// Seq of the last event handed out, to skip replays after a reconnect.
+    last_emitted: Option<Seq>,
     buffer: Vec<ChangeEvent>,
     buffer_idx: usize,
     state: LiveStreamState,
     count: u64,
+    /// Notifications dropped because the receiver lagged.
+    lagged: u64,
+    /// When the current wait in `Waiting` began; survives heartbeats so
+    /// they do not extend the timeout.
+    wait_started: Option<tokio::time::Instant>,
+    /// When the stream last ran out of changes; cleared when a fetch
+    /// returns something.
+    idle_started: Option<tokio::time::Instant>,
 }
 
 enum LiveStreamState {
@@ -202,8 +284,8 @@ enum LiveStreamState {
     Done,
 }
 
-impl LiveChangesStream {
-    pub fn new(
+impl StreamCore {
+    fn new(
         adapter: Arc<dyn Adapter>,
         receiver: Option<ChangeReceiver>,
         opts: ChangesStreamOptions,
@@ -214,10 +296,14 @@ impl LiveChangesStream {
             receiver,
             opts,
             last_seq,
+            last_emitted: None,
             buffer: Vec::new(),
             buffer_idx: 0,
             state: LiveStreamState::FetchingInitial,
             count: 0,
+            lagged: 0,
+            wait_started: None,
+            idle_started: None,
         }
     }
 
@@ -225,27 +311,74 @@ impl LiveChangesStream {
     async fn fetch_changes(&mut self) -> Result<()> {
         let changes_opts = ChangesOptions {
             since: self.last_seq.clone(),
-            limit: self.opts.limit.map(|l| l.saturating_sub(self.count)),
+            limit: fetch_limit(
+                &self.opts,
+                self.opts.limit.map(|l| l.saturating_sub(self.count)),
+            ),
             descending: false,
-            include_docs: self.opts.include_docs,
+            include_docs: self.opts.include_docs || self.opts.selector.is_some(),
             live: false,
             doc_ids: self.opts.doc_ids.clone(),
+            selector: self.opts.selector.clone(),
             conflicts: self.opts.conflicts,
             style: self.opts.style.clone(),
-            ..Default::default()
+            heartbeat: self.opts.heartbeat,
         };
 
-        let response = self.adapter.changes(changes_opts).await?;
+        let mut response = self.adapter.changes(changes_opts).await?;
+        // Advance past filtered-out changes too, so they are not refetched
         if !response.results.is_empty() {
             self.last_seq = response.last_seq;
         }
+        apply_filters(&mut response.results, &self.opts);
         self.buffer = response.results;
         self.buffer_idx = 0;
         Ok(())
     }
 
-    /// Get the next change event, blocking if in live mode.
-    pub async fn next_change(&mut self) -> Option<ChangeEvent> {
+    /// `fetch_changes`, retried with backoff while `reconnect` is set.
+    /// Returns `false` if the stream should end.
+    async fn fetch_or_reconnect(&mut self) -> bool {
+        let mut backoff = self.opts.poll_interval;
+        let mut reconnected = false;
+        loop {
+            match self.fetch_changes().await {
+                Ok(()) => break,
+                Err(e) if self.opts.reconnect && e.is_retriable() => {
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RECONNECT_BACKOFF);
+                    reconnected = true;
+                }
+                Err(_) => return false,
+            }
+        }
+        // A retried feed may start again before what was already emitted
+        if reconnected
+            && let Some(ref last) = self.last_emitted
+            && let Some(pos) = self.buffer.iter().position(|e| &e.seq == last)
+        {
+            self.buffer.drain(..=pos);
+        }
+        true
+    }
+
+    /// Pop the next buffered event without awaiting, if one is ready.
+    fn try_next_buffered(&mut self) -> Option<ChangeEvent> {
+        if !matches!(self.state, LiveStreamState::Yielding)
+            || self.buffer_idx >= self.buffer.len()
+            || self.opts.limit.is_some_and(|limit| self.count >= limit)
+        {
+            return None;
+        }
+        let event = self.buffer[self.buffer_idx].clone();
+        self.buffer_idx += 1;
+        self.count += 1;
+        self.last_emitted = Some(event.seq.clone());
+        Some(event)
+    }
+
+    /// Get the next change or heartbeat, blocking if in live mode.
+    async fn next_event(&mut self) -> Option<ChangesEvent> {
         loop {
             // Check limit
             if let Some(limit) = self.opts.limit
@@ -256,7 +389,7 @@ impl LiveChangesStream {
 
             match self.state {
                 LiveStreamState::FetchingInitial => {
-                    if self.fetch_changes().await.is_err() {
+                    if !self.fetch_or_reconnect().await {
                         return None;
                     }
                     self.state = if self.buffer.is_empty() {
@@ -274,7 +407,8 @@ impl LiveChangesStream {
                         let event = self.buffer[self.buffer_idx].clone();
                         self.buffer_idx += 1;
                         self.count += 1;
-                        return Some(event);
+                        self.last_emitted = Some(event.seq.clone());
+                        return Some(ChangesEvent::Change(event));
                     }
                     // Buffer exhausted
                     self.state = if self.opts.live {
@@ -285,43 +419,60 @@ impl LiveChangesStream {
                 }
                 LiveStreamState::Waiting => {
                     // Wait for a notification or poll, with optional timeout
-                    let wait_result = if let Some(ref mut receiver) = self.receiver {
-                        if let Some(timeout_dur) = self.opts.timeout {
-                            match tokio::time::timeout(timeout_dur, receiver.recv()).await {
-                                Ok(Some(_)) => true,
-                                Ok(None) => return None, // Channel closed
-                                Err(_) => return None,   // Timeout elapsed
+                    // and heartbeat
+                    let started = *self
+                        .wait_started
+                        .get_or_insert_with(tokio::time::Instant::now);
+                    let deadline = self.opts.timeout.map(|t| started + t);
+                    let idle_started = *self
+                        .idle_started
+                        .get_or_insert_with(tokio::time::Instant::now);
+                    let idle_deadline = self.opts.idle_timeout.map(|t| idle_started + t);
+                    let heartbeat = self.opts.heartbeat;
+                    let poll_interval = self.opts.poll_interval;
+
+                    let wait_result = tokio::select! {
+                        woke = async {
+                            match self.receiver {
+                                Some(ref mut receiver) => match receiver.recv().await {
+                                    Some(ChangeSignal::Notification(_)) => true,
+                                    // Fetching from `last_seq` picks up whatever
+                                    // the dropped notifications announced
+                                    Some(ChangeSignal::Lagged(n)) => {
+                                        self.lagged += n;
+                                        true
+                                    }
+                                    None => false,
+                                },
+                                // No broadcast channel, poll with interval
+                                None => {
+                                    tokio::time::sleep(poll_interval).await;
+                                    true
+                                }
                             }
-                        } else {
-                            receiver.recv().await.as_ref().is_some()
-                        }
-                    } else {
-                        // No broadcast channel, poll with interval
-                        if let Some(timeout_dur) = self.opts.timeout {
-                            match tokio::time::timeout(
-                                timeout_dur,
-                                tokio::time::sleep(self.opts.poll_interval),
-                            )
-                            .await
-                            {
-                                Ok(()) => true,
-                                Err(_) => return None, // Timeout elapsed
-                            }
-                        } else {
-                            tokio::time::sleep(self.opts.poll_interval).await;
-                            true
+                        } => woke,
+                        _ = tokio::time::sleep_until(deadline.unwrap_or(started)),
+                            if deadline.is_some() => return None, // Timeout elapsed
+                        _ = tokio::time::sleep_until(idle_deadline.unwrap_or(idle_started)),
+                            if idle_deadline.is_some() => {
+                            self.state = LiveStreamState::Done;
+                            return None;
                         }
+                        _ = tokio::time::sleep(heartbeat.unwrap_or_default()),
+                            if heartbeat.is_some() => return Some(ChangesEvent::Heartbeat),
                     };
+                    self.wait_started = None;
 
                     if !wait_result {
-                        return None;
+                        return None; // Channel closed
                     }
 
                     // Fetch new changes
-                    if self.fetch_changes().await.is_err() {
+                    if !self.fetch_or_reconnect().await {
                         return None;
                     }
                     if !self.buffer.is_empty() {
+                        self.idle_started = None;
                         self.state = LiveStreamState::Yielding;
                     }
                     // If still empty, stay in Waiting state
@@ -334,6 +485,116 @@ impl LiveChangesStream {
     }
 }
 
+type PendingNext = Pin<Box<dyn Future<Output = (StreamCore, Option<ChangesEvent>)> + Send>>;
+
+/// A live changes stream that yields change events as they happen.
+///
+/// In live mode, after fetching existing changes, it waits for
+/// notifications via a broadcast channel or polls at regular intervals.
+///
+/// Implements `Stream<Item = ChangeEvent>`, so it can be used with stream
+/// combinators; `next_change()` is a convenience wrapper around it.
+pub struct LiveChangesStream {
+    /// `None` while `pending` owns it.
+    core: Option<StreamCore>,
+    pending: Option<PendingNext>,
+    last_seq: Seq,
+    lagged: u64,
+}
+
+impl LiveChangesStream {
+    pub fn new(
+        adapter: Arc<dyn Adapter>,
+        receiver: Option<ChangeReceiver>,
+        opts: ChangesStreamOptions,
+    ) -> Self {
+        Self {
+            last_seq: opts.since.clone(),
+            core: Some(StreamCore::new(adapter, receiver, opts)),
+            pending: None,
+            lagged: 0,
+        }
+    }
+
+    /// The sequence of the last fetched batch of changes.
+    pub fn last_seq(&self) -> Seq {
+        self.last_seq.clone()
+    }
+
+    /// How many broadcast notifications this stream has missed by falling
+    /// behind. No changes are lost: the stream re-fetches after a lag.
+    pub fn lagged(&self) -> u64 {
+        self.lagged
+    }
+
+    /// Whether all fetched changes have been yielded and the stream is
+    /// waiting for new ones.
+    fn is_caught_up(&self) -> bool {
+        self.core.as_ref().is_some_and(|core| {
+            core.buffer_idx >= core.buffer.len() && matches!(core.state, LiveStreamState::Waiting)
+        })
+    }
+
+    /// Get the next change event, blocking if in live mode.
+    ///
+    /// Cancel-safe: if the returned future is dropped mid-fetch, the fetch
+    /// resumes on the next call.
+    pub async fn next_change(&mut self) -> Option<ChangeEvent> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Like `next_change()`, but also surfaces `ChangesEvent::Heartbeat`
+    /// ticks while waiting, if `heartbeat` is set.
+    ///
+    /// Only ever returns `Change` or `Heartbeat`.
+    pub async fn next_event(&mut self) -> Option<ChangesEvent> {
+        std::future::poll_fn(|cx| self.poll_next_event(cx)).await
+    }
+
+    fn poll_next_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<ChangesEvent>> {
+        if self.pending.is_none() {
+            let Some(mut core) = self.core.take() else {
+                return Poll::Ready(None);
+            };
+            // Fast path: buffered events need no future
+            if let Some(event) = core.try_next_buffered() {
+                self.core = Some(core);
+                return Poll::Ready(Some(ChangesEvent::Change(event)));
+            }
+            self.pending = Some(Box::pin(async move {
+                let event = core.next_event().await;
+                (core, event)
+            }));
+        }
+
+        let pending = self.pending.as_mut().expect("pending future just set");
+        match pending.as_mut().poll(cx) {
+            Poll::Ready((core, event)) => {
+                self.pending = None;
+                self.last_seq = core.last_seq.clone();
+                self.lagged = core.lagged;
+                self.core = Some(core);
+                Poll::Ready(event)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for LiveChangesStream {
+    type Item = ChangeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ChangeEvent>> {
+        loop {
+            match ready!(self.poll_next_event(cx)) {
+                Some(ChangesEvent::Change(event)) => return Poll::Ready(Some(event)),
+                Some(_) => continue, // Heartbeat
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
 /// Handle for a live changes stream. Dropping or cancelling stops the stream.
 pub struct ChangesHandle {
     cancel: CancellationToken,
@@ -364,8 +625,6 @@ pub fn live_changes(
     let (tx, rx) = mpsc::channel(64);
     let cancel = CancellationToken::new();
     let cancel_clone = cancel.clone();
-    let filter = opts.filter.clone();
-
     tokio::spawn(async move {
         let mut stream =
             LiveChangesStream::new(adapter, None, ChangesStreamOptions { live: true, ..opts });
@@ -375,12 +634,6 @@ pub fn live_changes(
                 change = stream.next_change() => {
                     match change {
                         Some(event) => {
-                            // Apply filter if set
-                            if let Some(ref f) = filter
-                                && !f(&event)
-                            {
-                                continue;
-                            }
                             if tx.send(event).await.is_err() {
                                 break; // Receiver dropped
                             }
@@ -408,8 +661,6 @@ pub fn live_changes_events(
     let (tx, rx) = mpsc::channel(64);
     let cancel = CancellationToken::new();
     let cancel_clone = cancel.clone();
-    let filter = opts.filter.clone();
-
     tokio::spawn(async move {
         let mut stream =
             LiveChangesStream::new(adapter, None, ChangesStreamOptions { live: true, ..opts });
@@ -418,30 +669,28 @@ pub fn live_changes_events(
 
         loop {
             tokio::select! {
-                change = stream.next_change() => {
+                change = stream.next_event() => {
                     match change {
-                        Some(event) => {
+                        Some(ChangesEvent::Change(event)) => {
                             // Emit Active if we were paused
                             if was_paused {
                                 was_paused = false;
                                 let _ = tx.send(ChangesEvent::Active).await;
                             }
 
-                            // Apply filter if set
-                            if let Some(ref f) = filter
-                                && !f(&event)
-                            {
-                                continue;
-                            }
-
                             if tx.send(ChangesEvent::Change(event)).await.is_err() {
                                 break;
                             }
                         }
+                        Some(other) => {
+                            if tx.send(other).await.is_err() {
+                                break;
+                            }
+                        }
                         None => {
                             // Stream ended
                             let _ = tx.send(ChangesEvent::Complete {
-                                last_seq: stream.last_seq.clone(),
+                                last_seq: stream.last_seq(),
                             }).await;
                             break;
                         }
@@ -449,17 +698,14 @@ pub fn live_changes_events(
                 }
                 _ = cancel_clone.cancelled() => {
                     let _ = tx.send(ChangesEvent::Complete {
-                        last_seq: stream.last_seq.clone(),
+                        last_seq: stream.last_seq(),
                     }).await;
                     break;
                 },
             }
 
             // If the buffer is exhausted and we're in waiting state, emit Paused
-            if stream.buffer_idx >= stream.buffer.len()
-                && matches!(stream.state, LiveStreamState::Waiting)
-                && !was_paused
-            {
+            if stream.is_caught_up() && !was_paused {
                 was_paused = true;
                 let _ = tx.send(ChangesEvent::Paused).await;
             }
@@ -537,6 +783,37 @@ mod tests {
         assert_eq!(events[0].id, "c");
     }
 
+    #[tokio::test]
+    async fn one_shot_descending() {
+        let (db, _sender) = setup().await;
+        for id in ["a", "b", "c", "d", "e"] {
+            put_doc(db.as_ref(), id, serde_json::json!({})).await;
+        }
+
+        let ids = |since| {
+            let db = db.clone();
+            async move {
+                get_changes(
+                    db.as_ref(),
+                    ChangesStreamOptions {
+                        since,
+                        descending: true,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|e| e.id)
+                .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(ids(Seq::default()).await, vec!["e", "d", "c", "b", "a"]);
+        // Only the changes before seq 4, newest first
+        assert_eq!(ids(Seq::Num(4)).await, vec!["c", "b", "a"]);
+    }
+
     #[tokio::test]
     async fn one_shot_with_limit() {
         let (db, _sender) = setup().await;
@@ -600,6 +877,66 @@ mod tests {
         assert!(stream.next_change().await.is_none());
     }
 
+    #[tokio::test]
+    async fn live_stream_catches_up_after_lag() {
+        let db = Arc::new(MemoryAdapter::new("test"));
+        let (sender, receiver) = ChangeSender::new(2);
+        put_doc(db.as_ref(), "first", serde_json::json!({})).await;
+
+        let mut stream = LiveChangesStream::new(
+            db.clone(),
+            Some(receiver),
+            ChangesStreamOptions {
+                live: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(stream.next_change().await.unwrap().id, "first");
+
+        // Ten writes overflow the two-slot channel before the stream looks
+        for i in 0..10 {
+            put_doc(db.as_ref(), &format!("d{i}"), serde_json::json!({})).await;
+            sender.notify(Seq::Num(i + 2), format!("d{i}"));
+        }
+
+        let mut ids = Vec::new();
+        while let Ok(Some(event)) =
+            tokio::time::timeout(Duration::from_millis(200), stream.next_change()).await
+        {
+            ids.push(event.id);
+        }
+        let expected: Vec<String> = (0..10).map(|i| format!("d{i}")).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(stream.lagged(), 8);
+    }
+
+    #[tokio::test]
+    async fn live_stream_ends_after_idle_timeout() {
+        let db = Arc::new(MemoryAdapter::new("test"));
+        put_doc(db.as_ref(), "a", serde_json::json!({})).await;
+
+        let mut stream = LiveChangesStream::new(
+            db.clone(),
+            None,
+            ChangesStreamOptions {
+                live: true,
+                poll_interval: Duration::from_millis(20),
+                idle_timeout: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+        );
+        assert_eq!(stream.next_change().await.unwrap().id, "a");
+
+        // Polls keep firing, but nothing changes
+        let started = tokio::time::Instant::now();
+        let end = tokio::time::timeout(Duration::from_secs(2), stream.next_change())
+            .await
+            .expect("idle stream should end");
+        assert!(end.is_none());
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(stream.next_change().await.is_none());
+    }
+
     #[tokio::test]
     async fn live_changes_via_channel() {
         let db = Arc::new(MemoryAdapter::new("test"));
@@ -633,6 +970,162 @@ mod tests {
         handle.cancel();
     }
 
+    #[tokio::test]
+    async fn one_shot_with_selector() {
+        let (db, _sender) = setup().await;
+        put_doc(db.as_ref(), "a", serde_json::json!({"type": "user"})).await;
+        put_doc(db.as_ref(), "b", serde_json::json!({"type": "order"})).await;
+        put_doc(db.as_ref(), "c", serde_json::json!({"type": "user"})).await;
+
+        let events = get_changes(
+            db.as_ref(),
+            ChangesStreamOptions {
+                selector: Some(serde_json::json!({"type": "user"})),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let ids: Vec<&str> = events.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        // Docs were only fetched for evaluation
+        assert!(events.iter().all(|e| e.doc.is_none()));
+    }
+
+    #[tokio::test]
+    async fn limit_counts_only_matching_changes() {
+        use futures_util::StreamExt;
+
+        let (db, _sender) = setup().await;
+        put_doc(db.as_ref(), "a", serde_json::json!({"type": "order"})).await;
+        put_doc(db.as_ref(), "b", serde_json::json!({"type": "order"})).await;
+        put_doc(db.as_ref(), "c", serde_json::json!({"type": "user"})).await;
+        put_doc(db.as_ref(), "d", serde_json::json!({"type": "user"})).await;
+        put_doc(db.as_ref(), "e", serde_json::json!({"type": "user"})).await;
+
+        let by_selector = ChangesStreamOptions {
+            selector: Some(serde_json::json!({"type": "user"})),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let by_filter = ChangesStreamOptions {
+            filter: Some(Arc::new(|e: &ChangeEvent| e.id != "a" && e.id != "b")),
+            limit: Some(2),
+            ..Default::default()
+        };
+        for opts in [by_selector, by_filter] {
+            let events = get_changes(db.as_ref(), opts.clone()).await.unwrap();
+            let ids: Vec<&str> = events.iter().map(|e| e.id.as_str()).collect();
+            assert_eq!(ids, vec!["c", "d"]);
+
+            let streamed: Vec<String> = LiveChangesStream::new(db.clone(), None, opts)
+                .map(|e| e.id)
+                .collect()
+                .await;
+            assert_eq!(streamed, vec!["c", "d"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn live_stream_with_selector() {
+        let db = Arc::new(MemoryAdapter::new("test"));
+        put_doc(db.as_ref(), "a", serde_json::json!({"type": "user"})).await;
+        put_doc(db.as_ref(), "b", serde_json::json!({"type": "order"})).await;
+
+        let (mut rx, handle) = live_changes(
+            db.clone(),
+            ChangesStreamOptions {
+                live: true,
+                include_docs: true,
+                selector: Some(serde_json::json!({"type": "user"})),
+                poll_interval: Duration::from_millis(50),
+                ..Default::default()
+            },
+        );
+
+        let event = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.id, "a");
+        assert_eq!(event.doc.unwrap()["type"], "user");
+
+        // The non-matching "b" is skipped; the next event is the new user
+        put_doc(db.as_ref(), "c", serde_json::json!({"type": "user"})).await;
+
+        let event = tokio::time::timeout(Duration::from_secs(2), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.id, "c");
+
+        handle.cancel();
+    }
+
+    #[tokio::test]
+    async fn live_stream_as_stream() {
+        use futures_util::StreamExt;
+
+        let db = Arc::new(MemoryAdapter::new("test"));
+        put_doc(db.as_ref(), "a", serde_json::json!({})).await;
+
+        let stream = LiveChangesStream::new(
+            db.clone(),
+            None,
+            ChangesStreamOptions {
+                live: true,
+                poll_interval: Duration::from_millis(20),
+                ..Default::default()
+            },
+        );
+
+        let db_clone = db.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            put_doc(db_clone.as_ref(), "b", serde_json::json!({})).await;
+            put_doc(db_clone.as_ref(), "c", serde_json::json!({})).await;
+        });
+
+        let events: Vec<ChangeEvent> =
+            tokio::time::timeout(Duration::from_secs(2), stream.take(3).collect())
+                .await
+                .unwrap();
+        let ids: Vec<&str> = events.iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn live_stream_heartbeat_while_idle() {
+        let db = Arc::new(MemoryAdapter::new("test"));
+
+        let (mut rx, handle) = live_changes_events(
+            db.clone(),
+            ChangesStreamOptions {
+                live: true,
+                poll_interval: Duration::from_secs(60),
+                heartbeat: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        );
+
+        // No documents are written, yet the waiting loop keeps ticking
+        let mut heartbeats = 0;
+        while heartbeats < 2 {
+            let event = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+                .await
+                .expect("waiting loop did not wake up")
+                .unwrap();
+            match event {
+                ChangesEvent::Heartbeat => heartbeats += 1,
+                ChangesEvent::Paused | ChangesEvent::Active => {}
+                other => panic!("unexpected event: {other:?}"),
+            }
+        }
+
+        handle.cancel();
+    }
+
     #[tokio::test]
     async fn change_sender_subscribe() {
         let (sender, _rx) = ChangeSender::new(16);
@@ -640,7 +1133,9 @@ mod tests {
 
         sender.notify(Seq::Num(1), "doc1".into());
 
-        let notification = sub.recv().await.unwrap();
+        let Some(ChangeSignal::Notification(notification)) = sub.recv().await else {
+            panic!("expected a notification");
+        };
         assert_eq!(notification.seq, Seq::Num(1));
         assert_eq!(notification.doc_id, "doc1");
     }
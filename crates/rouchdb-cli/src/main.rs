@@ -357,6 +357,10 @@ async fn run(cli: Cli) -> rouchdb::Result<()> {
                     sort,
                     limit,
                     skip,
+                    use_index: None,
+                    bookmark: None,
+                    // A freshly opened database has no indexes to sort with
+                    allow_fallback: true,
                 })
                 .await?;
 
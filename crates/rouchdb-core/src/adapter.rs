@@ -1,10 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
 
 use async_trait::async_trait;
+use futures_util::Stream;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 use crate::document::*;
 use crate::error::Result;
 
+/// A readable attachment body, as passed to `put_attachment_stream` and
+/// returned by `get_attachment_stream`.
+pub type AttachmentReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// A lazily produced sequence of `all_docs` rows, returned by
+/// `all_docs_stream`.
+pub type AllDocsStream<'a> = Pin<Box<dyn Stream<Item = Result<AllDocsRow>> + Send + 'a>>;
+
+/// Rows fetched per `all_docs` call by the default `all_docs_stream`.
+const ALL_DOCS_PAGE_SIZE: u64 = 100;
+
+/// Paging state for the default `all_docs_stream`.
+struct AllDocsPages {
+    opts: AllDocsOptions,
+    emitted: u64,
+    page: VecDeque<AllDocsRow>,
+    done: bool,
+}
+
 /// The trait all storage adapters must implement.
 ///
 /// This mirrors PouchDB's internal adapter interface (underscore-prefixed
@@ -26,6 +48,40 @@ pub trait Adapter: Send + Sync {
     /// and including conflict information.
     async fn get(&self, id: &str, opts: GetOptions) -> Result<crate::document::Document>;
 
+    /// Whether a document exists and its winning revision isn't deleted.
+    /// Default implementation fetches the document with `get`.
+    async fn exists(&self, id: &str) -> Result<bool> {
+        match self.get(id, GetOptions::default()).await {
+            Ok(_) => Ok(true),
+            Err(crate::error::RouchError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The winning revision of a non-deleted document, as `get` would
+    /// return it. Default implementation fetches the document with `get`.
+    async fn winning_rev(&self, id: &str) -> Result<Revision> {
+        self.get(id, GetOptions::default())
+            .await?
+            .rev
+            .ok_or_else(|| crate::error::RouchError::NotFound(id.to_string()))
+    }
+
+    /// Retrieve several leaf revisions of a document as full documents.
+    ///
+    /// `OpenRevs::All` returns every non-deleted leaf, winner first;
+    /// `OpenRevs::Specific` returns exactly the requested revisions, in
+    /// order, including deleted ones. Unknown revisions are `NotFound`.
+    async fn get_open_revs(
+        &self,
+        _id: &str,
+        _open_revs: OpenRevs,
+    ) -> Result<Vec<crate::document::Document>> {
+        Err(crate::error::RouchError::BadRequest(
+            "open_revs not supported".into(),
+        ))
+    }
+
     /// Write multiple documents atomically.
     ///
     /// When `opts.new_edits` is `true` (default), the adapter generates new
@@ -43,6 +99,52 @@ pub trait Adapter: Send + Sync {
     /// Query all documents, optionally filtered by key range.
     async fn all_docs(&self, opts: AllDocsOptions) -> Result<AllDocsResponse>;
 
+    /// Stream `all_docs` rows instead of collecting them into a response.
+    ///
+    /// Honors the same options as `all_docs`. The default implementation
+    /// pages through `all_docs`, holding one page at a time; adapters that
+    /// can iterate their storage directly (redb) override it.
+    fn all_docs_stream(&self, opts: AllDocsOptions) -> AllDocsStream<'_> {
+        let pages = AllDocsPages {
+            opts,
+            emitted: 0,
+            page: VecDeque::new(),
+            done: false,
+        };
+        Box::pin(futures_util::stream::unfold(
+            pages,
+            move |mut st| async move {
+                loop {
+                    if let Some(row) = st.page.pop_front() {
+                        st.emitted += 1;
+                        return Some((Ok(row), st));
+                    }
+                    let remaining = st.opts.limit.map(|l| l.saturating_sub(st.emitted));
+                    if st.done || remaining == Some(0) {
+                        return None;
+                    }
+                    let page_size =
+                        remaining.map_or(ALL_DOCS_PAGE_SIZE, |r| r.min(ALL_DOCS_PAGE_SIZE));
+                    let page_opts = AllDocsOptions {
+                        skip: st.opts.skip + st.emitted,
+                        limit: Some(page_size),
+                        ..st.opts.clone()
+                    };
+                    match self.all_docs(page_opts).await {
+                        Ok(response) => {
+                            st.done = (response.rows.len() as u64) < page_size;
+                            st.page = response.rows.into();
+                        }
+                        Err(e) => {
+                            st.done = true;
+                            return Some((Err(e), st));
+                        }
+                    }
+                }
+            },
+        ))
+    }
+
     /// Get changes since a given sequence number.
     async fn changes(&self, opts: ChangesOptions) -> Result<ChangesResponse>;
 
@@ -73,6 +175,39 @@ pub trait Adapter: Send + Sync {
         opts: GetAttachmentOptions,
     ) -> Result<Vec<u8>>;
 
+    /// Store an attachment read from `reader`.
+    ///
+    /// The default implementation reads the body into memory once and calls
+    /// `put_attachment`; adapters that can stream (HTTP, redb) override it.
+    async fn put_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        rev: &str,
+        content_type: &str,
+        mut reader: AttachmentReader,
+    ) -> Result<DocResult> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+        self.put_attachment(doc_id, att_id, rev, data, content_type)
+            .await
+    }
+
+    /// Open attachment data for reading.
+    ///
+    /// The default implementation loads the attachment with `get_attachment`
+    /// and reads from that buffer; adapters that can stream (HTTP, redb)
+    /// override it.
+    async fn get_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        opts: GetAttachmentOptions,
+    ) -> Result<AttachmentReader> {
+        let data = self.get_attachment(doc_id, att_id, opts).await?;
+        Ok(Box::new(std::io::Cursor::new(data)))
+    }
+
     /// Remove an attachment from a document.
     ///
     /// Creates a new revision of the document with the attachment removed.
@@ -91,6 +226,28 @@ pub trait Adapter: Send + Sync {
     /// attachment data.
     async fn compact(&self) -> Result<()>;
 
+    /// Compact, keeping the bodies of recent ancestors as `opts` asks.
+    /// Default implementation ignores the options and calls `compact`.
+    async fn compact_with_opts(&self, opts: CompactOptions) -> Result<()> {
+        let _ = opts;
+        self.compact().await
+    }
+
+    /// Whether compaction would reclaim much: true when more stored
+    /// revision bodies sit behind the leaves than at them. Default
+    /// implementation returns `false`.
+    async fn needs_compaction(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Write a point-in-time copy of the database to a file at `path` that
+    /// can be opened on its own. Writers are not blocked meanwhile.
+    async fn backup_to(&self, _path: &std::path::Path) -> Result<()> {
+        Err(crate::error::RouchError::BadRequest(
+            "backup not supported".into(),
+        ))
+    }
+
     /// Destroy the database and all its data.
     async fn destroy(&self) -> Result<()>;
 
@@ -119,4 +276,39 @@ pub trait Adapter: Send + Sync {
     async fn put_security(&self, _doc: crate::document::SecurityDocument) -> Result<()> {
         Ok(())
     }
+
+    /// Load a persisted map/reduce view index written by
+    /// [`update_view_index`](Self::update_view_index).
+    ///
+    /// Returns `None` if nothing is stored under `name`. The default
+    /// implementation never stores anything, so views are re-mapped in
+    /// full on every query.
+    async fn get_view_index(&self, _name: &str) -> Result<Option<StoredViewIndex>> {
+        Ok(None)
+    }
+
+    /// Apply `update` to the map/reduce view index stored under `name`,
+    /// creating it if needed. Only the listed rows are written. The
+    /// default implementation discards it.
+    async fn update_view_index(&self, _name: &str, _update: ViewIndexUpdate) -> Result<()> {
+        Ok(())
+    }
+
+    /// Query a view stored in a design document on the server, as
+    /// `POST /{db}/_design/{ddoc}/_view/{view}` with `query` as the body
+    /// (`startkey`, `limit`, `reduce`, ...). Returns the raw response.
+    ///
+    /// Local adapters can't run design document views, so the default
+    /// implementation returns `NotFound`.
+    async fn query_design_view(
+        &self,
+        ddoc: &str,
+        view: &str,
+        _query: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        Err(crate::error::RouchError::NotFound(format!(
+            "view _design/{}/_view/{}",
+            ddoc, view
+        )))
+    }
 }
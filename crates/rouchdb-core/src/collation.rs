@@ -9,6 +9,11 @@
 /// This module provides comparison and encoding functions that match this
 /// ordering, ensuring consistent behavior across local storage and remote
 /// CouchDB instances.
+///
+/// Strings compare by Unicode code point, as PouchDB does. CouchDB's ICU
+/// collation differs for mixed case (`"B" < "a"` here, `"a" < "B"` there),
+/// so keys that must sort identically on both sides should avoid relying on
+/// case order.
 use serde_json::Value;
 use std::cmp::Ordering;
 
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::str::FromStr;
 
@@ -25,6 +25,12 @@ impl Revision {
     pub fn new(pos: u64, hash: String) -> Self {
         Self { pos, hash }
     }
+
+    /// Whether `hash` is a well-formed revision hash: 32 lowercase hex
+    /// digits, the MD5 digests CouchDB and PouchDB generate.
+    pub fn is_valid_hash(hash: &str) -> bool {
+        hash.len() == 32 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
 }
 
 impl fmt::Display for Revision {
@@ -43,6 +49,9 @@ impl FromStr for Revision {
         let pos: u64 = pos_str
             .parse()
             .map_err(|_| RouchError::InvalidRev(s.to_string()))?;
+        if !Revision::is_valid_hash(hash) {
+            return Err(RouchError::InvalidRev(s.to_string()));
+        }
         Ok(Revision {
             pos,
             hash: hash.to_string(),
@@ -68,17 +77,45 @@ impl PartialOrd for Revision {
 // AttachmentMeta
 // ---------------------------------------------------------------------------
 
+/// An entry of a document's `_attachments` map.
+///
+/// Either a stub (`stub: true`, no `data`) describing stored bytes, or an
+/// inline attachment carrying its bytes in `data`, which appears as a
+/// Base64 string in JSON. Adapters fill in `digest` and `length` for
+/// inline attachments when they store them.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttachmentMeta {
     pub content_type: String,
+    #[serde(default)]
     pub digest: String,
-    pub length: u64,
     #[serde(default)]
+    pub length: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub stub: bool,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "base64_data")]
     pub data: Option<Vec<u8>>,
 }
 
+/// Serde adapter writing attachment bytes as a Base64 string.
+mod base64_data {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &Option<Vec<u8>>, s: S) -> Result<S::Ok, S::Error> {
+        match data {
+            Some(bytes) => s.serialize_str(&STANDARD.encode(bytes)),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(d)?
+            .map(|b64| STANDARD.decode(b64).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Document
 // ---------------------------------------------------------------------------
@@ -120,42 +157,21 @@ impl Document {
             .unwrap_or(false);
 
         let mut attachments: HashMap<String, AttachmentMeta> = HashMap::new();
-        if let Some(att_val) = obj.remove("_attachments")
-            && let Some(att_obj) = att_val.as_object()
-        {
+        if let Some(att_val) = obj.remove("_attachments") {
+            let serde_json::Value::Object(att_obj) = att_val else {
+                return Err(RouchError::BadRequest(
+                    "_attachments must be a JSON object".into(),
+                ));
+            };
             for (name, meta) in att_obj {
-                // Strip inline Base64 `data` string before serde parsing
-                // (serde expects Vec<u8> as an array, not a string).
-                let mut meta_for_parse = meta.clone();
-                let inline_b64 = if let Some(obj) = meta_for_parse.as_object_mut() {
-                    match obj.remove("data") {
-                        Some(serde_json::Value::String(s)) => Some(s),
-                        Some(other) => {
-                            obj.insert("data".to_string(), other);
-                            None
-                        }
-                        None => None,
-                    }
-                } else {
-                    None
-                };
-
-                if let Ok(mut att) = serde_json::from_value::<AttachmentMeta>(meta_for_parse) {
-                    // Decode inline Base64 data if present
-                    if att.data.is_none()
-                        && let Some(ref data_str) = inline_b64
-                    {
-                        use base64::Engine;
-                        if let Ok(bytes) =
-                            base64::engine::general_purpose::STANDARD.decode(data_str)
-                        {
-                            att.length = bytes.len() as u64;
-                            att.data = Some(bytes);
-                            att.stub = false;
-                        }
-                    }
-                    attachments.insert(name.clone(), att);
+                let mut att: AttachmentMeta = serde_json::from_value(meta).map_err(|e| {
+                    RouchError::BadRequest(format!("invalid attachment {}: {}", name, e))
+                })?;
+                if let Some(ref bytes) = att.data {
+                    att.length = bytes.len() as u64;
+                    att.stub = false;
                 }
+                attachments.insert(name, att);
             }
         }
 
@@ -286,10 +302,19 @@ pub struct AllDocsOptions {
     pub skip: u64,
     pub limit: Option<u64>,
     pub inclusive_end: bool,
+    /// Doc ID to start from among rows whose key equals `start_key`. The
+    /// key of an `_all_docs` row is its ID, so this only decides whether
+    /// the `start_key` row itself is included.
+    pub start_key_doc_id: Option<String>,
+    /// Doc ID to end at among rows whose key equals `end_key`.
+    pub end_key_doc_id: Option<String>,
     /// Include `_conflicts` for each document (requires `include_docs`).
     pub conflicts: bool,
     /// Include `update_seq` in the response.
     pub update_seq: bool,
+    /// Also return rows for deleted documents, with `value.deleted` set.
+    /// With `include_docs` their tombstone is returned as the doc.
+    pub include_deleted: bool,
 }
 
 impl AllDocsOptions {
@@ -299,6 +324,87 @@ impl AllDocsOptions {
             ..Default::default()
         }
     }
+
+    /// Whether the row for `id` lies between `start_key` and `end_key`.
+    ///
+    /// As in CouchDB, `descending` swaps the ends: `start_key` is then the
+    /// upper bound and `end_key` the lower one, which `inclusive_end`
+    /// still applies to. `key` and `keys` are not checked here.
+    pub fn in_key_range(&self, id: &str) -> bool {
+        // Past the range in iteration order
+        let after = |a: &str, b: &str| if self.descending { a < b } else { a > b };
+        if let Some(ref start) = self.start_key
+            && after(start, id)
+        {
+            return false;
+        }
+        if let Some(ref end) = self.end_key
+            && (after(id, end) || (!self.inclusive_end && id == end.as_str()))
+        {
+            return false;
+        }
+        !self.outside_doc_id_bounds(id)
+    }
+
+    /// Whether `start_key_doc_id` or `end_key_doc_id` rule out the row for
+    /// `id`. Only the rows at `start_key` and `end_key` can be ruled out;
+    /// the key range itself is checked separately.
+    pub fn outside_doc_id_bounds(&self, id: &str) -> bool {
+        let before = |doc_id: &str| {
+            if self.descending {
+                id > doc_id
+            } else {
+                id < doc_id
+            }
+        };
+        if self.start_key.as_deref() == Some(id)
+            && let Some(ref doc_id) = self.start_key_doc_id
+            && before(doc_id)
+        {
+            return true;
+        }
+        if self.end_key.as_deref() == Some(id)
+            && let Some(ref doc_id) = self.end_key_doc_id
+        {
+            return !(before(doc_id) || (self.inclusive_end && id == doc_id));
+        }
+        false
+    }
+
+    /// Options selecting every document ID that starts with `prefix`.
+    ///
+    /// The range starts at `prefix` and ends, exclusively, at the first ID
+    /// past the prefix in byte order, so no sentinel such as `"\u{fff0}"`
+    /// is needed. An empty prefix selects everything.
+    pub fn with_prefix(prefix: &str) -> Self {
+        let mut end: Vec<char> = prefix.chars().collect();
+        // Bump the last character; drop characters that can't be bumped
+        let end_key = loop {
+            let Some(last) = end.pop() else {
+                break None;
+            };
+            let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+            if let Some(next) = next {
+                end.push(next);
+                break Some(end.into_iter().collect());
+            }
+        };
+        Self {
+            start_key: (!prefix.is_empty()).then(|| prefix.to_string()),
+            end_key,
+            inclusive_end: false,
+            ..Default::default()
+        }
+    }
+}
+
+/// Options for `compact_with_opts`.
+#[derive(Debug, Clone, Default)]
+pub struct CompactOptions {
+    /// Bodies to keep per branch, counting the leaf: `retain_revs: 2`
+    /// keeps each leaf and its parent. `0` and `1` keep leaves only, like
+    /// `compact()`.
+    pub retain_revs: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -330,7 +436,25 @@ pub struct AllDocsResponse {
 pub struct DbInfo {
     pub db_name: String,
     pub doc_count: u64,
+    /// Documents whose winning revision is a deletion.
+    #[serde(default)]
+    pub doc_del_count: u64,
     pub update_seq: Seq,
+    /// Storage sizes, for adapters that report them (CouchDB does).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sizes: Option<DbSizes>,
+}
+
+/// Database sizes in bytes, as in CouchDB's `sizes` object.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbSizes {
+    /// Size of the database file on disk.
+    pub file: u64,
+    /// Size of live data inside the file; the rest is reclaimed by
+    /// compaction.
+    pub active: u64,
+    /// Uncompressed size of the document bodies and attachments.
+    pub external: u64,
 }
 
 // ---------------------------------------------------------------------------
@@ -341,6 +465,8 @@ pub struct DbInfo {
 pub struct ChangesOptions {
     pub since: Seq,
     pub limit: Option<u64>,
+    /// Newest change first. Combined with a non-zero `since`, returns the
+    /// changes with a seq below `since`.
     pub descending: bool,
     pub include_docs: bool,
     pub live: bool,
@@ -351,6 +477,9 @@ pub struct ChangesOptions {
     /// Changes style: `MainOnly` (default) returns only winning rev,
     /// `AllDocs` returns all leaf revisions.
     pub style: ChangesStyle,
+    /// Keep-alive interval, forwarded to CouchDB as `heartbeat=` (ms).
+    /// Local adapters ignore it.
+    pub heartbeat: Option<std::time::Duration>,
 }
 
 /// Controls which revisions appear in each change event.
@@ -457,6 +586,34 @@ impl Seq {
         Seq::Num(0)
     }
 
+    /// Whether this is the start of the feed: `Num(0)`, or a string whose
+    /// numeric prefix is `0` (e.g. `"0"` or `"0-g1AAAA..."`). Strings
+    /// without a numeric prefix, such as `"now"`, are not zero.
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Seq::Num(n) => *n == 0,
+            Seq::Str(s) => s.split('-').next().and_then(|n| n.parse::<u64>().ok()) == Some(0),
+        }
+    }
+
+    /// The sequence after this one. For opaque strings the numeric prefix
+    /// is incremented and the suffix kept (`"7-abc"` → `"8-abc"`); a
+    /// string without a numeric prefix counts as `0`. `None` once the
+    /// number is `u64::MAX`.
+    ///
+    /// Only a local stand-in for test fixtures and synthetic feeds: a real
+    /// CouchDB's next sequence has a different suffix.
+    pub fn next(&self) -> Option<Self> {
+        let next = self.as_num().checked_add(1)?;
+        Some(match self {
+            Seq::Num(_) => Seq::Num(next),
+            Seq::Str(s) => match s.split_once('-') {
+                Some((_, suffix)) => Seq::Str(format!("{next}-{suffix}")),
+                None => Seq::Str(next.to_string()),
+            },
+        })
+    }
+
     /// Extract the numeric value. For opaque strings, parses the numeric
     /// prefix (e.g., `"13-abc..."` → `13`). Returns 0 if unparseable.
     pub fn as_num(&self) -> u64 {
@@ -477,6 +634,18 @@ impl Seq {
             Seq::Str(s) => s.clone(),
         }
     }
+
+    /// `(numeric value, variant rank, suffix, full string)`. The full string
+    /// keeps the order consistent with `Eq` for strings like `"03-a"` / `"3-a"`.
+    fn sort_key(&self) -> (u64, u8, &str, &str) {
+        match self {
+            Seq::Num(n) => (*n, 0, "", ""),
+            Seq::Str(s) => {
+                let suffix = s.split_once('-').map_or("", |(_, rest)| rest);
+                (self.as_num(), 1, suffix, s.as_str())
+            }
+        }
+    }
 }
 
 impl Default for Seq {
@@ -491,6 +660,21 @@ impl From<u64> for Seq {
     }
 }
 
+/// Sequences order by numeric value first. Two opaque strings with the same
+/// numeric prefix are then ordered by their suffix; at equal value a `Num`
+/// sorts before a `Str`.
+impl Ord for Seq {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for Seq {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl std::fmt::Display for Seq {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -534,9 +718,90 @@ pub struct SecurityGroup {
 // Attachment options
 // ---------------------------------------------------------------------------
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct GetAttachmentOptions {
     pub rev: Option<String>,
+    /// Check the bytes read against the attachment's MD5 digest and fail
+    /// with a `DatabaseError` if they differ. On by default. Over HTTP the
+    /// check needs the server's `Content-MD5` header.
+    pub verify: bool,
+    /// Read only bytes `start..=end` (`None` end reads to the last byte).
+    /// An end past the data is clamped; a start past it is a `BadRequest`.
+    /// The digest check, when on, still covers the whole attachment.
+    pub range: Option<(u64, Option<u64>)>,
+}
+
+impl Default for GetAttachmentOptions {
+    fn default() -> Self {
+        Self {
+            rev: None,
+            verify: true,
+            range: None,
+        }
+    }
+}
+
+impl GetAttachmentOptions {
+    /// Cut `data` down to the requested `range`, if any.
+    pub fn slice(&self, mut data: Vec<u8>) -> Result<Vec<u8>> {
+        if self.range.is_none() {
+            return Ok(data);
+        }
+        let (start, end) = self.byte_range(data.len() as u64)?;
+        data.truncate(end as usize);
+        data.drain(..start as usize);
+        Ok(data)
+    }
+
+    /// The requested bytes of a `len`-byte attachment as `start..end`, the
+    /// whole attachment without a `range`.
+    pub fn byte_range(&self, len: u64) -> Result<(u64, u64)> {
+        let Some((start, end)) = self.range else {
+            return Ok((0, len));
+        };
+        let end = end.map_or(len, |end| end.saturating_add(1).min(len));
+        if start >= len || start >= end {
+            return Err(RouchError::BadRequest(format!(
+                "attachment range starting at byte {} does not fit {} bytes",
+                start, len
+            )));
+        }
+        Ok((start, end))
+    }
+}
+
+/// Options for `put_attachment_with_opts`.
+#[derive(Debug, Clone, Default)]
+pub struct PutAttachmentOptions {
+    /// Content type to store. Empty means `application/octet-stream`
+    /// unless `guess` is set.
+    pub content_type: String,
+    /// With an empty `content_type`, pick one from the attachment name's
+    /// extension (see [`crate::mime::guess`]).
+    pub guess: bool,
+}
+
+/// A persisted map/reduce view index, as returned by
+/// `Adapter::get_view_index`. Adapters store both parts opaquely.
+#[derive(Debug, Clone, Default)]
+pub struct StoredViewIndex {
+    /// Index-wide state, such as the sequence it was last updated at.
+    pub header: serde_json::Value,
+    /// The map output of each indexed document, by document ID.
+    pub rows: BTreeMap<String, serde_json::Value>,
+}
+
+/// Changes to a persisted view index, written by
+/// `Adapter::update_view_index` in one transaction.
+#[derive(Debug, Clone, Default)]
+pub struct ViewIndexUpdate {
+    /// Replaces the stored header.
+    pub header: serde_json::Value,
+    /// Rows to set, or to remove when `None`, by document ID. Rows not
+    /// listed are left as they are.
+    pub rows: Vec<(String, Option<serde_json::Value>)>,
+    /// Drop every stored row before applying `rows`.
+    pub reset: bool,
 }
 
 // ---------------------------------------------------------------------------
@@ -547,12 +812,26 @@ pub struct GetAttachmentOptions {
 mod tests {
     use super::*;
 
+    #[test]
+    fn all_docs_prefix_bounds() {
+        let opts = AllDocsOptions::with_prefix("user:");
+        assert_eq!(opts.start_key.as_deref(), Some("user:"));
+        assert_eq!(opts.end_key.as_deref(), Some("user;"));
+        assert!(!opts.inclusive_end);
+
+        let opts = AllDocsOptions::with_prefix("a\u{10ffff}");
+        assert_eq!(opts.end_key.as_deref(), Some("b"));
+
+        let opts = AllDocsOptions::with_prefix("");
+        assert_eq!((opts.start_key, opts.end_key), (None, None));
+    }
+
     #[test]
     fn revision_display_and_parse() {
-        let rev = Revision::new(3, "abc123".into());
-        assert_eq!(rev.to_string(), "3-abc123");
+        let rev = Revision::new(3, "967a00dff5e02add41819138abb3284d".into());
+        assert_eq!(rev.to_string(), "3-967a00dff5e02add41819138abb3284d");
 
-        let parsed: Revision = "3-abc123".parse().unwrap();
+        let parsed: Revision = "3-967a00dff5e02add41819138abb3284d".parse().unwrap();
         assert_eq!(parsed, rev);
     }
 
@@ -569,26 +848,41 @@ mod tests {
     fn invalid_revision() {
         assert!("nope".parse::<Revision>().is_err());
         assert!("abc-123".parse::<Revision>().is_err());
+
+        let invalid =
+            |s: &str| matches!(s.parse::<Revision>(), Err(RouchError::InvalidRev(r)) if r == s);
+        assert!(invalid("3-"));
+        assert!(invalid("1-xyz!"));
+        assert!(invalid("1-abc123"));
+        assert!(invalid("1-967A00DFF5E02ADD41819138ABB3284D"));
+        assert!(invalid("1-967a00dff5e02add41819138abb3284d0"));
+
+        assert!(Revision::is_valid_hash("967a00dff5e02add41819138abb3284d"));
+        assert!(!Revision::is_valid_hash(""));
+        assert!(!Revision::is_valid_hash("967A00DFF5E02ADD41819138ABB3284D"));
     }
 
     #[test]
     fn document_from_json_roundtrip() {
         let json = serde_json::json!({
             "_id": "doc1",
-            "_rev": "1-abc",
+            "_rev": "1-967a00dff5e02add41819138abb3284d",
             "name": "Alice",
             "age": 30
         });
 
         let doc = Document::from_json(json).unwrap();
         assert_eq!(doc.id, "doc1");
-        assert_eq!(doc.rev.as_ref().unwrap().to_string(), "1-abc");
+        assert_eq!(
+            doc.rev.as_ref().unwrap().to_string(),
+            "1-967a00dff5e02add41819138abb3284d"
+        );
         assert_eq!(doc.data["name"], "Alice");
         assert!(!doc.data.as_object().unwrap().contains_key("_id"));
 
         let back = doc.to_json();
         assert_eq!(back["_id"], "doc1");
-        assert_eq!(back["_rev"], "1-abc");
+        assert_eq!(back["_rev"], "1-967a00dff5e02add41819138abb3284d");
         assert_eq!(back["name"], "Alice");
     }
 
@@ -653,6 +947,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn inline_attachment_json_round_trip() {
+        let json = serde_json::json!({
+            "_id": "doc1",
+            "_attachments": {
+                "f.txt": {"content_type": "text/plain", "data": "aGVsbG8="}
+            }
+        });
+        let doc = Document::from_json(json).unwrap();
+        let att = &doc.attachments["f.txt"];
+        assert_eq!(att.data.as_deref(), Some(&b"hello"[..]));
+        assert_eq!(att.length, 5);
+        assert!(!att.stub);
+
+        let out = doc.to_json();
+        assert_eq!(out["_attachments"]["f.txt"]["data"], "aGVsbG8=");
+        assert!(out["_attachments"]["f.txt"].get("stub").is_none());
+    }
+
+    #[test]
+    fn unreadable_attachments_are_rejected() {
+        for attachments in [
+            serde_json::json!({"f.txt": {"content_type": "text/plain", "data": "not base64!"}}),
+            serde_json::json!({"f.txt": {"data": "aGVsbG8="}}),
+            serde_json::json!({"f.txt": "aGVsbG8="}),
+            serde_json::json!(["f.txt"]),
+        ] {
+            let json = serde_json::json!({"_id": "doc1", "_attachments": attachments});
+            assert!(matches!(
+                Document::from_json(json),
+                Err(RouchError::BadRequest(_))
+            ));
+        }
+    }
+
     #[test]
     fn to_json_non_object_data() {
         let doc = Document {
@@ -670,7 +999,7 @@ mod tests {
     fn document_from_json_with_deleted_and_attachments() {
         let json = serde_json::json!({
             "_id": "doc1",
-            "_rev": "1-abc",
+            "_rev": "1-967a00dff5e02add41819138abb3284d",
             "_deleted": true,
             "_attachments": {
                 "photo.jpg": {
@@ -716,9 +1045,73 @@ mod tests {
         assert_eq!(format!("{}", Seq::Str("opaque-seq".into())), "opaque-seq");
     }
 
+    #[test]
+    fn seq_ordering() {
+        assert!(Seq::Num(5) < Seq::Num(10));
+        assert!(Seq::Str("3-a".into()) < Seq::Str("3-b".into()));
+        // The numeric prefix wins over lexical order.
+        assert!(Seq::Str("9-z".into()) < Seq::Str("10-a".into()));
+
+        // Mixed variants compare by numeric value, then Num before Str.
+        assert!(Seq::Num(3) < Seq::Str("3-a".into()));
+        assert!(Seq::Str("3-a".into()) < Seq::Num(4));
+        assert!(Seq::Num(2) < Seq::Str("3-a".into()));
+
+        let mut map = std::collections::BTreeMap::new();
+        map.insert(Seq::Str("3-b".into()), "c");
+        map.insert(Seq::Num(3), "a");
+        map.insert(Seq::Str("3-a".into()), "b");
+        map.insert(Seq::Num(10), "d");
+        let order: Vec<&str> = map.values().copied().collect();
+        assert_eq!(order, vec!["a", "b", "c", "d"]);
+        assert_eq!(
+            [Seq::Num(1), Seq::Str("7-x".into())].iter().max(),
+            Some(&Seq::Str("7-x".into()))
+        );
+    }
+
     #[test]
     fn seq_from_u64() {
         let seq: Seq = 7u64.into();
         assert_eq!(seq, Seq::Num(7));
     }
+
+    #[test]
+    fn seq_next_and_is_zero() {
+        assert_eq!(Seq::Num(4).next(), Some(Seq::Num(5)));
+        assert_eq!(
+            Seq::Str("7-abc".into()).next(),
+            Some(Seq::Str("8-abc".into()))
+        );
+        assert_eq!(Seq::Str("7".into()).next(), Some(Seq::Str("8".into())));
+        assert_eq!(Seq::Num(u64::MAX).next(), None);
+        assert_eq!(Seq::Str(format!("{}-abc", u64::MAX)).next(), None);
+
+        assert!(Seq::zero().is_zero());
+        assert!(Seq::Str("0-abc".into()).is_zero());
+        assert!(!Seq::Num(1).is_zero());
+        assert!(!Seq::Str("now".into()).is_zero());
+        assert!(!Seq::zero().next().unwrap().is_zero());
+    }
+
+    #[test]
+    fn all_docs_doc_id_bounds_only_touch_boundary_rows() {
+        let opts = AllDocsOptions {
+            start_key: Some("b".into()),
+            start_key_doc_id: Some("c".into()),
+            end_key: Some("d".into()),
+            end_key_doc_id: Some("a".into()),
+            ..AllDocsOptions::new()
+        };
+        assert!(opts.outside_doc_id_bounds("b"));
+        assert!(!opts.outside_doc_id_bounds("c"));
+        assert!(opts.outside_doc_id_bounds("d"));
+
+        let opts = AllDocsOptions {
+            start_key: Some("b".into()),
+            start_key_doc_id: Some("a".into()),
+            ..AllDocsOptions::new()
+        };
+        assert!(!opts.outside_doc_id_bounds("b"));
+    }
 }
@@ -30,6 +30,18 @@ pub enum RouchError {
     #[error("database error: {0}")]
     DatabaseError(String),
 
+    /// The remote server failed (HTTP 5xx) with this status and reason.
+    #[error("server error {0}: {1}")]
+    Server(u16, String),
+
+    /// Any other HTTP failure, with CouchDB's `error` and `reason` fields.
+    #[error("HTTP {status} {error}: {reason}")]
+    Http {
+        status: u16,
+        error: String,
+        reason: String,
+    },
+
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -37,4 +49,65 @@ pub enum RouchError {
     Json(#[from] serde_json::Error),
 }
 
+impl RouchError {
+    /// Whether the operation that failed may succeed if retried.
+    ///
+    /// I/O failures (including dropped HTTP connections and timeouts),
+    /// 5xx responses, and 408/429 statuses are transient. Everything else,
+    /// including local storage errors and corrupt data, will fail the same
+    /// way again.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            RouchError::Io(_) | RouchError::Server(..) => true,
+            RouchError::Http { status, .. } => *status >= 500 || matches!(status, 408 | 429),
+            _ => false,
+        }
+    }
+
+    /// The HTTP status this error corresponds to, if any.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            RouchError::BadRequest(_) | RouchError::InvalidRev(_) | RouchError::MissingId => {
+                Some(400)
+            }
+            RouchError::Unauthorized => Some(401),
+            RouchError::Forbidden(_) => Some(403),
+            RouchError::NotFound(_) => Some(404),
+            RouchError::Conflict => Some(409),
+            RouchError::DatabaseExists(_) => Some(412),
+            RouchError::Server(status, _) | RouchError::Http { status, .. } => Some(*status),
+            RouchError::DatabaseError(_) | RouchError::Io(_) | RouchError::Json(_) => None,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, RouchError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retriable_errors() {
+        assert!(!RouchError::DatabaseError("digest mismatch".into()).is_retriable());
+        assert!(RouchError::Io(std::io::Error::other("reset")).is_retriable());
+        assert!(RouchError::Server(503, "unavailable".into()).is_retriable());
+        assert!(!RouchError::Conflict.is_retriable());
+        assert!(!RouchError::BadRequest("bad".into()).is_retriable());
+        assert!(!RouchError::NotFound("missing".into()).is_retriable());
+    }
+
+    #[test]
+    fn http_error_status() {
+        let http = |status: u16| RouchError::Http {
+            status,
+            error: "error".into(),
+            reason: "reason".into(),
+        };
+        assert!(http(503).is_retriable());
+        assert!(!http(400).is_retriable());
+        assert_eq!(http(503).status_code(), Some(503));
+        assert_eq!(RouchError::Conflict.status_code(), Some(409));
+        assert_eq!(RouchError::DatabaseError("x".into()).status_code(), None);
+    }
+}
@@ -1,6 +1,9 @@
 pub mod adapter;
 pub mod collation;
+pub mod digest;
 pub mod document;
 pub mod error;
 pub mod merge;
+pub mod mime;
+pub mod rev;
 pub mod rev_tree;
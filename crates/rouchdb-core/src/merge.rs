@@ -4,8 +4,9 @@
 /// - Merge incoming revision paths into an existing tree
 /// - Determine the winning revision deterministically
 /// - Stem (prune) old revisions beyond a configurable limit
-use crate::document::Revision;
-use crate::rev_tree::{RevNode, RevPath, RevStatus, RevTree, collect_leaves};
+use crate::document::{OpenRevs, Revision};
+use crate::error::{Result, RouchError};
+use crate::rev_tree::{RevNode, RevPath, RevStatus, RevTree, collect_leaves, rev_exists};
 
 /// Result of merging a new path into the tree.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -20,17 +21,52 @@ pub enum MergeResult {
 
 /// Merge a new revision path into the existing tree.
 ///
-/// Returns the updated tree and a `MergeResult` indicating what happened.
-pub fn merge_tree(tree: &RevTree, new_path: &RevPath, rev_limit: u64) -> (RevTree, MergeResult) {
+/// Returns the updated tree, a `MergeResult` indicating what happened, and
+/// the hashes of any revisions pruned by stemming to `rev_limit`, so callers
+/// can delete the bodies stored for them.
+pub fn merge_tree(
+    tree: &RevTree,
+    new_path: &RevPath,
+    rev_limit: u64,
+) -> (RevTree, MergeResult, Vec<String>) {
     let mut result_tree = tree.clone();
     let merge_result = do_merge(&mut result_tree, new_path);
 
     // Apply stemming if we have a rev_limit
-    if rev_limit > 0 {
-        let _stemmed = stem(&mut result_tree, rev_limit);
-    }
+    let stemmed = if rev_limit > 0 {
+        stem(&mut result_tree, rev_limit)
+    } else {
+        Vec::new()
+    };
+
+    (result_tree, merge_result, stemmed)
+}
+
+/// Merge several revision paths into the existing tree.
+///
+/// Same as folding [`merge_tree`] over `new_paths`, but the tree is cloned
+/// once and stemmed once at the end, so a later path can still attach to
+/// an ancestor an earlier one pushed past `rev_limit`. Returns the updated
+/// tree, one `MergeResult` per path in order, and the hashes pruned by
+/// stemming.
+pub fn merge_paths(
+    tree: &RevTree,
+    new_paths: &[RevPath],
+    rev_limit: u64,
+) -> (RevTree, Vec<MergeResult>, Vec<String>) {
+    let mut result_tree = tree.clone();
+    let results = new_paths
+        .iter()
+        .map(|path| do_merge(&mut result_tree, path))
+        .collect();
 
-    (result_tree, merge_result)
+    let stemmed = if rev_limit > 0 {
+        stem(&mut result_tree, rev_limit)
+    } else {
+        Vec::new()
+    };
+
+    (result_tree, results, stemmed)
 }
 
 /// Core merge logic. Tries to merge `new_path` into `tree`, modifying it
@@ -310,6 +346,12 @@ pub fn is_deleted(tree: &RevTree) -> bool {
         .unwrap_or(false)
 }
 
+/// Whether a new edit may be based on `rev`: the winner, or one of the
+/// conflicting leaves (which is how a losing branch gets deleted).
+pub fn is_editable_rev(tree: &RevTree, rev: &Revision) -> bool {
+    winning_rev(tree).as_ref() == Some(rev) || collect_conflicts(tree).contains(rev)
+}
+
 /// Collect all conflicting (non-winning, non-deleted) leaf revisions.
 pub fn collect_conflicts(tree: &RevTree) -> Vec<Revision> {
     let leaves = collect_leaves(tree);
@@ -321,6 +363,31 @@ pub fn collect_conflicts(tree: &RevTree) -> Vec<Revision> {
         .collect()
 }
 
+/// Resolve `open_revs` against the tree.
+///
+/// `All` selects every non-deleted leaf, winner first. `Specific` selects
+/// the requested revisions as given, failing with `NotFound` for any that
+/// aren't in the tree.
+pub fn select_open_revs(tree: &RevTree, open_revs: &OpenRevs) -> Result<Vec<Revision>> {
+    match open_revs {
+        OpenRevs::All => Ok(collect_leaves(tree)
+            .into_iter()
+            .filter(|l| !l.deleted)
+            .map(|l| Revision::new(l.pos, l.hash))
+            .collect()),
+        OpenRevs::Specific(revs) => revs
+            .iter()
+            .map(|rev_str| {
+                let rev: Revision = rev_str.parse()?;
+                if !rev_exists(tree, rev.pos, &rev.hash) {
+                    return Err(RouchError::NotFound(rev_str.clone()));
+                }
+                Ok(rev)
+            })
+            .collect(),
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Stemming (pruning old revisions)
 // ---------------------------------------------------------------------------
@@ -557,6 +624,19 @@ mod tests {
         assert_eq!(conflicts[0].hash, "b"); // loser
     }
 
+    #[test]
+    fn editable_revs_are_open_leaves() {
+        // 1-a -> 2-b, 2-c
+        let tree = vec![RevPath {
+            pos: 1,
+            tree: node("a", vec![leaf("b"), leaf("c")]),
+        }];
+        assert!(is_editable_rev(&tree, &Revision::new(2, "c".into())));
+        assert!(is_editable_rev(&tree, &Revision::new(2, "b".into())));
+        assert!(!is_editable_rev(&tree, &Revision::new(1, "a".into())));
+        assert!(!is_editable_rev(&tree, &Revision::new(2, "z".into())));
+    }
+
     // --- is_deleted ---
 
     #[test]
@@ -591,7 +671,7 @@ mod tests {
             RevStatus::Available,
         );
 
-        let (merged, result) = merge_tree(&tree, &new_path, 1000);
+        let (merged, result, _) = merge_tree(&tree, &new_path, 1000);
         assert_eq!(result, MergeResult::NewLeaf);
 
         let winner = winning_rev(&merged).unwrap();
@@ -599,6 +679,33 @@ mod tests {
         assert_eq!(winner.hash, "c");
     }
 
+    #[test]
+    fn merge_reports_stemmed_hashes() {
+        // Start: 1-a -> 2-b
+        let tree = vec![RevPath {
+            pos: 1,
+            tree: node("a", vec![leaf("b")]),
+        }];
+
+        // Add 3-c with rev_limit 2: 1-a falls off
+        let new_path = build_path_from_revs(
+            3,
+            &["c".into(), "b".into()],
+            NodeOpts::default(),
+            RevStatus::Available,
+        );
+
+        let (merged, result, stemmed) = merge_tree(&tree, &new_path, 2);
+        assert_eq!(result, MergeResult::NewLeaf);
+        assert_eq!(stemmed, vec!["a".to_string()]);
+        assert_eq!(merged[0].pos, 2);
+        assert_eq!(merged[0].tree.hash, "b");
+
+        // Within the limit nothing is reported
+        let (_, _, stemmed) = merge_tree(&tree, &new_path, 1000);
+        assert!(stemmed.is_empty());
+    }
+
     #[test]
     fn merge_creates_conflict_branch() {
         // Start: 1-a -> 2-b
@@ -615,7 +722,7 @@ mod tests {
             RevStatus::Available,
         );
 
-        let (merged, result) = merge_tree(&tree, &new_path, 1000);
+        let (merged, result, _) = merge_tree(&tree, &new_path, 1000);
         assert_eq!(result, MergeResult::NewBranch);
 
         let conflicts = collect_conflicts(&merged);
@@ -638,10 +745,65 @@ mod tests {
             RevStatus::Available,
         );
 
-        let (_merged, result) = merge_tree(&tree, &new_path, 1000);
+        let (_merged, result, _) = merge_tree(&tree, &new_path, 1000);
         assert_eq!(result, MergeResult::InternalNode);
     }
 
+    #[test]
+    fn merge_paths_matches_sequential_merges() {
+        // Start: 1-a -> 2-b
+        let tree = vec![RevPath {
+            pos: 1,
+            tree: node("a", vec![leaf("b")]),
+        }];
+
+        let path = |revs: &[&str]| {
+            let revs: Vec<String> = revs.iter().map(|r| r.to_string()).collect();
+            build_path_from_revs(
+                revs.len() as u64,
+                &revs,
+                NodeOpts::default(),
+                RevStatus::Available,
+            )
+        };
+        let paths = vec![
+            path(&["c", "b", "a"]), // extends 2-b
+            path(&["d", "a"]),      // conflicts with 2-b
+            path(&["b", "a"]),      // already there
+        ];
+
+        let mut folded = tree.clone();
+        let mut expected = Vec::new();
+        for p in &paths {
+            let (next, result, _) = merge_tree(&folded, p, 1000);
+            folded = next;
+            expected.push(result);
+        }
+
+        let (merged, results, stemmed) = merge_paths(&tree, &paths, 1000);
+        assert_eq!(
+            results,
+            vec![
+                MergeResult::NewLeaf,
+                MergeResult::NewBranch,
+                MergeResult::InternalNode
+            ]
+        );
+        assert_eq!(results, expected);
+        let leaves = |t: &RevTree| -> Vec<String> {
+            collect_leaves(t).iter().map(|l| l.rev_string()).collect()
+        };
+        assert_eq!(leaves(&merged), leaves(&folded));
+        assert!(stemmed.is_empty());
+
+        // Stemming happens once, after every path is in, so 2-d still
+        // finds its parent 1-a, which then can't be stemmed past the branch
+        let (merged, _, stemmed) = merge_paths(&tree, &paths, 2);
+        assert!(stemmed.is_empty());
+        assert_eq!(merged.len(), 1);
+        assert_eq!(leaves(&merged), vec!["3-c", "2-d"]);
+    }
+
     #[test]
     fn merge_disjoint_creates_new_root() {
         // Start: 1-a -> 2-b
@@ -658,7 +820,7 @@ mod tests {
             RevStatus::Available,
         );
 
-        let (merged, result) = merge_tree(&tree, &new_path, 1000);
+        let (merged, result, _) = merge_tree(&tree, &new_path, 1000);
         assert_eq!(result, MergeResult::NewBranch);
         assert_eq!(merged.len(), 2); // Two separate roots
     }
@@ -780,7 +942,7 @@ mod tests {
             tree: leaf("a"),
         };
 
-        let (_, result) = merge_tree(&tree, &new_path, 1000);
+        let (_, result, _) = merge_tree(&tree, &new_path, 1000);
         assert_eq!(result, MergeResult::InternalNode);
     }
 
@@ -797,7 +959,7 @@ mod tests {
             RevStatus::Available,
         );
 
-        let (merged, result) = merge_tree(&tree, &new_path, 1000);
+        let (merged, result, _) = merge_tree(&tree, &new_path, 1000);
         assert_eq!(result, MergeResult::NewLeaf);
         let winner = winning_rev(&merged).unwrap();
         assert_eq!(winner.pos, 4);
@@ -0,0 +1,46 @@
+//! Attachment content types.
+//!
+//! Attachments always carry a content type. `guess` picks one from a file
+//! name for callers that don't know it; adapters store an empty type as
+//! `application/octet-stream`, as CouchDB does for untyped uploads.
+
+/// The type for arbitrary binary data.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Guess a content type from the extension of `name`, case-insensitively,
+/// using `mime_guess`'s table. Names without a known extension get
+/// [`OCTET_STREAM`].
+pub fn guess(name: &str) -> &'static str {
+    mime_guess::from_path(name)
+        .first_raw()
+        .unwrap_or(OCTET_STREAM)
+}
+
+/// `content_type`, or [`OCTET_STREAM`] when it is empty.
+pub fn or_default(content_type: &str) -> &str {
+    if content_type.is_empty() {
+        OCTET_STREAM
+    } else {
+        content_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_from_extension() {
+        assert_eq!(guess("greeting.txt"), "text/plain");
+        assert_eq!(guess("photo.JPG"), "image/jpeg");
+        assert_eq!(guess("archive.tar.gz"), "application/gzip");
+        assert_eq!(guess("data.unknownext"), OCTET_STREAM);
+        assert_eq!(guess("README"), OCTET_STREAM);
+    }
+
+    #[test]
+    fn empty_type_defaults_to_octet_stream() {
+        assert_eq!(or_default(""), OCTET_STREAM);
+        assert_eq!(or_default("text/plain"), "text/plain");
+    }
+}
@@ -0,0 +1,276 @@
+/// CouchDB-compatible revision hashing.
+///
+/// CouchDB derives a new revision hash from the MD5 of the Erlang external
+/// term format encoding of `[Deleted, OldStart, OldRev, Body, Atts]`
+/// (`couch_db:new_revid/1`). This module reproduces that encoding so that
+/// the same edit yields the same revision id here and on a CouchDB server.
+use std::collections::HashMap;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use md5::{Digest, Md5};
+use serde_json::Value;
+
+use crate::document::{AttachmentMeta, Revision};
+
+// Erlang external term format tags.
+const VERSION: u8 = 131;
+const NEW_FLOAT_EXT: u8 = 70;
+const SMALL_INTEGER_EXT: u8 = 97;
+const INTEGER_EXT: u8 = 98;
+const ATOM_EXT: u8 = 100;
+const SMALL_TUPLE_EXT: u8 = 104;
+const NIL_EXT: u8 = 106;
+const STRING_EXT: u8 = 107;
+const LIST_EXT: u8 = 108;
+const BINARY_EXT: u8 = 109;
+const SMALL_BIG_EXT: u8 = 110;
+
+/// Compute the revision CouchDB would assign to an edit.
+///
+/// `prev` is the revision being edited (`None` for a new document) and
+/// `body` the document body. Top-level fields starting with `_` are
+/// metadata and are left out, as CouchDB does. Attachments are hashed by
+/// name, content type and MD5 of their data; those with neither data nor
+/// an `md5-` digest are skipped.
+///
+/// The result matches CouchDB as long as the body's object keys are in the
+/// order CouchDB received them: `serde_json` keeps keys sorted, and
+/// attachments are taken in name order.
+pub fn compute_rev(
+    prev: Option<&Revision>,
+    body: &Value,
+    deleted: bool,
+    attachments: &HashMap<String, AttachmentMeta>,
+) -> Revision {
+    let mut out = vec![VERSION];
+
+    list_header(&mut out, 5);
+    atom(&mut out, if deleted { "true" } else { "false" });
+    match prev {
+        Some(prev) => {
+            integer(&mut out, prev.pos as i128);
+            // CouchDB stores hex revision hashes as raw 16-byte binaries.
+            match parse_hex(&prev.hash) {
+                Some(raw) => binary(&mut out, &raw),
+                None => binary(&mut out, prev.hash.as_bytes()),
+            }
+        }
+        None => {
+            integer(&mut out, 0);
+            integer(&mut out, 0);
+        }
+    }
+    match body {
+        Value::Object(map) => {
+            let fields: Vec<(&String, &Value)> =
+                map.iter().filter(|(k, _)| !k.starts_with('_')).collect();
+            object(&mut out, &fields);
+        }
+        other => term(&mut out, other),
+    }
+    attachment_list(&mut out, attachments);
+    out.push(NIL_EXT);
+
+    let hash = format!("{:x}", Md5::digest(&out));
+    Revision::new(prev.map(|p| p.pos + 1).unwrap_or(1), hash)
+}
+
+/// `[{Name, Type, Md5}]`, built by CouchDB with a fold that prepends, so
+/// the last attachment comes first.
+fn attachment_list(out: &mut Vec<u8>, attachments: &HashMap<String, AttachmentMeta>) {
+    let mut digested: Vec<(&String, &AttachmentMeta, Vec<u8>)> = attachments
+        .iter()
+        .filter_map(|(name, att)| attachment_md5(att).map(|md5| (name, att, md5)))
+        .collect();
+    digested.sort_by(|a, b| b.0.cmp(a.0));
+
+    if digested.is_empty() {
+        out.push(NIL_EXT);
+        return;
+    }
+    list_header(out, digested.len());
+    for (name, att, md5) in digested {
+        out.extend_from_slice(&[SMALL_TUPLE_EXT, 3]);
+        binary(out, name.as_bytes());
+        binary(out, att.content_type.as_bytes());
+        binary(out, &md5);
+    }
+    out.push(NIL_EXT);
+}
+
+fn attachment_md5(att: &AttachmentMeta) -> Option<Vec<u8>> {
+    if let Some(ref data) = att.data {
+        return Some(Md5::digest(data).to_vec());
+    }
+    let encoded = att.digest.strip_prefix("md5-")?;
+    STANDARD.decode(encoded).ok()
+}
+
+fn parse_hex(hash: &str) -> Option<Vec<u8>> {
+    if hash.len() != 32 {
+        return None;
+    }
+    (0..32)
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hash.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Encode a JSON value the way CouchDB's JSON decoder represents it.
+fn term(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => atom(out, "null"),
+        Value::Bool(b) => atom(out, if *b { "true" } else { "false" }),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                integer(out, i as i128);
+            } else if let Some(u) = n.as_u64() {
+                integer(out, u as i128);
+            } else {
+                out.push(NEW_FLOAT_EXT);
+                out.extend_from_slice(&n.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        Value::String(s) => binary(out, s.as_bytes()),
+        Value::Array(items) => {
+            // Erlang packs lists of bytes-sized integers as strings.
+            let bytes: Option<Vec<u8>> = items
+                .iter()
+                .map(|v| v.as_u64().and_then(|u| u8::try_from(u).ok()))
+                .collect();
+            match bytes {
+                _ if items.is_empty() => out.push(NIL_EXT),
+                Some(bytes) if bytes.len() <= u16::MAX as usize => {
+                    out.push(STRING_EXT);
+                    out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+                    out.extend_from_slice(&bytes);
+                }
+                _ => {
+                    list_header(out, items.len());
+                    for item in items {
+                        term(out, item);
+                    }
+                    out.push(NIL_EXT);
+                }
+            }
+        }
+        Value::Object(map) => {
+            let fields: Vec<(&String, &Value)> = map.iter().collect();
+            object(out, &fields);
+        }
+    }
+}
+
+/// `{[{Key, Value}, ...]}`
+fn object(out: &mut Vec<u8>, fields: &[(&String, &Value)]) {
+    out.extend_from_slice(&[SMALL_TUPLE_EXT, 1]);
+    if fields.is_empty() {
+        out.push(NIL_EXT);
+        return;
+    }
+    list_header(out, fields.len());
+    for (key, value) in fields {
+        out.extend_from_slice(&[SMALL_TUPLE_EXT, 2]);
+        binary(out, key.as_bytes());
+        term(out, value);
+    }
+    out.push(NIL_EXT);
+}
+
+fn list_header(out: &mut Vec<u8>, len: usize) {
+    out.push(LIST_EXT);
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+}
+
+fn atom(out: &mut Vec<u8>, name: &str) {
+    out.push(ATOM_EXT);
+    out.extend_from_slice(&(name.len() as u16).to_be_bytes());
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn binary(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.push(BINARY_EXT);
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn integer(out: &mut Vec<u8>, n: i128) {
+    if (0..=255).contains(&n) {
+        out.extend_from_slice(&[SMALL_INTEGER_EXT, n as u8]);
+    } else if (i32::MIN as i128..=i32::MAX as i128).contains(&n) {
+        out.push(INTEGER_EXT);
+        out.extend_from_slice(&(n as i32).to_be_bytes());
+    } else {
+        let magnitude = n.unsigned_abs().to_le_bytes();
+        let len = magnitude.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+        let digits = &magnitude[..len];
+        out.extend_from_slice(&[SMALL_BIG_EXT, digits.len() as u8, (n < 0) as u8]);
+        out.extend_from_slice(digits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn empty_doc_matches_couchdb() {
+        // What CouchDB assigns to `PUT /db/doc {}`.
+        let rev = compute_rev(None, &json!({}), false, &HashMap::new());
+        assert_eq!(rev.to_string(), "1-967a00dff5e02add41819138abb3284d");
+
+        // Underscore fields are metadata, not part of the body.
+        let rev = compute_rev(None, &json!({"_id": "doc"}), false, &HashMap::new());
+        assert_eq!(rev.to_string(), "1-967a00dff5e02add41819138abb3284d");
+    }
+
+    #[test]
+    fn hash_covers_every_input() {
+        let none = HashMap::new();
+        let base = compute_rev(None, &json!({"a": 1}), false, &none);
+        assert_eq!(base, compute_rev(None, &json!({"a": 1}), false, &none));
+        assert_eq!(base.hash.len(), 32);
+
+        let mut atts = HashMap::new();
+        atts.insert(
+            "a.txt".to_string(),
+            AttachmentMeta {
+                content_type: "text/plain".into(),
+                digest: String::new(),
+                length: 2,
+                stub: false,
+                data: Some(b"hi".to_vec()),
+            },
+        );
+
+        let others = [
+            compute_rev(None, &json!({"a": 2}), false, &none),
+            compute_rev(None, &json!({"a": 1}), true, &none),
+            compute_rev(None, &json!({"a": 1}), false, &atts),
+            compute_rev(Some(&base), &json!({"a": 1}), false, &none),
+        ];
+        for other in &others {
+            assert_ne!(other.hash, base.hash);
+        }
+        assert_eq!(others[3].pos, 2);
+    }
+
+    #[test]
+    fn integers_use_the_smallest_encoding() {
+        let mut out = Vec::new();
+        integer(&mut out, 7);
+        integer(&mut out, -1);
+        integer(&mut out, 1 << 40);
+        assert_eq!(
+            out,
+            [
+                vec![SMALL_INTEGER_EXT, 7],
+                vec![INTEGER_EXT, 255, 255, 255, 255],
+                vec![SMALL_BIG_EXT, 6, 0, 0, 0, 0, 0, 0, 1],
+            ]
+            .concat()
+        );
+    }
+}
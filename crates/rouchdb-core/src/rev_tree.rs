@@ -1,3 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+use crate::document::{RevInfo, Revision};
+
 /// Revision tree data structure.
 ///
 /// Mirrors PouchDB's `pouchdb-merge` tree representation. A document's full
@@ -7,7 +11,8 @@
 /// Multiple roots arise when revisions are stemmed (pruned) and later a
 /// previously-stemmed branch is re-introduced during replication.
 /// Status of a revision's stored data.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum RevStatus {
     /// Full document data is stored for this revision.
     Available,
@@ -16,7 +21,7 @@ pub enum RevStatus {
 }
 
 /// A single node in the revision tree.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RevNode {
     /// The hash portion of the revision id.
     pub hash: String,
@@ -29,7 +34,7 @@ pub struct RevNode {
 }
 
 /// Per-node metadata flags.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NodeOpts {
     pub deleted: bool,
 }
@@ -38,7 +43,7 @@ pub struct NodeOpts {
 ///
 /// `pos` is the generation number of the root node. For example, if the
 /// earliest stored revision is `3-abc`, then `pos = 3`.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RevPath {
     pub pos: u64,
     pub tree: RevNode,
@@ -152,6 +157,57 @@ pub fn rev_exists(tree: &RevTree, pos: u64, hash: &str) -> bool {
     found
 }
 
+/// Remove the leaf revision at `pos`-`hash` from the tree (used by purge).
+///
+/// Ancestors that no remaining leaf shares are pruned along with it, and a
+/// root path left without nodes is dropped entirely. Returns the removed
+/// revisions leaf-first, or an empty list if `pos`-`hash` is not a leaf.
+pub fn remove_leaf(tree: &mut RevTree, pos: u64, hash: &str) -> Vec<String> {
+    let mut removed = Vec::new();
+    let Some(i) = tree.iter_mut().position(|path| {
+        if path.pos == pos && path.tree.hash == hash && path.tree.children.is_empty() {
+            return true;
+        }
+        remove_leaf_from_node(&mut path.tree, path.pos, pos, hash, &mut removed)
+    }) else {
+        return removed;
+    };
+    if tree[i].tree.children.is_empty() {
+        let path = tree.remove(i);
+        removed.push(format!("{}-{}", path.pos, path.tree.hash));
+    }
+    removed
+}
+
+fn remove_leaf_from_node(
+    node: &mut RevNode,
+    node_pos: u64,
+    pos: u64,
+    hash: &str,
+    removed: &mut Vec<String>,
+) -> bool {
+    if pos <= node_pos {
+        return false;
+    }
+    let child_pos = node_pos + 1;
+    let found = node.children.iter_mut().position(|child| {
+        if child_pos == pos {
+            child.hash == hash && child.children.is_empty()
+        } else {
+            remove_leaf_from_node(child, child_pos, pos, hash, removed)
+        }
+    });
+    let Some(i) = found else {
+        return false;
+    };
+    // The purged leaf itself, or an ancestor whose last branch just went.
+    if node.children[i].children.is_empty() {
+        let child = node.children.remove(i);
+        removed.push(format!("{}-{}", child_pos, child.hash));
+    }
+    true
+}
+
 // ---------------------------------------------------------------------------
 // Building paths from revision arrays (for merging incoming revisions)
 // ---------------------------------------------------------------------------
@@ -231,6 +287,71 @@ pub fn find_rev_ancestry(
     None
 }
 
+/// A revision's ancestry in CouchDB's `_revisions` format.
+///
+/// `ids` holds hashes from the revision itself back to the oldest known
+/// ancestor; `start` is the position of the first one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Revs {
+    pub start: u64,
+    pub ids: Vec<String>,
+}
+
+/// Build the `_revisions` structure for `leaf`, walking from it to the root.
+///
+/// Returns `None` if the revision is not in the tree.
+pub fn revisions_for(tree: &RevTree, leaf: &Revision) -> Option<Revs> {
+    find_rev_ancestry(tree, leaf.pos, &leaf.hash).map(|ids| Revs {
+        start: leaf.pos,
+        ids,
+    })
+}
+
+/// Build the `_revs_info` list for `rev`: it and each of its ancestors,
+/// newest first, with status `available`, `missing` or `deleted`.
+///
+/// Returns `None` if the revision is not in the tree.
+pub fn revs_info_for(tree: &RevTree, rev: &Revision) -> Option<Vec<RevInfo>> {
+    root_to_leaf(tree).into_iter().find_map(|(pos, path)| {
+        let depth = rev.pos.checked_sub(pos)? as usize;
+        if path.get(depth)?.0 != rev.hash {
+            return None;
+        }
+        let info = path[..=depth]
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, (hash, opts, status))| RevInfo {
+                rev: format!("{}-{}", pos + i as u64, hash),
+                status: match status {
+                    _ if opts.deleted => "deleted",
+                    RevStatus::Available => "available",
+                    RevStatus::Missing => "missing",
+                }
+                .to_string(),
+            })
+            .collect();
+        Some(info)
+    })
+}
+
+/// Revision strings of every leaf and its ancestors, keeping at most
+/// `depth` revisions per branch (the leaf counts as one; at least the leaf
+/// is always kept). This is what compaction leaves bodies for.
+pub fn recent_revs(tree: &RevTree, depth: u64) -> std::collections::HashSet<String> {
+    let depth = depth.max(1) as usize;
+    root_to_leaf(tree)
+        .into_iter()
+        .flat_map(|(pos, path)| {
+            let skip = path.len().saturating_sub(depth);
+            path.into_iter()
+                .enumerate()
+                .skip(skip)
+                .map(move |(i, (hash, _, _))| format!("{}-{}", pos + i as u64, hash))
+        })
+        .collect()
+}
+
 fn find_chain_in_node(
     node: &RevNode,
     current_pos: u64,
@@ -278,6 +399,118 @@ mod tests {
         }
     }
 
+    #[test]
+    fn revisions_for_walks_leaf_to_root() {
+        // 1-a -> 2-b -> 3-c
+        let tree = vec![RevPath {
+            pos: 1,
+            tree: node("a", vec![node("b", vec![leaf("c")])]),
+        }];
+
+        let revs = revisions_for(&tree, &Revision::new(3, "c".into())).unwrap();
+        assert_eq!(revs.start, 3);
+        assert_eq!(revs.ids, vec!["c", "b", "a"]);
+        assert_eq!(
+            serde_json::to_value(&revs).unwrap(),
+            serde_json::json!({"start": 3, "ids": ["c", "b", "a"]})
+        );
+
+        assert!(revisions_for(&tree, &Revision::new(3, "z".into())).is_none());
+    }
+
+    #[test]
+    fn revs_info_follows_one_branch() {
+        // 1-a (missing) -> 2-b -> 3-c (deleted), with a 2-x branch
+        let mut root = node("a", vec![node("b", vec![leaf("c")]), leaf("x")]);
+        root.status = RevStatus::Missing;
+        root.children[0].children[0].opts.deleted = true;
+        let tree = vec![RevPath { pos: 1, tree: root }];
+
+        let info = revs_info_for(&tree, &Revision::new(3, "c".into())).unwrap();
+        let pairs: Vec<(&str, &str)> = info
+            .iter()
+            .map(|i| (i.rev.as_str(), i.status.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [("3-c", "deleted"), ("2-b", "available"), ("1-a", "missing")]
+        );
+
+        let other = revs_info_for(&tree, &Revision::new(2, "x".into())).unwrap();
+        assert_eq!(other.len(), 2);
+        assert!(revs_info_for(&tree, &Revision::new(2, "c".into())).is_none());
+    }
+
+    #[test]
+    fn recent_revs_keeps_the_tail_of_each_branch() {
+        // 1-a -> 2-b -> 3-c
+        //     -> 2-d
+        let tree = vec![RevPath {
+            pos: 1,
+            tree: node("a", vec![node("b", vec![leaf("c")]), leaf("d")]),
+        }];
+        let sorted = |depth| {
+            let mut revs: Vec<String> = recent_revs(&tree, depth).into_iter().collect();
+            revs.sort();
+            revs
+        };
+
+        assert_eq!(sorted(0), ["2-d", "3-c"]);
+        assert_eq!(sorted(1), ["2-d", "3-c"]);
+        assert_eq!(sorted(2), ["1-a", "2-b", "2-d", "3-c"]);
+        assert_eq!(sorted(9), ["1-a", "2-b", "2-d", "3-c"]);
+    }
+
+    #[test]
+    fn remove_leaf_of_conflict_branch() {
+        // 1-a -> 2-b
+        //     -> 2-c
+        let mut tree = vec![RevPath {
+            pos: 1,
+            tree: node("a", vec![leaf("b"), leaf("c")]),
+        }];
+        assert_eq!(remove_leaf(&mut tree, 2, "c"), ["2-c"]);
+        let leaves = collect_leaves(&tree);
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].hash, "b");
+
+        // Interior nodes are not leaves and stay put.
+        assert!(remove_leaf(&mut tree, 1, "a").is_empty());
+        assert!(rev_exists(&tree, 1, "a"));
+    }
+
+    #[test]
+    fn remove_leaf_prunes_unshared_ancestors() {
+        // 1-a -> 2-b -> 3-c -> 4-d
+        //            -> 3-e
+        let mut tree = vec![RevPath {
+            pos: 1,
+            tree: node(
+                "a",
+                vec![node("b", vec![node("c", vec![leaf("d")]), leaf("e")])],
+            ),
+        }];
+        // 3-c is only on 4-d's branch; 2-b is still shared with 3-e.
+        assert_eq!(remove_leaf(&mut tree, 4, "d"), ["4-d", "3-c"]);
+        assert!(!rev_exists(&tree, 3, "c"));
+        assert!(rev_exists(&tree, 2, "b"));
+
+        // Purging the last leaf empties the tree.
+        assert_eq!(remove_leaf(&mut tree, 3, "e"), ["3-e", "2-b", "1-a"]);
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn remove_leaf_drops_single_node_root() {
+        let mut tree = vec![RevPath {
+            pos: 1,
+            tree: leaf("a"),
+        }];
+        assert_eq!(remove_leaf(&mut tree, 1, "a"), ["1-a"]);
+        assert!(tree.is_empty());
+        assert!(remove_leaf(&mut tree, 1, "a").is_empty());
+    }
+
     #[test]
     fn collect_leaves_simple_chain() {
         // 1-a -> 2-b -> 3-c
@@ -13,8 +13,9 @@ pub mod mapreduce;
 pub use mango::{
     BuiltIndex, CreateIndexResponse, ExplainIndex, ExplainResponse, FindOptions, FindResponse,
     IndexDefinition, IndexFields, IndexInfo, SortDirection, SortField, build_index, find,
-    matches_selector,
+    find_in_docs, matches_selector, normalize_selector, project, validate_selector,
 };
 pub use mapreduce::{
-    EmittedRow, ReduceFn, StaleOption, ViewQueryOptions, ViewResult, ViewRow, query_view,
+    EmittedRow, PersistentViewIndex, ReduceFn, StaleOption, ViewIndex, ViewQueryOptions,
+    ViewResult, ViewRow, ViewUpdate, include_view_docs, query_emitted, query_view,
 };
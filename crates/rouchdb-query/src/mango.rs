@@ -1,18 +1,21 @@
 //! Mango query engine — CouchDB-compatible selector-based document queries.
 //!
 //! Supports the standard Mango operators: `$eq`, `$ne`, `$gt`, `$gte`, `$lt`,
-//! `$lte`, `$in`, `$nin`, `$exists`, `$regex`, `$elemMatch`, `$all`, `$size`,
-//! `$or`, `$and`, `$not`, `$nor`, `$mod`, `$type`.
+//! `$lte`, `$in`, `$nin`, `$exists`, `$regex`, `$beginsWith`, `$elemMatch`,
+//! `$allMatch`, `$all`, `$size`, `$or`, `$and`, `$not`, `$nor`, `$mod`,
+//! `$type`.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, LazyLock, Mutex};
 
 use regex::Regex;
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use rouchdb_core::adapter::Adapter;
 use rouchdb_core::collation::collate;
-use rouchdb_core::document::AllDocsOptions;
-use rouchdb_core::error::Result;
+use rouchdb_core::document::{AllDocsOptions, ChangesOptions, Seq};
+use rouchdb_core::error::{Result, RouchError};
 
 /// Definition of a Mango index.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +27,10 @@ pub struct IndexDefinition {
     /// Optional design document name.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ddoc: Option<String>,
+    /// Only index documents matching this selector. The index then only
+    /// serves queries whose selector implies it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_filter_selector: Option<serde_json::Value>,
 }
 
 /// Information about an existing index.
@@ -41,6 +48,8 @@ pub struct IndexInfo {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IndexFields {
     pub fields: Vec<SortField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partial_filter_selector: Option<serde_json::Value>,
 }
 
 /// Result of creating an index.
@@ -57,8 +66,21 @@ pub struct CreateIndexResponse {
 pub struct ExplainResponse {
     pub dbname: String,
     pub index: ExplainIndex,
+    /// The selector after normalization (implicit `$eq` made explicit).
     pub selector: serde_json::Value,
     pub fields: Option<Vec<String>>,
+    pub limit: Option<u64>,
+    pub skip: u64,
+    /// Index fields the selector constrains. Empty for a full scan.
+    pub matched_fields: Vec<String>,
+    /// The part of the selector the index does not answer, which is
+    /// filtered in memory over the candidate documents.
+    pub residual_selector: serde_json::Value,
+    /// The index answering each branch of a top-level `$or`, in order,
+    /// when no single index serves the query and the candidates are the
+    /// union of per-branch lookups. `index` is then named `"_or"`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub or_branches: Vec<ExplainIndex>,
 }
 
 /// Description of the index used by a query.
@@ -76,9 +98,120 @@ pub struct ExplainIndex {
 pub struct BuiltIndex {
     pub def: IndexDefinition,
     pub entries: Vec<(Vec<serde_json::Value>, String)>,
+    /// Sequence the index has been brought up to by `update()`.
+    pub last_seq: Seq,
 }
 
 impl BuiltIndex {
+    /// Create an empty index for `def`. Call `update()` to populate it.
+    pub fn new(def: IndexDefinition) -> Self {
+        Self {
+            def,
+            entries: Vec::new(),
+            last_seq: Seq::zero(),
+        }
+    }
+
+    /// Whether the selector constrains the first indexed field, which is
+    /// what `find_matching` needs to narrow the candidate set, and implies
+    /// the partial filter if there is one.
+    pub fn can_serve(&self, selector: &serde_json::Value) -> bool {
+        let constrains_first = self
+            .def
+            .fields
+            .first()
+            .is_some_and(|sf| selector.get(sf.field_and_direction().0).is_some());
+        constrains_first
+            && self
+                .def
+                .partial_filter_selector
+                .as_ref()
+                .is_none_or(|filter| selector_implies(selector, filter))
+    }
+
+    /// Whether the index is keyed by the sort fields, i.e. they are a
+    /// prefix of the indexed fields. Directions are not compared.
+    pub fn covers_sort(&self, sort: &[SortField]) -> bool {
+        sort.len() <= self.def.fields.len()
+            && sort
+                .iter()
+                .zip(&self.def.fields)
+                .all(|(s, f)| s.field_and_direction().0 == f.field_and_direction().0)
+    }
+
+    /// Split a normalized selector into the index fields it matches and
+    /// the residual selector left for in-memory filtering.
+    ///
+    /// Only the range operators on the leading field narrow the candidate
+    /// set, so everything else stays in the residual.
+    pub fn split_selector(&self, selector: &serde_json::Value) -> (Vec<String>, serde_json::Value) {
+        let mut residual = selector.clone();
+        let Some(first) = self.def.fields.first() else {
+            return (Vec::new(), residual);
+        };
+        let (field, _) = first.field_and_direction();
+        let Some(map) = residual.as_object_mut() else {
+            return (Vec::new(), residual);
+        };
+        let Some(serde_json::Value::Object(ops)) = map.get_mut(field) else {
+            return (Vec::new(), residual);
+        };
+        ops.retain(|op, _| !matches!(op.as_str(), "$eq" | "$gt" | "$gte" | "$lt" | "$lte"));
+        if ops.is_empty() {
+            map.remove(field);
+        }
+        (vec![field.to_string()], residual)
+    }
+
+    /// Bring the index up to date by reading the changes feed since
+    /// `last_seq`, re-keying changed documents and dropping deleted ones.
+    pub async fn update(&mut self, adapter: &dyn Adapter) -> Result<()> {
+        let changes = adapter
+            .changes(ChangesOptions {
+                since: self.last_seq.clone(),
+                include_docs: true,
+                ..Default::default()
+            })
+            .await?;
+        if changes.results.is_empty() {
+            return Ok(());
+        }
+
+        let changed: std::collections::HashSet<&str> =
+            changes.results.iter().map(|c| c.id.as_str()).collect();
+        self.entries
+            .retain(|(_, id)| !changed.contains(id.as_str()));
+
+        for change in &changes.results {
+            if change.deleted {
+                continue;
+            }
+            if let Some(ref doc_json) = change.doc {
+                // Docs that stop matching the filter were dropped above
+                if let Some(ref filter) = self.def.partial_filter_selector
+                    && !matches_selector(doc_json, filter)
+                {
+                    continue;
+                }
+                let key = index_key(&self.def, doc_json);
+                self.entries.push((key, change.id.clone()));
+            }
+        }
+
+        // Sort by composite key, then by id like find results
+        self.entries.sort_by(|(a, id_a), (b, id_b)| {
+            for (va, vb) in a.iter().zip(b.iter()) {
+                let cmp = collate(va, vb);
+                if cmp != std::cmp::Ordering::Equal {
+                    return cmp;
+                }
+            }
+            id_a.cmp(id_b)
+        });
+        self.last_seq = changes.last_seq;
+        Ok(())
+    }
+
     /// Find doc IDs matching a simple equality/range selector on the indexed fields.
     pub fn find_matching(&self, selector: &serde_json::Value) -> Vec<String> {
         if self.def.fields.is_empty() {
@@ -134,48 +267,48 @@ impl BuiltIndex {
     }
 }
 
-/// Build an index from all documents in an adapter.
-pub async fn build_index(adapter: &dyn Adapter, def: &IndexDefinition) -> Result<BuiltIndex> {
-    let all = adapter
-        .all_docs(AllDocsOptions {
-            include_docs: true,
-            ..AllDocsOptions::new()
-        })
-        .await?;
-
-    let mut entries: Vec<(Vec<serde_json::Value>, String)> = Vec::new();
-
-    for row in &all.rows {
-        if let Some(ref doc_json) = row.doc {
-            let key: Vec<serde_json::Value> = def
-                .fields
+/// Whether every document matching `selector` also matches `filter`.
+///
+/// Conservative: each field condition of the filter has to appear in the
+/// selector, either verbatim or as a superset of its operators. Anything
+/// cleverer (e.g. `$gt: 5` implying `$gt: 3`) is not recognized.
+fn selector_implies(selector: &serde_json::Value, filter: &serde_json::Value) -> bool {
+    let selector = normalize_selector(selector);
+    let filter = normalize_selector(filter);
+    let (Some(selector), Some(filter)) = (selector.as_object(), filter.as_object()) else {
+        return false;
+    };
+    filter.iter().all(|(field, wanted)| {
+        let Some(have) = selector.get(field) else {
+            return false;
+        };
+        match (wanted.as_object(), have.as_object()) {
+            (Some(wanted_ops), Some(have_ops)) if !field.starts_with('$') => wanted_ops
                 .iter()
-                .map(|sf| {
-                    let (field, _) = sf.field_and_direction();
-                    get_nested_field(doc_json, field)
-                        .cloned()
-                        .unwrap_or(serde_json::Value::Null)
-                })
-                .collect();
-            entries.push((key, row.id.clone()));
+                .all(|(op, operand)| have_ops.get(op) == Some(operand)),
+            _ => wanted == have,
         }
-    }
+    })
+}
 
-    // Sort by composite key
-    entries.sort_by(|(a, _), (b, _)| {
-        for (va, vb) in a.iter().zip(b.iter()) {
-            let cmp = collate(va, vb);
-            if cmp != std::cmp::Ordering::Equal {
-                return cmp;
-            }
-        }
-        std::cmp::Ordering::Equal
-    });
+/// Build an index from all documents in an adapter.
+pub async fn build_index(adapter: &dyn Adapter, def: &IndexDefinition) -> Result<BuiltIndex> {
+    let mut index = BuiltIndex::new(def.clone());
+    index.update(adapter).await?;
+    Ok(index)
+}
 
-    Ok(BuiltIndex {
-        def: def.clone(),
-        entries,
-    })
+/// The composite key a document is stored under in an index.
+fn index_key(def: &IndexDefinition, doc: &serde_json::Value) -> Vec<serde_json::Value> {
+    def.fields
+        .iter()
+        .map(|sf| {
+            let (field, _) = sf.field_and_direction();
+            get_nested_field(doc, field)
+                .cloned()
+                .unwrap_or(serde_json::Value::Null)
+        })
+        .collect()
 }
 
 /// Options for a Mango find query.
@@ -195,36 +328,92 @@ pub struct FindOptions {
     /// Number of results to skip.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub skip: Option<u64>,
+    /// Name of the index to use. Falls back to index selection (and then
+    /// a full scan) if no index by that name can serve the selector.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub use_index: Option<String>,
+    /// Resume after the last document of a previous page, as returned in
+    /// `FindResponse::bookmark`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bookmark: Option<String>,
+    /// Sort in memory when no index covers `sort`, instead of failing with
+    /// `no_usable_index`. Only `Database::find` checks this; the free
+    /// [`find`] function always scans.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub allow_fallback: bool,
+}
+
+/// The bookmark of a first page that matched nothing. Passed back, it
+/// starts from the beginning, as on CouchDB.
+const NO_BOOKMARK: &str = "nil";
+
+impl FindOptions {
+    /// The bookmark to resume after, if any; `"nil"` counts as none.
+    pub fn resume_from(&self) -> Option<&str> {
+        self.bookmark.as_deref().filter(|b| *b != NO_BOOKMARK)
+    }
 }
 
 /// A single sort field with direction.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged)]
+///
+/// A sort is a list of these, compared in order, so
+/// `[{"age": "desc"}, {"name": "asc"}]` orders by age and breaks ties by
+/// name.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum SortField {
-    /// Simple field name (ascending).
+    /// Simple field name (ascending), serialized as `"field"`.
     Simple(String),
-    /// Field with direction: `{"field": "asc"}` or `{"field": "desc"}`.
-    WithDirection(HashMap<String, String>),
+    /// Field with direction, serialized as `{"field": "asc"}` or
+    /// `{"field": "desc"}`.
+    WithDirection(String, SortDirection),
 }
 
 impl SortField {
     pub fn field_and_direction(&self) -> (&str, SortDirection) {
         match self {
             SortField::Simple(f) => (f.as_str(), SortDirection::Asc),
-            SortField::WithDirection(map) => {
-                let (field, dir) = map.iter().next().unwrap();
-                let direction = if dir == "desc" {
-                    SortDirection::Desc
-                } else {
-                    SortDirection::Asc
-                };
-                (field.as_str(), direction)
+            SortField::WithDirection(f, direction) => (f.as_str(), *direction),
+        }
+    }
+}
+
+impl Serialize for SortField {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            SortField::Simple(field) => serializer.serialize_str(field),
+            SortField::WithDirection(field, direction) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry(field, direction)?;
+                map.end()
             }
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl<'de> Deserialize<'de> for SortField {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Simple(String),
+            WithDirection(BTreeMap<String, SortDirection>),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Simple(field) => Ok(SortField::Simple(field)),
+            Raw::WithDirection(map) if map.len() == 1 => {
+                let (field, direction) = map.into_iter().next().unwrap();
+                Ok(SortField::WithDirection(field, direction))
+            }
+            Raw::WithDirection(_) => Err(serde::de::Error::custom(
+                "each sort entry must name exactly one field",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SortDirection {
     Asc,
     Desc,
@@ -234,10 +423,89 @@ pub enum SortDirection {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FindResponse {
     pub docs: Vec<serde_json::Value>,
+    /// Opaque position after the last returned document. Pass it back as
+    /// `FindOptions::bookmark` to fetch the next page.
+    #[serde(default)]
+    pub bookmark: String,
+}
+
+/// Position of the last document on a find page: its sort key values and
+/// `_id`. Encoded as Base64 JSON so callers treat it as opaque.
+#[derive(Debug, Serialize, Deserialize)]
+struct Bookmark {
+    key: Vec<serde_json::Value>,
+    id: String,
+}
+
+impl Bookmark {
+    fn of(doc: &serde_json::Value, sort_fields: &[SortField]) -> Self {
+        let key = sort_fields
+            .iter()
+            .map(|sf| {
+                get_nested_field(doc, sf.field_and_direction().0)
+                    .cloned()
+                    .unwrap_or(serde_json::Value::Null)
+            })
+            .collect();
+        Bookmark {
+            key,
+            id: doc_id(doc).to_string(),
+        }
+    }
+
+    fn encode(&self) -> String {
+        use base64::Engine;
+        let json = serde_json::to_vec(self).expect("bookmark serializes");
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(bookmark: &str) -> Result<Self> {
+        use base64::Engine;
+        let invalid = || RouchError::BadRequest(format!("invalid bookmark: {}", bookmark));
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(bookmark)
+            .map_err(|_| invalid())?;
+        serde_json::from_slice(&json).map_err(|_| invalid())
+    }
+
+    /// Whether `doc` sorts after this position.
+    fn precedes(&self, doc: &serde_json::Value, sort_fields: &[SortField]) -> bool {
+        for (sf, value) in sort_fields.iter().zip(&self.key) {
+            let (field, direction) = sf.field_and_direction();
+            match compare_field(get_nested_field(doc, field), Some(value), direction) {
+                std::cmp::Ordering::Equal => continue,
+                cmp => return cmp == std::cmp::Ordering::Greater,
+            }
+        }
+        doc_id(doc) > self.id.as_str()
+    }
+}
+
+fn doc_id(doc: &serde_json::Value) -> &str {
+    doc.get("_id").and_then(|v| v.as_str()).unwrap_or("")
+}
+
+fn compare_field(
+    a: Option<&serde_json::Value>,
+    b: Option<&serde_json::Value>,
+    direction: SortDirection,
+) -> std::cmp::Ordering {
+    let null = serde_json::Value::Null;
+    let cmp = collate(a.unwrap_or(&null), b.unwrap_or(&null));
+    if direction == SortDirection::Desc {
+        cmp.reverse()
+    } else {
+        cmp
+    }
 }
 
 /// Execute a Mango find query against an adapter.
+///
+/// Large candidate sets are matched on tokio's blocking thread pool, so
+/// this needs a tokio runtime.
 pub async fn find(adapter: &dyn Adapter, opts: FindOptions) -> Result<FindResponse> {
+    validate_selector(&opts.selector)?;
+
     // Fetch all documents
     let all = adapter
         .all_docs(AllDocsOptions {
@@ -246,38 +514,73 @@ pub async fn find(adapter: &dyn Adapter, opts: FindOptions) -> Result<FindRespon
         })
         .await?;
 
-    let mut matched: Vec<serde_json::Value> = Vec::new();
+    let docs = all.rows.into_iter().filter_map(|row| row.doc).collect();
+    let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let matched = scan(docs, Arc::new(Matcher::new(&opts)?), workers).await?;
+    Ok(page(matched, &opts))
+}
 
-    for row in &all.rows {
-        if let Some(ref doc_json) = row.doc
-            && matches_selector(doc_json, &opts.selector)
-        {
-            matched.push(doc_json.clone());
-        }
+/// Run the selector, sort, bookmark, skip, limit and projection of a find
+/// query over an already-fetched set of candidate documents.
+///
+/// Results are ordered by the sort fields and then by `_id`, so every
+/// document has a stable position a bookmark can point at.
+pub fn find_in_docs(
+    docs: impl IntoIterator<Item = serde_json::Value>,
+    opts: &FindOptions,
+) -> Result<FindResponse> {
+    let matcher = Matcher::new(opts)?;
+    let matched = docs.into_iter().filter(|doc| matcher.keep(doc)).collect();
+    Ok(page(matched, opts))
+}
+
+/// The filtering half of a find query: the selector, with its `$regex`
+/// patterns compiled once, and the bookmark a page starts after.
+struct Matcher {
+    selector: serde_json::Value,
+    regexes: Regexes,
+    sort_fields: Vec<SortField>,
+    after: Option<Bookmark>,
+}
+
+impl Matcher {
+    fn new(opts: &FindOptions) -> Result<Self> {
+        Ok(Self {
+            selector: opts.selector.clone(),
+            regexes: regexes_in(&opts.selector),
+            sort_fields: opts.sort.clone().unwrap_or_default(),
+            after: opts.resume_from().map(Bookmark::decode).transpose()?,
+        })
     }
 
+    fn keep(&self, doc: &serde_json::Value) -> bool {
+        matches(doc, &self.selector, &self.regexes)
+            && self
+                .after
+                .as_ref()
+                .is_none_or(|b| b.precedes(doc, &self.sort_fields))
+    }
+}
+
+/// Sort, skip, limit and project the matched documents into a page.
+fn page(mut matched: Vec<serde_json::Value>, opts: &FindOptions) -> FindResponse {
+    let sort_fields = opts.sort.as_deref().unwrap_or_default();
+
     // Sort
-    if let Some(ref sort_fields) = opts.sort {
-        matched.sort_by(|a, b| {
-            for sf in sort_fields {
-                let (field, direction) = sf.field_and_direction();
-                let va = get_nested_field(a, field);
-                let vb = get_nested_field(b, field);
-                let va = va.unwrap_or(&serde_json::Value::Null);
-                let vb = vb.unwrap_or(&serde_json::Value::Null);
-                let cmp = collate(va, vb);
-                let cmp = if direction == SortDirection::Desc {
-                    cmp.reverse()
-                } else {
-                    cmp
-                };
-                if cmp != std::cmp::Ordering::Equal {
-                    return cmp;
-                }
+    matched.sort_by(|a, b| {
+        for sf in sort_fields {
+            let (field, direction) = sf.field_and_direction();
+            let cmp = compare_field(
+                get_nested_field(a, field),
+                get_nested_field(b, field),
+                direction,
+            );
+            if cmp != std::cmp::Ordering::Equal {
+                return cmp;
             }
-            std::cmp::Ordering::Equal
-        });
-    }
+        }
+        doc_id(a).cmp(doc_id(b))
+    });
 
     // Skip
     if let Some(skip) = opts.skip {
@@ -289,6 +592,12 @@ pub async fn find(adapter: &dyn Adapter, opts: FindOptions) -> Result<FindRespon
         matched.truncate(limit as usize);
     }
 
+    let bookmark = match (matched.last(), &opts.bookmark) {
+        (Some(last), _) => Bookmark::of(last, sort_fields).encode(),
+        (None, Some(previous)) => previous.clone(),
+        (None, None) => NO_BOOKMARK.to_string(),
+    };
+
     // Field projection
     if let Some(ref fields) = opts.fields {
         matched = matched
@@ -297,15 +606,93 @@ pub async fn find(adapter: &dyn Adapter, opts: FindOptions) -> Result<FindRespon
             .collect();
     }
 
-    Ok(FindResponse { docs: matched })
+    FindResponse {
+        docs: matched,
+        bookmark,
+    }
+}
+
+/// Candidate count from which `scan` splits the work across threads.
+const PARALLEL_SCAN_MIN_DOCS: usize = 4096;
+
+/// Keep the documents `matcher` accepts, in their original order.
+///
+/// Large candidate sets are split into one contiguous chunk per worker,
+/// each matched on the blocking thread pool, and the chunks' matches
+/// concatenated in order, so the result is the same as a sequential filter
+/// whatever the thread timing.
+async fn scan(
+    docs: Vec<serde_json::Value>,
+    matcher: Arc<Matcher>,
+    workers: usize,
+) -> Result<Vec<serde_json::Value>> {
+    if workers <= 1 || docs.len() < PARALLEL_SCAN_MIN_DOCS {
+        return Ok(docs.into_iter().filter(|doc| matcher.keep(doc)).collect());
+    }
+
+    let chunk_len = docs.len().div_ceil(workers);
+    let mut docs = docs.into_iter();
+    let mut tasks = Vec::with_capacity(workers);
+    loop {
+        let chunk: Vec<serde_json::Value> = docs.by_ref().take(chunk_len).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        let matcher = matcher.clone();
+        tasks.push(tokio::task::spawn_blocking(move || {
+            chunk
+                .into_iter()
+                .filter(|doc| matcher.keep(doc))
+                .collect::<Vec<_>>()
+        }));
+    }
+
+    let mut kept = Vec::new();
+    for task in tasks {
+        let chunk = task
+            .await
+            .map_err(|e| RouchError::DatabaseError(format!("find worker failed: {}", e)))?;
+        kept.extend(chunk);
+    }
+    Ok(kept)
+}
+
+/// Normalize a selector the way CouchDB's `_explain` reports it: implicit
+/// equality (`{"age": 30}`) becomes `{"age": {"$eq": 30}}`, recursively
+/// through `$and`, `$or`, `$nor` and `$not`.
+pub fn normalize_selector(selector: &serde_json::Value) -> serde_json::Value {
+    let serde_json::Value::Object(map) = selector else {
+        return selector.clone();
+    };
+    let normalized = map
+        .iter()
+        .map(|(key, condition)| {
+            let value = match (key.as_str(), condition) {
+                ("$and" | "$or" | "$nor", serde_json::Value::Array(subs)) => {
+                    serde_json::Value::Array(subs.iter().map(normalize_selector).collect())
+                }
+                ("$not", sub) => normalize_selector(sub),
+                (_, serde_json::Value::Object(_)) => condition.clone(),
+                (_, other) => serde_json::json!({ "$eq": other }),
+            };
+            (key.clone(), value)
+        })
+        .collect();
+    serde_json::Value::Object(normalized)
 }
 
 /// Check if a document matches a Mango selector.
 pub fn matches_selector(doc: &serde_json::Value, selector: &serde_json::Value) -> bool {
+    matches(doc, selector, &regexes_in(selector))
+}
+
+/// `matches_selector` with the selector's `$regex` patterns already
+/// compiled.
+fn matches(doc: &serde_json::Value, selector: &serde_json::Value, re: &Regexes) -> bool {
     match selector {
         serde_json::Value::Object(map) => {
             for (key, condition) in map {
-                if !match_condition(doc, key, condition) {
+                if !match_condition(doc, key, condition, re) {
                     return false;
                 }
             }
@@ -315,13 +702,18 @@ pub fn matches_selector(doc: &serde_json::Value, selector: &serde_json::Value) -
     }
 }
 
-fn match_condition(doc: &serde_json::Value, key: &str, condition: &serde_json::Value) -> bool {
+fn match_condition(
+    doc: &serde_json::Value,
+    key: &str,
+    condition: &serde_json::Value,
+    re: &Regexes,
+) -> bool {
     // Check for logical operators
     match key {
-        "$and" => return match_and(doc, condition),
-        "$or" => return match_or(doc, condition),
-        "$not" => return match_not(doc, condition),
-        "$nor" => return match_nor(doc, condition),
+        "$and" => return match_and(doc, condition, re),
+        "$or" => return match_or(doc, condition, re),
+        "$not" => return match_not(doc, condition, re),
+        "$nor" => return match_nor(doc, condition, re),
         _ => {}
     }
 
@@ -331,14 +723,14 @@ fn match_condition(doc: &serde_json::Value, key: &str, condition: &serde_json::V
         // Shorthand: {"field": value} means {"field": {"$eq": value}}
         serde_json::Value::Object(ops) => {
             for (op, operand) in ops {
-                if !match_operator(field_value, op, operand) {
+                if !match_operator(field_value, op, operand, re) {
                     return false;
                 }
             }
             true
         }
         // Implicit $eq
-        other => match_operator(field_value, "$eq", other),
+        other => match_operator(field_value, "$eq", other, re),
     }
 }
 
@@ -346,6 +738,7 @@ fn match_operator(
     field_value: Option<&serde_json::Value>,
     op: &str,
     operand: &serde_json::Value,
+    re: &Regexes,
 ) -> bool {
     match op {
         "$eq" => field_value.is_some_and(|v| collate(v, operand) == std::cmp::Ordering::Equal),
@@ -389,19 +782,14 @@ fn match_operator(
                 false
             }
         }
-        "$regex" => {
-            if let Some(pattern) = operand.as_str() {
-                field_value.is_some_and(|v| {
-                    if let Some(s) = v.as_str() {
-                        Regex::new(pattern).is_ok_and(|re| re.is_match(s))
-                    } else {
-                        false
-                    }
-                })
-            } else {
-                false
-            }
-        }
+        "$regex" => match (field_value.and_then(|v| v.as_str()), operand.as_str()) {
+            (Some(s), Some(pattern)) => re.get(pattern).is_some_and(|re| re.is_match(s)),
+            _ => false,
+        },
+        "$beginsWith" => match (field_value.and_then(|v| v.as_str()), operand.as_str()) {
+            (Some(s), Some(prefix)) => s.starts_with(prefix),
+            _ => false,
+        },
         "$size" => {
             if let Some(expected_size) = operand.as_u64() {
                 field_value.is_some_and(|v| {
@@ -430,70 +818,200 @@ fn match_operator(
         }
         "$elemMatch" => field_value.is_some_and(|v| {
             if let Some(arr) = v.as_array() {
-                arr.iter().any(|elem| matches_selector(elem, operand))
+                arr.iter().any(|elem| elem_matches(elem, operand, re))
             } else {
                 false
             }
         }),
+        // As in CouchDB, an empty array has no element to match
+        "$allMatch" => field_value
+            .and_then(|v| v.as_array())
+            .is_some_and(|arr| !arr.is_empty() && arr.iter().all(|e| elem_matches(e, operand, re))),
         "$not" => {
             // Field-level $not: negate the sub-condition applied to this field's value
             if let Some(ops) = operand.as_object() {
                 for (sub_op, sub_operand) in ops {
-                    if match_operator(field_value, sub_op, sub_operand) {
+                    if match_operator(field_value, sub_op, sub_operand, re) {
                         return false;
                     }
                 }
                 true
             } else {
                 // Implicit $eq negation
-                !match_operator(field_value, "$eq", operand)
+                !match_operator(field_value, "$eq", operand, re)
             }
         }
-        "$mod" => {
-            if let Some(arr) = operand.as_array() {
-                if arr.len() == 2 {
-                    let divisor = arr[0].as_i64();
-                    let remainder = arr[1].as_i64();
-                    if let (Some(d), Some(r)) = (divisor, remainder) {
-                        field_value
-                            .is_some_and(|v| v.as_i64().is_some_and(|n| d != 0 && n % d == r))
-                    } else {
-                        false
+        "$mod" => match (field_value.and_then(as_integer), mod_operands(operand)) {
+            (Some(n), Some((d, r))) => d != 0 && n % d == r,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Patterns compiled by `$regex`, so a selector that is matched again
+/// doesn't compile its patterns again. Cleared when it grows past
+/// `REGEX_CACHE_LIMIT` entries.
+static REGEX_CACHE: LazyLock<Mutex<HashMap<String, Regex>>> = LazyLock::new(Default::default);
+const REGEX_CACHE_LIMIT: usize = 256;
+
+/// A selector's `$regex` patterns, compiled. Invalid patterns are left
+/// out, so they match nothing.
+type Regexes = HashMap<String, Regex>;
+
+/// Compile every `$regex` pattern in `selector`.
+fn regexes_in(selector: &serde_json::Value) -> Regexes {
+    fn collect(value: &serde_json::Value, out: &mut Regexes) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    if key == "$regex"
+                        && let Some(pattern) = value.as_str()
+                        && !out.contains_key(pattern)
+                        && let Some(re) = compiled_regex(pattern)
+                    {
+                        out.insert(pattern.to_string(), re);
                     }
-                } else {
-                    false
+                    collect(value, out);
                 }
-            } else {
-                false
             }
+            serde_json::Value::Array(items) => items.iter().for_each(|v| collect(v, out)),
+            _ => {}
         }
-        _ => false,
     }
+    let mut out = Regexes::new();
+    collect(selector, &mut out);
+    out
+}
+
+/// Compile a `$regex` pattern, or `None` if it's invalid.
+///
+/// Inline flags such as `(?i)` (case-insensitive), `(?m)` (multi-line) and
+/// `(?s)` (`.` matches newlines) are honored, as in CouchDB.
+fn compiled_regex(pattern: &str) -> Option<Regex> {
+    let mut cache = REGEX_CACHE.lock().unwrap();
+    if let Some(re) = cache.get(pattern) {
+        return Some(re.clone());
+    }
+    let re = Regex::new(pattern).ok()?;
+    if cache.len() >= REGEX_CACHE_LIMIT {
+        cache.clear();
+    }
+    cache.insert(pattern.to_string(), re.clone());
+    Some(re)
 }
 
-fn match_and(doc: &serde_json::Value, condition: &serde_json::Value) -> bool {
+/// Coerce a number to an integer for `$mod`. Floats only count when they
+/// have no fractional part.
+fn as_integer(v: &serde_json::Value) -> Option<i64> {
+    v.as_i64().or_else(|| {
+        v.as_f64()
+            .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+            .map(|f| f as i64)
+    })
+}
+
+/// Parse the `[divisor, remainder]` operand of `$mod`.
+fn mod_operands(operand: &serde_json::Value) -> Option<(i64, i64)> {
+    match operand.as_array()?.as_slice() {
+        [d, r] => Some((d.as_i64()?, r.as_i64()?)),
+        _ => None,
+    }
+}
+
+/// Reject selectors that can't be evaluated, such as `$mod` with a zero
+/// divisor or an invalid `$regex`. `matches_selector` treats those as
+/// non-matching, so callers that can report errors should validate first.
+pub fn validate_selector(selector: &serde_json::Value) -> Result<()> {
+    match selector {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                if key == "$mod" {
+                    match mod_operands(value) {
+                        Some((0, _)) => {
+                            return Err(RouchError::BadRequest(
+                                "$mod divisor must not be zero".into(),
+                            ));
+                        }
+                        Some(_) => {}
+                        None => {
+                            return Err(RouchError::BadRequest(
+                                "$mod expects [divisor, remainder] integers".into(),
+                            ));
+                        }
+                    }
+                }
+                if key == "$regex" {
+                    let pattern = value.as_str().ok_or_else(|| {
+                        RouchError::BadRequest("$regex expects a string pattern".into())
+                    })?;
+                    if let Err(e) = Regex::new(pattern) {
+                        return Err(RouchError::BadRequest(format!("invalid $regex: {}", e)));
+                    }
+                }
+                if key == "$type" && !value.as_str().is_some_and(|t| JSON_TYPES.contains(&t)) {
+                    return Err(RouchError::BadRequest(format!(
+                        "$type expects one of {}, got {}",
+                        JSON_TYPES.join(", "),
+                        value
+                    )));
+                }
+                if key == "$beginsWith" && !value.is_string() {
+                    return Err(RouchError::BadRequest(
+                        "$beginsWith expects a string prefix".into(),
+                    ));
+                }
+                validate_selector(value)?;
+            }
+            Ok(())
+        }
+        serde_json::Value::Array(items) => items.iter().try_for_each(validate_selector),
+        _ => Ok(()),
+    }
+}
+
+/// Match one array element against an `$elemMatch`/`$allMatch` sub-selector.
+///
+/// A sub-selector made only of field operators (`{"$gte": 2}`) applies to
+/// the element itself, which is how arrays of scalars are matched; anything
+/// else is a full selector evaluated against the element as a document.
+fn elem_matches(elem: &serde_json::Value, selector: &serde_json::Value, re: &Regexes) -> bool {
+    if let Some(ops) = selector.as_object()
+        && !ops.is_empty()
+        && ops
+            .keys()
+            .all(|k| k.starts_with('$') && !matches!(k.as_str(), "$and" | "$or" | "$nor"))
+    {
+        return ops
+            .iter()
+            .all(|(op, operand)| match_operator(Some(elem), op, operand, re));
+    }
+    matches(elem, selector, re)
+}
+
+fn match_and(doc: &serde_json::Value, condition: &serde_json::Value, re: &Regexes) -> bool {
     if let Some(arr) = condition.as_array() {
-        arr.iter().all(|sub| matches_selector(doc, sub))
+        arr.iter().all(|sub| matches(doc, sub, re))
     } else {
         false
     }
 }
 
-fn match_or(doc: &serde_json::Value, condition: &serde_json::Value) -> bool {
+fn match_or(doc: &serde_json::Value, condition: &serde_json::Value, re: &Regexes) -> bool {
     if let Some(arr) = condition.as_array() {
-        arr.iter().any(|sub| matches_selector(doc, sub))
+        arr.iter().any(|sub| matches(doc, sub, re))
     } else {
         false
     }
 }
 
-fn match_not(doc: &serde_json::Value, condition: &serde_json::Value) -> bool {
-    !matches_selector(doc, condition)
+fn match_not(doc: &serde_json::Value, condition: &serde_json::Value, re: &Regexes) -> bool {
+    !matches(doc, condition, re)
 }
 
-fn match_nor(doc: &serde_json::Value, condition: &serde_json::Value) -> bool {
+fn match_nor(doc: &serde_json::Value, condition: &serde_json::Value, re: &Regexes) -> bool {
     if let Some(arr) = condition.as_array() {
-        !arr.iter().any(|sub| matches_selector(doc, sub))
+        !arr.iter().any(|sub| matches(doc, sub, re))
     } else {
         false
     }
@@ -511,6 +1029,9 @@ fn get_nested_field<'a>(doc: &'a serde_json::Value, path: &str) -> Option<&'a se
     Some(current)
 }
 
+/// The type names `$type` accepts.
+const JSON_TYPES: [&str; 6] = ["null", "boolean", "number", "string", "array", "object"];
+
 /// Return the CouchDB type name for a JSON value.
 fn json_type_name(value: &serde_json::Value) -> &'static str {
     match value {
@@ -524,18 +1045,21 @@ fn json_type_name(value: &serde_json::Value) -> &'static str {
 }
 
 /// Project a document to only include the specified fields.
-fn project(doc: serde_json::Value, fields: &[String]) -> serde_json::Value {
+///
+/// Dotted paths such as `address.city` keep their nesting in the result,
+/// so sibling fields under `address` are dropped. Missing paths are left
+/// out. `_id` is always included.
+pub fn project(doc: serde_json::Value, fields: &[String]) -> serde_json::Value {
     let mut result = serde_json::Map::new();
 
-    if let serde_json::Value::Object(map) = &doc {
+    if doc.is_object() {
         for field in fields {
-            // Always include _id and _rev
-            if let Some(val) = map.get(field) {
-                result.insert(field.clone(), val.clone());
+            if let Some(val) = get_nested_field(&doc, field) {
+                insert_path(&mut result, field, val.clone());
             }
         }
         // Always include _id
-        if let Some(id) = map.get("_id") {
+        if let Some(id) = doc.get("_id") {
             result
                 .entry("_id".to_string())
                 .or_insert_with(|| id.clone());
@@ -545,6 +1069,27 @@ fn project(doc: serde_json::Value, fields: &[String]) -> serde_json::Value {
     serde_json::Value::Object(result)
 }
 
+/// Insert `value` at a dotted `path`, creating intermediate objects.
+fn insert_path(
+    target: &mut serde_json::Map<String, serde_json::Value>,
+    path: &str,
+    value: serde_json::Value,
+) {
+    match path.split_once('.') {
+        None => {
+            target.insert(path.to_string(), value);
+        }
+        Some((head, rest)) => {
+            let child = target
+                .entry(head.to_string())
+                .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+            if let serde_json::Value::Object(child) = child {
+                insert_path(child, rest, value);
+            }
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Tests
 // ---------------------------------------------------------------------------
@@ -557,6 +1102,81 @@ mod tests {
         json
     }
 
+    #[tokio::test]
+    async fn parallel_scan_matches_sequential_order() {
+        let docs: Vec<serde_json::Value> = (0..10_000)
+            .map(|i| serde_json::json!({"_id": format!("doc{:05}", i), "n": i % 97, "tag": format!("t{}", i % 3)}))
+            .collect();
+        let selector = serde_json::json!({"n": {"$gt": 40}, "tag": {"$regex": "^t[02]$"}});
+        let opts = FindOptions {
+            selector: selector.clone(),
+            sort: Some(vec![SortField::Simple("n".into())]),
+            skip: Some(10),
+            limit: Some(50),
+            ..Default::default()
+        };
+        let matcher = Arc::new(Matcher::new(&opts).unwrap());
+        assert_eq!(matcher.regexes.len(), 1);
+
+        let sequential = scan(docs.clone(), matcher.clone(), 1).await.unwrap();
+        assert!(sequential.len() > 3000);
+        for workers in [2, 3, 8, 64] {
+            let parallel = scan(docs.clone(), matcher.clone(), workers).await.unwrap();
+            assert_eq!(parallel, sequential);
+        }
+
+        let found = find_in_docs(docs, &opts).unwrap();
+        let mut expected = sequential;
+        expected.sort_by(|a, b| collate(&a["n"], &b["n"]).then_with(|| doc_id(a).cmp(doc_id(b))));
+        assert_eq!(found.docs, expected[10..60]);
+    }
+
+    #[test]
+    fn multi_field_sort_breaks_ties_in_order() {
+        let sort: Vec<SortField> =
+            serde_json::from_value(serde_json::json!([{"age": "desc"}, {"name": "asc"}])).unwrap();
+        assert_eq!(
+            sort,
+            vec![
+                SortField::WithDirection("age".into(), SortDirection::Desc),
+                SortField::WithDirection("name".into(), SortDirection::Asc),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&sort).unwrap(),
+            serde_json::json!([{"age": "desc"}, {"name": "asc"}])
+        );
+
+        let docs = vec![
+            serde_json::json!({"_id": "1", "name": "Dan", "age": 30}),
+            serde_json::json!({"_id": "2", "name": "Bea", "age": 30}),
+            serde_json::json!({"_id": "3", "name": "Cid", "age": 40}),
+            serde_json::json!({"_id": "4", "name": "Abe", "age": 20}),
+        ];
+        let result = find_in_docs(
+            docs,
+            &FindOptions {
+                selector: serde_json::json!({}),
+                sort: Some(sort),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let names: Vec<&str> = result
+            .docs
+            .iter()
+            .map(|d| d["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Cid", "Bea", "Dan", "Abe"]);
+
+        for bad in [
+            serde_json::json!({"age": "desc", "name": "asc"}),
+            serde_json::json!({"age": "down"}),
+        ] {
+            assert!(serde_json::from_value::<SortField>(bad).is_err());
+        }
+    }
+
     // --- Basic matching ---
 
     #[test]
@@ -688,6 +1308,41 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn type_check_covers_every_json_type() {
+        let d = doc(serde_json::json!({
+            "null": null,
+            "boolean": false,
+            "number": 1.5,
+            "string": "s",
+            "array": [],
+            "object": {},
+        }));
+        for field in JSON_TYPES {
+            for type_name in JSON_TYPES {
+                let selector = serde_json::json!({field: {"$type": type_name}});
+                assert!(validate_selector(&selector).is_ok());
+                assert_eq!(
+                    matches_selector(&d, &selector),
+                    field == type_name,
+                    "{field} as {type_name}"
+                );
+            }
+        }
+        // A missing field has no type
+        assert!(!matches_selector(
+            &d,
+            &serde_json::json!({"missing": {"$type": "null"}})
+        ));
+
+        for bad in [serde_json::json!("integer"), serde_json::json!(1)] {
+            assert!(matches!(
+                validate_selector(&serde_json::json!({"n": {"$type": bad}})),
+                Err(RouchError::BadRequest(_))
+            ));
+        }
+    }
+
     #[test]
     fn regex_match() {
         let d = doc(serde_json::json!({"name": "Alice"}));
@@ -702,6 +1357,54 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn regex_inline_flags_and_invalid_patterns() {
+        let d = doc(serde_json::json!({"name": "ALICE", "bio": "line one\nline two"}));
+
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"name": {"$regex": "(?i)alice"}})
+        ));
+        assert!(!matches_selector(
+            &d,
+            &serde_json::json!({"name": {"$regex": "alice"}})
+        ));
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"bio": {"$regex": "(?m)^line two$"}})
+        ));
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"bio": {"$regex": "(?s)one.line"}})
+        ));
+        assert!(REGEX_CACHE.lock().unwrap().contains_key("(?i)alice"));
+
+        let invalid = serde_json::json!({"name": {"$regex": "(unclosed"}});
+        assert!(!matches_selector(&d, &invalid));
+        assert!(matches!(
+            validate_selector(&invalid),
+            Err(RouchError::BadRequest(_))
+        ));
+        assert!(validate_selector(&serde_json::json!({"name": {"$regex": 5}})).is_err());
+    }
+
+    #[test]
+    fn begins_with_matches_string_prefixes() {
+        let api = doc(serde_json::json!({"path": "/api/users"}));
+        let web = doc(serde_json::json!({"path": "/web/api"}));
+        let num = doc(serde_json::json!({"path": 42}));
+        let selector = serde_json::json!({"path": {"$beginsWith": "/api"}});
+
+        assert!(matches_selector(&api, &selector));
+        assert!(!matches_selector(&web, &selector));
+        assert!(!matches_selector(&num, &selector));
+        assert!(validate_selector(&selector).is_ok());
+        assert!(matches!(
+            validate_selector(&serde_json::json!({"path": {"$beginsWith": 1}})),
+            Err(RouchError::BadRequest(_))
+        ));
+    }
+
     #[test]
     fn size_operator() {
         let d = doc(serde_json::json!({"tags": ["a", "b", "c"]}));
@@ -749,6 +1452,81 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn elem_match_requires_a_single_element() {
+        let d = doc(serde_json::json!({
+            "items": [
+                {"sku": "a", "qty": 1},
+                {"sku": "b", "qty": 5}
+            ]
+        }));
+
+        // sku "a" and qty >= 2 hold on different elements: no match.
+        assert!(!matches_selector(
+            &d,
+            &serde_json::json!({"items": {"$elemMatch": {"sku": "a", "qty": {"$gte": 2}}}})
+        ));
+        // Both conditions hold on the second element.
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"items": {"$elemMatch": {"sku": "b", "qty": {"$gte": 2}}}})
+        ));
+        // Not an array.
+        assert!(!matches_selector(
+            &serde_json::json!({"items": {"sku": "b", "qty": 5}}),
+            &serde_json::json!({"items": {"$elemMatch": {"sku": "b"}}})
+        ));
+    }
+
+    #[test]
+    fn elem_match_scalar_array() {
+        let d = doc(serde_json::json!({"n": [1, 4, 9]}));
+
+        // 4 is alone in (3, 5); 1 < 5 and 9 > 3 individually don't count.
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"n": {"$elemMatch": {"$gt": 3, "$lt": 5}}})
+        ));
+        assert!(!matches_selector(
+            &d,
+            &serde_json::json!({"n": {"$elemMatch": {"$gt": 4, "$lt": 9}}})
+        ));
+    }
+
+    #[test]
+    fn all_match_requires_every_element() {
+        let selector = serde_json::json!({"grades": {"$allMatch": {"$gte": 80}}});
+
+        assert!(matches_selector(
+            &doc(serde_json::json!({"grades": [90, 85, 95]})),
+            &selector
+        ));
+        assert!(!matches_selector(
+            &doc(serde_json::json!({"grades": [90, 75, 95]})),
+            &selector
+        ));
+        // Not vacuously true: CouchDB needs at least one element
+        assert!(!matches_selector(
+            &doc(serde_json::json!({"grades": []})),
+            &selector
+        ));
+        assert!(!matches_selector(
+            &doc(serde_json::json!({"grades": 90})),
+            &selector
+        ));
+
+        // Sub-selectors over arrays of objects, and field-level $not
+        let d = doc(serde_json::json!({"items": [{"qty": 2}, {"qty": 5}]}));
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"items": {"$allMatch": {"qty": {"$gt": 1}}}})
+        ));
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"items": {"$not": {"$allMatch": {"qty": {"$gt": 2}}}}})
+        ));
+    }
+
     #[test]
     fn mod_operator() {
         let d = doc(serde_json::json!({"n": 10}));
@@ -763,6 +1541,44 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn mod_negative_and_non_numeric() {
+        // Remainder takes the sign of the dividend, as in CouchDB.
+        let d = doc(serde_json::json!({"n": -7, "f": 8.0, "g": 8.5, "s": "8"}));
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"n": {"$mod": [3, -1]}})
+        ));
+        assert!(!matches_selector(
+            &d,
+            &serde_json::json!({"n": {"$mod": [3, 2]}})
+        ));
+        assert!(matches_selector(
+            &d,
+            &serde_json::json!({"f": {"$mod": [4, 0]}})
+        ));
+        for field in ["g", "s", "missing"] {
+            assert!(!matches_selector(
+                &d,
+                &serde_json::json!({ field: {"$mod": [4, 0]} })
+            ));
+        }
+    }
+
+    #[test]
+    fn mod_zero_divisor_is_bad_request() {
+        let selector = serde_json::json!({"$or": [{"n": {"$mod": [0, 1]}}]});
+        assert!(matches!(
+            validate_selector(&selector),
+            Err(RouchError::BadRequest(_))
+        ));
+        assert!(!matches_selector(
+            &serde_json::json!({"n": 4}),
+            &serde_json::json!({"n": {"$mod": [0, 0]}})
+        ));
+        assert!(validate_selector(&serde_json::json!({"n": {"$mod": [4, 0]}})).is_ok());
+    }
+
     // --- Logical operators ---
 
     #[test]
@@ -881,6 +1697,37 @@ mod tests {
         assert!(projected.get("age").is_none());
     }
 
+    #[test]
+    fn project_nested_paths() {
+        let d = serde_json::json!({
+            "_id": "doc1",
+            "_rev": "1-abc",
+            "name": "Alice",
+            "address": {"city": "Paris", "zip": "75001", "geo": {"lat": 48.8, "lng": 2.3}},
+        });
+        let fields: Vec<String> = [
+            "name",
+            "_rev",
+            "address.city",
+            "address.geo.lat",
+            "address.street",
+        ]
+        .iter()
+        .map(|f| f.to_string())
+        .collect();
+        let projected = project(d, &fields);
+
+        assert_eq!(
+            projected,
+            serde_json::json!({
+                "_id": "doc1",
+                "_rev": "1-abc",
+                "name": "Alice",
+                "address": {"city": "Paris", "geo": {"lat": 48.8}},
+            })
+        );
+    }
+
     // --- Missing fields ---
 
     #[test]
@@ -901,4 +1748,43 @@ mod tests {
             &serde_json::json!({"age": {"$eq": 30}})
         ));
     }
+
+    // --- Explain helpers ---
+
+    #[test]
+    fn normalize_makes_eq_explicit() {
+        let normalized = normalize_selector(&serde_json::json!({
+            "name": "Alice",
+            "age": {"$gt": 20},
+            "$or": [{"city": "Paris"}, {"city": {"$eq": "Rome"}}]
+        }));
+        assert_eq!(
+            normalized,
+            serde_json::json!({
+                "name": {"$eq": "Alice"},
+                "age": {"$gt": 20},
+                "$or": [{"city": {"$eq": "Paris"}}, {"city": {"$eq": "Rome"}}]
+            })
+        );
+    }
+
+    #[test]
+    fn split_selector_leaves_residual() {
+        let index = BuiltIndex::new(IndexDefinition {
+            name: "idx-age".into(),
+            fields: vec![SortField::Simple("age".into())],
+            ddoc: None,
+            partial_filter_selector: None,
+        });
+        let selector = normalize_selector(&serde_json::json!({
+            "age": {"$gte": 20, "$ne": 25},
+            "name": "Alice"
+        }));
+        let (matched, residual) = index.split_selector(&selector);
+        assert_eq!(matched, vec!["age".to_string()]);
+        assert_eq!(
+            residual,
+            serde_json::json!({"age": {"$ne": 25}, "name": {"$eq": "Alice"}})
+        );
+    }
 }
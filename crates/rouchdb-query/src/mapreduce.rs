@@ -5,11 +5,14 @@
 //! key-value pairs, then optionally reduces them.
 
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
 
 use rouchdb_core::adapter::Adapter;
 use rouchdb_core::collation::collate;
-use rouchdb_core::document::AllDocsOptions;
-use rouchdb_core::error::Result;
+use rouchdb_core::document::{AllDocsOptions, ChangesOptions, GetOptions, Seq, ViewIndexUpdate};
+use rouchdb_core::error::{Result, RouchError};
 
 /// A key-value pair emitted by a map function.
 #[derive(Debug, Clone)]
@@ -19,6 +22,10 @@ pub struct EmittedRow {
     pub value: serde_json::Value,
 }
 
+/// Maximum number of rows passed to a single reduce call before the
+/// partial results are rereduced.
+pub const REDUCE_CHUNK_SIZE: usize = 1000;
+
 /// Built-in reduce functions matching CouchDB's built-ins.
 pub enum ReduceFn {
     /// Sum all numeric values.
@@ -27,17 +34,30 @@ pub enum ReduceFn {
     Count,
     /// Compute statistics (sum, count, min, max, sumsqr).
     Stats,
-    /// Custom reduce function.
+    /// Custom reduce function, called as `f(keys, values, rereduce)`.
+    ///
+    /// Rows are reduced in chunks of `REDUCE_CHUNK_SIZE`; when a group spans
+    /// several chunks the partial results are combined with `rereduce =
+    /// true`, in which case `values` are earlier outputs and `keys` is empty.
     #[allow(clippy::type_complexity)]
-    Custom(Box<dyn Fn(&[serde_json::Value], &[serde_json::Value], bool) -> serde_json::Value>),
+    Custom(
+        Box<
+            dyn Fn(&[serde_json::Value], &[serde_json::Value], bool) -> serde_json::Value
+                + Send
+                + Sync,
+        >,
+    ),
 }
 
 /// Options for querying a view.
 #[derive(Debug, Clone, Default)]
 pub struct ViewQueryOptions {
-    /// Only return rows with this exact key.
+    /// Only return rows with this exact key (collation equality).
+    /// Can't be combined with `keys`, `start_key` or `end_key`.
     pub key: Option<serde_json::Value>,
-    /// Return rows matching any of these keys, in the given order.
+    /// Return rows matching any of these keys, in the given order. A key
+    /// listed twice returns its rows twice. Can't be combined with `key`,
+    /// `start_key` or `end_key`.
     pub keys: Option<Vec<serde_json::Value>>,
     /// Start of key range (inclusive).
     pub start_key: Option<serde_json::Value>,
@@ -45,22 +65,69 @@ pub struct ViewQueryOptions {
     pub end_key: Option<serde_json::Value>,
     /// Whether to include the end_key in the range.
     pub inclusive_end: bool,
+    /// Doc ID to start from among rows whose key equals `start_key`.
+    /// Rows with equal keys are sorted by doc ID, so this resumes a page
+    /// in the middle of a run of equal keys. Ignored without `start_key`.
+    pub start_key_doc_id: Option<String>,
+    /// Doc ID to end at among rows whose key equals `end_key`. Whether
+    /// that row is included follows `inclusive_end`. Ignored without
+    /// `end_key`.
+    pub end_key_doc_id: Option<String>,
     /// Reverse the order.
     pub descending: bool,
-    /// Number of rows to skip.
+    /// Number of rows to skip. Applies to grouped rows when reducing.
     pub skip: u64,
-    /// Maximum number of rows.
+    /// Maximum number of rows. Applies to grouped rows when reducing.
     pub limit: Option<u64>,
-    /// Include the full document in each row.
+    /// Include the full document in each row. A row whose value is
+    /// `{"_id": ...}` gets that document instead of the one that emitted it.
+    /// Ignored when reducing.
     pub include_docs: bool,
-    /// Whether to run the reduce function.
+    /// Whether to run the reduce function. When `false`, any `ReduceFn`
+    /// passed alongside is ignored and the sorted map rows are returned.
     pub reduce: bool,
     /// Group by key (requires reduce).
     pub group: bool,
     /// Group to this many array elements of the key.
+    ///
+    /// Array keys are truncated before grouping, so `[2024, 1, 15]` rolls
+    /// up to `[2024]` at level 1. Scalar keys are grouped as with `group`,
+    /// and level 0 reduces everything into a single row keyed `null`.
     pub group_level: Option<u64>,
     /// Use stale index without rebuilding.
+    ///
+    /// Legacy alias for [`update`](Self::update): `Ok` behaves like
+    /// [`ViewUpdate::Never`] and `UpdateAfter` like [`ViewUpdate::Lazy`].
+    /// Ignored when `update` is set to anything other than `Eager`.
     pub stale: StaleOption,
+    /// How a persisted view index is brought up to date before querying.
+    pub update: ViewUpdate,
+    /// Keep the map output of [`query_view`] in a persisted index.
+    ///
+    /// Later queries only re-map documents changed since the index was
+    /// last updated. Adapters that don't store view indexes re-map every
+    /// document, as when this is `None`.
+    pub index: Option<ViewIndex>,
+}
+
+/// Identifies the persisted index a [`query_view`] call reads and updates.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewIndex {
+    /// Name the index is stored under.
+    pub name: String,
+    /// Identifies the map function. An index stored with a different
+    /// signature is discarded and every document is re-mapped, so change
+    /// it whenever the map function's output changes.
+    pub signature: String,
+}
+
+impl ViewIndex {
+    pub fn new(name: impl Into<String>, signature: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            signature: signature.into(),
+        }
+    }
 }
 
 /// Controls whether the index is rebuilt before querying.
@@ -75,6 +142,31 @@ pub enum StaleOption {
     UpdateAfter,
 }
 
+/// Consistency mode for persisted view indexes (CouchDB's `update=`).
+///
+/// Only meaningful for indexes that are kept between queries, such as the
+/// ones maintained by `ViewEngine` or by [`query_view`] with
+/// [`ViewQueryOptions::index`] set. Temporary views always read the current
+/// state of the database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ViewUpdate {
+    /// Catch the index up with the changes feed before querying (default).
+    ///
+    /// Results reflect every write committed before the query started.
+    #[default]
+    Eager,
+    /// Query the index as it is, then catch it up.
+    ///
+    /// Results may miss writes made since the previous update; the next
+    /// query sees them. Mirrors CouchDB's `update=lazy`.
+    Lazy,
+    /// Query the index as it is and leave it untouched.
+    ///
+    /// Results may be arbitrarily old (empty if the index was never built)
+    /// and no index work is done. Mirrors `update=false` / `stale=ok`.
+    Never,
+}
+
 impl ViewQueryOptions {
     pub fn new() -> Self {
         Self {
@@ -82,6 +174,15 @@ impl ViewQueryOptions {
             ..Default::default()
         }
     }
+
+    /// The effective update mode, taking the legacy `stale` option into account.
+    pub fn update_mode(&self) -> ViewUpdate {
+        match (self.update, &self.stale) {
+            (ViewUpdate::Eager, StaleOption::Ok) => ViewUpdate::Never,
+            (ViewUpdate::Eager, StaleOption::UpdateAfter) => ViewUpdate::Lazy,
+            (update, _) => update,
+        }
+    }
 }
 
 /// Result of querying a view.
@@ -104,12 +205,23 @@ pub struct ViewRow {
 /// Run a temporary (ad-hoc) map/reduce query.
 ///
 /// The `map_fn` receives a document JSON and returns emitted key-value pairs.
+/// With [`ViewQueryOptions::index`] set, the map output is kept in the
+/// adapter's view index store and only documents changed since the last
+/// query are re-mapped.
 pub async fn query_view(
     adapter: &dyn Adapter,
     map_fn: &dyn Fn(&serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)>,
     reduce_fn: Option<&ReduceFn>,
     opts: ViewQueryOptions,
 ) -> Result<ViewResult> {
+    if let Some(ref index) = opts.index {
+        let mut result = query_indexed_view(adapter, index, map_fn, reduce_fn, &opts).await?;
+        if opts.include_docs {
+            include_view_docs(adapter, &mut result).await?;
+        }
+        return Ok(result);
+    }
+
     // Run map over all documents
     let all = adapter
         .all_docs(AllDocsOptions {
@@ -133,6 +245,242 @@ pub async fn query_view(
         }
     }
 
+    let mut result = query_emitted(emitted, reduce_fn, &opts)?;
+    if opts.include_docs {
+        include_view_docs(adapter, &mut result).await?;
+    }
+    Ok(result)
+}
+
+/// Fill in `doc` on each map row of a view result.
+///
+/// Rows are looked up by the emitting document's ID, or by `_id` when the
+/// emitted value is an object carrying one (CouchDB's linked documents).
+/// Reduced rows and rows whose document no longer exists are left with
+/// `doc: None`.
+pub async fn include_view_docs(adapter: &dyn Adapter, result: &mut ViewResult) -> Result<()> {
+    for row in &mut result.rows {
+        let Some(ref row_id) = row.id else {
+            continue;
+        };
+        let id = row
+            .value
+            .get("_id")
+            .and_then(|v| v.as_str())
+            .unwrap_or(row_id);
+        row.doc = match adapter.get(id, GetOptions::default()).await {
+            Ok(doc) => Some(doc.to_json()),
+            Err(RouchError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+    }
+    Ok(())
+}
+
+/// A view's map output by document, kept up to date from the changes feed.
+///
+/// `rouchdb_views::ViewEngine` keeps these in memory. Indexed
+/// [`query_view`] calls persist them through
+/// [`Adapter::update_view_index`], with an empty `ddoc` and the
+/// [`ViewIndex`] name as `view_name`.
+#[derive(Debug, Clone, Default)]
+pub struct PersistentViewIndex {
+    pub ddoc: String,
+    pub view_name: String,
+    pub last_seq: Seq,
+    /// doc_id -> list of emitted (key, value) pairs.
+    pub entries: BTreeMap<String, Vec<(serde_json::Value, serde_json::Value)>>,
+}
+
+impl PersistentViewIndex {
+    pub fn new(ddoc: impl Into<String>, view_name: impl Into<String>) -> Self {
+        Self {
+            ddoc: ddoc.into(),
+            view_name: view_name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Re-map the documents changed since `last_seq`. Returns the IDs of
+    /// the documents whose entries were replaced or removed, none if the
+    /// feed hasn't moved.
+    pub async fn update<F>(&mut self, adapter: &dyn Adapter, map_fn: &F) -> Result<Vec<String>>
+    where
+        F: Fn(&serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)> + ?Sized,
+    {
+        let changes = adapter
+            .changes(ChangesOptions {
+                since: self.last_seq.clone(),
+                include_docs: true,
+                ..Default::default()
+            })
+            .await?;
+
+        let mut changed = Vec::with_capacity(changes.results.len());
+        for event in &changes.results {
+            self.entries.remove(&event.id);
+            changed.push(event.id.clone());
+            if event.deleted {
+                continue;
+            }
+            if let Some(ref doc) = event.doc {
+                let emitted = map_fn(doc);
+                if !emitted.is_empty() {
+                    self.entries.insert(event.id.clone(), emitted);
+                }
+            }
+        }
+
+        self.last_seq = changes.last_seq;
+        Ok(changed)
+    }
+
+    /// Every emitted row, in document ID order.
+    pub fn emitted(&self) -> Vec<EmittedRow> {
+        self.entries
+            .iter()
+            .flat_map(|(doc_id, pairs)| {
+                pairs.iter().map(move |(key, value)| EmittedRow {
+                    id: doc_id.clone(),
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// The header of an index stored by [`query_indexed_view`]. Indexes stored
+/// whole, with their entries beside these fields, are rejected and rebuilt.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IndexHeader {
+    signature: String,
+    last_seq: Seq,
+}
+
+/// A view index loaded for [`query_indexed_view`], and whether its stored
+/// rows must be dropped on the next write because they were built by
+/// another signature or couldn't be read.
+struct LoadedIndex {
+    view: PersistentViewIndex,
+    reset: bool,
+}
+
+impl LoadedIndex {
+    async fn load(adapter: &dyn Adapter, index: &ViewIndex) -> Result<Self> {
+        let fresh = || Self {
+            view: PersistentViewIndex::new("", index.name.clone()),
+            reset: true,
+        };
+        let Some(stored) = adapter.get_view_index(&index.name).await? else {
+            return Ok(fresh());
+        };
+        let Some(header) = serde_json::from_value::<IndexHeader>(stored.header)
+            .ok()
+            .filter(|header| header.signature == index.signature)
+        else {
+            return Ok(fresh());
+        };
+
+        let mut loaded = fresh();
+        for (doc_id, row) in stored.rows {
+            let Ok(pairs) = serde_json::from_value(row) else {
+                return Ok(fresh());
+            };
+            loaded.view.entries.insert(doc_id, pairs);
+        }
+        loaded.view.last_seq = header.last_seq;
+        loaded.reset = false;
+        Ok(loaded)
+    }
+
+    /// Catch the index up and write the rows that changed. Nothing is
+    /// written if the changes feed hasn't moved.
+    async fn update(
+        &mut self,
+        adapter: &dyn Adapter,
+        index: &ViewIndex,
+        map_fn: &dyn Fn(&serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)>,
+    ) -> Result<()> {
+        let changed = self.view.update(adapter, map_fn).await?;
+        if let Some(update) = self.write_for(index, changed)? {
+            adapter.update_view_index(&index.name, update).await?;
+        }
+        Ok(())
+    }
+
+    /// The write that stores the rows of `changed`, or `None` if there is
+    /// nothing to store.
+    fn write_for(
+        &mut self,
+        index: &ViewIndex,
+        changed: Vec<String>,
+    ) -> Result<Option<ViewIndexUpdate>> {
+        if changed.is_empty() && !self.reset {
+            return Ok(None);
+        }
+
+        let rows = changed
+            .into_iter()
+            .map(|doc_id| {
+                let row = self
+                    .view
+                    .entries
+                    .get(&doc_id)
+                    .map(serde_json::to_value)
+                    .transpose()?;
+                Ok((doc_id, row))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let header = IndexHeader {
+            signature: index.signature.clone(),
+            last_seq: self.view.last_seq.clone(),
+        };
+        let update = ViewIndexUpdate {
+            header: serde_json::to_value(header)?,
+            rows,
+            reset: std::mem::take(&mut self.reset),
+        };
+        Ok(Some(update))
+    }
+}
+
+async fn query_indexed_view(
+    adapter: &dyn Adapter,
+    index: &ViewIndex,
+    map_fn: &dyn Fn(&serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)>,
+    reduce_fn: Option<&ReduceFn>,
+    opts: &ViewQueryOptions,
+) -> Result<ViewResult> {
+    let mut loaded = LoadedIndex::load(adapter, index).await?;
+
+    let update = opts.update_mode();
+    if update == ViewUpdate::Eager {
+        loaded.update(adapter, index, map_fn).await?;
+    }
+
+    let result = query_emitted(loaded.view.emitted(), reduce_fn, opts)?;
+
+    if update == ViewUpdate::Lazy {
+        loaded.update(adapter, index, map_fn).await?;
+    }
+
+    Ok(result)
+}
+
+/// Apply view query options to rows that have already been emitted by a map
+/// function.
+///
+/// Sorts by key collation, filters by key/range, then either reduces or
+/// applies skip/limit. Shared by temporary views and persisted indexes.
+pub fn query_emitted(
+    mut emitted: Vec<EmittedRow>,
+    reduce_fn: Option<&ReduceFn>,
+    opts: &ViewQueryOptions,
+) -> Result<ViewResult> {
+    check_key_options(opts)?;
+
     // Sort by key using CouchDB collation
     emitted.sort_by(|a, b| {
         let cmp = collate(&a.key, &b.key);
@@ -159,7 +507,7 @@ pub async fn query_view(
         }
         ordered_rows
     } else {
-        filter_by_range(emitted, &opts)
+        filter_by_range(emitted, opts)
     };
 
     let total_rows = emitted.len() as u64;
@@ -169,11 +517,11 @@ pub async fn query_view(
         && let Some(reduce) = reduce_fn
     {
         let rows = if opts.group || opts.group_level.is_some() {
-            group_reduce(&emitted, reduce, opts.group_level)
+            group_reduce(&emitted, reduce, opts.group_level)?
         } else {
             let keys: Vec<serde_json::Value> = emitted.iter().map(|r| r.key.clone()).collect();
             let values: Vec<serde_json::Value> = emitted.iter().map(|r| r.value.clone()).collect();
-            let result = apply_reduce(reduce, &keys, &values, false);
+            let result = reduce_rows(reduce, &keys, &values)?;
             vec![ViewRow {
                 id: None,
                 key: serde_json::Value::Null,
@@ -182,8 +530,16 @@ pub async fn query_view(
             }]
         };
 
+        // Like CouchDB, skip/limit page through the reduced rows
+        let total_rows = rows.len() as u64;
+        let rows = rows
+            .into_iter()
+            .skip(opts.skip as usize)
+            .take(opts.limit.unwrap_or(u64::MAX) as usize)
+            .collect();
+
         return Ok(ViewResult {
-            total_rows: rows.len() as u64,
+            total_rows,
             offset: 0,
             rows,
         });
@@ -210,7 +566,33 @@ pub async fn query_view(
     })
 }
 
+/// `key` and `keys` select exact keys, so neither mixes with a range or
+/// with the other.
+fn check_key_options(opts: &ViewQueryOptions) -> Result<()> {
+    let ranged = opts.start_key.is_some() || opts.end_key.is_some();
+    if opts.keys.is_some() && (opts.key.is_some() || ranged) {
+        return Err(RouchError::BadRequest(
+            "`keys` is incompatible with `key`, `start_key` and `end_key`".into(),
+        ));
+    }
+    if opts.key.is_some() && ranged {
+        return Err(RouchError::BadRequest(
+            "`key` is incompatible with `start_key` and `end_key`".into(),
+        ));
+    }
+    Ok(())
+}
+
 fn filter_by_range(rows: Vec<EmittedRow>, opts: &ViewQueryOptions) -> Vec<EmittedRow> {
+    // Where a row sits relative to a (key, doc id) bound in ascending
+    // order. Without a doc id the bound covers every row with that key.
+    let position = |r: &EmittedRow, key: &serde_json::Value, doc_id: &Option<String>| {
+        collate(&r.key, key).then_with(|| {
+            doc_id
+                .as_deref()
+                .map_or(Ordering::Equal, |doc_id| r.id.as_str().cmp(doc_id))
+        })
+    };
     rows.into_iter()
         .filter(|r| {
             if let Some(ref key) = opts.key {
@@ -218,18 +600,19 @@ fn filter_by_range(rows: Vec<EmittedRow>, opts: &ViewQueryOptions) -> Vec<Emitte
             }
 
             if let Some(ref start) = opts.start_key {
+                let cmp = position(r, start, &opts.start_key_doc_id);
                 if opts.descending {
-                    if collate(&r.key, start) == Ordering::Greater {
+                    if cmp == Ordering::Greater {
                         return false;
                     }
-                } else if collate(&r.key, start) == Ordering::Less {
+                } else if cmp == Ordering::Less {
                     return false;
                 }
             }
 
             if let Some(ref end) = opts.end_key {
+                let cmp = position(r, end, &opts.end_key_doc_id);
                 if opts.descending {
-                    let cmp = collate(&r.key, end);
                     if opts.inclusive_end {
                         if cmp == Ordering::Less {
                             return false;
@@ -237,15 +620,12 @@ fn filter_by_range(rows: Vec<EmittedRow>, opts: &ViewQueryOptions) -> Vec<Emitte
                     } else if cmp != Ordering::Greater {
                         return false;
                     }
-                } else {
-                    let cmp = collate(&r.key, end);
-                    if opts.inclusive_end {
-                        if cmp == Ordering::Greater {
-                            return false;
-                        }
-                    } else if cmp != Ordering::Less {
+                } else if opts.inclusive_end {
+                    if cmp == Ordering::Greater {
                         return false;
                     }
+                } else if cmp != Ordering::Less {
+                    return false;
                 }
             }
 
@@ -254,9 +634,13 @@ fn filter_by_range(rows: Vec<EmittedRow>, opts: &ViewQueryOptions) -> Vec<Emitte
         .collect()
 }
 
-fn group_reduce(rows: &[EmittedRow], reduce: &ReduceFn, group_level: Option<u64>) -> Vec<ViewRow> {
+fn group_reduce(
+    rows: &[EmittedRow],
+    reduce: &ReduceFn,
+    group_level: Option<u64>,
+) -> Result<Vec<ViewRow>> {
     if rows.is_empty() {
-        return vec![];
+        return Ok(vec![]);
     }
 
     let mut result = Vec::new();
@@ -271,7 +655,7 @@ fn group_reduce(rows: &[EmittedRow], reduce: &ReduceFn, group_level: Option<u64>
             values.push(row.value.clone());
         } else {
             // Emit group
-            let reduced = apply_reduce(reduce, &keys, &values, false);
+            let reduced = reduce_rows(reduce, &keys, &values)?;
             result.push(ViewRow {
                 id: None,
                 key: current_key,
@@ -286,7 +670,7 @@ fn group_reduce(rows: &[EmittedRow], reduce: &ReduceFn, group_level: Option<u64>
     }
 
     // Emit last group
-    let reduced = apply_reduce(reduce, &keys, &values, false);
+    let reduced = reduce_rows(reduce, &keys, &values)?;
     result.push(ViewRow {
         id: None,
         key: current_key,
@@ -294,12 +678,13 @@ fn group_reduce(rows: &[EmittedRow], reduce: &ReduceFn, group_level: Option<u64>
         doc: None,
     });
 
-    result
+    Ok(result)
 }
 
 fn group_key(key: &serde_json::Value, group_level: Option<u64>) -> serde_json::Value {
     match group_level {
         None => key.clone(), // Full grouping
+        Some(0) => serde_json::Value::Null,
         Some(level) => {
             if let Some(arr) = key.as_array() {
                 let truncated: Vec<serde_json::Value> =
@@ -312,25 +697,80 @@ fn group_key(key: &serde_json::Value, group_level: Option<u64>) -> serde_json::V
     }
 }
 
+/// Reduce one group of rows, splitting it into chunks and rereducing the
+/// partial results so no single reduce call sees more than
+/// `REDUCE_CHUNK_SIZE` values.
+fn reduce_rows(
+    reduce: &ReduceFn,
+    keys: &[serde_json::Value],
+    values: &[serde_json::Value],
+) -> Result<serde_json::Value> {
+    if values.len() <= REDUCE_CHUNK_SIZE {
+        return apply_reduce(reduce, keys, values, false);
+    }
+    let mut partials = keys
+        .chunks(REDUCE_CHUNK_SIZE)
+        .zip(values.chunks(REDUCE_CHUNK_SIZE))
+        .map(|(k, v)| apply_reduce(reduce, k, v, false))
+        .collect::<Result<Vec<_>>>()?;
+    while partials.len() > REDUCE_CHUNK_SIZE {
+        partials = partials
+            .chunks(REDUCE_CHUNK_SIZE)
+            .map(|v| apply_reduce(reduce, &[], v, true))
+            .collect::<Result<Vec<_>>>()?;
+    }
+    apply_reduce(reduce, &[], &partials, true)
+}
+
 fn apply_reduce(
     reduce: &ReduceFn,
     keys: &[serde_json::Value],
     values: &[serde_json::Value],
     rereduce: bool,
-) -> serde_json::Value {
-    match reduce {
+) -> Result<serde_json::Value> {
+    let reduced = match reduce {
         ReduceFn::Sum => {
             let sum: f64 = values.iter().filter_map(|v| v.as_f64()).sum();
             serde_json::json!(sum)
         }
+        ReduceFn::Count if rereduce => {
+            let count: u64 = values.iter().filter_map(|v| v.as_u64()).sum();
+            serde_json::json!(count)
+        }
         ReduceFn::Count => {
             serde_json::json!(values.len())
         }
+        ReduceFn::Stats if rereduce => {
+            let stats: Vec<&serde_json::Value> = values
+                .iter()
+                .filter(|v| v["count"].as_u64().is_some_and(|c| c > 0))
+                .collect();
+            if stats.is_empty() {
+                return Ok(empty_stats());
+            }
+            let field = |name: &'static str| stats.iter().filter_map(move |v| v[name].as_f64());
+            serde_json::json!({
+                "sum": field("sum").sum::<f64>(),
+                "count": stats.iter().filter_map(|v| v["count"].as_u64()).sum::<u64>(),
+                "min": field("min").fold(f64::INFINITY, f64::min),
+                "max": field("max").fold(f64::NEG_INFINITY, f64::max),
+                "sumsqr": field("sumsqr").sum::<f64>()
+            })
+        }
         ReduceFn::Stats => {
-            let nums: Vec<f64> = values.iter().filter_map(|v| v.as_f64()).collect();
-            let count = nums.len();
-            if count == 0 {
-                return serde_json::json!({"sum": 0, "count": 0, "min": 0, "max": 0, "sumsqr": 0});
+            let nums = values
+                .iter()
+                .map(|v| {
+                    v.as_f64().ok_or_else(|| {
+                        RouchError::BadRequest(format!(
+                            "_stats reduce requires numeric values, got {}",
+                            v
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<f64>>>()?;
+            if nums.is_empty() {
+                return Ok(empty_stats());
             }
             let sum: f64 = nums.iter().sum();
             let min = nums.iter().copied().fold(f64::INFINITY, f64::min);
@@ -338,14 +778,19 @@ fn apply_reduce(
             let sumsqr: f64 = nums.iter().map(|n| n * n).sum();
             serde_json::json!({
                 "sum": sum,
-                "count": count,
+                "count": nums.len(),
                 "min": min,
                 "max": max,
                 "sumsqr": sumsqr
             })
         }
         ReduceFn::Custom(f) => f(keys, values, rereduce),
-    }
+    };
+    Ok(reduced)
+}
+
+fn empty_stats() -> serde_json::Value {
+    serde_json::json!({"sum": 0, "count": 0, "min": 0, "max": 0, "sumsqr": 0})
 }
 
 // ---------------------------------------------------------------------------
@@ -434,6 +879,97 @@ mod tests {
         assert_eq!(result.rows[0].key, "Bob");
     }
 
+    #[tokio::test]
+    async fn map_with_exact_array_key() {
+        let db = setup_db().await;
+        let map_fn = |doc: &serde_json::Value| {
+            vec![(
+                serde_json::json!([doc["city"], doc["age"]]),
+                serde_json::json!(1),
+            )]
+        };
+
+        let result = query_view(
+            &db,
+            &map_fn,
+            None,
+            ViewQueryOptions {
+                key: Some(serde_json::json!(["NYC", 35])),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.rows.len(), 1);
+        assert_eq!(result.rows[0].id.as_deref(), Some("charlie"));
+
+        // A prefix of the key is not an exact match
+        let result = query_view(
+            &db,
+            &map_fn,
+            None,
+            ViewQueryOptions {
+                key: Some(serde_json::json!(["NYC"])),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(result.rows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keys_return_rows_in_requested_order() {
+        let db = setup_db().await;
+        let map_fn = |doc: &serde_json::Value| {
+            let n = match doc["name"].as_str() {
+                Some("Alice") => 1,
+                Some("Bob") => 2,
+                _ => 3,
+            };
+            vec![(serde_json::json!(n), doc["name"].clone())]
+        };
+
+        let result = query_view(
+            &db,
+            &map_fn,
+            None,
+            ViewQueryOptions {
+                keys: Some(vec![
+                    serde_json::json!(3),
+                    serde_json::json!(1),
+                    serde_json::json!(3),
+                ]),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        let keys: Vec<_> = result.rows.iter().map(|r| r.key.clone()).collect();
+        assert_eq!(keys, vec![3, 1, 3]);
+
+        for opts in [
+            ViewQueryOptions {
+                keys: Some(vec![serde_json::json!(1)]),
+                start_key: Some(serde_json::json!(0)),
+                ..ViewQueryOptions::new()
+            },
+            ViewQueryOptions {
+                key: Some(serde_json::json!(1)),
+                keys: Some(vec![serde_json::json!(1)]),
+                ..ViewQueryOptions::new()
+            },
+            ViewQueryOptions {
+                key: Some(serde_json::json!(1)),
+                end_key: Some(serde_json::json!(2)),
+                ..ViewQueryOptions::new()
+            },
+        ] {
+            let result = query_view(&db, &map_fn, None, opts).await;
+            assert!(matches!(result, Err(RouchError::BadRequest(_))));
+        }
+    }
+
     #[tokio::test]
     async fn reduce_sum() {
         let db = setup_db().await;
@@ -558,6 +1094,150 @@ mod tests {
         assert_eq!(result.rows[1].key, "Bob");
     }
 
+    async fn numbered_db() -> MemoryAdapter {
+        let db = MemoryAdapter::new("numbers");
+        let docs = (0..10)
+            .map(|n| Document {
+                id: format!("doc{n}"),
+                rev: None,
+                deleted: false,
+                data: serde_json::json!({"n": n}),
+                attachments: HashMap::new(),
+            })
+            .collect();
+        db.bulk_docs(docs, BulkDocsOptions::new()).await.unwrap();
+        db
+    }
+
+    fn emit_n(doc: &serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)> {
+        vec![(doc["n"].clone(), serde_json::json!(1))]
+    }
+
+    fn row_keys(result: &ViewResult) -> Vec<serde_json::Value> {
+        result.rows.iter().map(|r| r.key.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn descending_skip_limit_over_numeric_keys() {
+        let db = numbered_db().await;
+        let query = |opts| query_view(&db, &emit_n, None, opts);
+
+        let result = query(ViewQueryOptions {
+            descending: true,
+            ..ViewQueryOptions::new()
+        })
+        .await
+        .unwrap();
+        assert_eq!(row_keys(&result), (0..10).rev().collect::<Vec<_>>());
+
+        let result = query(ViewQueryOptions {
+            skip: 2,
+            limit: Some(3),
+            ..ViewQueryOptions::new()
+        })
+        .await
+        .unwrap();
+        assert_eq!(row_keys(&result), vec![2, 3, 4]);
+        assert_eq!(result.total_rows, 10);
+        assert_eq!(result.offset, 2);
+
+        // Descending walks from start_key down to end_key
+        let result = query(ViewQueryOptions {
+            descending: true,
+            start_key: Some(serde_json::json!(7)),
+            end_key: Some(serde_json::json!(3)),
+            skip: 1,
+            ..ViewQueryOptions::new()
+        })
+        .await
+        .unwrap();
+        assert_eq!(row_keys(&result), vec![6, 5, 4, 3]);
+    }
+
+    #[tokio::test]
+    async fn reduce_false_returns_map_rows() {
+        let db = numbered_db().await;
+
+        let result = query_view(
+            &db,
+            &emit_n,
+            Some(&ReduceFn::Sum),
+            ViewQueryOptions {
+                reduce: false,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(row_keys(&result), (0..10).collect::<Vec<_>>());
+        assert!(result.rows.iter().all(|r| r.id.is_some() && r.value == 1));
+    }
+
+    #[tokio::test]
+    async fn skip_limit_page_grouped_rows() {
+        let db = numbered_db().await;
+
+        let result = query_view(
+            &db,
+            &emit_n,
+            Some(&ReduceFn::Count),
+            ViewQueryOptions {
+                reduce: true,
+                group: true,
+                descending: true,
+                skip: 2,
+                limit: Some(3),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(row_keys(&result), vec![7, 6, 5]);
+        assert!(result.rows.iter().all(|r| r.value == 1));
+    }
+
+    #[tokio::test]
+    async fn include_docs_attaches_emitting_and_linked_docs() {
+        let db = setup_db().await;
+
+        let result = query_view(
+            &db,
+            &|doc| vec![(doc["name"].clone(), serde_json::Value::Null)],
+            None,
+            ViewQueryOptions {
+                include_docs: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.rows.len(), 3);
+        for row in &result.rows {
+            let doc = row.doc.as_ref().unwrap();
+            assert_eq!(doc["_id"].as_str(), row.id.as_deref());
+            assert_eq!(doc["name"], row.key);
+        }
+
+        // Everyone links to alice
+        let result = query_view(
+            &db,
+            &|doc| vec![(doc["name"].clone(), serde_json::json!({"_id": "alice"}))],
+            None,
+            ViewQueryOptions {
+                include_docs: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(
+            result
+                .rows
+                .iter()
+                .all(|r| r.doc.as_ref().unwrap()["name"] == "Alice")
+        );
+    }
+
     #[tokio::test]
     async fn start_end_key_range() {
         let db = setup_db().await;
@@ -582,4 +1262,436 @@ mod tests {
         assert_eq!(result.rows[0].key, "Bob");
         assert_eq!(result.rows[1].key, "Charlie");
     }
+
+    #[tokio::test]
+    async fn doc_id_bounds_page_through_equal_keys() {
+        let db = setup_db().await;
+        let same_key =
+            |_: &serde_json::Value| vec![(serde_json::json!("k"), serde_json::Value::Null)];
+        let ids = |result: ViewResult| -> Vec<String> {
+            result.rows.into_iter().map(|r| r.id.unwrap()).collect()
+        };
+
+        let first = query_view(
+            &db,
+            &same_key,
+            None,
+            ViewQueryOptions {
+                limit: Some(2),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(first), ["alice", "bob"]);
+
+        // Resume after the last row of the page
+        let next = query_view(
+            &db,
+            &same_key,
+            None,
+            ViewQueryOptions {
+                start_key: Some(serde_json::json!("k")),
+                start_key_doc_id: Some("bob".into()),
+                skip: 1,
+                limit: Some(2),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(next), ["charlie"]);
+
+        let bounded = |descending, inclusive_end| ViewQueryOptions {
+            start_key: Some(serde_json::json!("k")),
+            end_key: Some(serde_json::json!("k")),
+            end_key_doc_id: Some("bob".into()),
+            descending,
+            inclusive_end,
+            ..ViewQueryOptions::new()
+        };
+        let up_to_bob = query_view(&db, &same_key, None, bounded(false, true)).await;
+        assert_eq!(ids(up_to_bob.unwrap()), ["alice", "bob"]);
+        let before_bob = query_view(&db, &same_key, None, bounded(false, false)).await;
+        assert_eq!(ids(before_bob.unwrap()), ["alice"]);
+        let down_to_bob = query_view(&db, &same_key, None, bounded(true, true)).await;
+        assert_eq!(ids(down_to_bob.unwrap()), ["charlie", "bob"]);
+    }
+
+    fn average_reducer() -> ReduceFn {
+        ReduceFn::Custom(Box::new(|_keys, values, rereduce| {
+            let (sum, count) = if rereduce {
+                values.iter().fold((0.0, 0.0), |(s, c), v| {
+                    (
+                        s + v["sum"].as_f64().unwrap(),
+                        c + v["count"].as_f64().unwrap(),
+                    )
+                })
+            } else {
+                let sum: f64 = values.iter().filter_map(|v| v.as_f64()).sum();
+                (sum, values.len() as f64)
+            };
+            serde_json::json!({"sum": sum, "count": count, "avg": sum / count})
+        }))
+    }
+
+    fn emitted(rows: Vec<(&str, serde_json::Value, serde_json::Value)>) -> Vec<EmittedRow> {
+        rows.into_iter()
+            .map(|(id, key, value)| EmittedRow {
+                id: id.into(),
+                key,
+                value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn custom_reduce_average_per_group() {
+        let rows = emitted(vec![
+            ("a", serde_json::json!("eng"), serde_json::json!(100)),
+            ("b", serde_json::json!("ops"), serde_json::json!(50)),
+            ("c", serde_json::json!("eng"), serde_json::json!(200)),
+        ]);
+        let result = query_emitted(
+            rows,
+            Some(&average_reducer()),
+            &ViewQueryOptions {
+                reduce: true,
+                group: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.rows[0].key, "eng");
+        assert_eq!(result.rows[0].value["avg"], 150.0);
+        assert_eq!(result.rows[1].key, "ops");
+        assert_eq!(result.rows[1].value["avg"], 50.0);
+    }
+
+    #[test]
+    fn large_groups_take_the_rereduce_path() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+        let n = REDUCE_CHUNK_SIZE * 2 + 500;
+        let rows = || {
+            (0..n)
+                .map(|i| EmittedRow {
+                    id: format!("d{i}"),
+                    key: serde_json::json!("dept"),
+                    value: serde_json::json!(i % 10),
+                })
+                .collect::<Vec<_>>()
+        };
+        let opts = ViewQueryOptions {
+            reduce: true,
+            group: true,
+            ..ViewQueryOptions::new()
+        };
+
+        let count = query_emitted(rows(), Some(&ReduceFn::Count), &opts).unwrap();
+        assert_eq!(count.rows[0].value, n);
+        let stats = query_emitted(rows(), Some(&ReduceFn::Stats), &opts).unwrap();
+        assert_eq!(stats.rows[0].value["count"], n);
+        assert_eq!(stats.rows[0].value["max"], 9.0);
+
+        let rereduces = Arc::new(AtomicUsize::new(0));
+        let largest = Arc::new(AtomicUsize::new(0));
+        let (r, l) = (rereduces.clone(), largest.clone());
+        let inner = average_reducer();
+        let tracking = ReduceFn::Custom(Box::new(move |keys, values, rereduce| {
+            if rereduce {
+                r.fetch_add(1, AtomicOrdering::SeqCst);
+            }
+            l.fetch_max(values.len(), AtomicOrdering::SeqCst);
+            apply_reduce(&inner, keys, values, rereduce).unwrap()
+        }));
+        let avg = query_emitted(rows(), Some(&tracking), &opts).unwrap();
+        assert_eq!(avg.rows[0].value["avg"], 4.5);
+        assert_eq!(rereduces.load(AtomicOrdering::SeqCst), 1);
+        assert!(largest.load(AtomicOrdering::SeqCst) <= REDUCE_CHUNK_SIZE);
+    }
+
+    #[test]
+    fn stats_grouped_and_ungrouped() {
+        let rows = || {
+            emitted(vec![
+                ("a", serde_json::json!("eng"), serde_json::json!(2)),
+                ("b", serde_json::json!("ops"), serde_json::json!(5)),
+                ("c", serde_json::json!("eng"), serde_json::json!(4)),
+            ])
+        };
+        let ungrouped = query_emitted(
+            rows(),
+            Some(&ReduceFn::Stats),
+            &ViewQueryOptions {
+                reduce: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            ungrouped.rows[0].value,
+            serde_json::json!({"sum": 11.0, "count": 3, "min": 2.0, "max": 5.0, "sumsqr": 45.0})
+        );
+
+        let grouped = query_emitted(
+            rows(),
+            Some(&ReduceFn::Stats),
+            &ViewQueryOptions {
+                reduce: true,
+                group: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .unwrap();
+        assert_eq!(grouped.rows.len(), 2);
+        assert_eq!(
+            grouped.rows[0].value,
+            serde_json::json!({"sum": 6.0, "count": 2, "min": 2.0, "max": 4.0, "sumsqr": 20.0})
+        );
+        assert_eq!(
+            grouped.rows[1].value,
+            serde_json::json!({"sum": 5.0, "count": 1, "min": 5.0, "max": 5.0, "sumsqr": 25.0})
+        );
+    }
+
+    #[test]
+    fn stats_rereduce_across_chunks() {
+        // Values 1..=n split over three chunks; the merged stats must match
+        // a single pass.
+        let n = REDUCE_CHUNK_SIZE * 2 + 1;
+        let rows = (1..=n)
+            .map(|i| EmittedRow {
+                id: format!("d{i}"),
+                key: serde_json::Value::Null,
+                value: serde_json::json!(i),
+            })
+            .collect();
+        let result = query_emitted(
+            rows,
+            Some(&ReduceFn::Stats),
+            &ViewQueryOptions {
+                reduce: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .unwrap();
+        let stats = &result.rows[0].value;
+        let n = n as f64;
+        assert_eq!(stats["count"], n as u64);
+        assert_eq!(stats["sum"], n * (n + 1.0) / 2.0);
+        assert_eq!(stats["min"], 1.0);
+        assert_eq!(stats["max"], n);
+        assert_eq!(stats["sumsqr"], n * (n + 1.0) * (2.0 * n + 1.0) / 6.0);
+    }
+
+    #[test]
+    fn stats_rejects_non_numeric_values() {
+        let rows = emitted(vec![
+            ("a", serde_json::json!("eng"), serde_json::json!(2)),
+            ("b", serde_json::json!("eng"), serde_json::json!("two")),
+        ]);
+        let result = query_emitted(
+            rows,
+            Some(&ReduceFn::Stats),
+            &ViewQueryOptions {
+                reduce: true,
+                ..ViewQueryOptions::new()
+            },
+        );
+        assert!(matches!(result, Err(RouchError::BadRequest(_))));
+    }
+
+    #[test]
+    fn group_level_rolls_up_date_keys() {
+        let rows = || {
+            emitted(vec![
+                (
+                    "a",
+                    serde_json::json!(["2024", "01", "15"]),
+                    serde_json::json!(1),
+                ),
+                (
+                    "b",
+                    serde_json::json!(["2024", "01", "20"]),
+                    serde_json::json!(2),
+                ),
+                (
+                    "c",
+                    serde_json::json!(["2024", "02", "03"]),
+                    serde_json::json!(3),
+                ),
+                (
+                    "d",
+                    serde_json::json!(["2025", "01", "01"]),
+                    serde_json::json!(4),
+                ),
+            ])
+        };
+        let at = |level| {
+            let result = query_emitted(
+                rows(),
+                Some(&ReduceFn::Sum),
+                &ViewQueryOptions {
+                    reduce: true,
+                    group_level: Some(level),
+                    ..ViewQueryOptions::new()
+                },
+            )
+            .unwrap();
+            result
+                .rows
+                .into_iter()
+                .map(|r| (r.key, r.value))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            at(1),
+            vec![
+                (serde_json::json!(["2024"]), serde_json::json!(6.0)),
+                (serde_json::json!(["2025"]), serde_json::json!(4.0)),
+            ]
+        );
+        assert_eq!(
+            at(2),
+            vec![
+                (serde_json::json!(["2024", "01"]), serde_json::json!(3.0)),
+                (serde_json::json!(["2024", "02"]), serde_json::json!(3.0)),
+                (serde_json::json!(["2025", "01"]), serde_json::json!(4.0)),
+            ]
+        );
+        assert_eq!(
+            at(0),
+            vec![(serde_json::Value::Null, serde_json::json!(10.0))]
+        );
+    }
+
+    #[test]
+    fn group_level_with_scalar_keys_groups_fully() {
+        let rows = emitted(vec![
+            ("a", serde_json::json!("x"), serde_json::json!(1)),
+            ("b", serde_json::json!("y"), serde_json::json!(2)),
+            ("c", serde_json::json!("x"), serde_json::json!(3)),
+        ]);
+        let result = query_emitted(
+            rows,
+            Some(&ReduceFn::Sum),
+            &ViewQueryOptions {
+                reduce: true,
+                group_level: Some(2),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .unwrap();
+        assert_eq!(result.rows.len(), 2);
+        assert_eq!(result.rows[0].key, "x");
+        assert_eq!(result.rows[0].value, 4.0);
+    }
+
+    #[test]
+    fn view_keys_follow_couchdb_collation() {
+        // Emitted in scrambled order; every JSON type is represented.
+        let keys = vec![
+            serde_json::json!({"b": 1}),
+            serde_json::json!("b"),
+            serde_json::json!([1, "a"]),
+            serde_json::json!(true),
+            serde_json::json!(2.5),
+            serde_json::json!(null),
+            serde_json::json!({"a": 1}),
+            serde_json::json!([1]),
+            serde_json::json!(-3),
+            serde_json::json!("a"),
+            serde_json::json!(false),
+        ];
+        let rows = || {
+            keys.iter()
+                .enumerate()
+                .map(|(i, k)| EmittedRow {
+                    id: format!("d{i}"),
+                    key: k.clone(),
+                    value: serde_json::Value::Null,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let all = query_emitted(rows(), None, &ViewQueryOptions::new()).unwrap();
+        let sorted: Vec<serde_json::Value> = all.rows.into_iter().map(|r| r.key).collect();
+        assert_eq!(
+            sorted,
+            vec![
+                serde_json::json!(null),
+                serde_json::json!(false),
+                serde_json::json!(true),
+                serde_json::json!(-3),
+                serde_json::json!(2.5),
+                serde_json::json!("a"),
+                serde_json::json!("b"),
+                serde_json::json!([1]),
+                serde_json::json!([1, "a"]),
+                serde_json::json!({"a": 1}),
+                serde_json::json!({"b": 1}),
+            ]
+        );
+
+        // Ranges cross type boundaries in collation order: every number and
+        // string, but no booleans or arrays.
+        let range = query_emitted(
+            rows(),
+            None,
+            &ViewQueryOptions {
+                start_key: Some(serde_json::json!(f64::MIN)),
+                end_key: Some(serde_json::json!("\u{fff0}")),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .unwrap();
+        let in_range: Vec<serde_json::Value> = range.rows.into_iter().map(|r| r.key).collect();
+        assert_eq!(
+            in_range,
+            vec![
+                serde_json::json!(-3),
+                serde_json::json!(2.5),
+                serde_json::json!("a"),
+                serde_json::json!("b"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn indexed_views_write_only_changed_rows() {
+        let db = setup_db().await;
+        let index = ViewIndex::new("by_city", "v1");
+        let map_fn = |doc: &serde_json::Value| vec![(doc["city"].clone(), serde_json::json!(1))];
+
+        // A missing index is written whole, dropping anything stored before
+        let mut loaded = LoadedIndex::load(&db, &index).await.unwrap();
+        let changed = loaded.view.update(&db, &map_fn).await.unwrap();
+        let first = loaded.write_for(&index, changed).unwrap().unwrap();
+        assert!(first.reset);
+        assert_eq!(first.rows.len(), 3);
+
+        // Nothing moved, nothing to write
+        let changed = loaded.view.update(&db, &map_fn).await.unwrap();
+        assert!(loaded.write_for(&index, changed).unwrap().is_none());
+
+        // Only the deleted document's row goes out
+        let bob = db.get("bob", GetOptions::default()).await.unwrap();
+        db.bulk_docs(
+            vec![Document {
+                deleted: true,
+                ..bob
+            }],
+            BulkDocsOptions::new(),
+        )
+        .await
+        .unwrap();
+        let changed = loaded.view.update(&db, &map_fn).await.unwrap();
+        let next = loaded.write_for(&index, changed).unwrap().unwrap();
+        assert!(!next.reset);
+        assert_eq!(next.rows, vec![("bob".to_string(), None)]);
+    }
 }
@@ -5,6 +5,9 @@ use rouchdb_core::adapter::Adapter;
 use rouchdb_core::document::Seq;
 use rouchdb_core::error::Result;
 
+/// Number of past sessions kept in a checkpoint's `history`.
+const MAX_CHECKPOINT_HISTORY: usize = 50;
+
 /// A checkpoint document stored as `_local/{replication_id}`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CheckpointDoc {
@@ -61,12 +64,9 @@ impl Checkpointer {
         target: &dyn Adapter,
         last_seq: Seq,
     ) -> Result<()> {
-        let doc = self.build_checkpoint_doc(last_seq);
-        let json = serde_json::to_value(&doc)?;
-
         // Write to both sides — fail if either side fails to keep them in sync
-        let source_result = source.put_local(&self.replication_id, json.clone()).await;
-        let target_result = target.put_local(&self.replication_id, json).await;
+        let source_result = self.write_to(source, &last_seq).await;
+        let target_result = self.write_to(target, &last_seq).await;
 
         match (source_result, target_result) {
             (Ok(()), Ok(())) => Ok(()),
@@ -80,16 +80,42 @@ impl Checkpointer {
         Ok(doc)
     }
 
-    fn build_checkpoint_doc(&self, last_seq: Seq) -> CheckpointDoc {
-        CheckpointDoc {
+    /// Write this session's checkpoint to one side, keeping the history of
+    /// earlier sessions so the two sides can still find a common point if
+    /// a previous write only reached one of them.
+    async fn write_to(&self, adapter: &dyn Adapter, last_seq: &Seq) -> Result<()> {
+        let previous = self.read_from(adapter).await.ok();
+        let doc = self.build_checkpoint_doc(last_seq.clone(), previous);
+        adapter
+            .put_local(&self.replication_id, serde_json::to_value(&doc)?)
+            .await
+    }
+
+    fn build_checkpoint_doc(
+        &self,
+        last_seq: Seq,
+        previous: Option<CheckpointDoc>,
+    ) -> CheckpointDoc {
+        let mut history = vec![CheckpointHistory {
             last_seq: last_seq.clone(),
             session_id: self.session_id.clone(),
+        }];
+        if let Some(previous) = previous {
+            history.extend(
+                previous
+                    .history
+                    .into_iter()
+                    .filter(|h| h.session_id != self.session_id),
+            );
+        }
+        history.truncate(MAX_CHECKPOINT_HISTORY);
+
+        CheckpointDoc {
+            last_seq,
+            session_id: self.session_id.clone(),
             version: 1,
             replicator: "rouchdb".into(),
-            history: vec![CheckpointHistory {
-                last_seq,
-                session_id: self.session_id.clone(),
-            }],
+            history,
         }
     }
 }
@@ -199,6 +225,22 @@ mod tests {
         assert_eq!(compare_checkpoints(&source, &target).as_num(), 30);
     }
 
+    #[test]
+    fn checkpoint_history_keeps_earlier_sessions() {
+        let first = Checkpointer::new("a", "b");
+        let doc = first.build_checkpoint_doc(Seq::Num(5), None);
+        let doc = first.build_checkpoint_doc(Seq::Num(8), Some(doc));
+        assert_eq!(doc.history.len(), 1);
+
+        let second = Checkpointer::new("a", "b");
+        let doc = second.build_checkpoint_doc(Seq::Num(12), Some(doc));
+        assert_eq!(doc.session_id, second.session_id);
+        assert_eq!(doc.history.len(), 2);
+        assert_eq!(doc.history[0].last_seq.as_num(), 12);
+        assert_eq!(doc.history[1].session_id, first.session_id);
+        assert_eq!(doc.history[1].last_seq.as_num(), 8);
+    }
+
     #[test]
     fn compare_no_common_session() {
         let source = CheckpointDoc {
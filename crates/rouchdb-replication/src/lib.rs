@@ -13,6 +13,6 @@ mod protocol;
 
 pub use checkpoint::Checkpointer;
 pub use protocol::{
-    ReplicationEvent, ReplicationFilter, ReplicationHandle, ReplicationOptions, ReplicationResult,
-    replicate, replicate_live, replicate_with_events,
+    ConflictHook, ReplicationEvent, ReplicationFilter, ReplicationHandle, ReplicationOptions,
+    ReplicationProgress, ReplicationResult, replicate, replicate_live, replicate_with_events,
 };
@@ -4,10 +4,12 @@ use std::time::Duration;
 
 use rouchdb_core::adapter::Adapter;
 use rouchdb_core::document::*;
-use rouchdb_core::error::Result;
+use rouchdb_core::error::{Result, RouchError};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
+use rouchdb_changes::{ChangesStreamOptions, LiveChangesStream};
+
 use crate::checkpoint::Checkpointer;
 
 /// Filter for selective replication.
@@ -33,6 +35,10 @@ impl Clone for ReplicationFilter {
     }
 }
 
+/// Conflict resolver for `ReplicationOptions::on_conflict`: receives the
+/// document ID and its leaves (winner first) and returns the resolved doc.
+pub type ConflictHook = dyn Fn(&str, &[Document]) -> Option<Document> + Send + Sync;
+
 /// Replication configuration.
 pub struct ReplicationOptions {
     /// Number of documents to process per batch.
@@ -45,15 +51,29 @@ pub struct ReplicationOptions {
     pub live: bool,
     /// Automatically retry on transient errors.
     pub retry: bool,
+    /// Retries per request before a transient error is returned (default: 5).
+    pub max_retries: u32,
     /// Polling interval for live replication (default: 500ms).
     pub poll_interval: Duration,
     /// Backoff function for retry: takes attempt number, returns delay.
     pub back_off_function: Option<Box<dyn Fn(u32) -> Duration + Send + Sync>>,
-    /// Override the starting sequence (skip checkpoint lookup).
+    /// Override the starting sequence (skip checkpoint lookup), e.g. the
+    /// `last_seq` of an earlier run.
     pub since: Option<Seq>,
     /// Whether to save/read checkpoints (default: true).
     /// Set to false to always replicate from scratch.
     pub checkpoint: bool,
+    /// Called after each batch with the running totals.
+    pub on_progress: Option<Arc<dyn Fn(ReplicationProgress) + Send + Sync>>,
+    /// Called for each written document left in conflict on the target,
+    /// with the winner first followed by the conflicting leaves. Returning
+    /// `Some(doc)` writes it on top of the winner and deletes the others.
+    pub on_conflict: Option<Arc<ConflictHook>>,
+    /// Work out what would be replicated without touching the target:
+    /// changes are diffed and fetched as usual and `docs_written` counts
+    /// the documents that would be written, but nothing is written and no
+    /// checkpoint is saved. Live replication ignores it.
+    pub dry_run: bool,
 }
 
 impl Default for ReplicationOptions {
@@ -64,10 +84,14 @@ impl Default for ReplicationOptions {
             filter: None,
             live: false,
             retry: false,
+            max_retries: 5,
             poll_interval: Duration::from_millis(500),
             back_off_function: None,
             since: None,
             checkpoint: true,
+            on_progress: None,
+            on_conflict: None,
+            dry_run: false,
         }
     }
 }
@@ -79,6 +103,19 @@ pub struct ReplicationResult {
     pub docs_read: u64,
     pub docs_written: u64,
     pub errors: Vec<String>,
+    /// Source sequence the replication got to. Pass it back as
+    /// `ReplicationOptions::since` to resume from here.
+    pub last_seq: Seq,
+}
+
+/// Running totals passed to `ReplicationOptions::on_progress` after each batch.
+#[derive(Debug, Clone)]
+pub struct ReplicationProgress {
+    /// Changes read from the source so far.
+    pub docs_read: u64,
+    /// Documents written to the target so far.
+    pub docs_written: u64,
+    /// Source sequence the replication has checkpointed up to.
     pub last_seq: Seq,
 }
 
@@ -105,6 +142,38 @@ pub async fn replicate(
     source: &dyn Adapter,
     target: &dyn Adapter,
     opts: ReplicationOptions,
+) -> Result<ReplicationResult> {
+    run_batches(source, target, &opts, None).await
+}
+
+/// Run a one-shot replication with event streaming.
+///
+/// Same as `replicate()` but emits `ReplicationEvent` through the provided
+/// channel as replication progresses.
+pub async fn replicate_with_events(
+    source: &dyn Adapter,
+    target: &dyn Adapter,
+    opts: ReplicationOptions,
+    events_tx: mpsc::Sender<ReplicationEvent>,
+) -> Result<ReplicationResult> {
+    let _ = events_tx.send(ReplicationEvent::Active).await;
+
+    let result = run_batches(source, target, &opts, Some(&events_tx)).await?;
+
+    let _ = events_tx
+        .send(ReplicationEvent::Complete(result.clone()))
+        .await;
+
+    Ok(result)
+}
+
+/// The batch loop shared by one-shot and live replication. Emits a
+/// `Change` event after each batch when `events_tx` is set.
+async fn run_batches(
+    source: &dyn Adapter,
+    target: &dyn Adapter,
+    opts: &ReplicationOptions,
+    events_tx: Option<&mpsc::Sender<ReplicationEvent>>,
 ) -> Result<ReplicationResult> {
     let source_info = source.info().await?;
     let target_info = target.info().await?;
@@ -121,10 +190,7 @@ pub async fn replicate(
     };
 
     // Extract doc_ids from filter (for ChangesOptions)
-    let filter_doc_ids = match &opts.filter {
-        Some(ReplicationFilter::DocIds(ids)) => Some(ids.clone()),
-        _ => None,
-    };
+    let filter_doc_ids = filter_doc_ids(opts);
 
     let mut total_docs_read = 0u64;
     let mut total_docs_written = 0u64;
@@ -133,15 +199,19 @@ pub async fn replicate(
 
     loop {
         // Step 2: Fetch changes from source
-        let changes = source
-            .changes(ChangesOptions {
+        let changes = with_retry(opts, || {
+            source.changes(ChangesOptions {
                 since: current_seq.clone(),
                 limit: Some(opts.batch_size),
                 include_docs: false,
+                // Every leaf, so conflicting branches and their deletions
+                // replicate too
+                style: ChangesStyle::AllDocs,
                 doc_ids: filter_doc_ids.clone(),
                 ..Default::default()
             })
-            .await?;
+        })
+        .await?;
 
         if changes.results.is_empty() {
             break; // No more changes
@@ -161,6 +231,7 @@ pub async fn replicate(
 
         if filtered_changes.is_empty() {
             current_seq = batch_last_seq;
+            report_progress(opts, total_docs_read, total_docs_written, &current_seq);
             if (changes.results.len() as u64) < opts.batch_size {
                 break;
             }
@@ -174,11 +245,12 @@ pub async fn replicate(
             rev_map.insert(change.id.clone(), revs);
         }
 
-        let diff = target.revs_diff(rev_map).await?;
+        let diff = with_retry(opts, || target.revs_diff(rev_map.clone())).await?;
 
         if diff.results.is_empty() {
             // Target already has everything in this batch
             current_seq = batch_last_seq;
+            report_progress(opts, total_docs_read, total_docs_written, &current_seq);
             if (changes.results.len() as u64) < opts.batch_size {
                 break;
             }
@@ -196,7 +268,8 @@ pub async fn replicate(
             }
         }
 
-        let bulk_get_response = source.bulk_get(bulk_get_items).await?;
+        let bulk_get_response =
+            with_retry(opts, || source.bulk_get(bulk_get_items.clone())).await?;
 
         // Step 5: Write to target with new_edits=false
         let mut docs_to_write: Vec<Document> = Vec::new();
@@ -216,11 +289,31 @@ pub async fn replicate(
             docs_to_write.retain(|doc| rouchdb_query::matches_selector(&doc.data, selector));
         }
 
-        if !docs_to_write.is_empty() {
+        // Step 4.6: Fetch the bytes of attachments the target lacks
+        let mut with_attachments = Vec::with_capacity(docs_to_write.len());
+        for mut doc in docs_to_write {
+            if opts.dry_run {
+                with_attachments.push(doc);
+                continue;
+            }
+            match fetch_missing_attachments(source, target, &mut doc, opts).await {
+                Ok(()) => with_attachments.push(doc),
+                Err(e) => errors.push(format!("attachments for {}: {}", doc.id, e)),
+            }
+        }
+        let docs_to_write = with_attachments;
+
+        if opts.dry_run {
+            // Count what would be written and leave the target alone
+            total_docs_written += docs_to_write.len() as u64;
+        } else if !docs_to_write.is_empty() {
             let write_count = docs_to_write.len() as u64;
-            let write_results = target
-                .bulk_docs(docs_to_write, BulkDocsOptions::replication())
-                .await?;
+            let mut written_ids: Vec<String> = docs_to_write.iter().map(|d| d.id.clone()).collect();
+            written_ids.dedup();
+            let write_results = with_retry(opts, || {
+                target.bulk_docs(docs_to_write.clone(), BulkDocsOptions::replication())
+            })
+            .await?;
 
             for wr in &write_results {
                 if !wr.ok {
@@ -233,15 +326,33 @@ pub async fn replicate(
             }
 
             total_docs_written += write_count;
+
+            if let Some(ref on_conflict) = opts.on_conflict {
+                for id in &written_ids {
+                    if let Err(e) = resolve_conflicts(target, id, on_conflict.as_ref()).await {
+                        errors.push(format!("conflict resolution for {}: {}", id, e));
+                    }
+                }
+            }
+        }
+
+        // Emit change event
+        if let Some(tx) = events_tx {
+            let _ = tx
+                .send(ReplicationEvent::Change {
+                    docs_read: total_docs_read,
+                })
+                .await;
         }
 
         // Step 6: Save checkpoint (if enabled)
         current_seq = batch_last_seq;
-        if opts.checkpoint {
+        if opts.checkpoint && !opts.dry_run {
             let _ = checkpointer
                 .write_checkpoint(source, target, current_seq.clone())
                 .await;
         }
+        report_progress(opts, total_docs_read, total_docs_written, &current_seq);
 
         // Check if we got fewer results than batch_size (last batch)
         if (changes.results.len() as u64) < opts.batch_size {
@@ -258,179 +369,179 @@ pub async fn replicate(
     })
 }
 
-/// Run a one-shot replication with event streaming.
-///
-/// Same as `replicate()` but emits `ReplicationEvent` through the provided
-/// channel as replication progresses.
-pub async fn replicate_with_events(
-    source: &dyn Adapter,
+/// Offer a conflicted document to the `on_conflict` hook and write its
+/// choice: the resolved body on top of the winner, plus a tombstone on
+/// every losing leaf.
+async fn resolve_conflicts(
     target: &dyn Adapter,
-    opts: ReplicationOptions,
-    events_tx: mpsc::Sender<ReplicationEvent>,
-) -> Result<ReplicationResult> {
-    let source_info = source.info().await?;
-    let target_info = target.info().await?;
-
-    let checkpointer = Checkpointer::new(&source_info.db_name, &target_info.db_name);
-
-    let since = if let Some(ref override_since) = opts.since {
-        override_since.clone()
-    } else if opts.checkpoint {
-        checkpointer.read_checkpoint(source, target).await?
-    } else {
-        Seq::default()
+    id: &str,
+    on_conflict: &ConflictHook,
+) -> Result<()> {
+    let mut winner = match target
+        .get(
+            id,
+            GetOptions {
+                conflicts: true,
+                ..Default::default()
+            },
+        )
+        .await
+    {
+        Ok(doc) => doc,
+        // A deleted winner means every leaf is deleted
+        Err(RouchError::NotFound(_)) => return Ok(()),
+        Err(e) => return Err(e),
     };
 
-    let filter_doc_ids = match &opts.filter {
-        Some(ReplicationFilter::DocIds(ids)) => Some(ids.clone()),
-        _ => None,
+    let conflicts: Vec<String> = match winner
+        .data
+        .as_object_mut()
+        .and_then(|m| m.remove("_conflicts"))
+    {
+        Some(serde_json::Value::Array(revs)) => revs
+            .iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect(),
+        _ => return Ok(()),
     };
 
-    let mut total_docs_read = 0u64;
-    let mut total_docs_written = 0u64;
-    let mut errors = Vec::new();
-    let mut current_seq = since;
-
-    let _ = events_tx.send(ReplicationEvent::Active).await;
-
-    loop {
-        let changes = source
-            .changes(ChangesOptions {
-                since: current_seq.clone(),
-                limit: Some(opts.batch_size),
-                include_docs: false,
-                doc_ids: filter_doc_ids.clone(),
-                ..Default::default()
-            })
-            .await?;
-
-        if changes.results.is_empty() {
-            break;
-        }
-
-        let batch_last_seq = changes.last_seq;
-
-        let filtered_changes: Vec<&ChangeEvent> = match &opts.filter {
-            Some(ReplicationFilter::Custom(predicate)) => {
-                changes.results.iter().filter(|c| predicate(c)).collect()
-            }
-            _ => changes.results.iter().collect(),
-        };
-
-        total_docs_read += filtered_changes.len() as u64;
+    let mut leaves = vec![winner];
+    for rev in conflicts {
+        leaves.push(
+            target
+                .get(
+                    id,
+                    GetOptions {
+                        rev: Some(rev),
+                        ..Default::default()
+                    },
+                )
+                .await?,
+        );
+    }
 
-        if filtered_changes.is_empty() {
-            current_seq = batch_last_seq;
-            if (changes.results.len() as u64) < opts.batch_size {
-                break;
-            }
-            continue;
-        }
+    let Some(mut resolved) = on_conflict(id, &leaves) else {
+        return Ok(());
+    };
+    resolved.id = id.to_string();
+    resolved.rev = leaves[0].rev.clone();
+
+    let mut writes = vec![resolved];
+    for loser in &leaves[1..] {
+        writes.push(Document {
+            id: id.to_string(),
+            rev: loser.rev.clone(),
+            deleted: true,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        });
+    }
 
-        let mut rev_map: HashMap<String, Vec<String>> = HashMap::new();
-        for change in &filtered_changes {
-            let revs: Vec<String> = change.changes.iter().map(|c| c.rev.clone()).collect();
-            rev_map.insert(change.id.clone(), revs);
+    for result in target.bulk_docs(writes, BulkDocsOptions::new()).await? {
+        if !result.ok {
+            return Err(RouchError::Conflict);
         }
+    }
+    Ok(())
+}
 
-        let diff = target.revs_diff(rev_map).await?;
-
-        if diff.results.is_empty() {
-            current_seq = batch_last_seq;
-            if (changes.results.len() as u64) < opts.batch_size {
-                break;
-            }
+/// Inline the bytes of `doc`'s attachment stubs so the target can store
+/// them. Stubs whose digest matches the attachment of the same name on the
+/// target's current revision are left as stubs: the target already has
+/// those bytes.
+async fn fetch_missing_attachments(
+    source: &dyn Adapter,
+    target: &dyn Adapter,
+    doc: &mut Document,
+    opts: &ReplicationOptions,
+) -> Result<()> {
+    if doc.deleted || doc.attachments.values().all(|att| att.data.is_some()) {
+        return Ok(());
+    }
+    let existing = match target.get(&doc.id, GetOptions::default()).await {
+        Ok(current) => current.attachments,
+        Err(RouchError::NotFound(_)) => HashMap::new(),
+        Err(e) => return Err(e),
+    };
+    let rev = doc.rev.as_ref().map(|r| r.to_string());
+    for (name, att) in doc.attachments.iter_mut() {
+        let on_target = existing
+            .get(name)
+            .is_some_and(|e| !e.digest.is_empty() && e.digest == att.digest);
+        if att.data.is_some() || on_target {
             continue;
         }
+        let data = with_retry(opts, || {
+            let opts = GetAttachmentOptions {
+                rev: rev.clone(),
+                ..Default::default()
+            };
+            source.get_attachment(&doc.id, name, opts)
+        })
+        .await?;
+        att.data = Some(data);
+        att.stub = false;
+    }
+    Ok(())
+}
 
-        let mut bulk_get_items: Vec<BulkGetItem> = Vec::new();
-        for (doc_id, diff_result) in &diff.results {
-            for missing_rev in &diff_result.missing {
-                bulk_get_items.push(BulkGetItem {
-                    id: doc_id.clone(),
-                    rev: Some(missing_rev.clone()),
-                });
-            }
-        }
-
-        let bulk_get_response = source.bulk_get(bulk_get_items).await?;
-
-        let mut docs_to_write: Vec<Document> = Vec::new();
-        for result in &bulk_get_response.results {
-            for doc in &result.docs {
-                if let Some(ref json) = doc.ok {
-                    match Document::from_json(json.clone()) {
-                        Ok(document) => docs_to_write.push(document),
-                        Err(e) => errors.push(format!("parse error for {}: {}", result.id, e)),
-                    }
-                }
-            }
-        }
-
-        if let Some(ReplicationFilter::Selector(ref selector)) = opts.filter {
-            docs_to_write.retain(|doc| rouchdb_query::matches_selector(&doc.data, selector));
-        }
-
-        if !docs_to_write.is_empty() {
-            let write_count = docs_to_write.len() as u64;
-            let write_results = target
-                .bulk_docs(docs_to_write, BulkDocsOptions::replication())
-                .await?;
-
-            for wr in &write_results {
-                if !wr.ok {
-                    errors.push(format!(
-                        "write error for {}: {}",
-                        wr.id,
-                        wr.reason.as_deref().unwrap_or("unknown")
-                    ));
-                }
+/// Run one replication request, retrying retriable errors when
+/// `opts.retry` is set. Waits `back_off_function(attempt)` between tries,
+/// or a jittered exponential delay when no function is given.
+async fn with_retry<T, F, Fut>(opts: &ReplicationOptions, mut request: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut attempt: u32 = 0;
+    loop {
+        match request().await {
+            Err(e) if opts.retry && e.is_retriable() && attempt < opts.max_retries => {
+                attempt += 1;
+                let delay = match opts.back_off_function {
+                    Some(ref f) => f(attempt),
+                    None => jittered_backoff(attempt),
+                };
+                tokio::time::sleep(delay).await;
             }
-
-            total_docs_written += write_count;
-        }
-
-        // Emit change event
-        let _ = events_tx
-            .send(ReplicationEvent::Change {
-                docs_read: total_docs_read,
-            })
-            .await;
-
-        current_seq = batch_last_seq;
-        if opts.checkpoint {
-            let _ = checkpointer
-                .write_checkpoint(source, target, current_seq.clone())
-                .await;
-        }
-
-        if (changes.results.len() as u64) < opts.batch_size {
-            break;
+            other => return other,
         }
     }
+}
 
-    let result = ReplicationResult {
-        ok: errors.is_empty(),
-        docs_read: total_docs_read,
-        docs_written: total_docs_written,
-        errors,
-        last_seq: current_seq,
-    };
+/// `min(100ms * 2^attempt, 10s)`, scaled by a random factor in [0.5, 1).
+fn jittered_backoff(attempt: u32) -> Duration {
+    let base = Duration::from_millis(100 << attempt.min(7)).min(Duration::from_secs(10));
+    let jitter = (uuid::Uuid::new_v4().as_u128() % 500) as u32 + 500;
+    base * jitter / 1000
+}
 
-    let _ = events_tx
-        .send(ReplicationEvent::Complete(result.clone()))
-        .await;
+fn report_progress(opts: &ReplicationOptions, docs_read: u64, docs_written: u64, seq: &Seq) {
+    if let Some(ref on_progress) = opts.on_progress {
+        on_progress(ReplicationProgress {
+            docs_read,
+            docs_written,
+            last_seq: seq.clone(),
+        });
+    }
+}
 
-    Ok(result)
+fn filter_doc_ids(opts: &ReplicationOptions) -> Option<Vec<String>> {
+    match &opts.filter {
+        Some(ReplicationFilter::DocIds(ids)) => Some(ids.clone()),
+        _ => None,
+    }
 }
 
 /// Run continuous (live) replication from source to target.
 ///
-/// Performs an initial one-shot replication, then polls for new changes
-/// at the configured `poll_interval`. Runs until the returned
-/// `ReplicationHandle` is cancelled/dropped.
+/// Performs an initial one-shot replication, then tails the source with a
+/// `LiveChangesStream` and runs another pass whenever a change arrives.
+/// Runs until the returned `ReplicationHandle` is cancelled/dropped.
 ///
-/// Events are emitted through the returned channel receiver.
+/// Events are emitted through the returned channel receiver: `Active`
+/// when a pass starts, `Change` per batch, `Complete` per pass, and
+/// `Paused` once caught up with the source.
 pub fn replicate_live(
     source: Arc<dyn Adapter>,
     target: Arc<dyn Adapter>,
@@ -439,7 +550,17 @@ pub fn replicate_live(
     let (tx, rx) = mpsc::channel(64);
     let poll_interval = opts.poll_interval;
     let retry = opts.retry;
-    let back_off = opts.back_off_function;
+    let max_retries = opts.max_retries;
+    let doc_ids = filter_doc_ids(&opts);
+    // Shared between the passes, which retry single requests, and the
+    // loop below, which retries failed passes
+    let back_off: Option<Arc<dyn Fn(u32) -> Duration + Send + Sync>> =
+        opts.back_off_function.map(Arc::from);
+    let (batch_size, batches_limit, checkpoint) =
+        (opts.batch_size, opts.batches_limit, opts.checkpoint);
+    let filter = opts.filter;
+    let on_progress = opts.on_progress;
+    let on_conflict = opts.on_conflict;
 
     let cancel = CancellationToken::new();
     let cancel_clone = cancel.clone();
@@ -450,29 +571,38 @@ pub fn replicate_live(
         loop {
             // Clone the filter for each iteration so Selector and Custom
             // filters remain active across the entire live replication.
-            let one_shot_opts = ReplicationOptions {
-                batch_size: opts.batch_size,
-                batches_limit: opts.batches_limit,
-                filter: opts.filter.clone(),
+            let pass_opts = ReplicationOptions {
+                batch_size,
+                batches_limit,
+                filter: filter.clone(),
                 live: false,
-                retry: false,
+                retry,
+                max_retries,
                 poll_interval,
-                back_off_function: None,
+                back_off_function: back_off.clone().map(|f| {
+                    Box::new(move |attempt| f(attempt))
+                        as Box<dyn Fn(u32) -> Duration + Send + Sync>
+                }),
                 since: None,
-                checkpoint: opts.checkpoint,
+                checkpoint,
+                on_progress: on_progress.clone(),
+                on_conflict: on_conflict.clone(),
+                dry_run: false,
             };
 
-            let result =
-                replicate_with_events(source.as_ref(), target.as_ref(), one_shot_opts, tx.clone())
-                    .await;
+            let _ = tx.send(ReplicationEvent::Active).await;
+            let result = tokio::select! {
+                r = run_batches(source.as_ref(), target.as_ref(), &pass_opts, Some(&tx)) => r,
+                _ = cancel_clone.cancelled() => break,
+            };
 
-            match result {
+            let caught_up_at = match result {
                 Ok(r) => {
                     attempt = 0; // Reset retry counter on success
-                    if r.docs_read == 0 {
-                        // No changes — emit Paused and wait
-                        let _ = tx.send(ReplicationEvent::Paused).await;
-                    }
+                    let last_seq = r.last_seq.clone();
+                    let _ = tx.send(ReplicationEvent::Complete(r)).await;
+                    let _ = tx.send(ReplicationEvent::Paused).await;
+                    last_seq
                 }
                 Err(e) => {
                     let _ = tx.send(ReplicationEvent::Error(e.to_string())).await;
@@ -493,11 +623,26 @@ pub fn replicate_live(
                         break;
                     }
                 }
-            }
+            };
 
-            // Wait for poll_interval or cancellation
+            // Idle until the source changes past what was just replicated
+            let mut changes = LiveChangesStream::new(
+                source.clone(),
+                None,
+                ChangesStreamOptions {
+                    since: caught_up_at,
+                    live: true,
+                    doc_ids: doc_ids.clone(),
+                    poll_interval,
+                    ..Default::default()
+                },
+            );
             tokio::select! {
-                _ = tokio::time::sleep(poll_interval) => {},
+                change = changes.next_change() => {
+                    if change.is_none() {
+                        break;
+                    }
+                }
                 _ = cancel_clone.cancelled() => break,
             }
         }
@@ -561,6 +706,75 @@ mod tests {
         assert_eq!(result.docs_written, 0);
     }
 
+    #[tokio::test]
+    async fn replicate_carries_attachment_bytes() {
+        let source = MemoryAdapter::new("source");
+        let target = MemoryAdapter::new("target");
+
+        put_doc(&source, "doc1", serde_json::json!({"name": "Alice"})).await;
+        let rev = source.get("doc1", GetOptions::default()).await.unwrap().rev;
+        let bytes: Vec<u8> = (0..1024).map(|i| (i % 251) as u8).collect();
+        source
+            .put_attachment(
+                "doc1",
+                "blob.bin",
+                &rev.unwrap().to_string(),
+                bytes.clone(),
+                "application/octet-stream",
+            )
+            .await
+            .unwrap();
+
+        let result = replicate(&source, &target, ReplicationOptions::default())
+            .await
+            .unwrap();
+        assert!(result.ok, "{:?}", result.errors);
+        let copied = target
+            .get_attachment("doc1", "blob.bin", GetAttachmentOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(copied, bytes);
+
+        // A stub the target already holds is not fetched again: the empty
+        // source here has no bytes to give
+        let mut doc = target.get("doc1", GetOptions::default()).await.unwrap();
+        let empty = MemoryAdapter::new("empty");
+        fetch_missing_attachments(&empty, &target, &mut doc, &ReplicationOptions::default())
+            .await
+            .unwrap();
+        assert!(doc.attachments["blob.bin"].data.is_none());
+    }
+
+    #[tokio::test]
+    async fn dry_run_counts_without_writing() {
+        let source = MemoryAdapter::new("source");
+        let target = MemoryAdapter::new("target");
+
+        put_doc(&source, "doc1", serde_json::json!({"n": 1})).await;
+        put_doc(&source, "doc2", serde_json::json!({"n": 2})).await;
+        put_doc(&source, "doc3", serde_json::json!({"n": 3})).await;
+
+        let dry_run = || ReplicationOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let result = replicate(&source, &target, dry_run()).await.unwrap();
+        assert!(result.ok);
+        assert_eq!(result.docs_read, 3);
+        assert_eq!(result.docs_written, 3);
+        assert_eq!(target.info().await.unwrap().doc_count, 0);
+
+        // No checkpoint was saved, so a second dry run sees the same work
+        let again = replicate(&source, &target, dry_run()).await.unwrap();
+        assert_eq!(again.docs_written, 3);
+
+        let real = replicate(&source, &target, ReplicationOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(real.docs_written, 3);
+        assert_eq!(target.info().await.unwrap().doc_count, 3);
+    }
+
     #[tokio::test]
     async fn replicate_source_to_target() {
         let source = MemoryAdapter::new("source");
@@ -667,6 +881,48 @@ mod tests {
         assert_eq!(target_info.doc_count, 15);
     }
 
+    #[tokio::test]
+    async fn replicate_reports_progress_per_batch() {
+        let source = MemoryAdapter::new("source");
+        let target = MemoryAdapter::new("target");
+
+        for i in 0..25 {
+            put_doc(
+                &source,
+                &format!("doc{:03}", i),
+                serde_json::json!({"i": i}),
+            )
+            .await;
+        }
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        replicate(
+            &source,
+            &target,
+            ReplicationOptions {
+                batch_size: 10,
+                on_progress: Some(Arc::new(move |p: ReplicationProgress| {
+                    sink.lock().unwrap().push(p);
+                })),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert!(seen.len() >= 3);
+        for pair in seen.windows(2) {
+            assert!(pair[1].docs_read > pair[0].docs_read);
+            assert!(pair[1].docs_written > pair[0].docs_written);
+            assert!(pair[1].last_seq > pair[0].last_seq);
+        }
+        let last = seen.last().unwrap();
+        assert_eq!(last.docs_read, 25);
+        assert_eq!(last.docs_written, 25);
+    }
+
     #[tokio::test]
     async fn replicate_with_deletes() {
         let source = MemoryAdapter::new("source");
@@ -19,6 +19,8 @@ pub struct AllDocsQuery {
     pub skip: Option<u64>,
     pub descending: Option<bool>,
     pub inclusive_end: Option<bool>,
+    pub startkey_docid: Option<String>,
+    pub endkey_docid: Option<String>,
     pub conflicts: Option<bool>,
     pub update_seq: Option<bool>,
 }
@@ -35,8 +37,11 @@ impl AllDocsQuery {
             skip: self.skip.unwrap_or(0),
             descending: self.descending.unwrap_or(false),
             inclusive_end: self.inclusive_end.unwrap_or(true),
+            start_key_doc_id: self.startkey_docid,
+            end_key_doc_id: self.endkey_docid,
             conflicts: self.conflicts.unwrap_or(false),
             update_seq: self.update_seq.unwrap_or(false),
+            include_deleted: false,
         }
     }
 }
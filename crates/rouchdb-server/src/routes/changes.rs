@@ -75,6 +75,7 @@ pub async fn get_changes(
         selector: None,
         conflicts: query.conflicts.unwrap_or(false),
         style,
+        heartbeat: query.heartbeat.map(std::time::Duration::from_millis),
     };
 
     let response = state.db.changes(opts).await?;
@@ -136,6 +137,7 @@ pub async fn post_changes(
             .or_else(|| body.get("conflicts").and_then(|v| v.as_bool()))
             .unwrap_or(false),
         style,
+        heartbeat: query.heartbeat.map(std::time::Duration::from_millis),
     };
 
     let response = state.db.changes(opts).await?;
@@ -17,24 +17,25 @@ pub async fn get_db_info(
     }
 
     let info = state.db.info().await?;
+    let sizes = info.sizes.unwrap_or_default();
     Ok(Json(serde_json::json!({
         "db_name": info.db_name,
         "doc_count": info.doc_count,
-        "doc_del_count": 0,
+        "doc_del_count": info.doc_del_count,
         "update_seq": info.update_seq,
         "purge_seq": 0,
         "compact_running": false,
-        "disk_size": 0,
-        "data_size": 0,
+        "disk_size": sizes.file,
+        "data_size": sizes.active,
         "instance_start_time": "0",
         "disk_format_version": 8,
         "committed_update_seq": info.update_seq,
         "compacted_seq": 0,
         "uuid": "rouchdb",
         "sizes": {
-            "file": 0,
-            "external": 0,
-            "active": 0,
+            "file": sizes.file,
+            "external": sizes.external,
+            "active": sizes.active,
         },
         "props": {},
     })))
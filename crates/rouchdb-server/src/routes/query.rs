@@ -27,7 +27,7 @@ pub async fn find(
     let response = state.db.find(opts).await?;
     Ok(Json(serde_json::json!({
         "docs": response.docs,
-        "bookmark": "nil",
+        "bookmark": response.bookmark,
     })))
 }
 
@@ -43,6 +43,8 @@ pub struct CreateIndexBody {
 #[derive(Deserialize)]
 pub struct IndexFieldsBody {
     pub fields: Vec<rouchdb::SortField>,
+    #[serde(default)]
+    pub partial_filter_selector: Option<serde_json::Value>,
 }
 
 /// POST /{db}/_index — create a Mango index.
@@ -57,6 +59,7 @@ pub async fn create_index(
         name: body.name.unwrap_or_default(),
         fields: body.index.fields,
         ddoc: body.ddoc,
+        partial_filter_selector: body.index.partial_filter_selector,
     };
 
     let result = state.db.create_index(def).await?;
@@ -92,7 +95,7 @@ pub async fn get_indexes(
             "ddoc": idx.ddoc,
             "name": idx.name,
             "type": "json",
-            "def": { "fields": idx.def.fields },
+            "def": idx.def,
         }));
     }
 
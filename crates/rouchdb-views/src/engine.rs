@@ -1,23 +1,17 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use rouchdb_core::adapter::Adapter;
-use rouchdb_core::document::*;
-use rouchdb_core::error::Result;
+use rouchdb_core::error::{Result, RouchError};
+use rouchdb_query::{
+    PersistentViewIndex, ReduceFn, ViewQueryOptions, ViewResult, ViewUpdate, include_view_docs,
+    query_emitted,
+};
 
 /// A map function that takes a document JSON and returns emitted (key, value) pairs.
 pub type MapFn =
     Arc<dyn Fn(&serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)> + Send + Sync>;
 
-/// A persistent view index that is incrementally updated.
-pub struct PersistentViewIndex {
-    pub ddoc: String,
-    pub view_name: String,
-    pub last_seq: Seq,
-    /// doc_id -> list of emitted (key, value) pairs.
-    pub entries: BTreeMap<String, Vec<(serde_json::Value, serde_json::Value)>>,
-}
-
 /// Engine for building and querying persistent views.
 ///
 /// Views are defined as Rust closures (map functions). The engine
@@ -48,6 +42,12 @@ impl ViewEngine {
         self.map_fns.insert(key, Arc::new(f));
     }
 
+    /// Whether a map function is registered for a design doc view.
+    pub fn has_map(&self, ddoc: &str, view_name: &str) -> bool {
+        self.map_fns
+            .contains_key(&format!("{}/{}", ddoc, view_name))
+    }
+
     /// Update a view index by fetching changes since the last known seq.
     pub async fn update_index(
         &mut self,
@@ -61,7 +61,7 @@ impl ViewEngine {
             .map_fns
             .get(&key)
             .ok_or_else(|| {
-                rouchdb_core::error::RouchError::BadRequest(format!(
+                RouchError::BadRequest(format!(
                     "no map function registered for {}/{}",
                     ddoc, view_name
                 ))
@@ -71,40 +71,62 @@ impl ViewEngine {
         let index = self
             .indexes
             .entry(key)
-            .or_insert_with(|| PersistentViewIndex {
-                ddoc: ddoc.into(),
-                view_name: view_name.into(),
-                last_seq: Seq::default(),
-                entries: BTreeMap::new(),
-            });
-
-        let changes = adapter
-            .changes(ChangesOptions {
-                since: index.last_seq.clone(),
-                include_docs: true,
-                ..Default::default()
-            })
-            .await?;
-
-        for event in &changes.results {
-            // Remove old entries for this doc
-            index.entries.remove(&event.id);
-
-            // Skip design docs and deleted docs
-            if event.deleted || event.id.starts_with("_design/") {
-                continue;
-            }
-
-            if let Some(ref doc) = event.doc {
-                let emitted = map_fn(doc);
-                if !emitted.is_empty() {
-                    index.entries.insert(event.id.clone(), emitted);
-                }
-            }
+            .or_insert_with(|| PersistentViewIndex::new(ddoc, view_name));
+
+        // Design docs aren't indexed
+        let map_design_aware = |doc: &serde_json::Value| {
+            let is_design = doc
+                .get("_id")
+                .and_then(|id| id.as_str())
+                .is_some_and(|id| id.starts_with("_design/"));
+            if is_design { Vec::new() } else { map_fn(doc) }
+        };
+        index.update(adapter, &map_design_aware).await?;
+        Ok(())
+    }
+
+    /// Query a persisted view index.
+    ///
+    /// `opts.update_mode()` decides whether the index is caught up first
+    /// (`Eager`), afterwards (`Lazy`), or not at all (`Never`). See
+    /// [`ViewUpdate`] for the staleness guarantees of each mode.
+    pub async fn query(
+        &mut self,
+        adapter: &dyn Adapter,
+        ddoc: &str,
+        view_name: &str,
+        reduce_fn: Option<&ReduceFn>,
+        opts: ViewQueryOptions,
+    ) -> Result<ViewResult> {
+        let key = format!("{}/{}", ddoc, view_name);
+        if !self.map_fns.contains_key(&key) {
+            return Err(RouchError::BadRequest(format!(
+                "no map function registered for {}/{}",
+                ddoc, view_name
+            )));
         }
 
-        index.last_seq = changes.last_seq;
-        Ok(())
+        let update = opts.update_mode();
+        if update == ViewUpdate::Eager {
+            self.update_index(adapter, ddoc, view_name).await?;
+        }
+
+        let emitted = self
+            .indexes
+            .get(&key)
+            .map(PersistentViewIndex::emitted)
+            .unwrap_or_default();
+
+        let mut result = query_emitted(emitted, reduce_fn, &opts)?;
+        if opts.include_docs {
+            include_view_docs(adapter, &mut result).await?;
+        }
+
+        if update == ViewUpdate::Lazy {
+            self.update_index(adapter, ddoc, view_name).await?;
+        }
+
+        Ok(result)
     }
 
     /// Get a view index by ddoc/view_name.
@@ -7,7 +7,8 @@ mod design_doc;
 mod engine;
 
 pub use design_doc::{DesignDocument, ViewDef};
-pub use engine::{PersistentViewIndex, ViewEngine};
+pub use engine::ViewEngine;
+pub use rouchdb_query::PersistentViewIndex;
 
 #[cfg(test)]
 mod tests {
@@ -100,4 +101,66 @@ mod tests {
         let index = engine.get_index("myapp", "by_type").unwrap();
         assert_eq!(index.entries.len(), 3); // alice, bob, order1 (not the design doc)
     }
+
+    #[tokio::test]
+    async fn view_engine_update_modes() {
+        use rouchdb_query::{ViewQueryOptions, ViewUpdate};
+
+        let db = setup_db().await;
+        let mut engine = ViewEngine::new();
+        engine.register_map("myapp", "by_type", |doc| match doc.get("type") {
+            Some(t) => vec![(t.clone(), serde_json::json!(1))],
+            None => vec![],
+        });
+
+        let opts = |update| ViewQueryOptions {
+            update,
+            ..ViewQueryOptions::new()
+        };
+
+        // Never on an unbuilt index returns nothing and builds nothing
+        let r = engine
+            .query(&db, "myapp", "by_type", None, opts(ViewUpdate::Never))
+            .await
+            .unwrap();
+        assert!(r.rows.is_empty());
+        assert!(engine.get_index("myapp", "by_type").is_none());
+
+        // Eager builds the index first
+        let r = engine
+            .query(&db, "myapp", "by_type", None, opts(ViewUpdate::Eager))
+            .await
+            .unwrap();
+        assert_eq!(r.rows.len(), 3);
+
+        let new_doc = Document {
+            id: "carol".into(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({"type": "user"}),
+            attachments: HashMap::new(),
+        };
+        db.bulk_docs(vec![new_doc], BulkDocsOptions::new())
+            .await
+            .unwrap();
+
+        // Never keeps serving the old index
+        let r = engine
+            .query(&db, "myapp", "by_type", None, opts(ViewUpdate::Never))
+            .await
+            .unwrap();
+        assert_eq!(r.rows.len(), 3);
+
+        // Lazy serves the old index but catches up afterwards
+        let r = engine
+            .query(&db, "myapp", "by_type", None, opts(ViewUpdate::Lazy))
+            .await
+            .unwrap();
+        assert_eq!(r.rows.len(), 3);
+        let r = engine
+            .query(&db, "myapp", "by_type", None, opts(ViewUpdate::Never))
+            .await
+            .unwrap();
+        assert_eq!(r.rows.len(), 4);
+    }
 }
@@ -32,39 +32,53 @@
 
 use std::collections::HashMap;
 use std::path::Path;
+use std::pin::Pin;
 use std::sync::Arc;
 
-use tokio::sync::RwLock;
+use futures_util::stream::{self, Stream, StreamExt, TryStreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tokio::sync::{Mutex, RwLock};
 
 // Re-export core types
-pub use rouchdb_core::adapter::Adapter;
+pub use rouchdb_core::adapter::{Adapter, AllDocsStream, AttachmentReader};
 pub use rouchdb_core::document::*;
 pub use rouchdb_core::error::{Result, RouchError};
-pub use rouchdb_core::merge::{is_deleted, winning_rev};
+pub use rouchdb_core::merge::{collect_conflicts, is_deleted, winning_rev};
+pub use rouchdb_core::mime;
 
 // Re-export adapters
-pub use rouchdb_adapter_http::HttpAdapter;
 pub use rouchdb_adapter_http::auth::{AuthClient, Session, UserContext};
+pub use rouchdb_adapter_http::{HttpAdapter, HttpOptions};
 pub use rouchdb_adapter_memory::MemoryAdapter;
-pub use rouchdb_adapter_redb::RedbAdapter;
+pub use rouchdb_adapter_redb::{ATTACHMENT_CHUNK_LEN, RedbAdapter, RedbOptions};
 
 // Re-export subsystems
 pub use rouchdb_changes::{
-    ChangeReceiver, ChangeSender, ChangesEvent, ChangesFilter, ChangesHandle, ChangesStreamOptions,
-    LiveChangesStream, live_changes, live_changes_events,
+    ChangeReceiver, ChangeSender, ChangeSignal, ChangesEvent, ChangesFilter, ChangesHandle,
+    ChangesStreamOptions, LiveChangesStream, live_changes, live_changes_events,
 };
 pub use rouchdb_query::{
     BuiltIndex, CreateIndexResponse, ExplainIndex, ExplainResponse, FindOptions, FindResponse,
-    IndexDefinition, IndexFields, IndexInfo, ReduceFn, SortField, StaleOption, ViewQueryOptions,
-    ViewResult, build_index, find, matches_selector, query_view,
+    IndexDefinition, IndexFields, IndexInfo, ReduceFn, SortDirection, SortField, StaleOption,
+    ViewIndex, ViewQueryOptions, ViewResult, ViewRow, ViewUpdate, build_index, find, find_in_docs,
+    include_view_docs, matches_selector, normalize_selector, project, query_view,
+    validate_selector,
 };
 pub use rouchdb_views::{DesignDocument, PersistentViewIndex, ViewDef, ViewEngine};
 
 pub use rouchdb_replication::{
-    ReplicationEvent, ReplicationFilter, ReplicationHandle, ReplicationOptions, ReplicationResult,
-    replicate, replicate_live, replicate_with_events,
+    ConflictHook, ReplicationEvent, ReplicationFilter, ReplicationHandle, ReplicationOptions,
+    ReplicationProgress, ReplicationResult, replicate, replicate_live, replicate_with_events,
 };
 
+/// A lazily produced sequence of documents matching a find query,
+/// returned by `find_stream`.
+pub type FindStream<'a> = Pin<Box<dyn Stream<Item = Result<serde_json::Value>> + Send + 'a>>;
+
+/// Documents fetched per `all_docs` call when streaming index candidates.
+const FIND_STREAM_PAGE_SIZE: usize = 100;
+
 /// Plugin trait for extending Database behavior.
 ///
 /// Plugins receive lifecycle hooks during database operations.
@@ -86,64 +100,174 @@ pub trait Plugin: Send + Sync {
     }
 }
 
+/// Check run on each document before a local edit is written, like a
+/// design document's `validate_doc_update`.
+///
+/// Receives the new document and the current winning revision, if any.
+/// Returning an error rejects just that document: `Forbidden` and
+/// `Unauthorized` are reported as `forbidden` and `unauthorized`, anything
+/// else as `validation_failed` with the error's message.
+pub type Validator = Arc<dyn Fn(&Document, Option<&Document>) -> Result<()> + Send + Sync>;
+
+/// Outcome of a bidirectional [`Database::sync`].
+///
+/// Each direction is reported separately so a failure in one does not hide
+/// the progress made by the other.
+#[derive(Debug)]
+pub struct SyncResult {
+    /// Replication from this database to the other one.
+    pub push: Result<ReplicationResult>,
+    /// Replication from the other database to this one.
+    pub pull: Result<ReplicationResult>,
+}
+
+impl SyncResult {
+    /// Whether both directions completed without errors.
+    pub fn is_ok(&self) -> bool {
+        matches!(&self.push, Ok(r) if r.ok) && matches!(&self.pull, Ok(r) if r.ok)
+    }
+
+    /// Collapse into a single result, returning the first error encountered.
+    /// Agrees with [`is_ok`](Self::is_ok): a direction that finished with
+    /// per-document errors fails with a `DatabaseError` listing them.
+    pub fn into_result(self) -> Result<(ReplicationResult, ReplicationResult)> {
+        let ok = self.is_ok();
+        let (push, pull) = (self.push?, self.pull?);
+        if ok {
+            return Ok((push, pull));
+        }
+        let (direction, failed) = if push.ok {
+            ("pull", pull)
+        } else {
+            ("push", push)
+        };
+        Err(RouchError::DatabaseError(format!(
+            "{} failed: {}",
+            direction,
+            failed.errors.join("; ")
+        )))
+    }
+}
+
 /// A high-level database handle that wraps any adapter implementation.
 ///
 /// Provides a user-friendly API similar to PouchDB's JavaScript interface.
 pub struct Database {
     adapter: Arc<dyn Adapter>,
     indexes: Arc<RwLock<HashMap<String, BuiltIndex>>>,
+    views: Arc<RwLock<HashMap<String, Arc<RegisteredView>>>>,
     plugins: Vec<Arc<dyn Plugin>>,
+    validator: Option<Validator>,
+}
+
+/// A map/reduce view registered with `Database::register_view`, kept under
+/// `"{design}/{view}"`. Each view has its own lock, so catching one index
+/// up doesn't hold back queries of other views or new registrations.
+struct RegisteredView {
+    engine: Mutex<ViewEngine>,
+    reduce: Option<ReduceFn>,
 }
 
 impl Database {
     /// Create an in-memory database (data lost when dropped).
     pub fn memory(name: &str) -> Self {
-        Self {
-            adapter: Arc::new(MemoryAdapter::new(name)),
-            indexes: Arc::new(RwLock::new(HashMap::new())),
-            plugins: Vec::new(),
-        }
+        Self::with_adapter(Arc::new(MemoryAdapter::new(name)))
     }
 
     /// Open or create a persistent database backed by redb.
     pub fn open(path: impl AsRef<Path>, name: &str) -> Result<Self> {
         let adapter = RedbAdapter::open(path, name)?;
-        Ok(Self {
-            adapter: Arc::new(adapter),
-            indexes: Arc::new(RwLock::new(HashMap::new())),
-            plugins: Vec::new(),
-        })
+        Ok(Self::with_adapter(Arc::new(adapter)))
+    }
+
+    /// Open or create a redb database with `opts`, for example to compact
+    /// automatically while writing.
+    pub fn open_with_opts(path: impl AsRef<Path>, name: &str, opts: RedbOptions) -> Result<Self> {
+        let adapter = RedbAdapter::open_with_opts(path, name, opts)?;
+        Ok(Self::with_adapter(Arc::new(adapter)))
+    }
+
+    /// Open or create a redb database that encrypts document bodies,
+    /// attachments, local documents and view indexes with `key`.
+    ///
+    /// Document IDs and revision trees stay readable. A wrong key fails
+    /// with `RouchError::Forbidden`.
+    #[cfg(feature = "encryption")]
+    pub fn open_encrypted(path: impl AsRef<Path>, name: &str, key: &[u8; 32]) -> Result<Self> {
+        let adapter = RedbAdapter::open_encrypted(path, name, key)?;
+        Ok(Self::with_adapter(Arc::new(adapter)))
+    }
+
+    /// Open an existing redb database for reading only.
+    ///
+    /// Takes a shared file lock, so several readers can share the file with
+    /// each other but not with a writer; opening fails while the file is
+    /// open for writing. Writes fail with `RouchError::Forbidden`.
+    pub fn open_read_only(path: impl AsRef<Path>, name: &str) -> Result<Self> {
+        let adapter = RedbAdapter::open_read_only(path, name)?;
+        Ok(Self::with_adapter(Arc::new(adapter)))
     }
 
     /// Connect to a remote CouchDB instance.
     pub fn http(url: &str) -> Self {
-        Self {
-            adapter: Arc::new(HttpAdapter::new(url)),
-            indexes: Arc::new(RwLock::new(HashMap::new())),
-            plugins: Vec::new(),
-        }
+        Self::with_adapter(Arc::new(HttpAdapter::new(url)))
+    }
+
+    /// Connect to a remote CouchDB instance with custom timeouts and
+    /// compression settings. Fails if the HTTP client can't be built with
+    /// them.
+    pub fn http_with_opts(url: &str, opts: HttpOptions) -> Result<Self> {
+        Ok(Self::with_adapter(Arc::new(HttpAdapter::with_opts(
+            url, opts,
+        )?)))
     }
 
     /// Connect to a remote CouchDB instance using an authenticated client.
     ///
     /// The `AuthClient` should have been logged in via `auth.login()` first.
     pub fn http_with_auth(url: &str, auth: &AuthClient) -> Self {
-        Self {
-            adapter: Arc::new(HttpAdapter::with_auth_client(url, auth)),
-            indexes: Arc::new(RwLock::new(HashMap::new())),
-            plugins: Vec::new(),
-        }
+        Self::with_adapter(Arc::new(HttpAdapter::with_auth_client(url, auth)))
+    }
+
+    /// Connect to a remote CouchDB instance using `_session` cookie auth.
+    ///
+    /// Logs in immediately; if the session later expires the adapter logs in
+    /// again and retries the failed request once.
+    pub async fn http_session(url: &str, username: &str, password: &str) -> Result<Self> {
+        let adapter = HttpAdapter::new(url);
+        adapter.authenticate(username, password).await?;
+        Ok(Self::with_adapter(Arc::new(adapter)))
     }
 
     /// Create a database from any adapter implementation.
     pub fn from_adapter(adapter: Arc<dyn Adapter>) -> Self {
+        Self::with_adapter(adapter)
+    }
+
+    /// The one place a `Database` is put together; every constructor ends
+    /// here.
+    fn with_adapter(adapter: Arc<dyn Adapter>) -> Self {
         Self {
             adapter,
             indexes: Arc::new(RwLock::new(HashMap::new())),
+            views: Arc::new(RwLock::new(HashMap::new())),
             plugins: Vec::new(),
+            validator: None,
         }
     }
 
+    /// Check every local edit with `validator` before it is written.
+    ///
+    /// Runs in `bulk_docs` (and so `put`, `update` and `remove`) for
+    /// `new_edits: true` writes only; replication writes skip it. Adding
+    /// or removing an attachment is checked too, with the document as the
+    /// edit would leave it. A rejected document gets an error `DocResult`
+    /// while the rest of the batch is written. Replaces any earlier
+    /// validator.
+    pub fn set_validator(&mut self, validator: Validator) {
+        self.validator = Some(validator);
+    }
+
     /// Add a plugin to this database.
     pub fn with_plugin(mut self, plugin: Arc<dyn Plugin>) -> Self {
         self.plugins.push(plugin);
@@ -169,11 +293,147 @@ impl Database {
         self.adapter.get(id, GetOptions::default()).await
     }
 
+    /// Whether a document exists and isn't deleted, without loading its body.
+    pub async fn exists(&self, id: &str) -> Result<bool> {
+        self.adapter.exists(id).await
+    }
+
+    /// The current winning revision of a document, without loading its
+    /// body. `NotFound` if the document is missing or deleted.
+    pub async fn winning_rev(&self, id: &str) -> Result<Revision> {
+        self.adapter.winning_rev(id).await
+    }
+
     /// Retrieve a document with options (specific rev, conflicts, etc.).
     pub async fn get_with_opts(&self, id: &str, opts: GetOptions) -> Result<Document> {
         self.adapter.get(id, opts).await
     }
 
+    /// Retrieve leaf revisions of a document, for inspecting conflicts.
+    ///
+    /// `OpenRevs::All` returns every non-deleted leaf, winner first.
+    /// `OpenRevs::Specific` returns exactly the given revisions, in order.
+    pub async fn get_open_revs(&self, id: &str, open_revs: OpenRevs) -> Result<Vec<Document>> {
+        self.adapter.get_open_revs(id, open_revs).await
+    }
+
+    /// Retrieve each conflicting leaf of a document as a full document,
+    /// leaving out the winner. Empty if the document has no conflicts.
+    pub async fn conflicts(&self, id: &str) -> Result<Vec<Document>> {
+        let (_, revs) = self.winner_and_conflicts(id).await?;
+        if revs.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.adapter
+            .get_open_revs(id, OpenRevs::Specific(revs))
+            .await
+    }
+
+    /// Resolve a conflict in one call: write `winner` as the next revision
+    /// of the current winning leaf and delete every conflicting leaf, in a
+    /// single `bulk_docs` call, so only one branch is left, here and
+    /// wherever this replicates to. Fails with the first write that didn't
+    /// land.
+    pub async fn resolve_conflict(
+        &self,
+        id: &str,
+        winner: serde_json::Value,
+    ) -> Result<PutResponse> {
+        let (current, losers) = self.winner_and_conflicts(id).await?;
+        let rev = current.rev.map(|r| r.to_string()).unwrap_or_default();
+        let mut writes = vec![edit_doc(id, Some(&rev), winner)?];
+        for loser in &losers {
+            writes.push(deletion_doc(id, loser)?);
+        }
+        let mut results = self
+            .bulk_docs(writes, BulkDocsOptions::new())
+            .await?
+            .into_iter();
+        let rev = written(results.next().ok_or(RouchError::Conflict)?)?;
+        for result in results {
+            written(result)?;
+        }
+        Ok(PutResponse {
+            ok: true,
+            id: id.to_string(),
+            rev,
+        })
+    }
+
+    /// The winning revision of a document and its `_conflicts` revisions.
+    async fn winner_and_conflicts(&self, id: &str) -> Result<(Document, Vec<String>)> {
+        let winner = self
+            .adapter
+            .get(
+                id,
+                GetOptions {
+                    conflicts: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let revs = winner
+            .data
+            .get("_conflicts")
+            .and_then(|c| c.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|rev| rev.as_str().map(String::from))
+            .collect();
+        Ok((winner, revs))
+    }
+
+    /// Retrieve several documents by ID in one adapter call.
+    ///
+    /// Results come back in request order. A missing or deleted document
+    /// yields `Err(NotFound)` in its slot instead of failing the whole call.
+    pub async fn bulk_get(&self, ids: Vec<String>) -> Result<Vec<Result<Document>>> {
+        let items = ids
+            .into_iter()
+            .map(|id| BulkGetItem { id, rev: None })
+            .collect();
+        let response = self.adapter.bulk_get(items).await?;
+
+        Ok(response
+            .results
+            .into_iter()
+            .map(|result| {
+                let not_found = || RouchError::NotFound(result.id.clone());
+                let mut json = result
+                    .docs
+                    .into_iter()
+                    .find_map(|d| d.ok)
+                    .ok_or_else(not_found)?;
+                if let Some(obj) = json.as_object_mut() {
+                    obj.remove("_revisions");
+                }
+                let doc = Document::from_json(json)?;
+                if doc.deleted {
+                    return Err(not_found());
+                }
+                Ok(doc)
+            })
+            .collect())
+    }
+
+    /// Retrieve several documents by ID as a map.
+    ///
+    /// A missing or deleted document maps to `None`; any other failure,
+    /// for the call or for a single document, is returned as `Err`.
+    pub async fn get_many(&self, ids: &[&str]) -> Result<HashMap<String, Option<Document>>> {
+        let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+        let results = self.bulk_get(ids.clone()).await?;
+
+        ids.into_iter()
+            .zip(results)
+            .map(|(id, result)| match result {
+                Ok(doc) => Ok((id, Some(doc))),
+                Err(RouchError::NotFound(_)) => Ok((id, None)),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+
     /// Create a new document with an auto-generated ID.
     ///
     /// Equivalent to PouchDB's `db.post(doc)`. Generates a UUID v4 as the
@@ -189,54 +449,81 @@ impl Database {
     /// If it does exist, you must provide the current `_rev` in `opts_rev`
     /// to avoid conflicts.
     pub async fn put(&self, id: &str, data: serde_json::Value) -> Result<DocResult> {
-        if id.is_empty() {
-            return Err(RouchError::MissingId);
-        }
-        let doc = Document {
-            id: id.to_string(),
-            rev: None,
-            deleted: false,
-            data,
-            attachments: HashMap::new(),
-        };
+        let doc = edit_doc(id, None, data)?;
         let mut results = self.bulk_docs(vec![doc], BulkDocsOptions::new()).await?;
         Ok(results.remove(0))
     }
 
     /// Update an existing document (requires providing the current rev).
     pub async fn update(&self, id: &str, rev: &str, data: serde_json::Value) -> Result<DocResult> {
-        if id.is_empty() {
-            return Err(RouchError::MissingId);
-        }
-        let revision: Revision = rev.parse()?;
-        let doc = Document {
-            id: id.to_string(),
-            rev: Some(revision),
-            deleted: false,
-            data,
-            attachments: HashMap::new(),
-        };
+        let doc = edit_doc(id, Some(rev), data)?;
         let mut results = self.bulk_docs(vec![doc], BulkDocsOptions::new()).await?;
         Ok(results.remove(0))
     }
 
+    /// Read a document, pass its body through `f` and write the result as
+    /// the next revision.
+    ///
+    /// Fails with `NotFound` if the document doesn't exist, and with
+    /// `Conflict` if another write lands between the read and the write.
+    /// Nothing is retried and nothing is created.
+    pub async fn modify(
+        &self,
+        id: &str,
+        f: impl FnOnce(serde_json::Value) -> serde_json::Value,
+    ) -> Result<PutResponse> {
+        let doc = self.get(id).await?;
+        let rev = doc.rev.map(|r| r.to_string()).unwrap_or_default();
+        let rev = written(self.update(id, &rev, f(doc.data)).await?)?;
+        Ok(PutResponse {
+            ok: true,
+            id: id.to_string(),
+            rev,
+        })
+    }
+
+    /// Retrieve a document and deserialize its body into `T`.
+    ///
+    /// `_id` and `_rev` are not part of the body; use `get()` when you
+    /// need the revision.
+    pub async fn get_as<T: DeserializeOwned>(&self, id: &str) -> Result<T> {
+        let doc = self.get(id).await?;
+        Ok(serde_json::from_value(doc.data)?)
+    }
+
+    /// Create a document from any serializable value.
+    ///
+    /// `value` must serialize to a JSON object, which becomes the body.
+    pub async fn put_typed<T: Serialize>(&self, id: &str, value: &T) -> Result<DocResult> {
+        self.put(id, typed_body(value)?).await
+    }
+
+    /// Update a document from any serializable value.
+    pub async fn update_typed<T: Serialize>(
+        &self,
+        id: &str,
+        rev: &str,
+        value: &T,
+    ) -> Result<DocResult> {
+        self.update(id, rev, typed_body(value)?).await
+    }
+
     /// Delete a document (requires the current rev).
     pub async fn remove(&self, id: &str, rev: &str) -> Result<DocResult> {
-        if id.is_empty() {
-            return Err(RouchError::MissingId);
-        }
-        let revision: Revision = rev.parse()?;
-        let doc = Document {
-            id: id.to_string(),
-            rev: Some(revision),
-            deleted: true,
-            data: serde_json::json!({}),
-            attachments: HashMap::new(),
-        };
+        let doc = deletion_doc(id, rev)?;
         let mut results = self.bulk_docs(vec![doc], BulkDocsOptions::new()).await?;
         Ok(results.remove(0))
     }
 
+    /// Start a batch of `put`/`update`/`remove` operations that are
+    /// written together by [`BatchBuilder::execute`].
+    pub fn batch(&self) -> BatchBuilder<'_> {
+        BatchBuilder {
+            db: self,
+            docs: Vec::new(),
+        }
+    }
+
     /// Write multiple documents at once.
     pub async fn bulk_docs(
         &self,
@@ -246,18 +533,101 @@ impl Database {
         for plugin in &self.plugins {
             plugin.before_write(&mut docs).await?;
         }
-        let results = self.adapter.bulk_docs(docs, opts).await?;
+        // Replicated revisions were validated where they were written
+        let mut rejected = Vec::new();
+        if opts.new_edits
+            && let Some(ref validate) = self.validator
+        {
+            let ids = docs
+                .iter()
+                .filter(|doc| !doc.id.is_empty())
+                .map(|doc| doc.id.clone())
+                .collect();
+            let current = self.current_docs(ids).await?;
+            let mut accepted = Vec::with_capacity(docs.len());
+            for (i, doc) in docs.into_iter().enumerate() {
+                match validate(&doc, current.get(&doc.id)) {
+                    Ok(()) => accepted.push(doc),
+                    Err(e) => rejected.push((i, rejection(doc.id, e))),
+                }
+            }
+            docs = accepted;
+        }
+        let mut results = self.adapter.bulk_docs(docs, opts).await?;
+        for (i, result) in rejected {
+            results.insert(i, result);
+        }
         for plugin in &self.plugins {
             plugin.after_write(&results).await?;
         }
         Ok(results)
     }
 
+    /// The winning revisions of the live documents among `ids`, fetched in
+    /// one `all_docs` call.
+    async fn current_docs(&self, ids: Vec<String>) -> Result<HashMap<String, Document>> {
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let response = self
+            .adapter
+            .all_docs(AllDocsOptions {
+                keys: Some(ids),
+                include_docs: true,
+                ..AllDocsOptions::new()
+            })
+            .await?;
+        let mut current = HashMap::new();
+        for row in response.rows {
+            if row.value.deleted == Some(true) {
+                continue;
+            }
+            // Missing documents come back without a body
+            if let Some(doc) = row.doc.filter(|doc| doc.is_object()) {
+                current.insert(row.id, Document::from_json(doc)?);
+            }
+        }
+        Ok(current)
+    }
+
+    /// Run the validator, if one is set, on `doc_id` as `edit` would leave
+    /// its winning revision. Returns the rejection if it fails.
+    async fn check_edit(
+        &self,
+        doc_id: &str,
+        edit: impl FnOnce(&mut Document),
+    ) -> Result<Option<DocResult>> {
+        let Some(ref validate) = self.validator else {
+            return Ok(None);
+        };
+        let current = match self.adapter.get(doc_id, GetOptions::default()).await {
+            Ok(current) => Some(current),
+            Err(RouchError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let mut doc = current.clone().unwrap_or_else(|| Document {
+            id: doc_id.to_string(),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({}),
+            attachments: HashMap::new(),
+        });
+        edit(&mut doc);
+        Ok(validate(&doc, current.as_ref())
+            .err()
+            .map(|e| rejection(doc.id, e)))
+    }
+
     /// Query all documents.
     pub async fn all_docs(&self, opts: AllDocsOptions) -> Result<AllDocsResponse> {
         self.adapter.all_docs(opts).await
     }
 
+    /// Stream all documents one row at a time instead of collecting them.
+    pub fn all_docs_stream(&self, opts: AllDocsOptions) -> AllDocsStream<'_> {
+        self.adapter.all_docs_stream(opts)
+    }
+
     /// Get changes since a sequence number.
     ///
     /// If `opts.selector` is set, changes are fetched with `include_docs: true`
@@ -293,45 +663,13 @@ impl Database {
     /// Returns a receiver for `ChangeEvent` and a `ChangesHandle` that can be
     /// used to cancel the stream. Dropping the handle also cancels it.
     ///
-    /// If `opts.selector` is set, events are post-filtered using the Mango
-    /// selector — only matching changes are forwarded through the channel.
+    /// If `opts.selector` is set, only changes whose document matches the
+    /// Mango selector are forwarded through the channel.
     pub fn live_changes(
         &self,
         opts: ChangesStreamOptions,
     ) -> (tokio::sync::mpsc::Receiver<ChangeEvent>, ChangesHandle) {
-        if let Some(selector) = opts.selector.clone() {
-            let user_wants_docs = opts.include_docs;
-            let inner_opts = ChangesStreamOptions {
-                include_docs: true, // Need docs for selector evaluation
-                selector: None,
-                ..opts
-            };
-            let (inner_rx, handle) = live_changes(self.adapter.clone(), inner_opts);
-            let (tx, rx) = tokio::sync::mpsc::channel(64);
-
-            tokio::spawn(async move {
-                let mut inner_rx = inner_rx;
-                while let Some(mut event) = inner_rx.recv().await {
-                    let matches = event
-                        .doc
-                        .as_ref()
-                        .is_some_and(|d| matches_selector(d, &selector));
-                    if !matches {
-                        continue;
-                    }
-                    if !user_wants_docs {
-                        event.doc = None;
-                    }
-                    if tx.send(event).await.is_err() {
-                        break;
-                    }
-                }
-            });
-
-            (rx, handle)
-        } else {
-            live_changes(self.adapter.clone(), opts)
-        }
+        live_changes(self.adapter.clone(), opts)
     }
 
     /// Start a live changes feed with lifecycle events.
@@ -342,48 +680,33 @@ impl Database {
         &self,
         opts: ChangesStreamOptions,
     ) -> (tokio::sync::mpsc::Receiver<ChangesEvent>, ChangesHandle) {
-        if let Some(selector) = opts.selector.clone() {
-            let user_wants_docs = opts.include_docs;
-            let inner_opts = ChangesStreamOptions {
-                include_docs: true,
-                selector: None,
-                ..opts
-            };
-            let (inner_rx, handle) = live_changes_events(self.adapter.clone(), inner_opts);
-            let (tx, rx) = tokio::sync::mpsc::channel(64);
-
-            tokio::spawn(async move {
-                let mut inner_rx = inner_rx;
-                while let Some(event) = inner_rx.recv().await {
-                    let forward = match &event {
-                        ChangesEvent::Change(ce) => {
-                            let matches = ce
-                                .doc
-                                .as_ref()
-                                .is_some_and(|d| matches_selector(d, &selector));
-                            if !matches {
-                                continue;
-                            }
-                            if !user_wants_docs {
-                                let mut ce = ce.clone();
-                                ce.doc = None;
-                                ChangesEvent::Change(ce)
-                            } else {
-                                event
-                            }
-                        }
-                        _ => event, // Pass through lifecycle events
-                    };
-                    if tx.send(forward).await.is_err() {
-                        break;
-                    }
-                }
-            });
+        live_changes_events(self.adapter.clone(), opts)
+    }
 
-            (rx, handle)
-        } else {
-            live_changes_events(self.adapter.clone(), opts)
-        }
+    // -----------------------------------------------------------------
+    // Local document operations
+    // -----------------------------------------------------------------
+
+    /// Store a `_local` document.
+    ///
+    /// Local documents have no revision tree, never appear in `all_docs()`
+    /// or `changes()`, and are not replicated. The id may be given with or
+    /// without the `_local/` prefix.
+    pub async fn put_local(&self, id: &str, value: serde_json::Value) -> Result<()> {
+        let id = local_id(id)?;
+        self.adapter.put_local(id, value).await
+    }
+
+    /// Retrieve a `_local` document.
+    pub async fn get_local(&self, id: &str) -> Result<serde_json::Value> {
+        let id = local_id(id)?;
+        self.adapter.get_local(id).await
+    }
+
+    /// Delete a `_local` document.
+    pub async fn remove_local(&self, id: &str) -> Result<()> {
+        let id = local_id(id)?;
+        self.adapter.remove_local(id).await
     }
 
     // -----------------------------------------------------------------
@@ -399,11 +722,44 @@ impl Database {
         data: Vec<u8>,
         content_type: &str,
     ) -> Result<DocResult> {
+        let added = AttachmentMeta {
+            content_type: content_type.to_string(),
+            digest: String::new(),
+            length: data.len() as u64,
+            stub: false,
+            data: None,
+        };
+        if let Some(rejected) = self
+            .check_edit(doc_id, |doc| {
+                doc.attachments.insert(att_id.to_string(), added);
+            })
+            .await?
+        {
+            return Ok(rejected);
+        }
         self.adapter
             .put_attachment(doc_id, att_id, rev, data, content_type)
             .await
     }
 
+    /// Store an attachment, optionally guessing its content type from
+    /// `att_id`.
+    pub async fn put_attachment_with_opts(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        rev: &str,
+        data: Vec<u8>,
+        opts: PutAttachmentOptions,
+    ) -> Result<DocResult> {
+        let content_type = match opts.content_type.as_str() {
+            "" if opts.guess => mime::guess(att_id),
+            content_type => content_type,
+        };
+        self.put_attachment(doc_id, att_id, rev, data, content_type)
+            .await
+    }
+
     /// Retrieve raw attachment data.
     pub async fn get_attachment(&self, doc_id: &str, att_id: &str) -> Result<Vec<u8>> {
         self.adapter
@@ -421,6 +777,49 @@ impl Database {
         self.adapter.get_attachment(doc_id, att_id, opts).await
     }
 
+    /// Store an attachment read from `reader` without buffering it first
+    /// where the adapter supports streaming.
+    pub async fn put_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        rev: &str,
+        content_type: &str,
+        reader: impl tokio::io::AsyncRead + Send + Unpin + 'static,
+    ) -> Result<DocResult> {
+        // The length isn't known until the stream has been read
+        let added = AttachmentMeta {
+            content_type: content_type.to_string(),
+            digest: String::new(),
+            length: 0,
+            stub: false,
+            data: None,
+        };
+        if let Some(rejected) = self
+            .check_edit(doc_id, |doc| {
+                doc.attachments.insert(att_id.to_string(), added);
+            })
+            .await?
+        {
+            return Ok(rejected);
+        }
+        self.adapter
+            .put_attachment_stream(doc_id, att_id, rev, content_type, Box::new(reader))
+            .await
+    }
+
+    /// Open attachment data for reading.
+    pub async fn get_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        opts: GetAttachmentOptions,
+    ) -> Result<AttachmentReader> {
+        self.adapter
+            .get_attachment_stream(doc_id, att_id, opts)
+            .await
+    }
+
     /// Remove an attachment from a document.
     ///
     /// Equivalent to PouchDB's `db.removeAttachment(docId, attachmentId, rev)`.
@@ -430,6 +829,14 @@ impl Database {
         att_id: &str,
         rev: &str,
     ) -> Result<DocResult> {
+        if let Some(rejected) = self
+            .check_edit(doc_id, |doc| {
+                doc.attachments.remove(att_id);
+            })
+            .await?
+        {
+            return Ok(rejected);
+        }
         self.adapter.remove_attachment(doc_id, att_id, rev).await
     }
 
@@ -440,114 +847,182 @@ impl Database {
     /// Run a Mango find query.
     ///
     /// If a matching index exists (created via `create_index()`), it will be
-    /// used to avoid a full table scan. Otherwise falls back to scanning all
+    /// used to avoid a full table scan. `FindOptions::use_index` picks a
+    /// specific index by name. Otherwise falls back to scanning all
     /// documents.
+    ///
+    /// Like CouchDB, sorting needs an index keyed by the sort fields:
+    /// without one, this fails with `BadRequest("no_usable_index")` unless
+    /// `FindOptions::allow_fallback` is set. Sorting on `_id` alone is
+    /// always allowed.
     pub async fn find(&self, opts: FindOptions) -> Result<FindResponse> {
-        // Check if we have a usable index
+        validate_selector(&opts.selector)?;
         let mut indexes = self.indexes.write().await;
 
-        // Find the name of a usable index (if any)
-        let usable_name = indexes
-            .iter()
-            .find(|(_, idx)| {
-                if idx.def.fields.is_empty() {
-                    return false;
-                }
-                let (first_field, _) = idx.def.fields[0].field_and_direction();
-                opts.selector.get(first_field).is_some()
-            })
-            .map(|(name, _)| name.clone());
-
-        if let Some(name) = usable_name {
-            // Rebuild the index lazily to pick up any document changes
-            let def = indexes[&name].def.clone();
-            let rebuilt = build_index(self.adapter.as_ref(), &def).await?;
-            indexes.insert(name.clone(), rebuilt);
+        let selected = select_index(&indexes, &opts);
+        let sort = opts.sort.as_deref().unwrap_or_default();
+        let sorts_by_field = sort.iter().any(|sf| sf.field_and_direction().0 != "_id");
+        if sorts_by_field
+            && !opts.allow_fallback
+            && selected.is_none_or(|idx| !idx.covers_sort(sort))
+        {
+            return Err(RouchError::BadRequest("no_usable_index".into()));
+        }
 
-            let candidate_ids = indexes[&name].find_matching(&opts.selector);
+        let Some(name) = selected.map(|idx| idx.def.name.clone()) else {
+            let Some(branches) = or_branch_indexes(&indexes, &opts.selector) else {
+                drop(indexes);
+                // No usable index — full table scan
+                return find(self.adapter.as_ref(), opts).await;
+            };
+            // Union the candidates of each `$or` branch's index
+            let mut candidate_ids = Vec::new();
+            for (name, branch) in branches {
+                let index = indexes.get_mut(&name).expect("selected index must exist");
+                index.update(self.adapter.as_ref()).await?;
+                candidate_ids.extend(index.find_matching(branch));
+            }
             drop(indexes);
+            candidate_ids.sort();
+            candidate_ids.dedup();
+            return self.find_among(candidate_ids, &opts).await;
+        };
 
-            // Fetch only the candidate docs
-            let all = self
-                .adapter
-                .all_docs(AllDocsOptions {
-                    include_docs: true,
-                    keys: Some(candidate_ids),
-                    ..AllDocsOptions::new()
-                })
-                .await?;
+        // Catch the index up with any document changes since it was built
+        let index = indexes.get_mut(&name).expect("selected index must exist");
+        index.update(self.adapter.as_ref()).await?;
+        let candidate_ids = index.find_matching(&opts.selector);
+        drop(indexes);
+        self.find_among(candidate_ids, &opts).await
+    }
 
-            let mut matched: Vec<serde_json::Value> = Vec::new();
-            for row in &all.rows {
-                if let Some(ref doc_json) = row.doc
-                    && matches_selector(doc_json, &opts.selector)
-                {
-                    matched.push(doc_json.clone());
-                }
-            }
+    /// Run `opts` over just the documents in `candidate_ids`.
+    async fn find_among(
+        &self,
+        candidate_ids: Vec<String>,
+        opts: &FindOptions,
+    ) -> Result<FindResponse> {
+        let all = self
+            .adapter
+            .all_docs(AllDocsOptions {
+                include_docs: true,
+                keys: Some(candidate_ids),
+                ..AllDocsOptions::new()
+            })
+            .await?;
 
-            // Sort
-            if let Some(ref sort_fields) = opts.sort {
-                matched.sort_by(|a, b| {
-                    use rouchdb_core::collation::collate;
-                    use rouchdb_query::SortDirection;
-                    for sf in sort_fields {
-                        let (field, direction) = sf.field_and_direction();
-                        let va = a.get(field).unwrap_or(&serde_json::Value::Null);
-                        let vb = b.get(field).unwrap_or(&serde_json::Value::Null);
-                        let cmp = collate(va, vb);
-                        let cmp = if direction == SortDirection::Desc {
-                            cmp.reverse()
-                        } else {
-                            cmp
-                        };
-                        if cmp != std::cmp::Ordering::Equal {
-                            return cmp;
-                        }
-                    }
-                    std::cmp::Ordering::Equal
-                });
-            }
+        find_in_docs(all.rows.into_iter().filter_map(|row| row.doc), opts)
+    }
 
-            // Skip
-            if let Some(skip) = opts.skip {
-                matched = matched.into_iter().skip(skip as usize).collect();
-            }
+    /// Run a Mango find query, yielding matching documents as they are
+    /// found instead of collecting them into a `FindResponse`.
+    ///
+    /// `skip` and `limit` are applied as documents stream past. Unsorted
+    /// queries and sorts on `_id` walk `all_docs` in order; ascending sorts
+    /// on exactly the fields of a usable index follow the index and fetch
+    /// documents a page at a time. Any other sort, or a bookmark, needs
+    /// every match before the first can be yielded, so those queries fall
+    /// back to buffering through `find`. Errors, including an invalid
+    /// selector or `no_usable_index`, are yielded as the only item.
+    pub fn find_stream(&self, opts: FindOptions) -> FindStream<'_> {
+        Box::pin(stream::once(self.open_find_stream(opts)).flatten())
+    }
 
-            // Limit
-            if let Some(limit) = opts.limit {
-                matched.truncate(limit as usize);
-            }
+    async fn open_find_stream(&self, opts: FindOptions) -> FindStream<'_> {
+        fn failed<'a>(e: RouchError) -> FindStream<'a> {
+            Box::pin(stream::once(async { Err(e) }))
+        }
 
-            // Field projection
-            if let Some(ref fields) = opts.fields {
-                matched = matched
-                    .into_iter()
-                    .map(|doc| {
-                        let mut result = serde_json::Map::new();
-                        if let serde_json::Value::Object(map) = &doc {
-                            for field in fields {
-                                if let Some(val) = map.get(field) {
-                                    result.insert(field.clone(), val.clone());
-                                }
-                            }
-                            if let Some(id) = map.get("_id") {
-                                result
-                                    .entry("_id".to_string())
-                                    .or_insert_with(|| id.clone());
-                            }
-                        }
-                        serde_json::Value::Object(result)
-                    })
-                    .collect();
+        if let Err(e) = validate_selector(&opts.selector) {
+            return failed(e);
+        }
+        let sort = opts.sort.as_deref().unwrap_or_default();
+        let rows = if opts.resume_from().is_some() {
+            None
+        } else if sort.iter().all(|sf| sf.field_and_direction().0 == "_id") {
+            let descending = sort
+                .first()
+                .is_some_and(|sf| sf.field_and_direction().1 == SortDirection::Desc);
+            Some(self.adapter.all_docs_stream(AllDocsOptions {
+                include_docs: true,
+                descending,
+                ..AllDocsOptions::new()
+            }))
+        } else {
+            match self.sorted_candidates(&opts).await {
+                Ok(ids) => ids.map(|ids| self.docs_by_id(ids)),
+                Err(e) => return failed(e),
             }
+        };
+        let Some(rows) = rows else {
+            return match self.find(opts).await {
+                Ok(response) => Box::pin(stream::iter(response.docs.into_iter().map(Ok))),
+                Err(e) => failed(e),
+            };
+        };
 
-            Ok(FindResponse { docs: matched })
-        } else {
-            drop(indexes);
-            // No usable index — full table scan
-            find(self.adapter.as_ref(), opts).await
+        let FindOptions {
+            selector,
+            fields,
+            skip,
+            limit,
+            ..
+        } = opts;
+        Box::pin(
+            rows.try_filter_map(move |row| {
+                let doc = row.doc.filter(|doc| matches_selector(doc, &selector));
+                async move { Ok(doc) }
+            })
+            .skip(skip.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |l| l as usize))
+            .map_ok(move |doc| match &fields {
+                Some(fields) => project(doc, fields),
+                None => doc,
+            }),
+        )
+    }
+
+    /// Candidate ids in sort order, if an index keyed by exactly the
+    /// (ascending) sort fields can serve the query. Index entries are
+    /// ordered by key and then `_id`, the same order `find` sorts in.
+    async fn sorted_candidates(&self, opts: &FindOptions) -> Result<Option<Vec<String>>> {
+        let sort = opts.sort.as_deref().unwrap_or_default();
+        if sort
+            .iter()
+            .any(|sf| sf.field_and_direction().1 == SortDirection::Desc)
+        {
+            return Ok(None);
         }
+        let mut indexes = self.indexes.write().await;
+        let Some(name) = select_index(&indexes, opts)
+            .filter(|idx| idx.covers_sort(sort) && idx.def.fields.len() == sort.len())
+            .map(|idx| idx.def.name.clone())
+        else {
+            return Ok(None);
+        };
+        let index = indexes.get_mut(&name).expect("selected index must exist");
+        index.update(self.adapter.as_ref()).await?;
+        Ok(Some(index.find_matching(&opts.selector)))
+    }
+
+    /// Fetch the documents for `ids` in order, one page of keys at a time.
+    fn docs_by_id(&self, ids: Vec<String>) -> AllDocsStream<'_> {
+        let pages: Vec<Vec<String>> = ids
+            .chunks(FIND_STREAM_PAGE_SIZE)
+            .map(<[String]>::to_vec)
+            .collect();
+        Box::pin(
+            stream::iter(pages)
+                .then(move |keys| {
+                    self.adapter.all_docs(AllDocsOptions {
+                        include_docs: true,
+                        keys: Some(keys),
+                        ..AllDocsOptions::new()
+                    })
+                })
+                .map_ok(|page| stream::iter(page.rows.into_iter().map(Ok)))
+                .try_flatten(),
+        )
     }
 
     // -----------------------------------------------------------------
@@ -586,6 +1061,7 @@ impl Database {
             name: name.clone(),
             fields: def.fields,
             ddoc: def.ddoc,
+            partial_filter_selector: def.partial_filter_selector,
         };
 
         let built = build_index(self.adapter.as_ref(), &index_def).await?;
@@ -607,6 +1083,7 @@ impl Database {
                 ddoc: idx.def.ddoc.clone(),
                 def: IndexFields {
                     fields: idx.def.fields.clone(),
+                    partial_filter_selector: idx.def.partial_filter_selector.clone(),
                 },
             })
             .collect();
@@ -616,45 +1093,71 @@ impl Database {
 
     /// Explain how a query would be executed without running it.
     ///
-    /// Returns which index would be used and the query plan.
+    /// Returns which index would be used, the normalized selector, the
+    /// index fields it matches and the residual selector that is filtered
+    /// in memory.
     pub async fn explain(&self, opts: FindOptions) -> ExplainResponse {
         let indexes = self.indexes.read().await;
-        let usable = indexes.values().find(|idx| {
-            if idx.def.fields.is_empty() {
-                return false;
-            }
-            let (first_field, _) = idx.def.fields[0].field_and_direction();
-            opts.selector.get(first_field).is_some()
-        });
+        let usable = select_index(&indexes, &opts);
 
         let dbname = self.info().await.map(|i| i.db_name).unwrap_or_default();
+        let selector = normalize_selector(&opts.selector);
 
-        if let Some(index) = usable {
-            ExplainResponse {
-                dbname,
-                index: ExplainIndex {
-                    ddoc: index.def.ddoc.clone(),
-                    name: index.def.name.clone(),
-                    index_type: "json".into(),
-                    def: IndexFields {
-                        fields: index.def.fields.clone(),
-                    },
-                },
-                selector: opts.selector,
-                fields: opts.fields,
+        let or_branches = match usable {
+            None => or_branch_indexes(&indexes, &opts.selector).unwrap_or_default(),
+            Some(_) => Vec::new(),
+        };
+
+        let (index, matched_fields, residual_selector) = if let Some(index) = usable {
+            let (matched, residual) = index.split_selector(&selector);
+            (explain_index(index), matched, residual)
+        } else if !or_branches.is_empty() {
+            let mut matched = Vec::new();
+            for (name, branch) in &or_branches {
+                let (fields, _) = indexes[name].split_selector(&normalize_selector(branch));
+                for field in fields {
+                    if !matched.contains(&field) {
+                        matched.push(field);
+                    }
+                }
             }
+            let explained = ExplainIndex {
+                ddoc: None,
+                name: "_or".into(),
+                index_type: "special".into(),
+                def: IndexFields {
+                    fields: vec![],
+                    partial_filter_selector: None,
+                },
+            };
+            // Every candidate is still checked against the whole selector
+            (explained, matched, selector.clone())
         } else {
-            ExplainResponse {
-                dbname,
-                index: ExplainIndex {
-                    ddoc: None,
-                    name: "_all_docs".into(),
-                    index_type: "special".into(),
-                    def: IndexFields { fields: vec![] },
+            let explained = ExplainIndex {
+                ddoc: None,
+                name: "_all_docs".into(),
+                index_type: "special".into(),
+                def: IndexFields {
+                    fields: vec![],
+                    partial_filter_selector: None,
                 },
-                selector: opts.selector,
-                fields: opts.fields,
-            }
+            };
+            (explained, Vec::new(), selector.clone())
+        };
+
+        ExplainResponse {
+            dbname,
+            index,
+            selector,
+            fields: opts.fields,
+            limit: opts.limit,
+            skip: opts.skip.unwrap_or(0),
+            matched_fields,
+            residual_selector,
+            or_branches: or_branches
+                .iter()
+                .map(|(name, _)| explain_index(&indexes[name]))
+                .collect(),
         }
     }
 
@@ -702,6 +1205,90 @@ impl Database {
         self.remove(&id, rev).await
     }
 
+    /// Register a Rust map function, and optionally a reduce, as the view
+    /// `view` of design document `design`, so `query` can run it by name.
+    ///
+    /// Registered views are indexed incrementally from the changes feed
+    /// and take precedence over views stored in design documents.
+    /// Registering a view again replaces it and starts a new index.
+    pub async fn register_view<F>(
+        &self,
+        design: &str,
+        view: &str,
+        map_fn: F,
+        reduce: Option<ReduceFn>,
+    ) where
+        F: Fn(&serde_json::Value) -> Vec<(serde_json::Value, serde_json::Value)>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let design = design.strip_prefix("_design/").unwrap_or(design);
+        let mut engine = ViewEngine::new();
+        engine.register_map(design, view, map_fn);
+        let registered = RegisteredView {
+            engine: Mutex::new(engine),
+            reduce,
+        };
+        self.views
+            .write()
+            .await
+            .insert(format!("{}/{}", design, view), Arc::new(registered));
+    }
+
+    /// Query a view by name, like CouchDB's
+    /// `GET /{db}/_design/{design}/_view/{view}`.
+    ///
+    /// A view registered with `register_view` runs locally. Otherwise the
+    /// query goes to the adapter, which for HTTP runs the design
+    /// document's view on the server; local adapters return `NotFound`.
+    pub async fn query(
+        &self,
+        design: &str,
+        view: &str,
+        opts: ViewQueryOptions,
+    ) -> Result<ViewResult> {
+        let design = design.strip_prefix("_design/").unwrap_or(design);
+        let registered = self
+            .views
+            .read()
+            .await
+            .get(&format!("{}/{}", design, view))
+            .cloned();
+        if let Some(registered) = registered {
+            let mut engine = registered.engine.lock().await;
+            return engine
+                .query(
+                    self.adapter.as_ref(),
+                    design,
+                    view,
+                    registered.reduce.as_ref(),
+                    opts,
+                )
+                .await;
+        }
+
+        let response = self
+            .adapter
+            .query_design_view(design, view, view_query_body(&opts))
+            .await?;
+        Ok(ViewResult {
+            total_rows: response["total_rows"].as_u64().unwrap_or(0),
+            offset: response["offset"].as_u64().unwrap_or(0),
+            rows: response["rows"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|row| ViewRow {
+                    id: row["id"].as_str().map(String::from),
+                    key: row["key"].clone(),
+                    value: row["value"].clone(),
+                    doc: row.get("doc").filter(|d| !d.is_null()).cloned(),
+                })
+                .collect(),
+        })
+    }
+
     /// Remove orphaned view indexes.
     ///
     /// Scans all design documents and removes any cached indexes
@@ -717,6 +1304,15 @@ impl Database {
     // Replication
     // -----------------------------------------------------------------
 
+    /// Report which of the given revisions this database is missing.
+    ///
+    /// `revs` maps document ids to revision strings. Only documents with at
+    /// least one missing revision appear in the response, together with the
+    /// stored leaves that could be ancestors of the missing ones.
+    pub async fn revs_diff(&self, revs: HashMap<String, Vec<String>>) -> Result<RevsDiffResponse> {
+        self.adapter.revs_diff(revs).await
+    }
+
     /// Replicate from this database to the target.
     pub async fn replicate_to(&self, target: &Database) -> Result<ReplicationResult> {
         replicate(
@@ -781,10 +1377,13 @@ impl Database {
     }
 
     /// Bidirectional sync (replicate in both directions).
-    pub async fn sync(&self, other: &Database) -> Result<(ReplicationResult, ReplicationResult)> {
-        let push = self.replicate_to(other).await?;
-        let pull = self.replicate_from(other).await?;
-        Ok((push, pull))
+    ///
+    /// The pull runs even if the push fails; inspect each side of the
+    /// returned [`SyncResult`] to retry only the direction that failed.
+    pub async fn sync(&self, other: &Database) -> SyncResult {
+        let push = self.replicate_to(other).await;
+        let pull = self.replicate_from(other).await;
+        SyncResult { push, pull }
     }
 
     // -----------------------------------------------------------------
@@ -801,6 +1400,28 @@ impl Database {
         self.adapter.compact().await
     }
 
+    /// Compact the database, keeping the last `opts.retain_revs` bodies of
+    /// each branch instead of only the leaves. Adapters that can't honour
+    /// the option (HTTP) do a normal compaction.
+    pub async fn compact_with_opts(&self, opts: CompactOptions) -> Result<()> {
+        self.adapter.compact_with_opts(opts).await
+    }
+
+    /// Whether `compact()` is worth running: more old revision bodies are
+    /// stored than current ones. Always `false` over HTTP, where CouchDB
+    /// tracks this itself.
+    pub async fn needs_compaction(&self) -> Result<bool> {
+        self.adapter.needs_compaction().await
+    }
+
+    /// Copy the database as it is right now into a new redb file at
+    /// `path`, which opens like any other database. Writes may continue
+    /// during the backup; they don't appear in the copy. Fails if `path`
+    /// exists, and with `BadRequest` on adapters other than redb.
+    pub async fn backup_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.adapter.backup_to(path.as_ref()).await
+    }
+
     /// Destroy the database and all its data.
     pub async fn destroy(&self) -> Result<()> {
         for plugin in &self.plugins {
@@ -830,6 +1451,494 @@ impl Database {
     }
 }
 
+/// Pick the index a find query should use: the `use_index` hint if it can
+/// serve the selector, otherwise the first index whose leading field the
+/// selector constrains, preferring one that covers the sort.
+fn select_index<'a>(
+    indexes: &'a HashMap<String, BuiltIndex>,
+    opts: &FindOptions,
+) -> Option<&'a BuiltIndex> {
+    if let Some(hint) = &opts.use_index
+        && let Some(idx) = indexes.get(hint)
+        && idx.can_serve(&opts.selector)
+    {
+        return Some(idx);
+    }
+    let mut names: Vec<&String> = indexes.keys().collect();
+    names.sort();
+    let usable: Vec<&BuiltIndex> = names
+        .into_iter()
+        .map(|name| &indexes[name])
+        .filter(|idx| idx.can_serve(&opts.selector))
+        .collect();
+    let sort = opts.sort.as_deref().unwrap_or_default();
+    usable
+        .iter()
+        .find(|idx| idx.covers_sort(sort))
+        .or(usable.first())
+        .copied()
+}
+
+/// For a selector with a top-level `$or`, the index that serves each
+/// branch, by name, alongside the branch. `None` unless every branch has
+/// one. The union of the branches' candidates covers every match even if
+/// the selector has other conditions too.
+fn or_branch_indexes<'s>(
+    indexes: &HashMap<String, BuiltIndex>,
+    selector: &'s serde_json::Value,
+) -> Option<Vec<(String, &'s serde_json::Value)>> {
+    let branches = selector.get("$or")?.as_array()?;
+    if branches.is_empty() {
+        return None;
+    }
+    let mut names: Vec<&String> = indexes.keys().collect();
+    names.sort();
+    branches
+        .iter()
+        .map(|branch| {
+            let name = names
+                .iter()
+                .find(|name| indexes[**name].can_serve(branch))?;
+            Some(((*name).clone(), branch))
+        })
+        .collect()
+}
+
+fn explain_index(index: &BuiltIndex) -> ExplainIndex {
+    ExplainIndex {
+        ddoc: index.def.ddoc.clone(),
+        name: index.def.name.clone(),
+        index_type: "json".into(),
+        def: IndexFields {
+            fields: index.def.fields.clone(),
+            partial_filter_selector: index.def.partial_filter_selector.clone(),
+        },
+    }
+}
+
+/// The CouchDB view query parameters for `opts`, as a `POST` body.
+fn view_query_body(opts: &ViewQueryOptions) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "inclusive_end": opts.inclusive_end,
+        "descending": opts.descending,
+        "skip": opts.skip,
+        "include_docs": opts.include_docs,
+        "reduce": opts.reduce,
+        "group": opts.group,
+        "update": match opts.update_mode() {
+            ViewUpdate::Eager => "true",
+            ViewUpdate::Lazy => "lazy",
+            ViewUpdate::Never => "false",
+        },
+    });
+    let optional = [
+        ("key", opts.key.clone()),
+        ("keys", opts.keys.clone().map(serde_json::Value::from)),
+        ("startkey", opts.start_key.clone()),
+        ("endkey", opts.end_key.clone()),
+        (
+            "startkey_docid",
+            opts.start_key_doc_id.clone().map(serde_json::Value::from),
+        ),
+        (
+            "endkey_docid",
+            opts.end_key_doc_id.clone().map(serde_json::Value::from),
+        ),
+        ("limit", opts.limit.map(serde_json::Value::from)),
+        ("group_level", opts.group_level.map(serde_json::Value::from)),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            body[name] = value;
+        }
+    }
+    body
+}
+
+/// The document `put` (no `rev`) or `update` writes.
+fn edit_doc(id: &str, rev: Option<&str>, mut data: serde_json::Value) -> Result<Document> {
+    if id.is_empty() {
+        return Err(RouchError::MissingId);
+    }
+    let rev = rev.map(str::parse::<Revision>).transpose()?;
+    let attachments = take_attachments(&mut data)?;
+    Ok(Document {
+        id: id.to_string(),
+        rev,
+        deleted: false,
+        data,
+        attachments,
+    })
+}
+
+/// The result reported for a document the validator turned down.
+fn rejection(id: String, e: RouchError) -> DocResult {
+    let (error, reason) = match e {
+        RouchError::Forbidden(reason) => ("forbidden", reason),
+        RouchError::Unauthorized => ("unauthorized", String::new()),
+        e => ("validation_failed", e.to_string()),
+    };
+    DocResult {
+        ok: false,
+        id,
+        rev: None,
+        error: Some(error.into()),
+        reason: Some(reason),
+    }
+}
+
+/// The tombstone `remove` writes.
+fn deletion_doc(id: &str, rev: &str) -> Result<Document> {
+    if id.is_empty() {
+        return Err(RouchError::MissingId);
+    }
+    Ok(Document {
+        id: id.to_string(),
+        rev: Some(rev.parse()?),
+        deleted: true,
+        data: serde_json::json!({}),
+        attachments: HashMap::new(),
+    })
+}
+
+/// Serialize a typed document body, which has to be a JSON object.
+fn typed_body<T: Serialize>(value: &T) -> Result<serde_json::Value> {
+    let body = serde_json::to_value(value)?;
+    if !body.is_object() {
+        return Err(RouchError::BadRequest(
+            "document must be a JSON object".into(),
+        ));
+    }
+    Ok(body)
+}
+
+/// Pull an `_attachments` map (stubs or inline Base64 `data`) out of the
+/// body passed to `put`/`update`.
+fn take_attachments(data: &mut serde_json::Value) -> Result<HashMap<String, AttachmentMeta>> {
+    match data.as_object_mut().and_then(|m| m.remove("_attachments")) {
+        Some(atts) => {
+            Ok(Document::from_json(serde_json::json!({ "_attachments": atts }))?.attachments)
+        }
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// The new revision of a successful write, or the write's error.
+fn written(result: DocResult) -> Result<String> {
+    match result {
+        DocResult {
+            ok: true,
+            rev: Some(rev),
+            ..
+        } => Ok(rev),
+        DocResult { error, .. } if error.as_deref() == Some("conflict") => {
+            Err(RouchError::Conflict)
+        }
+        DocResult { error, reason, .. } if error.as_deref() == Some("forbidden") => {
+            Err(RouchError::Forbidden(reason.unwrap_or_default()))
+        }
+        DocResult { error, .. } if error.as_deref() == Some("unauthorized") => {
+            Err(RouchError::Unauthorized)
+        }
+        DocResult { id, reason, .. } => Err(RouchError::DatabaseError(format!(
+            "write to {} failed: {}",
+            id,
+            reason.unwrap_or_default()
+        ))),
+    }
+}
+
+/// Strip the optional `_local/` prefix from a local document id.
+fn local_id(id: &str) -> Result<&str> {
+    let id = id.strip_prefix("_local/").unwrap_or(id);
+    if id.is_empty() {
+        return Err(RouchError::MissingId);
+    }
+    Ok(id)
+}
+
+/// Counts reported by `Database::load`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadStats {
+    /// Document revisions read from the dump.
+    pub docs_read: u64,
+    /// Revisions the target accepted.
+    pub docs_written: u64,
+    /// Revisions the target rejected.
+    pub docs_failed: u64,
+    /// Things left out of the load, such as attachments without data.
+    pub warnings: Vec<String>,
+}
+
+/// Revisions written per `bulk_docs` call while loading a dump.
+const LOAD_BATCH_SIZE: usize = 100;
+
+impl Database {
+    /// Write every document to `writer` as newline-delimited JSON.
+    ///
+    /// The first line is a header (`{"type": "header", ...}`) with the
+    /// database name, doc count and update sequence. Each following line
+    /// (`{"type": "doc", "doc": ...}`) holds one leaf revision, deleted and
+    /// conflicting leaves included, with its `_revisions` ancestry and its
+    /// attachments inline as Base64. `load` reads the format back.
+    pub async fn dump(&self, mut writer: impl std::io::Write) -> Result<()> {
+        let info = self.info().await?;
+        let header = serde_json::json!({
+            "type": "header",
+            "db_name": info.db_name,
+            "doc_count": info.doc_count,
+            "update_seq": info.update_seq,
+        });
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        let changes = self
+            .adapter
+            .changes(ChangesOptions {
+                style: ChangesStyle::AllDocs,
+                ..Default::default()
+            })
+            .await?;
+        for change in changes.results {
+            let items = change
+                .changes
+                .iter()
+                .map(|c| BulkGetItem {
+                    id: change.id.clone(),
+                    rev: Some(c.rev.clone()),
+                })
+                .collect();
+            let fetched = self.adapter.bulk_get(items).await?;
+            for json in fetched
+                .results
+                .into_iter()
+                .flat_map(|r| r.docs)
+                .filter_map(|d| d.ok)
+            {
+                let line = serde_json::json!({
+                    "type": "doc",
+                    "doc": self.with_inline_attachments(json).await?,
+                });
+                serde_json::to_writer(&mut writer, &line)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Add the leaf's attachments, with their data, to a `bulk_get` body.
+    async fn with_inline_attachments(
+        &self,
+        mut json: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let (Some(id), Some(rev)) = (json["_id"].as_str(), json["_rev"].as_str()) else {
+            return Ok(json);
+        };
+        let (id, rev) = (id.to_string(), rev.to_string());
+        let doc = self
+            .adapter
+            .get(
+                &id,
+                GetOptions {
+                    rev: Some(rev.clone()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        if doc.attachments.is_empty() {
+            return Ok(json);
+        }
+
+        let mut attachments = serde_json::Map::new();
+        for (name, mut meta) in doc.attachments {
+            let opts = GetAttachmentOptions {
+                rev: Some(rev.clone()),
+                ..Default::default()
+            };
+            meta.data = Some(self.adapter.get_attachment(&id, &name, opts).await?);
+            meta.stub = false;
+            attachments.insert(name, serde_json::to_value(meta)?);
+        }
+        json["_attachments"] = serde_json::Value::Object(attachments);
+        Ok(json)
+    }
+
+    /// Replay a dump written by `dump` into this database.
+    ///
+    /// Revisions are written with `new_edits: false`, so revision trees,
+    /// conflicts and deletions come back exactly as dumped. A missing or
+    /// malformed header is a `BadRequest`.
+    pub async fn load(&self, reader: impl std::io::Read) -> Result<LoadStats> {
+        use std::io::BufRead;
+
+        let mut lines = std::io::BufReader::new(reader).lines();
+        let header: serde_json::Value = loop {
+            match lines.next().transpose()? {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => break serde_json::from_str(&line)?,
+                None => break serde_json::Value::Null,
+            }
+        };
+        if header["type"] != "header" {
+            return Err(RouchError::BadRequest(
+                "dump must start with a header line".into(),
+            ));
+        }
+
+        let mut stats = LoadStats::default();
+        let mut batch = Vec::with_capacity(LOAD_BATCH_SIZE);
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut record: serde_json::Value = serde_json::from_str(&line)?;
+            if record["type"] != "doc" {
+                continue;
+            }
+            batch.push(Document::from_json(record["doc"].take())?);
+            stats.docs_read += 1;
+            if batch.len() == LOAD_BATCH_SIZE {
+                self.load_batch(std::mem::take(&mut batch), &mut stats)
+                    .await?;
+            }
+        }
+        if !batch.is_empty() {
+            self.load_batch(batch, &mut stats).await?;
+        }
+        Ok(stats)
+    }
+
+    /// Seed this database from a PouchDB dump (`pouchdb-dump` /
+    /// `pouchdb-replication-stream` output).
+    ///
+    /// The dump is newline-delimited JSON: a header with `db_info`,
+    /// batches of `{"docs": [...]}` and `{"seq": ...}` checkpoints. Only
+    /// the document batches are used; each document's `_revisions` rebuilds
+    /// its ancestry and it is written with `new_edits: false`. Attachments
+    /// without inline data (`follows` or `stub`) can't be resolved from
+    /// the dump, so they are dropped and reported in `LoadStats::warnings`.
+    pub async fn load_pouch_dump(&self, reader: impl std::io::Read) -> Result<LoadStats> {
+        use std::io::BufRead;
+
+        let mut stats = LoadStats::default();
+        let mut batch = Vec::with_capacity(LOAD_BATCH_SIZE);
+        for line in std::io::BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut record: serde_json::Value = serde_json::from_str(&line)?;
+            if !record.is_object() {
+                return Err(RouchError::BadRequest(
+                    "dump lines must be JSON objects".into(),
+                ));
+            }
+            let Some(docs) = record.get_mut("docs").and_then(|d| d.as_array_mut()) else {
+                // Header and checkpoint lines
+                continue;
+            };
+            for mut json in docs.drain(..) {
+                drop_unresolved_attachments(&mut json, &mut stats.warnings);
+                batch.push(Document::from_json(json)?);
+                stats.docs_read += 1;
+                if batch.len() == LOAD_BATCH_SIZE {
+                    self.load_batch(std::mem::take(&mut batch), &mut stats)
+                        .await?;
+                }
+            }
+        }
+        if !batch.is_empty() {
+            self.load_batch(batch, &mut stats).await?;
+        }
+        Ok(stats)
+    }
+
+    async fn load_batch(&self, docs: Vec<Document>, stats: &mut LoadStats) -> Result<()> {
+        let results = self
+            .adapter
+            .bulk_docs(docs, BulkDocsOptions::replication())
+            .await?;
+        for result in results {
+            if result.ok {
+                stats.docs_written += 1;
+            } else {
+                stats.docs_failed += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove attachments that carry no inline `data`, noting each in
+/// `warnings`.
+fn drop_unresolved_attachments(doc: &mut serde_json::Value, warnings: &mut Vec<String>) {
+    let id = doc["_id"].as_str().unwrap_or_default().to_string();
+    let rev = doc["_rev"].as_str().unwrap_or_default().to_string();
+    let Some(attachments) = doc.get_mut("_attachments").and_then(|a| a.as_object_mut()) else {
+        return;
+    };
+    attachments.retain(|name, meta| {
+        let resolved = meta.get("data").is_some_and(|d| d.is_string());
+        if !resolved {
+            warnings.push(format!(
+                "skipped attachment {} of {}@{}: no data in dump",
+                name, id, rev
+            ));
+        }
+        resolved
+    });
+    if attachments.is_empty()
+        && let Some(obj) = doc.as_object_mut()
+    {
+        obj.remove("_attachments");
+    }
+}
+
+/// Several writes sent to the adapter as a single `bulk_docs` call.
+///
+/// Created with [`Database::batch`]. Each write succeeds or fails on its
+/// own, as with `bulk_docs`; `execute` returns one result per operation,
+/// in the order they were added.
+pub struct BatchBuilder<'a> {
+    db: &'a Database,
+    docs: Vec<Result<Document>>,
+}
+
+impl BatchBuilder<'_> {
+    /// Create a document, as `Database::put`.
+    pub fn put(mut self, id: &str, data: serde_json::Value) -> Self {
+        self.docs.push(edit_doc(id, None, data));
+        self
+    }
+
+    /// Update a document at `rev`, as `Database::update`.
+    pub fn update(mut self, id: &str, rev: &str, data: serde_json::Value) -> Self {
+        self.docs.push(edit_doc(id, Some(rev), data));
+        self
+    }
+
+    /// Delete a document at `rev`, as `Database::remove`.
+    pub fn remove(mut self, id: &str, rev: &str) -> Self {
+        self.docs.push(deletion_doc(id, rev));
+        self
+    }
+
+    /// Send every operation in one `bulk_docs` call.
+    ///
+    /// A malformed operation (an empty id or an unparsable rev) fails the
+    /// whole batch before anything is written. Conflicts and other
+    /// rejections are reported in that operation's `DocResult`.
+    pub async fn execute(self) -> Result<Vec<DocResult>> {
+        let docs = self.docs.into_iter().collect::<Result<Vec<_>>>()?;
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.db.bulk_docs(docs, BulkDocsOptions::new()).await
+    }
+}
+
 /// A partitioned view of a database.
 ///
 /// Scopes queries to documents whose `_id` starts with `"{partition}:"`.
@@ -921,6 +2030,188 @@ impl Partition<'_> {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn local_docs_stay_local() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("local.redb"), "local").unwrap();
+
+        for db in [Database::memory("local"), redb] {
+            db.put_local("_local/foo", serde_json::json!({"theme": "dark"}))
+                .await
+                .unwrap();
+            db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
+
+            // The prefix is optional on lookup.
+            assert_eq!(db.get_local("foo").await.unwrap()["theme"], "dark");
+            assert_eq!(db.get_local("_local/foo").await.unwrap()["theme"], "dark");
+
+            let changes = db.changes(ChangesOptions::default()).await.unwrap();
+            let ids: Vec<&str> = changes.results.iter().map(|r| r.id.as_str()).collect();
+            assert_eq!(ids, vec!["doc1"]);
+            let all = db.all_docs(AllDocsOptions::new()).await.unwrap();
+            assert_eq!(all.rows.len(), 1);
+
+            let target = Database::memory("target");
+            db.replicate_to(&target).await.unwrap();
+            assert!(target.get("doc1").await.is_ok());
+            assert!(target.get_local("foo").await.is_err());
+
+            db.remove_local("_local/foo").await.unwrap();
+            assert!(db.get_local("foo").await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn dump_and_load_preserve_rev_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Database::open(dir.path().join("dump.redb"), "source").unwrap();
+
+        let r1 = source.put("a", serde_json::json!({"v": 1})).await.unwrap();
+        let r2 = source
+            .update("a", &r1.rev.unwrap(), serde_json::json!({"v": 2}))
+            .await
+            .unwrap();
+        source
+            .put_attachment(
+                "a",
+                "note.txt",
+                &r2.rev.unwrap(),
+                b"hello".to_vec(),
+                "text/plain",
+            )
+            .await
+            .unwrap();
+        let branch = Document {
+            id: "a".into(),
+            rev: Some(Revision::new(1, "ffffffffffffffffffffffffffffffff".into())),
+            deleted: false,
+            data: serde_json::json!({"v": "branch"}),
+            attachments: HashMap::new(),
+        };
+        source
+            .bulk_docs(vec![branch], BulkDocsOptions::replication())
+            .await
+            .unwrap();
+        let b = source.put("b", serde_json::json!({})).await.unwrap();
+        source.remove("b", &b.rev.unwrap()).await.unwrap();
+
+        let mut out = Vec::new();
+        source.dump(&mut out).await.unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text.lines().count(), 4);
+
+        let target = Database::memory("target");
+        let stats = target.load(text.as_bytes()).await.unwrap();
+        assert_eq!(
+            stats,
+            LoadStats {
+                docs_read: 3,
+                docs_written: 3,
+                ..Default::default()
+            }
+        );
+
+        async fn leaves(db: &Database) -> Vec<(String, Vec<String>, bool)> {
+            let changes = db
+                .changes(ChangesOptions {
+                    style: ChangesStyle::AllDocs,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            let mut leaves: Vec<(String, Vec<String>, bool)> = changes
+                .results
+                .into_iter()
+                .map(|c| {
+                    let mut revs: Vec<String> = c.changes.into_iter().map(|r| r.rev).collect();
+                    revs.sort();
+                    (c.id, revs, c.deleted)
+                })
+                .collect();
+            leaves.sort();
+            leaves
+        }
+        assert_eq!(leaves(&target).await, leaves(&source).await);
+
+        let conflicted = GetOptions {
+            conflicts: true,
+            ..Default::default()
+        };
+        let original = source.get_with_opts("a", conflicted.clone()).await.unwrap();
+        let loaded = target.get_with_opts("a", conflicted).await.unwrap();
+        assert_eq!(loaded.rev, original.rev);
+        assert_eq!(loaded.data, original.data);
+        assert_eq!(
+            target.get_attachment("a", "note.txt").await.unwrap(),
+            b"hello"
+        );
+        assert!(matches!(
+            target.get("b").await,
+            Err(RouchError::NotFound(_))
+        ));
+
+        // Full ancestry survives, not just the leaves
+        async fn history(db: &Database, rev: &str) -> serde_json::Value {
+            let item = BulkGetItem {
+                id: "a".into(),
+                rev: Some(rev.to_string()),
+            };
+            let resp = db.adapter().bulk_get(vec![item]).await.unwrap();
+            resp.results[0].docs[0].ok.as_ref().unwrap()["_revisions"].clone()
+        }
+        let rev = original.rev.as_ref().unwrap().to_string();
+        assert_eq!(history(&target, &rev).await, history(&source, &rev).await);
+        assert_eq!(
+            history(&target, &rev).await["ids"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn load_pouch_dump_rebuilds_ancestry() {
+        let dump = r#"{"version":"1.2.6","db_type":"idb","start_time":"2024-01-01T00:00:00.000Z","db_info":{"db_name":"src","doc_count":2,"update_seq":5}}
+{"docs":[{"_id":"a","_rev":"3-cccccccccccccccccccccccccccccccc","v":3,"_revisions":{"start":3,"ids":["cccccccccccccccccccccccccccccccc","bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb","aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"]}},{"_id":"b","_rev":"1-dddddddddddddddddddddddddddddddd","_revisions":{"start":1,"ids":["dddddddddddddddddddddddddddddddd"]},"_attachments":{"inline.txt":{"content_type":"text/plain","data":"aGk="},"big.bin":{"content_type":"application/octet-stream","follows":true,"length":9}}}]}
+{"seq":5}
+"#;
+        let db = Database::memory("target");
+        let stats = db.load_pouch_dump(dump.as_bytes()).await.unwrap();
+        assert_eq!((stats.docs_read, stats.docs_written), (2, 2));
+        assert_eq!(stats.warnings.len(), 1);
+        assert!(stats.warnings[0].contains("big.bin"));
+
+        let a = db.get("a").await.unwrap();
+        assert_eq!(
+            a.rev.unwrap().to_string(),
+            "3-cccccccccccccccccccccccccccccccc"
+        );
+        assert_eq!(a.data["v"], 3);
+        let resp = db
+            .adapter()
+            .bulk_get(vec![BulkGetItem {
+                id: "a".into(),
+                rev: Some("3-cccccccccccccccccccccccccccccccc".into()),
+            }])
+            .await
+            .unwrap();
+        assert_eq!(
+            resp.results[0].docs[0].ok.as_ref().unwrap()["_revisions"],
+            serde_json::json!({"start": 3, "ids": ["cccccccccccccccccccccccccccccccc", "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"]})
+        );
+
+        assert_eq!(db.get_attachment("b", "inline.txt").await.unwrap(), b"hi");
+        assert!(db.get_attachment("b", "big.bin").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn load_requires_a_header() {
+        let db = Database::memory("test");
+        let err = db.load(&b"{\"type\":\"doc\",\"doc\":{}}\n"[..]).await;
+        assert!(matches!(err, Err(RouchError::BadRequest(_))));
+    }
+
     #[tokio::test]
     async fn database_put_and_get() {
         let db = Database::memory("test");
@@ -990,22 +2281,87 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn database_sync() {
-        let local = Database::memory("local");
-        let remote = Database::memory("remote");
+    async fn find_paginates_with_bookmarks() {
+        let db = Database::memory("test");
+        for i in 0..10 {
+            db.put(&format!("doc{}", i), serde_json::json!({"n": i}))
+                .await
+                .unwrap();
+        }
 
-        local
-            .put("doc1", serde_json::json!({"from": "local"}))
+        let page = |bookmark: Option<String>| FindOptions {
+            selector: serde_json::json!({"n": {"$gte": -100}}),
+            sort: Some(vec![SortField::Simple("n".into())]),
+            limit: Some(3),
+            bookmark,
+            allow_fallback: true,
+            ..Default::default()
+        };
+
+        let mut seen = Vec::new();
+        let mut bookmark = None;
+        loop {
+            let result = db.find(page(bookmark)).await.unwrap();
+            if result.docs.is_empty() {
+                break;
+            }
+            assert!(result.docs.len() <= 3);
+            seen.extend(result.docs.iter().map(|d| d["n"].as_i64().unwrap()));
+            bookmark = Some(result.bookmark);
+
+            // A doc sorting before the current position must not shift
+            // later pages.
+            if seen.len() == 3 {
+                db.put("early", serde_json::json!({"n": -1})).await.unwrap();
+            }
+        }
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+
+        let bad = db.find(page(Some("not a bookmark".into()))).await;
+        assert!(matches!(bad, Err(RouchError::BadRequest(_))));
+
+        // An empty page's "nil" bookmark starts from the beginning again
+        let empty = db
+            .find(FindOptions {
+                selector: serde_json::json!({"n": 100}),
+                ..Default::default()
+            })
             .await
             .unwrap();
-        remote
-            .put("doc2", serde_json::json!({"from": "remote"}))
+        assert_eq!(empty.bookmark, "nil");
+        let first = db.find(page(Some(empty.bookmark))).await.unwrap();
+        assert_eq!(first.docs[0]["n"], -1);
+        let streamed: Vec<_> = db
+            .find_stream(FindOptions {
+                selector: serde_json::json!({}),
+                bookmark: Some("nil".into()),
+                ..Default::default()
+            })
+            .try_collect()
             .await
             .unwrap();
+        assert_eq!(streamed.len(), 11);
+    }
 
-        let (push, pull) = local.sync(&remote).await.unwrap();
-        assert!(push.ok);
-        assert!(pull.ok);
+    #[tokio::test]
+    async fn database_sync() {
+        let local = Database::memory("local");
+        let remote = Database::memory("remote");
+
+        local
+            .put("doc1", serde_json::json!({"from": "local"}))
+            .await
+            .unwrap();
+        remote
+            .put("doc2", serde_json::json!({"from": "remote"}))
+            .await
+            .unwrap();
+
+        let result = local.sync(&remote).await;
+        assert!(result.is_ok());
+        let (push, pull) = result.into_result().unwrap();
+        assert_eq!(push.docs_written, 1);
+        assert_eq!(pull.docs_written, 1);
 
         // Both should have both docs
         let local_info = local.info().await.unwrap();
@@ -1014,6 +2370,597 @@ mod tests {
         assert_eq!(remote_info.doc_count, 2);
     }
 
+    #[tokio::test]
+    async fn conflicts_returns_losing_leaves() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("conflicts.redb"), "conflicts").unwrap();
+
+        for db in [Database::memory("conflicts"), redb] {
+            let other = Database::memory("other");
+            let rev1 = db
+                .put("doc1", serde_json::json!({"side": "base"}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            assert!(db.conflicts("doc1").await.unwrap().is_empty());
+
+            db.replicate_to(&other).await.unwrap();
+            db.update("doc1", &rev1, serde_json::json!({"side": "a"}))
+                .await
+                .unwrap();
+            other
+                .update("doc1", &rev1, serde_json::json!({"side": "b"}))
+                .await
+                .unwrap();
+            db.replicate_from(&other).await.unwrap();
+
+            let winner = db.get("doc1").await.unwrap();
+            let losing = if winner.data["side"] == "a" { "b" } else { "a" };
+            let conflicts = db.conflicts("doc1").await.unwrap();
+            assert_eq!(conflicts.len(), 1);
+            assert_eq!(conflicts[0].data["side"], losing);
+            assert_ne!(conflicts[0].rev, winner.rev);
+
+            assert!(matches!(
+                db.conflicts("missing").await,
+                Err(RouchError::NotFound(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn winning_rev_matches_get_on_conflicted_doc() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("winner.redb"), "winner").unwrap();
+
+        for db in [Database::memory("winner"), redb] {
+            let other = Database::memory("other");
+            let rev1 = db
+                .put("doc1", serde_json::json!({"side": "base"}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            db.replicate_to(&other).await.unwrap();
+            db.update("doc1", &rev1, serde_json::json!({"side": "a"}))
+                .await
+                .unwrap();
+            other
+                .update("doc1", &rev1, serde_json::json!({"side": "b"}))
+                .await
+                .unwrap();
+            db.replicate_from(&other).await.unwrap();
+            assert_eq!(db.conflicts("doc1").await.unwrap().len(), 1);
+
+            let winner = db.winning_rev("doc1").await.unwrap();
+            assert_eq!(Some(winner.clone()), db.get("doc1").await.unwrap().rev);
+
+            db.remove("doc1", &winner.to_string()).await.unwrap();
+            // The surviving branch takes over
+            let winner = db.winning_rev("doc1").await.unwrap();
+            assert_eq!(Some(winner.clone()), db.get("doc1").await.unwrap().rev);
+            db.remove("doc1", &winner.to_string()).await.unwrap();
+            assert!(matches!(
+                db.winning_rev("doc1").await,
+                Err(RouchError::NotFound(_))
+            ));
+            assert!(matches!(
+                db.winning_rev("missing").await,
+                Err(RouchError::NotFound(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_conflict_leaves_one_branch() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("resolve.redb"), "resolve").unwrap();
+
+        for db in [Database::memory("resolve"), redb] {
+            let other = Database::memory("other");
+            let rev1 = db
+                .put("doc1", serde_json::json!({"side": "base"}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            db.replicate_to(&other).await.unwrap();
+            db.update("doc1", &rev1, serde_json::json!({"side": "a"}))
+                .await
+                .unwrap();
+            other
+                .update("doc1", &rev1, serde_json::json!({"side": "b"}))
+                .await
+                .unwrap();
+            db.replicate_from(&other).await.unwrap();
+            assert_eq!(db.conflicts("doc1").await.unwrap().len(), 1);
+
+            let merged = serde_json::json!({"side": "merged"});
+            let resolved = db.resolve_conflict("doc1", merged.clone()).await.unwrap();
+            assert!(resolved.ok);
+            assert!(db.conflicts("doc1").await.unwrap().is_empty());
+
+            let doc = db.get("doc1").await.unwrap();
+            assert_eq!(doc.rev.unwrap().to_string(), resolved.rev);
+            assert_eq!(doc.data, merged);
+            assert!(resolved.rev.starts_with("3-"));
+
+            // The resolution replicates: the other side loses its conflict too
+            db.replicate_to(&other).await.unwrap();
+            assert!(other.conflicts("doc1").await.unwrap().is_empty());
+            assert_eq!(other.get("doc1").await.unwrap().data, merged);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_conflict_reports_failed_tombstones() {
+        let mut db = Database::memory("resolve");
+        let other = Database::memory("other");
+        let rev1 = db
+            .put("doc1", serde_json::json!({}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+        db.replicate_to(&other).await.unwrap();
+        db.update("doc1", &rev1, serde_json::json!({"side": "a"}))
+            .await
+            .unwrap();
+        other
+            .update("doc1", &rev1, serde_json::json!({"side": "b"}))
+            .await
+            .unwrap();
+        db.replicate_from(&other).await.unwrap();
+
+        db.set_validator(Arc::new(|doc, _| match doc.deleted {
+            true => Err(RouchError::Forbidden("no deletes".into())),
+            false => Ok(()),
+        }));
+        let err = db
+            .resolve_conflict("doc1", serde_json::json!({"side": "merged"}))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RouchError::Forbidden(_)));
+    }
+
+    #[tokio::test]
+    async fn attachment_content_type_defaults_and_guesses() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("mime.redb"), "test").unwrap(),
+        ] {
+            let mut rev = db
+                .put("doc", serde_json::json!({}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            let guess = PutAttachmentOptions {
+                guess: true,
+                ..Default::default()
+            };
+            for name in ["greeting.txt", "blob.unknownext"] {
+                let result = db
+                    .put_attachment_with_opts("doc", name, &rev, b"hi".to_vec(), guess.clone())
+                    .await
+                    .unwrap();
+                rev = result.rev.unwrap();
+            }
+            // Without `guess`, an empty type is stored as binary
+            db.put_attachment("doc", "plain.txt", &rev, b"hi".to_vec(), "")
+                .await
+                .unwrap();
+
+            let atts = db.get("doc").await.unwrap().attachments;
+            assert_eq!(atts["greeting.txt"].content_type, "text/plain");
+            assert_eq!(atts["blob.unknownext"].content_type, mime::OCTET_STREAM);
+            assert_eq!(atts["plain.txt"].content_type, mime::OCTET_STREAM);
+        }
+    }
+
+    #[tokio::test]
+    async fn attachment_range_reads_a_slice() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("range.redb"), "test").unwrap(),
+        ] {
+            let rev = db
+                .put("doc", serde_json::json!({}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            db.put_attachment("doc", "blob.bin", &rev, data.clone(), "")
+                .await
+                .unwrap();
+            let read = |range| {
+                db.get_attachment_with_opts(
+                    "doc",
+                    "blob.bin",
+                    GetAttachmentOptions {
+                        range: Some(range),
+                        ..Default::default()
+                    },
+                )
+            };
+
+            let slice = read((100, Some(199))).await.unwrap();
+            assert_eq!(slice.len(), 100);
+            assert_eq!(slice, data[100..200]);
+
+            // Open and overlong ends run to the last byte
+            assert_eq!(read((990, None)).await.unwrap(), data[990..]);
+            assert_eq!(read((990, Some(5000))).await.unwrap(), data[990..]);
+            assert!(matches!(
+                read((1000, None)).await,
+                Err(RouchError::BadRequest(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn replication_copies_attachments_through_redb() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = Database::memory("source");
+        let redb = Database::open(dir.path().join("atts.redb"), "atts").unwrap();
+        let back = Database::memory("back");
+
+        let rev = source
+            .put("doc1", serde_json::json!({"v": 1}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+        let bytes = vec![7u8; 1024];
+        source
+            .put_attachment(
+                "doc1",
+                "a.bin",
+                &rev,
+                bytes.clone(),
+                "application/octet-stream",
+            )
+            .await
+            .unwrap();
+
+        source.replicate_to(&redb).await.unwrap();
+        redb.replicate_to(&back).await.unwrap();
+        assert_eq!(redb.get_attachment("doc1", "a.bin").await.unwrap(), bytes);
+        assert_eq!(back.get_attachment("doc1", "a.bin").await.unwrap(), bytes);
+
+        // A later edit keeps the attachment as a stub the target already has
+        let doc = source.get("doc1").await.unwrap();
+        let mut body = doc.to_json();
+        body["v"] = serde_json::json!(2);
+        source
+            .update("doc1", &doc.rev.unwrap().to_string(), body)
+            .await
+            .unwrap();
+        let result = source.replicate_to(&redb).await.unwrap();
+        assert!(result.ok, "{:?}", result.errors);
+        assert_eq!(redb.get("doc1").await.unwrap().data["v"], 2);
+        assert_eq!(redb.get_attachment("doc1", "a.bin").await.unwrap(), bytes);
+    }
+
+    #[tokio::test]
+    async fn query_runs_registered_views_by_name() {
+        let db = Database::memory("views");
+        for (id, n) in [("a", 1), ("b", 2), ("c", 3), ("d", 4)] {
+            db.put(id, serde_json::json!({"n": n})).await.unwrap();
+        }
+        db.register_view(
+            "app",
+            "by_n",
+            |doc| vec![(doc["n"].clone(), serde_json::json!(1))],
+            Some(ReduceFn::Count),
+        )
+        .await;
+
+        let rows = db
+            .query(
+                "_design/app",
+                "by_n",
+                ViewQueryOptions {
+                    start_key: Some(serde_json::json!(2)),
+                    end_key: Some(serde_json::json!(3)),
+                    ..ViewQueryOptions::new()
+                },
+            )
+            .await
+            .unwrap()
+            .rows;
+        let ids: Vec<_> = rows.iter().map(|r| r.id.as_deref().unwrap()).collect();
+        assert_eq!(ids, ["b", "c"]);
+
+        // The index picks up later writes, and the reduce runs on request
+        db.put("e", serde_json::json!({"n": 5})).await.unwrap();
+        let reduced = db
+            .query(
+                "app",
+                "by_n",
+                ViewQueryOptions {
+                    start_key: Some(serde_json::json!(2)),
+                    reduce: true,
+                    ..ViewQueryOptions::new()
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(reduced.rows[0].value, 4);
+
+        assert!(matches!(
+            db.query("app", "missing", ViewQueryOptions::new()).await,
+            Err(RouchError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn view_queries_only_wait_for_their_own_view() {
+        let db = Arc::new(Database::memory("views"));
+        db.put("a", serde_json::json!({"n": 1})).await.unwrap();
+
+        // The slow view's map function blocks until released
+        let (entered_tx, entered_rx) = std::sync::mpsc::channel();
+        let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+        let gate = std::sync::Mutex::new(Some((entered_tx, release_rx)));
+        db.register_view(
+            "app",
+            "slow",
+            move |doc| {
+                if let Some((entered, release)) = gate.lock().unwrap().take() {
+                    entered.send(()).unwrap();
+                    release.recv().unwrap();
+                }
+                vec![(doc["n"].clone(), serde_json::Value::Null)]
+            },
+            None,
+        )
+        .await;
+        let slow = tokio::spawn({
+            let db = db.clone();
+            async move { db.query("app", "slow", ViewQueryOptions::new()).await }
+        });
+        tokio::task::block_in_place(|| entered_rx.recv()).unwrap();
+
+        // Registering and querying another view goes ahead meanwhile
+        let fast = async {
+            db.register_view(
+                "app",
+                "fast",
+                |doc| vec![(doc["n"].clone(), serde_json::Value::Null)],
+                None,
+            )
+            .await;
+            db.query("app", "fast", ViewQueryOptions::new()).await
+        };
+        let fast = tokio::time::timeout(std::time::Duration::from_secs(2), fast)
+            .await
+            .expect("query waited for another view")
+            .unwrap();
+        assert_eq!(fast.rows.len(), 1);
+
+        release_tx.send(()).unwrap();
+        assert_eq!(slow.await.unwrap().unwrap().rows.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn batch_reports_each_operation() {
+        let db = Database::memory("batch");
+        let stale = db
+            .put("existing", serde_json::json!({"v": 1}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+        let current = db
+            .update("existing", &stale, serde_json::json!({"v": 2}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+        let doomed = db
+            .put("doomed", serde_json::json!({}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+
+        let results = db
+            .batch()
+            .put("fresh", serde_json::json!({"v": 1}))
+            .update("existing", &stale, serde_json::json!({"v": 3}))
+            .remove("doomed", &doomed)
+            .execute()
+            .await
+            .unwrap();
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["fresh", "existing", "doomed"]);
+        assert!(results[0].ok);
+        assert!(!results[1].ok);
+        assert_eq!(results[1].error.as_deref(), Some("conflict"));
+        assert!(results[2].ok);
+
+        let existing = db.get("existing").await.unwrap();
+        assert_eq!(existing.rev.unwrap().to_string(), current);
+        assert!(!db.exists("doomed").await.unwrap());
+
+        // A malformed operation fails the batch before anything is written
+        let malformed = db
+            .batch()
+            .put("never", serde_json::json!({}))
+            .update("existing", "not-a-rev", serde_json::json!({}))
+            .execute()
+            .await;
+        assert!(malformed.is_err());
+        assert!(!db.exists("never").await.unwrap());
+        assert!(db.batch().execute().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_open_revs_returns_conflicting_leaves() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("open_revs.redb"), "open_revs").unwrap();
+
+        for db in [Database::memory("open_revs"), redb] {
+            let other = Database::memory("other");
+            let rev1 = db
+                .put("doc1", serde_json::json!({"side": "base"}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            db.replicate_to(&other).await.unwrap();
+            db.update("doc1", &rev1, serde_json::json!({"side": "a"}))
+                .await
+                .unwrap();
+            other
+                .update("doc1", &rev1, serde_json::json!({"side": "b"}))
+                .await
+                .unwrap();
+            db.replicate_from(&other).await.unwrap();
+
+            let leaves = db.get_open_revs("doc1", OpenRevs::All).await.unwrap();
+            assert_eq!(leaves.len(), 2);
+            let mut sides: Vec<&str> = leaves
+                .iter()
+                .map(|d| d.data["side"].as_str().unwrap())
+                .collect();
+            sides.sort();
+            assert_eq!(sides, ["a", "b"]);
+            let winner = db.get("doc1").await.unwrap();
+            assert_eq!(leaves[0].rev, winner.rev);
+
+            let specific = db
+                .get_open_revs("doc1", OpenRevs::Specific(vec![rev1.clone()]))
+                .await
+                .unwrap();
+            assert_eq!(specific.len(), 1);
+            assert_eq!(specific[0].data["side"], "base");
+
+            let bogus = OpenRevs::Specific(vec!["2-0123456789abcdef0123456789abcdef".into()]);
+            assert!(matches!(
+                db.get_open_revs("doc1", bogus).await,
+                Err(RouchError::NotFound(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn changes_all_docs_style_lists_every_leaf() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("style.redb"), "style").unwrap();
+
+        for db in [Database::memory("style"), redb] {
+            let other = Database::memory("other");
+            let rev1 = db
+                .put("doc1", serde_json::json!({"side": "base"}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            db.replicate_to(&other).await.unwrap();
+            db.update("doc1", &rev1, serde_json::json!({"side": "a"}))
+                .await
+                .unwrap();
+            other
+                .update("doc1", &rev1, serde_json::json!({"side": "b"}))
+                .await
+                .unwrap();
+            db.replicate_from(&other).await.unwrap();
+
+            let changes = |style| {
+                let db = &db;
+                async move {
+                    let response = db
+                        .changes(ChangesOptions {
+                            style,
+                            ..Default::default()
+                        })
+                        .await
+                        .unwrap();
+                    assert_eq!(response.results.len(), 1);
+                    response.results[0].changes.clone()
+                }
+            };
+
+            let main_only = changes(ChangesStyle::MainOnly).await;
+            let winner = db.get("doc1").await.unwrap().rev.unwrap().to_string();
+            assert_eq!(main_only.len(), 1);
+            assert_eq!(main_only[0].rev, winner);
+
+            let all_docs = changes(ChangesStyle::AllDocs).await;
+            assert_eq!(all_docs.len(), 2);
+            assert!(all_docs.iter().any(|c| c.rev == winner));
+        }
+    }
+
+    #[tokio::test]
+    async fn replication_conflict_hook_picks_a_winner() {
+        let local = Database::memory("local");
+        let remote = Database::memory("remote");
+
+        let r1 = local
+            .put("doc1", serde_json::json!({"version": 1}))
+            .await
+            .unwrap();
+        local.replicate_to(&remote).await.unwrap();
+        let rev1 = r1.rev.unwrap();
+
+        // Concurrent edits on both sides
+        local
+            .update("doc1", &rev1, serde_json::json!({"version": 3}))
+            .await
+            .unwrap();
+        remote
+            .update("doc1", &rev1, serde_json::json!({"version": 2}))
+            .await
+            .unwrap();
+
+        let seen = Arc::new(std::sync::Mutex::new(0usize));
+        let calls = seen.clone();
+        let result = local
+            .replicate_to_with_opts(
+                &remote,
+                ReplicationOptions {
+                    on_conflict: Some(Arc::new(move |id: &str, leaves: &[Document]| {
+                        assert_eq!(id, "doc1");
+                        *calls.lock().unwrap() = leaves.len();
+                        leaves
+                            .iter()
+                            .max_by_key(|d| d.data["version"].as_u64())
+                            .cloned()
+                    })),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(result.ok);
+        assert_eq!(*seen.lock().unwrap(), 2);
+
+        local.replicate_from(&remote).await.unwrap();
+
+        for db in [&local, &remote] {
+            let doc = db
+                .get_with_opts(
+                    "doc1",
+                    GetOptions {
+                        conflicts: true,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+            assert_eq!(doc.data["version"], 3);
+            assert!(doc.data.get("_conflicts").is_none());
+            assert_eq!(doc.rev.unwrap().pos, 3);
+        }
+    }
+
     #[tokio::test]
     async fn database_info() {
         let db = Database::memory("test");
@@ -1025,6 +2972,368 @@ mod tests {
         assert_eq!(info.db_name, "test");
     }
 
+    #[tokio::test]
+    async fn all_docs_exclusive_end_in_both_directions() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("range.redb"), "test").unwrap(),
+        ] {
+            for id in ["apple", "banana", "cherry", "date", "elderberry"] {
+                db.put(id, serde_json::json!({})).await.unwrap();
+            }
+            let range = |start: &str, descending, inclusive_end| AllDocsOptions {
+                start_key: Some(start.into()),
+                end_key: Some("date".into()),
+                descending,
+                inclusive_end,
+                ..AllDocsOptions::new()
+            };
+
+            for (opts, expected) in [
+                (
+                    range("banana", false, true),
+                    vec!["banana", "cherry", "date"],
+                ),
+                (range("banana", false, false), vec!["banana", "cherry"]),
+                (range("elderberry", true, true), vec!["elderberry", "date"]),
+                (range("elderberry", true, false), vec!["elderberry"]),
+            ] {
+                let rows = db.all_docs(opts.clone()).await.unwrap().rows;
+                let ids: Vec<&str> = rows.iter().map(|r| r.id.as_str()).collect();
+                assert_eq!(ids, expected, "{opts:?}");
+
+                let streamed: Vec<AllDocsRow> =
+                    db.all_docs_stream(opts).try_collect().await.unwrap();
+                let ids: Vec<&str> = streamed.iter().map(|r| r.id.as_str()).collect();
+                assert_eq!(ids, expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn all_docs_include_deleted_lists_tombstones() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("tombstones.redb"), "test").unwrap(),
+        ] {
+            for id in ["a", "b", "c"] {
+                db.put(id, serde_json::json!({"v": 1})).await.unwrap();
+            }
+            let b = db.get("b").await.unwrap();
+            db.remove("b", &b.rev.unwrap().to_string()).await.unwrap();
+
+            let ids = |rows: &[AllDocsRow]| rows.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+            let live = db.all_docs(AllDocsOptions::new()).await.unwrap();
+            assert_eq!(ids(&live.rows), ["a", "c"]);
+
+            let all = db
+                .all_docs(AllDocsOptions {
+                    include_deleted: true,
+                    ..AllDocsOptions::new()
+                })
+                .await
+                .unwrap();
+            assert_eq!(ids(&all.rows), ["a", "b", "c"]);
+            assert_eq!(all.rows[1].value.deleted, Some(true));
+            assert_eq!(all.rows[0].value.deleted, None);
+            assert!(all.rows[1].doc.is_none());
+
+            let with_docs = db
+                .all_docs(AllDocsOptions {
+                    include_deleted: true,
+                    include_docs: true,
+                    key: Some("b".into()),
+                    ..AllDocsOptions::new()
+                })
+                .await
+                .unwrap();
+            let tombstone = with_docs.rows[0].doc.as_ref().unwrap();
+            assert_eq!(tombstone["_deleted"], true);
+
+            let streamed: Vec<AllDocsRow> = db
+                .all_docs_stream(AllDocsOptions {
+                    include_deleted: true,
+                    ..AllDocsOptions::new()
+                })
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(ids(&streamed), ["a", "b", "c"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_with_revs_returns_winning_history() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("revs.redb"), "test").unwrap(),
+        ] {
+            let mut revs = Vec::new();
+            let mut rev = db
+                .put("doc", serde_json::json!({"v": 1}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            revs.push(rev.clone());
+            for v in 2..=3 {
+                rev = db
+                    .update("doc", &rev, serde_json::json!({"v": v}))
+                    .await
+                    .unwrap()
+                    .rev
+                    .unwrap();
+                revs.push(rev.clone());
+            }
+
+            let opts = GetOptions {
+                revs: true,
+                ..Default::default()
+            };
+            let doc = db.get_with_opts("doc", opts).await.unwrap();
+            let history = &doc.data["_revisions"];
+            assert_eq!(history["start"], 3);
+            let hashes: Vec<&str> = revs
+                .iter()
+                .rev()
+                .map(|r| r.split_once('-').unwrap().1)
+                .collect();
+            assert_eq!(history["ids"], serde_json::json!(hashes));
+
+            // Without the option the body stays clean
+            let plain = db.get("doc").await.unwrap();
+            assert!(plain.data.get("_revisions").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn get_with_revs_info_reports_missing_ancestors() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("revs_info.redb"), "test").unwrap(),
+        ] {
+            // Replicated with ancestry but without the ancestors' bodies
+            let doc = Document {
+                id: "doc".into(),
+                rev: Some(Revision::new(3, "c".repeat(32))),
+                deleted: false,
+                data: serde_json::json!({
+                    "_revisions": {"start": 3, "ids": ["c".repeat(32), "b".repeat(32), "a".repeat(32)]}
+                }),
+                attachments: HashMap::new(),
+            };
+            db.bulk_docs(vec![doc], BulkDocsOptions::replication())
+                .await
+                .unwrap();
+
+            let opts = GetOptions {
+                revs_info: true,
+                ..Default::default()
+            };
+            let doc = db.get_with_opts("doc", opts).await.unwrap();
+            assert_eq!(
+                doc.data["_revs_info"],
+                serde_json::json!([
+                    {"rev": format!("3-{}", "c".repeat(32)), "status": "available"},
+                    {"rev": format!("2-{}", "b".repeat(32)), "status": "missing"},
+                    {"rev": format!("1-{}", "a".repeat(32)), "status": "missing"},
+                ])
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn modify_applies_closure_to_current_body() {
+        let db = Database::memory("test");
+        db.put("counter", serde_json::json!({"n": 1}))
+            .await
+            .unwrap();
+
+        let response = db
+            .modify("counter", |mut body| {
+                body["n"] = serde_json::json!(body["n"].as_i64().unwrap() + 1);
+                body
+            })
+            .await
+            .unwrap();
+        assert!(response.rev.starts_with("2-"));
+        let doc = db.get("counter").await.unwrap();
+        assert_eq!(doc.data["n"], 2);
+        assert_eq!(doc.rev.unwrap().to_string(), response.rev);
+
+        let missing = db.modify("missing", |body| body).await;
+        assert!(matches!(missing, Err(RouchError::NotFound(_))));
+        assert!(db.get("missing").await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn modify_conflicts_when_rev_moves() {
+        let db = Database::memory("test");
+        let rev = db
+            .put("doc", serde_json::json!({"v": 1}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+
+        // Another writer gets in while the closure runs
+        let result = db
+            .modify("doc", |body| {
+                tokio::task::block_in_place(|| {
+                    tokio::runtime::Handle::current()
+                        .block_on(db.update("doc", &rev, serde_json::json!({"v": 2})))
+                        .unwrap();
+                });
+                body
+            })
+            .await;
+        assert!(matches!(result, Err(RouchError::Conflict)));
+        assert_eq!(db.get("doc").await.unwrap().data["v"], 2);
+    }
+
+    #[tokio::test]
+    async fn database_info_counts_deleted_docs() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("info.redb"), "test").unwrap(),
+        ] {
+            for id in ["a", "b", "c"] {
+                db.put(id, serde_json::json!({})).await.unwrap();
+            }
+            let b = db.get("b").await.unwrap();
+            db.remove("b", &b.rev.unwrap().to_string()).await.unwrap();
+
+            let info = db.info().await.unwrap();
+            assert_eq!(info.doc_count, 2);
+            assert_eq!(info.doc_del_count, 1);
+            assert!(info.sizes.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn revs_diff_reports_only_unknown_revs() {
+        let dir = tempfile::tempdir().unwrap();
+        let redb = Database::open(dir.path().join("diff.redb"), "diff").unwrap();
+
+        for db in [Database::memory("diff"), redb] {
+            let rev = db
+                .put("doc1", serde_json::json!({"v": 1}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            let bogus = "2-0123456789abcdef0123456789abcdef".to_string();
+
+            let mut revs = HashMap::new();
+            revs.insert("doc1".to_string(), vec![rev.clone(), bogus.clone()]);
+            let diff = db.revs_diff(revs).await.unwrap();
+
+            let result = &diff.results["doc1"];
+            assert_eq!(result.missing, vec![bogus]);
+            assert_eq!(result.possible_ancestors, vec![rev]);
+        }
+    }
+
+    #[tokio::test]
+    async fn all_docs_stream_yields_every_row_in_order() {
+        use futures_util::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let memory = Database::memory("test");
+        let redb = Database::open(dir.path().join("stream.redb"), "stream").unwrap();
+
+        for db in [memory, redb] {
+            let docs = (0..1000)
+                .map(|i| Document {
+                    id: format!("doc{:04}", i),
+                    rev: None,
+                    deleted: false,
+                    data: serde_json::json!({"i": i}),
+                    attachments: HashMap::new(),
+                })
+                .collect();
+            db.bulk_docs(docs, BulkDocsOptions::new()).await.unwrap();
+
+            let mut stream = db.all_docs_stream(AllDocsOptions::new());
+            let mut count = 0;
+            while let Some(row) = stream.next().await {
+                assert_eq!(row.unwrap().id, format!("doc{:04}", count));
+                count += 1;
+            }
+            assert_eq!(count, 1000);
+
+            let ids: Vec<String> = db
+                .all_docs_stream(AllDocsOptions {
+                    descending: true,
+                    limit: Some(150),
+                    ..AllDocsOptions::new()
+                })
+                .map(|row| row.unwrap().id)
+                .collect()
+                .await;
+            assert_eq!(ids.len(), 150);
+            assert_eq!(ids[0], "doc0999");
+            assert_eq!(ids[149], "doc0850");
+        }
+    }
+
+    #[tokio::test]
+    async fn attachment_streams_round_trip() {
+        use tokio::io::AsyncReadExt;
+
+        const SIZE: usize = 10 * 1024 * 1024;
+        let byte_at = |i: usize| (i % 251) as u8;
+
+        let dir = tempfile::tempdir().unwrap();
+        let memory = Database::memory("test");
+        let redb = Database::open(dir.path().join("att.redb"), "att").unwrap();
+
+        // Memory holds attachments whole anyway; redb reads one stored
+        // chunk at a time
+        for (db, chunked) in [(memory, false), (redb, true)] {
+            let r = db.put("doc1", serde_json::json!({})).await.unwrap();
+            let body: Vec<u8> = (0..SIZE).map(byte_at).collect();
+            let r = db
+                .put_attachment_stream(
+                    "doc1",
+                    "big.bin",
+                    &r.rev.unwrap(),
+                    "application/octet-stream",
+                    std::io::Cursor::new(body),
+                )
+                .await
+                .unwrap();
+            assert!(r.ok);
+
+            // Compare chunk by chunk against the pattern
+            let mut reader = db
+                .get_attachment_stream("doc1", "big.bin", GetAttachmentOptions::default())
+                .await
+                .unwrap();
+            let mut buf = vec![0u8; 1024 * 1024];
+            let mut offset = 0;
+            loop {
+                let n = reader.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                if chunked {
+                    assert!(n <= ATTACHMENT_CHUNK_LEN);
+                }
+                for (i, b) in buf[..n].iter().enumerate() {
+                    assert_eq!(*b, byte_at(offset + i));
+                }
+                offset += n;
+            }
+            assert_eq!(offset, SIZE);
+        }
+    }
+
     #[tokio::test]
     async fn database_open_redb() {
         let dir = tempfile::tempdir().unwrap();
@@ -1036,6 +3345,95 @@ mod tests {
         assert_eq!(doc.data["x"], 1);
     }
 
+    #[tokio::test]
+    async fn indexed_view_only_remaps_changed_docs() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("views.redb");
+        let mapped = AtomicUsize::new(0);
+        let map_fn = |doc: &serde_json::Value| {
+            mapped.fetch_add(1, Ordering::SeqCst);
+            vec![(doc["n"].clone(), serde_json::json!(1))]
+        };
+        let opts = |signature: &str| ViewQueryOptions {
+            index: Some(ViewIndex::new("by_n", signature)),
+            ..ViewQueryOptions::new()
+        };
+
+        {
+            let db = Database::open(&path, "views").unwrap();
+            for i in 0..100 {
+                db.put(&format!("doc{i:03}"), serde_json::json!({"n": i}))
+                    .await
+                    .unwrap();
+            }
+            let result = query_view(db.adapter(), &map_fn, None, opts("v1"))
+                .await
+                .unwrap();
+            assert_eq!(result.rows.len(), 100);
+            assert_eq!(mapped.swap(0, Ordering::SeqCst), 100);
+
+            let doc = db.get("doc042").await.unwrap();
+            db.update(
+                "doc042",
+                &doc.rev.unwrap().to_string(),
+                serde_json::json!({"n": 1000}),
+            )
+            .await
+            .unwrap();
+            let result = query_view(db.adapter(), &map_fn, None, opts("v1"))
+                .await
+                .unwrap();
+            assert_eq!(mapped.swap(0, Ordering::SeqCst), 1);
+            assert_eq!(result.rows[99].id.as_deref(), Some("doc042"));
+            assert_eq!(result.rows[99].key, 1000);
+        }
+
+        // The index outlives the handle; a new signature rebuilds it.
+        let db = Database::open(&path, "views").unwrap();
+        let result = query_view(db.adapter(), &map_fn, None, opts("v1"))
+            .await
+            .unwrap();
+        assert_eq!(result.rows.len(), 100);
+        assert_eq!(mapped.swap(0, Ordering::SeqCst), 0);
+        query_view(db.adapter(), &map_fn, None, opts("v2"))
+            .await
+            .unwrap();
+        assert_eq!(mapped.swap(0, Ordering::SeqCst), 100);
+    }
+
+    #[tokio::test]
+    async fn replication_resumes_from_checkpoint_after_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("local.redb");
+        let remote = Database::memory("remote");
+
+        {
+            let local = Database::open(&path, "local").unwrap();
+            for i in 0..5 {
+                local
+                    .put(&format!("doc{i}"), serde_json::json!({"i": i}))
+                    .await
+                    .unwrap();
+            }
+            let first = local.replicate_to(&remote).await.unwrap();
+            assert_eq!(first.docs_read, 5);
+        }
+
+        // A fresh process: reopen the file and add one document
+        let local = Database::open(&path, "local").unwrap();
+        local
+            .put("doc5", serde_json::json!({"i": 5}))
+            .await
+            .unwrap();
+
+        let second = local.replicate_to(&remote).await.unwrap();
+        assert_eq!(second.docs_read, 1);
+        assert_eq!(second.docs_written, 1);
+        assert_eq!(remote.info().await.unwrap().doc_count, 6);
+    }
+
     #[tokio::test]
     async fn database_from_adapter_and_accessor() {
         let adapter = Arc::new(MemoryAdapter::new("custom"));
@@ -1047,6 +3445,28 @@ mod tests {
         assert_eq!(info.doc_count, 1);
     }
 
+    #[tokio::test]
+    async fn exists_tracks_put_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("exists.redb"), "test").unwrap(),
+        ] {
+            assert!(!db.exists("doc").await.unwrap());
+
+            let rev = db
+                .put("doc", serde_json::json!({}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            assert!(db.exists("doc").await.unwrap());
+
+            db.remove("doc", &rev).await.unwrap();
+            assert!(!db.exists("doc").await.unwrap());
+        }
+    }
+
     #[tokio::test]
     async fn database_get_with_opts() {
         let db = Database::memory("test");
@@ -1102,6 +3522,47 @@ mod tests {
         assert_eq!(result.rows.len(), 2);
     }
 
+    #[tokio::test]
+    async fn prefix_queries_on_ids_and_fields() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("prefix.redb"), "test").unwrap(),
+        ] {
+            for (id, path) in [
+                ("user", "/api"),
+                ("user:alice", "/api/users"),
+                ("user:bob", "/web"),
+                ("user;carol", "/apix"),
+                ("users", "/"),
+            ] {
+                db.put(id, serde_json::json!({"path": path})).await.unwrap();
+            }
+
+            let result = db
+                .all_docs(AllDocsOptions::with_prefix("user:"))
+                .await
+                .unwrap();
+            let ids: Vec<&str> = result.rows.iter().map(|r| r.id.as_str()).collect();
+            assert_eq!(ids, ["user:alice", "user:bob"]);
+
+            let found = db
+                .find(FindOptions {
+                    selector: serde_json::json!({"path": {"$beginsWith": "/api"}}),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            let mut ids: Vec<&str> = found
+                .docs
+                .iter()
+                .map(|d| d["_id"].as_str().unwrap())
+                .collect();
+            ids.sort();
+            assert_eq!(ids, ["user", "user:alice", "user;carol"]);
+        }
+    }
+
     #[tokio::test]
     async fn database_changes() {
         let db = Database::memory("test");
@@ -1151,29 +3612,169 @@ mod tests {
         assert!(r2.ok);
         assert_ne!(r1.id, r2.id); // Different auto-generated IDs
 
-        let doc = db.get(&r1.id).await.unwrap();
-        assert_eq!(doc.data["name"], "Alice");
+        let doc = db.get(&r1.id).await.unwrap();
+        assert_eq!(doc.data["name"], "Alice");
+
+        let info = db.info().await.unwrap();
+        assert_eq!(info.doc_count, 2);
+    }
+
+    #[tokio::test]
+    async fn inline_attachment_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = Database::memory("test");
+        let redb = Database::open(dir.path().join("att.redb"), "att").unwrap();
+
+        for db in [memory, redb] {
+            db.put(
+                "doc1",
+                serde_json::json!({
+                    "title": "notes",
+                    "_attachments": {
+                        "f.txt": {"content_type": "text/plain", "data": "aGVsbG8gd29ybGQ="}
+                    }
+                }),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                db.get_attachment("doc1", "f.txt").await.unwrap(),
+                b"hello world"
+            );
+
+            let doc = db.get("doc1").await.unwrap();
+            assert_eq!(doc.data["title"], "notes");
+            assert!(doc.data.get("_attachments").is_none());
+            let att = &doc.attachments["f.txt"];
+            assert!(att.stub);
+            assert_eq!(att.length, 11);
+            assert!(att.digest.starts_with("md5-"));
+            assert_eq!(doc.to_json()["_attachments"]["f.txt"]["stub"], true);
+        }
+    }
 
-        let info = db.info().await.unwrap();
-        assert_eq!(info.doc_count, 2);
+    #[tokio::test]
+    async fn bulk_get_reports_missing_per_document() {
+        let db = Database::memory("test");
+        db.put("a", serde_json::json!({"n": 1})).await.unwrap();
+        db.put("c", serde_json::json!({"n": 3})).await.unwrap();
+
+        let results = db
+            .bulk_get(vec!["a".into(), "missing".into(), "c".into()])
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().data["n"], 1);
+        assert!(matches!(results[1], Err(RouchError::NotFound(_))));
+        assert_eq!(results[2].as_ref().unwrap().id, "c");
     }
 
     #[tokio::test]
-    async fn database_remove_attachment() {
+    async fn get_many_maps_missing_to_none() {
         let db = Database::memory("test");
+        db.put("a", serde_json::json!({"n": 1})).await.unwrap();
+        db.put("c", serde_json::json!({"n": 3})).await.unwrap();
+
+        let docs = db.get_many(&["a", "missing", "c"]).await.unwrap();
+        assert_eq!(docs.len(), 3);
+        assert_eq!(docs["a"].as_ref().unwrap().data["n"], 1);
+        assert!(docs["missing"].is_none());
+        assert_eq!(docs["c"].as_ref().unwrap().data["n"], 3);
+    }
 
-        let r1 = db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
-        let rev = r1.rev.unwrap();
+    #[tokio::test]
+    async fn typed_documents_round_trip() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Person {
+            name: String,
+            age: u32,
+        }
 
-        // remove_attachment creates a new revision even though attachment
-        // tracking in the memory adapter is simplified
-        let r2 = db
-            .remove_attachment("doc1", "photo.jpg", &rev)
+        let db = Database::memory("test");
+        let alice = Person {
+            name: "Alice".into(),
+            age: 30,
+        };
+        let r1 = db.put_typed("alice", &alice).await.unwrap();
+        assert!(r1.ok);
+        assert_eq!(db.get_as::<Person>("alice").await.unwrap(), alice);
+
+        let older = Person {
+            name: "Alice".into(),
+            age: 31,
+        };
+        db.update_typed("alice", &r1.rev.unwrap(), &older)
             .await
             .unwrap();
-        assert!(r2.ok);
-        assert!(r2.rev.is_some());
-        assert_ne!(r2.rev.as_deref().unwrap(), rev);
+        assert_eq!(db.get_as::<Person>("alice").await.unwrap().age, 31);
+
+        // The stored body is the flattened struct
+        let doc = db.get("alice").await.unwrap();
+        assert_eq!(doc.data, serde_json::json!({"name": "Alice", "age": 31}));
+
+        assert!(matches!(
+            db.put_typed("n", &42).await,
+            Err(RouchError::BadRequest(_))
+        ));
+        assert!(matches!(
+            db.get_as::<Person>("missing").await,
+            Err(RouchError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn database_remove_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let memory = Database::memory("test");
+        let redb = Database::open(dir.path().join("att.redb"), "att").unwrap();
+
+        for db in [memory, redb] {
+            let r1 = db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
+            let r2 = db
+                .put_attachment(
+                    "doc1",
+                    "a.txt",
+                    &r1.rev.unwrap(),
+                    b"first".to_vec(),
+                    "text/plain",
+                )
+                .await
+                .unwrap();
+            let r3 = db
+                .put_attachment(
+                    "doc1",
+                    "b.txt",
+                    &r2.rev.unwrap(),
+                    b"second".to_vec(),
+                    "text/plain",
+                )
+                .await
+                .unwrap();
+            let rev = r3.rev.unwrap();
+
+            let r4 = db.remove_attachment("doc1", "a.txt", &rev).await.unwrap();
+            assert!(r4.ok);
+            let rev4 = r4.rev.unwrap();
+            assert_ne!(rev4, rev);
+
+            assert!(matches!(
+                db.get_attachment("doc1", "a.txt").await,
+                Err(RouchError::NotFound(_))
+            ));
+            assert_eq!(db.get_attachment("doc1", "b.txt").await.unwrap(), b"second");
+
+            let doc = db.get("doc1").await.unwrap();
+            assert_eq!(doc.data["v"], 1);
+            assert_eq!(doc.attachments.len(), 1);
+            assert!(doc.attachments.contains_key("b.txt"));
+
+            // Removing an attachment that isn't there
+            assert!(matches!(
+                db.remove_attachment("doc1", "a.txt", &rev4).await,
+                Err(RouchError::NotFound(_))
+            ));
+        }
     }
 
     #[tokio::test]
@@ -1196,6 +3797,7 @@ mod tests {
                 name: String::new(),
                 fields: vec![SortField::Simple("age".into())],
                 ddoc: None,
+                partial_filter_selector: None,
             })
             .await
             .unwrap();
@@ -1208,6 +3810,7 @@ mod tests {
                 name: "idx-age".into(),
                 fields: vec![SortField::Simple("age".into())],
                 ddoc: None,
+                partial_filter_selector: None,
             })
             .await
             .unwrap();
@@ -1236,6 +3839,280 @@ mod tests {
         assert!(indexes.is_empty());
     }
 
+    #[tokio::test]
+    async fn find_sort_needs_a_covering_index() {
+        let db = Database::memory("test");
+        for (id, age) in [("a", 30), ("b", 20), ("c", 40)] {
+            db.put(id, serde_json::json!({"age": age})).await.unwrap();
+        }
+        let sorted = |allow_fallback| FindOptions {
+            selector: serde_json::json!({"age": {"$gt": 0}}),
+            sort: Some(vec![SortField::Simple("age".into())]),
+            allow_fallback,
+            ..Default::default()
+        };
+        let ages = |found: FindResponse| -> Vec<i64> {
+            found
+                .docs
+                .iter()
+                .map(|d| d["age"].as_i64().unwrap())
+                .collect()
+        };
+
+        let rejected = db.find(sorted(false)).await;
+        assert!(matches!(rejected, Err(RouchError::BadRequest(ref m)) if m == "no_usable_index"));
+        assert_eq!(ages(db.find(sorted(true)).await.unwrap()), [20, 30, 40]);
+
+        // Sorting on _id alone never needs an index
+        let by_id = FindOptions {
+            sort: Some(vec![SortField::Simple("_id".into())]),
+            ..sorted(false)
+        };
+        assert_eq!(ages(db.find(by_id).await.unwrap()), [30, 20, 40]);
+
+        db.create_index(IndexDefinition {
+            name: String::new(),
+            fields: vec![SortField::Simple("age".into())],
+            ddoc: None,
+            partial_filter_selector: None,
+        })
+        .await
+        .unwrap();
+        assert_eq!(ages(db.find(sorted(false)).await.unwrap()), [20, 30, 40]);
+    }
+
+    #[tokio::test]
+    async fn partial_index_only_holds_matching_docs() {
+        let db = Database::memory("test");
+        for (id, kind, amount) in [("o1", "order", 10), ("o2", "order", 20), ("n1", "note", 30)] {
+            db.put(id, serde_json::json!({"type": kind, "amount": amount}))
+                .await
+                .unwrap();
+        }
+        db.create_index(IndexDefinition {
+            name: "orders-by-amount".into(),
+            fields: vec![SortField::Simple("amount".into())],
+            ddoc: None,
+            partial_filter_selector: Some(serde_json::json!({"type": "order"})),
+        })
+        .await
+        .unwrap();
+
+        let ids = |selector: serde_json::Value| {
+            let db = &db;
+            async move {
+                let opts = FindOptions {
+                    selector,
+                    ..Default::default()
+                };
+                let index = db.explain(opts.clone()).await.index.name;
+                let mut ids: Vec<String> = db
+                    .find(opts)
+                    .await
+                    .unwrap()
+                    .docs
+                    .iter()
+                    .map(|d| d["_id"].as_str().unwrap().to_string())
+                    .collect();
+                ids.sort();
+                (index, ids)
+            }
+        };
+
+        // The selector implies the filter, so the partial index serves it
+        let orders = serde_json::json!({"type": "order", "amount": {"$gt": 0}});
+        assert_eq!(
+            ids(orders.clone()).await,
+            ("orders-by-amount".into(), vec!["o1".into(), "o2".into()])
+        );
+
+        // Without the filter the partial index would drop n1, so it isn't used
+        let (index, all) = ids(serde_json::json!({"amount": {"$gt": 0}})).await;
+        assert_eq!(index, "_all_docs");
+        assert_eq!(all, ["n1", "o1", "o2"]);
+
+        // A doc that stops matching the filter leaves the index
+        let rev = db.get("o2").await.unwrap().rev.unwrap().to_string();
+        db.update(
+            "o2",
+            &rev,
+            serde_json::json!({"type": "refund", "amount": 20}),
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(orders).await.1, ["o1"]);
+        let indexes = db.indexes.read().await;
+        let entries: Vec<&str> = indexes["orders-by-amount"]
+            .entries
+            .iter()
+            .map(|(_, id)| id.as_str())
+            .collect();
+        assert_eq!(entries, ["o1"]);
+    }
+
+    #[tokio::test]
+    async fn database_index_range_query_tracks_writes() {
+        let db = Database::memory("test");
+        for i in 0..200 {
+            db.put(
+                &format!("p{:03}", i),
+                serde_json::json!({"age": i % 50, "name": format!("n{}", i)}),
+            )
+            .await
+            .unwrap();
+        }
+        for field in ["age", "name"] {
+            db.create_index(IndexDefinition {
+                name: String::new(),
+                fields: vec![SortField::Simple(field.into())],
+                ddoc: None,
+                partial_filter_selector: None,
+            })
+            .await
+            .unwrap();
+        }
+
+        let query = FindOptions {
+            selector: serde_json::json!({"age": {"$gt": 45}}),
+            use_index: Some("idx-age".into()),
+            ..Default::default()
+        };
+        assert_eq!(db.explain(query.clone()).await.index.name, "idx-age");
+        let found = db.find(query.clone()).await.unwrap();
+        assert_eq!(found.docs.len(), 16);
+        assert!(found.docs.iter().all(|d| d["age"].as_u64().unwrap() > 45));
+
+        // Writes after the index was built are picked up incrementally.
+        let old = db.get("p049").await.unwrap();
+        db.update(
+            "p049",
+            &old.rev.unwrap().to_string(),
+            serde_json::json!({"age": 1}),
+        )
+        .await
+        .unwrap();
+        let gone = db.get("p048").await.unwrap();
+        db.remove("p048", &gone.rev.unwrap().to_string())
+            .await
+            .unwrap();
+        db.put("new", serde_json::json!({"age": 99})).await.unwrap();
+
+        let found = db.find(query).await.unwrap();
+        let mut ids: Vec<&str> = found
+            .docs
+            .iter()
+            .map(|d| d["_id"].as_str().unwrap())
+            .collect();
+        ids.sort();
+        assert_eq!(ids.len(), 15);
+        assert!(ids.contains(&"new"));
+        assert!(!ids.contains(&"p049"));
+        assert!(!ids.contains(&"p048"));
+
+        // A hint that can't serve the selector falls back to index selection.
+        let hinted = FindOptions {
+            selector: serde_json::json!({"age": 99}),
+            use_index: Some("idx-name".into()),
+            ..Default::default()
+        };
+        assert_eq!(db.explain(hinted.clone()).await.index.name, "idx-age");
+        assert_eq!(db.find(hinted).await.unwrap().docs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn database_explain_reports_index_or_scan() {
+        let db = Database::memory("test");
+        db.put("alice", serde_json::json!({"name": "Alice", "age": 30}))
+            .await
+            .unwrap();
+        db.create_index(IndexDefinition {
+            name: String::new(),
+            fields: vec![SortField::Simple("age".into())],
+            ddoc: None,
+            partial_filter_selector: None,
+        })
+        .await
+        .unwrap();
+
+        let plan = db
+            .explain(FindOptions {
+                selector: serde_json::json!({"age": 30, "name": "Alice"}),
+                limit: Some(5),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(plan.index.name, "idx-age");
+        assert_eq!(plan.matched_fields, vec!["age".to_string()]);
+        assert_eq!(plan.selector["age"], serde_json::json!({"$eq": 30}));
+        assert_eq!(
+            plan.residual_selector,
+            serde_json::json!({"name": {"$eq": "Alice"}})
+        );
+        assert_eq!(plan.limit, Some(5));
+        assert_eq!(plan.skip, 0);
+
+        let plan = db
+            .explain(FindOptions {
+                selector: serde_json::json!({"name": "Alice"}),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(plan.index.name, "_all_docs");
+        assert!(plan.matched_fields.is_empty());
+        assert_eq!(plan.residual_selector, plan.selector);
+    }
+
+    #[tokio::test]
+    async fn or_query_unions_index_lookups() {
+        let db = Database::memory("test");
+        for (id, a, b) in [("d1", 1, 0), ("d2", 0, 2), ("d3", 1, 2), ("d4", 0, 0)] {
+            db.put(id, serde_json::json!({"a": a, "b": b}))
+                .await
+                .unwrap();
+        }
+        for field in ["a", "b"] {
+            db.create_index(IndexDefinition {
+                name: String::new(),
+                fields: vec![SortField::Simple(field.into())],
+                ddoc: None,
+                partial_filter_selector: None,
+            })
+            .await
+            .unwrap();
+        }
+        // Written after the indexes were built
+        db.put("d5", serde_json::json!({"a": 0, "b": 2}))
+            .await
+            .unwrap();
+
+        let query = |selector| FindOptions {
+            selector,
+            ..Default::default()
+        };
+        let selector = serde_json::json!({"$or": [{"a": 1}, {"b": 2}]});
+        let plan = db.explain(query(selector.clone())).await;
+        assert_eq!(plan.index.name, "_or");
+        let branches: Vec<&str> = plan.or_branches.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(branches, ["idx-a", "idx-b"]);
+        assert_eq!(plan.matched_fields, ["a", "b"]);
+
+        let found = db.find(query(selector)).await.unwrap();
+        let ids: Vec<&str> = found
+            .docs
+            .iter()
+            .map(|d| d["_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["d1", "d2", "d3", "d5"]);
+
+        // One unindexed branch means a scan
+        let selector = serde_json::json!({"$or": [{"a": 1}, {"c": 3}]});
+        let plan = db.explain(query(selector.clone())).await;
+        assert_eq!(plan.index.name, "_all_docs");
+        assert!(plan.or_branches.is_empty());
+        let found = db.find(query(selector)).await.unwrap();
+        assert_eq!(found.docs.len(), 2);
+    }
+
     #[tokio::test]
     async fn database_replicate_with_events() {
         let local = Database::memory("local");
@@ -1301,18 +4178,14 @@ mod tests {
             tokio::select! {
                 event = rx.recv() => {
                     match event {
-                        Some(ReplicationEvent::Complete(r)) => {
-                            if r.docs_written > 0 {
-                                got_complete = true;
-                                break;
-                            }
+                        Some(ReplicationEvent::Complete(r)) if r.docs_written > 0 => {
+                            got_complete = true;
+                            break;
                         }
-                        Some(ReplicationEvent::Paused) => {
-                            // No changes, check if doc was replicated
-                            if remote.get("doc1").await.is_ok() {
-                                got_complete = true;
-                                break;
-                            }
+                        // No changes, check if doc was replicated
+                        Some(ReplicationEvent::Paused) if remote.get("doc1").await.is_ok() => {
+                            got_complete = true;
+                            break;
                         }
                         None => break,
                         _ => {}
@@ -1326,6 +4199,58 @@ mod tests {
         assert!(got_complete || remote.get("doc1").await.is_ok());
     }
 
+    #[tokio::test]
+    async fn live_replication_picks_up_later_writes() {
+        let local = Database::memory("local");
+        let remote = Database::memory("remote");
+
+        let (mut rx, handle) = local.replicate_to_live(
+            &remote,
+            ReplicationOptions {
+                poll_interval: std::time::Duration::from_millis(20),
+                live: true,
+                ..Default::default()
+            },
+        );
+
+        // Nothing to do yet: the first pass goes idle
+        let wait_for_paused = async {
+            while let Some(event) = rx.recv().await {
+                if matches!(event, ReplicationEvent::Paused) {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(2), wait_for_paused)
+            .await
+            .unwrap();
+
+        local
+            .put("later", serde_json::json!({"v": 1}))
+            .await
+            .unwrap();
+
+        // The write wakes the replicator, which goes active and pauses again
+        let mut saw_active = false;
+        let wait_for_doc = async {
+            while let Some(event) = rx.recv().await {
+                match event {
+                    ReplicationEvent::Active => saw_active = true,
+                    ReplicationEvent::Paused if remote.get("later").await.is_ok() => break,
+                    _ => {}
+                }
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(2), wait_for_doc)
+            .await
+            .unwrap();
+
+        assert!(saw_active);
+        assert_eq!(remote.get("later").await.unwrap().data["v"], 1);
+
+        handle.cancel();
+    }
+
     #[tokio::test]
     async fn database_changes_with_selector() {
         let db = Database::memory("test");
@@ -1409,6 +4334,72 @@ mod tests {
         handle.cancel();
     }
 
+    #[tokio::test]
+    async fn live_changes_reconnect_after_dropped_feed() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/db", listener.local_addr().unwrap());
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            let change = |seq: u64, id: &str| serde_json::json!({"seq": seq, "id": id, "changes": [{"rev": "1-a"}]});
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let head = String::from_utf8_lossy(&buf[..n]).to_string();
+                let since = head
+                    .split_once("since=")
+                    .and_then(|(_, rest)| rest.split(['&', ' ']).next())
+                    .unwrap_or_default()
+                    .to_string();
+                let attempt = {
+                    let mut seen = seen.lock().unwrap();
+                    seen.push(since.clone());
+                    seen.len()
+                };
+                let body = match (attempt, since.as_str()) {
+                    (1, _) => serde_json::json!({
+                        "results": [change(1, "a"), change(2, "b")], "last_seq": 2
+                    }),
+                    // Drop the connection without a response
+                    (2, _) => continue,
+                    // The server replays from before what was already seen
+                    (_, "2") => serde_json::json!({
+                        "results": [change(2, "b"), change(3, "c")], "last_seq": 3
+                    }),
+                    _ => serde_json::json!({"results": [], "last_seq": 3}),
+                }
+                .to_string();
+                let reply = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        let db = Database::http(&url);
+        let (mut rx, handle) = db.live_changes(ChangesStreamOptions {
+            poll_interval: std::time::Duration::from_millis(20),
+            reconnect: true,
+            ..Default::default()
+        });
+        let mut ids = Vec::new();
+        while let Ok(Some(event)) =
+            tokio::time::timeout(std::time::Duration::from_millis(500), rx.recv()).await
+        {
+            ids.push(event.id);
+        }
+        assert_eq!(ids, ["a", "b", "c"]);
+        // The retry asked for changes since the last seq it had seen
+        assert_eq!(requests.lock().unwrap()[..3], ["0", "2", "2"]);
+
+        handle.cancel();
+    }
+
     #[tokio::test]
     async fn database_live_changes_with_selector() {
         let db = Database::memory("test");
@@ -1452,12 +4443,362 @@ mod tests {
         handle.cancel();
     }
 
+    #[tokio::test]
+    async fn database_live_changes_with_doc_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("live.redb"), "test").unwrap(),
+        ] {
+            let (mut rx, handle) = db.live_changes(ChangesStreamOptions {
+                doc_ids: Some(vec!["a".into(), "c".into()]),
+                poll_interval: std::time::Duration::from_millis(20),
+                ..Default::default()
+            });
+
+            let mut next_id = async || {
+                tokio::time::timeout(std::time::Duration::from_secs(5), rx.recv())
+                    .await
+                    .expect("no change arrived")
+                    .unwrap()
+                    .id
+            };
+            let rev = |result: DocResult| result.rev.unwrap();
+
+            // Waiting for each matching change means the poll that saw it
+            // has run. Events arrive in seq order, so a `b` let through by
+            // a later poll would show up before the change waited for.
+            let a = rev(db.put("a", serde_json::json!({})).await.unwrap());
+            assert_eq!(next_id().await, "a");
+
+            let b = rev(db.put("b", serde_json::json!({})).await.unwrap());
+            db.put("c", serde_json::json!({})).await.unwrap();
+            assert_eq!(next_id().await, "c");
+
+            db.update("b", &b, serde_json::json!({"v": 2}))
+                .await
+                .unwrap();
+            db.update("a", &a, serde_json::json!({"v": 2}))
+                .await
+                .unwrap();
+            assert_eq!(next_id().await, "a");
+            handle.cancel();
+        }
+    }
+
     #[tokio::test]
     async fn database_compact() {
         let db = Database::memory("test");
         db.compact().await.unwrap();
     }
 
+    #[tokio::test]
+    async fn compact_retains_recent_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("compact.redb"), "test").unwrap(),
+        ] {
+            let mut revs = vec![
+                db.put("doc", serde_json::json!({"v": 0}))
+                    .await
+                    .unwrap()
+                    .rev
+                    .unwrap(),
+            ];
+            for v in 1..5 {
+                let rev = revs.last().unwrap().clone();
+                let r = db
+                    .update("doc", &rev, serde_json::json!({"v": v}))
+                    .await
+                    .unwrap();
+                revs.push(r.rev.unwrap());
+            }
+
+            db.compact_with_opts(CompactOptions { retain_revs: 2 })
+                .await
+                .unwrap();
+
+            let at = |rev: &str| GetOptions {
+                rev: Some(rev.to_string()),
+                ..Default::default()
+            };
+            for (v, rev) in revs.iter().enumerate() {
+                let got = db.get_with_opts("doc", at(rev)).await;
+                if v >= 3 {
+                    assert_eq!(got.unwrap().data["v"], v);
+                } else {
+                    assert!(matches!(got, Err(RouchError::NotFound(_))), "{rev}");
+                }
+            }
+
+            db.compact().await.unwrap();
+            assert!(db.get_with_opts("doc", at(&revs[3])).await.is_err());
+            assert_eq!(db.get("doc").await.unwrap().data["v"], 4);
+        }
+    }
+
+    #[tokio::test]
+    async fn auto_compaction_prunes_dead_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        let opts = RedbOptions {
+            auto_compact_every: Some(50),
+        };
+        let db = Database::open_with_opts(dir.path().join("auto.redb"), "test", opts).unwrap();
+
+        let mut revs = vec![
+            db.put("doc", serde_json::json!({"v": 0}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap(),
+        ];
+        for v in 1..100 {
+            let rev = revs.last().unwrap().clone();
+            let r = db
+                .update("doc", &rev, serde_json::json!({"v": v}))
+                .await
+                .unwrap();
+            revs.push(r.rev.unwrap());
+        }
+
+        // The hundredth write starts a compaction in the background
+        let at = |rev: &str| GetOptions {
+            rev: Some(rev.to_string()),
+            ..Default::default()
+        };
+        let mut pruned = false;
+        for _ in 0..200 {
+            if db.get_with_opts("doc", at(&revs[98])).await.is_err() {
+                pruned = true;
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(pruned);
+        for rev in &revs[..99] {
+            assert!(db.get_with_opts("doc", at(rev)).await.is_err(), "{rev}");
+        }
+        assert_eq!(db.get("doc").await.unwrap().data["v"], 99);
+        assert!(!db.needs_compaction().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn backup_is_a_point_in_time_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::open(dir.path().join("live.redb"), "live").unwrap();
+        for i in 0..3 {
+            db.put(&format!("before{i}"), serde_json::json!({"i": i}))
+                .await
+                .unwrap();
+        }
+        db.put_local("checkpoint", serde_json::json!({"seq": 3}))
+            .await
+            .unwrap();
+
+        let backup = dir.path().join("backup.redb");
+        db.backup_to(&backup).await.unwrap();
+        db.put("after", serde_json::json!({})).await.unwrap();
+        assert!(db.backup_to(&backup).await.is_err());
+
+        let copy = Database::open(&backup, "copy").unwrap();
+        let ids: Vec<String> = copy
+            .all_docs(AllDocsOptions::new())
+            .await
+            .unwrap()
+            .rows
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        assert_eq!(ids, ["before0", "before1", "before2"]);
+        assert_eq!(copy.info().await.unwrap().update_seq, Seq::Num(3));
+        assert_eq!(copy.get_local("checkpoint").await.unwrap()["seq"], 3);
+        assert_eq!(copy.get("before1").await.unwrap().data["i"], 1);
+
+        // Both stay writable and independent
+        copy.put("copy_only", serde_json::json!({})).await.unwrap();
+        assert!(db.get("copy_only").await.is_err());
+        assert_eq!(db.info().await.unwrap().doc_count, 4);
+    }
+
+    #[tokio::test]
+    async fn needs_compaction_counts_dead_bodies() {
+        let dir = tempfile::tempdir().unwrap();
+        for db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("dead.redb"), "test").unwrap(),
+        ] {
+            let mut rev = db
+                .put("doc", serde_json::json!({"v": 0}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            assert!(!db.needs_compaction().await.unwrap());
+            for v in 1..3 {
+                rev = db
+                    .update("doc", &rev, serde_json::json!({"v": v}))
+                    .await
+                    .unwrap()
+                    .rev
+                    .unwrap();
+            }
+            assert!(db.needs_compaction().await.unwrap());
+
+            db.compact().await.unwrap();
+            assert!(!db.needs_compaction().await.unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn validator_rejects_single_docs() {
+        let dir = tempfile::tempdir().unwrap();
+        for mut db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("validate.redb"), "test").unwrap(),
+        ] {
+            db.set_validator(Arc::new(|doc, current| {
+                let Some(kind) = doc.data.get("type") else {
+                    return Err(RouchError::Forbidden("type is required".into()));
+                };
+                match current {
+                    Some(current) if current.data.get("type") != Some(kind) => {
+                        Err(RouchError::Forbidden("type cannot change".into()))
+                    }
+                    _ => Ok(()),
+                }
+            }));
+
+            let doc = |id: &str, data| Document {
+                id: id.into(),
+                rev: None,
+                deleted: false,
+                data,
+                attachments: HashMap::new(),
+            };
+            let results = db
+                .bulk_docs(
+                    vec![
+                        doc("untyped", serde_json::json!({"x": 1})),
+                        doc("typed", serde_json::json!({"type": "note"})),
+                    ],
+                    BulkDocsOptions::new(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(results[0].id, "untyped");
+            assert_eq!(results[0].error.as_deref(), Some("forbidden"));
+            assert_eq!(results[0].reason.as_deref(), Some("type is required"));
+            assert!(results[1].ok);
+            assert!(db.get("untyped").await.is_err());
+
+            // The current revision is passed to the validator
+            let rev = results[1].rev.clone().unwrap();
+            let result = db
+                .update("typed", &rev, serde_json::json!({"type": "task"}))
+                .await
+                .unwrap();
+            assert_eq!(result.reason.as_deref(), Some("type cannot change"));
+            assert_eq!(db.get("typed").await.unwrap().data["type"], "note");
+
+            // Replicated revisions are not validated
+            let mut replicated = doc("replicated", serde_json::json!({}));
+            replicated.rev = Some(Revision::new(1, "a".repeat(32)));
+            let results = db
+                .bulk_docs(vec![replicated], BulkDocsOptions::replication())
+                .await
+                .unwrap();
+            assert!(results[0].ok);
+            assert!(db.get("replicated").await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn validator_errors_are_reported_per_doc() {
+        let mut db = Database::memory("test");
+        db.set_validator(Arc::new(|doc, _| match doc.data.get("n") {
+            Some(_) => Ok(()),
+            None => Err(RouchError::BadRequest("n is required".into())),
+        }));
+
+        let results = db
+            .bulk_docs(
+                vec![
+                    Document::from_json(serde_json::json!({"_id": "a"})).unwrap(),
+                    Document::from_json(serde_json::json!({"_id": "b", "n": 1})).unwrap(),
+                ],
+                BulkDocsOptions::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results[0].error.as_deref(), Some("validation_failed"));
+        assert!(
+            results[0]
+                .reason
+                .as_deref()
+                .unwrap()
+                .contains("n is required")
+        );
+        assert!(results[1].ok);
+    }
+
+    #[tokio::test]
+    async fn validator_checks_attachment_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        for mut db in [
+            Database::memory("test"),
+            Database::open(dir.path().join("validate.redb"), "test").unwrap(),
+        ] {
+            let rev = db
+                .put("doc", serde_json::json!({}))
+                .await
+                .unwrap()
+                .rev
+                .unwrap();
+            db.set_validator(Arc::new(|doc, _| {
+                match doc
+                    .attachments
+                    .values()
+                    .any(|att| att.content_type != "text/plain")
+                {
+                    true => Err(RouchError::Forbidden("text only".into())),
+                    false => Ok(()),
+                }
+            }));
+
+            let result = db
+                .put_attachment("doc", "a.png", &rev, vec![1], "image/png")
+                .await
+                .unwrap();
+            assert_eq!(result.error.as_deref(), Some("forbidden"));
+            assert!(db.get_attachment("doc", "a.png").await.is_err());
+
+            let result = db
+                .put_attachment_stream("doc", "b.png", &rev, "image/png", &b"x"[..])
+                .await
+                .unwrap();
+            assert_eq!(result.error.as_deref(), Some("forbidden"));
+
+            let result = db
+                .put_attachment("doc", "a.txt", &rev, b"hi".to_vec(), "text/plain")
+                .await
+                .unwrap();
+            assert!(result.ok);
+
+            // Removing is checked against the document it would leave
+            db.set_validator(Arc::new(|doc, _| match doc.attachments.is_empty() {
+                true => Err(RouchError::Forbidden("keep one".into())),
+                false => Ok(()),
+            }));
+            let result = db
+                .remove_attachment("doc", "a.txt", result.rev.as_deref().unwrap())
+                .await
+                .unwrap();
+            assert_eq!(result.reason.as_deref(), Some("keep one"));
+            assert!(db.get_attachment("doc", "a.txt").await.is_ok());
+        }
+    }
+
     #[tokio::test]
     async fn database_destroy() {
         let db = Database::memory("test");
@@ -0,0 +1,338 @@
+//! Database behaviour over adapters that fail or count calls. These run
+//! against the memory adapter and need no CouchDB.
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use common::wrapped::WrappedAdapter;
+use futures_util::{StreamExt, TryStreamExt};
+use rouchdb::{
+    BulkDocsOptions, ChangesStreamOptions, Database, Document, FindOptions, IndexDefinition,
+    MemoryAdapter, ReplicationEvent, ReplicationOptions, Result, RouchError, SortDirection,
+    SortField,
+};
+
+#[tokio::test]
+async fn database_sync_reports_each_direction() {
+    let local = Database::memory("local");
+    let remote = Database::from_adapter(Arc::new(
+        WrappedAdapter::new(MemoryAdapter::new("remote")).failing(|method, _| {
+            (method == "changes")
+                .then(|| RouchError::DatabaseError("changes feed unavailable".into()))
+        }),
+    ));
+
+    local
+        .put("doc1", serde_json::json!({"from": "local"}))
+        .await
+        .unwrap();
+
+    let result = local.sync(&remote).await;
+    assert!(!result.is_ok());
+
+    // The push still landed even though the pull failed
+    assert_eq!(result.push.as_ref().unwrap().docs_written, 1);
+    assert!(result.pull.is_err());
+    assert_eq!(remote.info().await.unwrap().doc_count, 1);
+
+    assert!(result.into_result().is_err());
+}
+
+#[tokio::test]
+async fn database_sync_fails_on_document_errors() {
+    // Attachment bytes can't be read, so the push finishes with errors
+    let local = Database::from_adapter(Arc::new(
+        WrappedAdapter::new(MemoryAdapter::new("local")).failing(|method, _| {
+            method
+                .starts_with("get_attachment")
+                .then(|| RouchError::DatabaseError("disk error".into()))
+        }),
+    ));
+    let remote = Database::memory("remote");
+    let rev = local
+        .put("doc1", serde_json::json!({}))
+        .await
+        .unwrap()
+        .rev
+        .unwrap();
+    local
+        .put_attachment("doc1", "a.txt", &rev, b"hi".to_vec(), "text/plain")
+        .await
+        .unwrap();
+
+    let result = local.sync(&remote).await;
+    assert!(result.push.as_ref().is_ok_and(|push| !push.ok));
+    assert!(!result.is_ok());
+    let err = result.into_result().unwrap_err();
+    assert!(err.to_string().contains("push failed"));
+}
+
+/// A database whose first `failures` bulk_docs calls fail with a
+/// transient error, like a connection reset mid-batch.
+fn flaky(name: &str, failures: usize) -> Database {
+    Database::from_adapter(Arc::new(
+        WrappedAdapter::new(MemoryAdapter::new(name)).failing(move |method, before| {
+            (method == "bulk_docs" && before < failures)
+                .then(|| RouchError::Io(std::io::ErrorKind::ConnectionReset.into()))
+        }),
+    ))
+}
+
+#[tokio::test]
+async fn replication_retries_transient_write_failures() {
+    let local = Database::memory("local");
+    local
+        .put("doc1", serde_json::json!({"v": 1}))
+        .await
+        .unwrap();
+
+    // Without retry the first connection reset aborts replication
+    let remote = flaky("remote", 2);
+    let err = local.replicate_to(&remote).await.unwrap_err();
+    assert!(err.is_retriable());
+
+    let remote = flaky("remote", 2);
+    let result = local
+        .replicate_to_with_opts(
+            &remote,
+            ReplicationOptions {
+                retry: true,
+                max_retries: 3,
+                back_off_function: Some(Box::new(|_| Duration::from_millis(1))),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    assert!(result.ok);
+    assert_eq!(result.docs_written, 1);
+    assert_eq!(remote.get("doc1").await.unwrap().data["v"], 1);
+
+    // Giving up once max_retries is exhausted
+    let remote = flaky("remote", 5);
+    let result = local
+        .replicate_to_with_opts(
+            &remote,
+            ReplicationOptions {
+                retry: true,
+                max_retries: 2,
+                back_off_function: Some(Box::new(|_| Duration::from_millis(1))),
+                ..Default::default()
+            },
+        )
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn live_replication_retries_transient_write_failures() {
+    let local = Database::memory("local");
+    local
+        .put("doc1", serde_json::json!({"v": 1}))
+        .await
+        .unwrap();
+    let remote = flaky("remote", 2);
+
+    let (mut rx, handle) = local.replicate_to_live(
+        &remote,
+        ReplicationOptions {
+            live: true,
+            retry: true,
+            max_retries: 3,
+            back_off_function: Some(Box::new(|_| Duration::from_millis(1))),
+            ..Default::default()
+        },
+    );
+
+    // The resets are retried within the pass, which never fails
+    let first_pass = async {
+        loop {
+            match rx.recv().await.unwrap() {
+                ReplicationEvent::Complete(result) => break Ok(result),
+                ReplicationEvent::Error(e) => break Err(e),
+                _ => {}
+            }
+        }
+    };
+    let result = tokio::time::timeout(Duration::from_secs(2), first_pass)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(result.docs_written, 1);
+    assert_eq!(remote.get("doc1").await.unwrap().data["v"], 1);
+
+    handle.cancel();
+}
+
+#[tokio::test]
+async fn find_stream_reads_lazily() {
+    let adapter = Arc::new(WrappedAdapter::new(MemoryAdapter::new("stream")));
+    let db = Database::from_adapter(adapter.clone());
+    let docs = (0..1000)
+        .map(|i| Document {
+            id: format!("doc{:04}", i),
+            rev: None,
+            deleted: false,
+            data: serde_json::json!({"n": 1000 - i, "even": i % 2 == 0}),
+            attachments: HashMap::new(),
+        })
+        .collect();
+    db.bulk_docs(docs, BulkDocsOptions::new()).await.unwrap();
+
+    let selector = serde_json::json!({"even": true});
+    adapter.reset_rows_read();
+    let first: Vec<serde_json::Value> = db
+        .find_stream(FindOptions {
+            selector: selector.clone(),
+            skip: Some(1),
+            ..Default::default()
+        })
+        .take(5)
+        .try_collect()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = first.iter().map(|d| d["_id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["doc0002", "doc0004", "doc0006", "doc0008", "doc0010"]);
+    assert!(adapter.rows_read() < 1000);
+
+    // Streamed results match find, with and without an index to sort by
+    let sorted = FindOptions {
+        selector: serde_json::json!({"even": true, "n": {"$gt": 100}}),
+        sort: Some(vec![SortField::Simple("n".into())]),
+        fields: Some(vec!["_id".into(), "n".into()]),
+        limit: Some(50),
+        allow_fallback: true,
+        ..Default::default()
+    };
+    let descending = FindOptions {
+        sort: Some(vec![SortField::WithDirection(
+            "n".into(),
+            SortDirection::Desc,
+        )]),
+        ..sorted.clone()
+    };
+    let buffered = db.find(sorted.clone()).await.unwrap().docs;
+    let streamed: Vec<serde_json::Value> =
+        db.find_stream(sorted.clone()).try_collect().await.unwrap();
+    assert_eq!(streamed, buffered);
+
+    db.create_index(IndexDefinition {
+        name: String::new(),
+        fields: vec![SortField::Simple("n".into())],
+        ddoc: None,
+        partial_filter_selector: None,
+    })
+    .await
+    .unwrap();
+    adapter.reset_rows_read();
+    let streamed: Vec<serde_json::Value> = db.find_stream(sorted).try_collect().await.unwrap();
+    assert_eq!(streamed, buffered);
+    assert!(adapter.rows_read() < 1000);
+
+    let buffered = db.find(descending.clone()).await.unwrap().docs;
+    let streamed: Vec<serde_json::Value> = db.find_stream(descending).try_collect().await.unwrap();
+    assert_eq!(streamed, buffered);
+
+    let invalid: Vec<Result<serde_json::Value>> = db
+        .find_stream(FindOptions {
+            selector: serde_json::json!({"n": {"$regex": "("}}),
+            ..Default::default()
+        })
+        .collect()
+        .await;
+    assert!(matches!(
+        invalid.as_slice(),
+        [Err(RouchError::BadRequest(_))]
+    ));
+}
+
+#[tokio::test]
+async fn validation_fetches_current_docs_in_one_call() {
+    let adapter = Arc::new(WrappedAdapter::new(MemoryAdapter::new("test")));
+    let mut db = Database::from_adapter(adapter.clone());
+    db.set_validator(Arc::new(|_, _| Ok(())));
+
+    let docs = (0..10)
+        .map(|i| Document::from_json(serde_json::json!({"_id": format!("doc{i}")})).unwrap())
+        .collect();
+    db.bulk_docs(docs, BulkDocsOptions::new()).await.unwrap();
+    assert_eq!(adapter.calls("all_docs"), 1);
+    assert_eq!(adapter.calls("get"), 0);
+}
+
+#[tokio::test]
+async fn interrupted_replication_resumes_from_its_checkpoint() {
+    let source = Database::memory("source");
+    for i in 0..5 {
+        source
+            .put(&format!("doc{}", i), serde_json::json!({"v": i}))
+            .await
+            .unwrap();
+    }
+    // The second batch's write fails once, after doc0 and doc1 landed
+    let target = Database::from_adapter(Arc::new(
+        WrappedAdapter::new(MemoryAdapter::new("target")).failing(|method, before| {
+            (method == "bulk_docs" && before == 1)
+                .then(|| RouchError::Io(std::io::ErrorKind::ConnectionReset.into()))
+        }),
+    ));
+    let opts = || ReplicationOptions {
+        batch_size: 2,
+        ..Default::default()
+    };
+
+    assert!(
+        source
+            .replicate_to_with_opts(&target, opts())
+            .await
+            .is_err()
+    );
+    assert_eq!(target.info().await.unwrap().doc_count, 2);
+
+    // The next run starts from the checkpoint saved after the first batch
+    let resumed = source
+        .replicate_to_with_opts(&target, opts())
+        .await
+        .unwrap();
+    assert!(resumed.ok);
+    assert_eq!(resumed.docs_read, 3);
+    assert_eq!(resumed.docs_written, 3);
+    assert_eq!(target.info().await.unwrap().doc_count, 5);
+}
+
+#[tokio::test]
+async fn live_changes_reconnect_only_after_transient_errors() {
+    let feed = |error: fn() -> RouchError| {
+        Database::from_adapter(Arc::new(
+            WrappedAdapter::new(MemoryAdapter::new("feed"))
+                .failing(move |method, before| (method == "changes" && before == 0).then(error)),
+        ))
+    };
+    let opts = || ChangesStreamOptions {
+        poll_interval: Duration::from_millis(10),
+        reconnect: true,
+        ..Default::default()
+    };
+
+    // A dropped connection is retried and the stream carries on
+    let db = feed(|| RouchError::Io(std::io::ErrorKind::ConnectionReset.into()));
+    db.put("doc1", serde_json::json!({})).await.unwrap();
+    let (mut rx, handle) = db.live_changes(opts());
+    let event = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap();
+    assert_eq!(event.unwrap().id, "doc1");
+    handle.cancel();
+
+    // A refused request ends the stream instead of retrying forever
+    let db = feed(|| RouchError::Unauthorized);
+    db.put("doc1", serde_json::json!({})).await.unwrap();
+    let (mut rx, _handle) = db.live_changes(opts());
+    let ended = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap();
+    assert!(ended.is_none());
+}
@@ -65,6 +65,36 @@ async fn all_docs_key_range() {
     delete_remote_db(&url).await;
 }
 
+#[tokio::test]
+#[ignore]
+async fn all_docs_exclusive_end() {
+    let url = fresh_remote_db("ad_exclusive").await;
+    let db = Database::http(&url);
+
+    for id in ["banana", "cherry", "date", "elderberry"] {
+        db.put(id, serde_json::json!({})).await.unwrap();
+    }
+
+    let ids = |descending, start: &str| {
+        let db = &db;
+        let opts = AllDocsOptions {
+            start_key: Some(start.into()),
+            end_key: Some("date".into()),
+            descending,
+            inclusive_end: false,
+            ..AllDocsOptions::new()
+        };
+        async move {
+            let result = db.all_docs(opts).await.unwrap();
+            result.rows.into_iter().map(|r| r.id).collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(ids(false, "banana").await, ["banana", "cherry"]);
+    assert_eq!(ids(true, "elderberry").await, ["elderberry"]);
+
+    delete_remote_db(&url).await;
+}
+
 #[tokio::test]
 #[ignore]
 async fn all_docs_descending() {
@@ -693,6 +693,7 @@ async fn find_with_sort_and_limit() {
             selector: serde_json::json!({"age": {"$exists": true}}),
             sort: Some(vec![SortField::Simple("name".into())]),
             limit: Some(2),
+            allow_fallback: true,
             ..Default::default()
         })
         .await
@@ -793,8 +794,8 @@ async fn sync_idempotent() {
     a.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
 
     // Sync twice — second should be a no-op
-    a.sync(&b).await.unwrap();
-    let (push, pull) = a.sync(&b).await.unwrap();
+    a.sync(&b).await.into_result().unwrap();
+    let (push, pull) = a.sync(&b).await.into_result().unwrap();
     assert!(push.ok);
     assert!(pull.ok);
     assert_eq!(push.docs_written, 0);
@@ -974,7 +975,11 @@ async fn update_with_wrong_rev_fails() {
     db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
 
     let result = db
-        .update("doc1", "1-wronghash", serde_json::json!({"v": 2}))
+        .update(
+            "doc1",
+            "1-00000000000000000000000000000000",
+            serde_json::json!({"v": 2}),
+        )
         .await
         .unwrap();
     assert!(!result.ok, "Update with wrong rev should fail");
@@ -985,7 +990,10 @@ async fn remove_with_wrong_rev_fails() {
     let db = Database::memory("test");
     db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
 
-    let result = db.remove("doc1", "1-wronghash").await.unwrap();
+    let result = db
+        .remove("doc1", "1-00000000000000000000000000000000")
+        .await
+        .unwrap();
     assert!(!result.ok, "Remove with wrong rev should fail");
 }
 
@@ -1008,6 +1016,7 @@ async fn explain_picks_matching_index() {
         name: "".into(),
         fields: vec![SortField::Simple("age".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -1016,6 +1025,7 @@ async fn explain_picks_matching_index() {
         name: "".into(),
         fields: vec![SortField::Simple("city".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -1,3 +1,14 @@
+// Each test binary uses only some of these helpers
+#![allow(dead_code)]
+
+pub mod wrapped;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
 /// Shared helpers for integration tests against a real CouchDB instance.
 ///
 /// These tests require a running CouchDB:
@@ -40,3 +51,93 @@ pub async fn delete_remote_db(url: &str) {
     let client = reqwest::Client::new();
     let _ = client.delete(url).send().await;
 }
+
+/// Start a proxy in front of `url` that counts the HTTP requests passing
+/// through it. Returns `url` pointed at the proxy, credentials kept, and
+/// the count. Each request gets its own upstream connection, and only
+/// bodies sent with a `Content-Length` are forwarded.
+pub async fn counting_proxy(url: &str) -> (String, Arc<AtomicUsize>) {
+    let mut proxied = reqwest::Url::parse(url).unwrap();
+    let upstream = format!(
+        "{}:{}",
+        proxied.host_str().unwrap(),
+        proxied.port_or_known_default().unwrap()
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    proxied.set_host(Some("127.0.0.1")).unwrap();
+    proxied
+        .set_port(Some(listener.local_addr().unwrap().port()))
+        .unwrap();
+
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let upstream = upstream.clone();
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                let _ = forward(client, &upstream, &counter).await;
+            });
+        }
+    });
+    (proxied.to_string(), requests)
+}
+
+/// Relay one request from `client` to `upstream` and the whole response
+/// back, asking both sides to close afterwards.
+async fn forward(
+    mut client: TcpStream,
+    upstream: &str,
+    counter: &AtomicUsize,
+) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let head_end = loop {
+        let n = client.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        request.extend_from_slice(&chunk[..n]);
+        if let Some(at) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break at;
+        }
+    };
+    let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+    let length = head
+        .lines()
+        .find_map(|l| {
+            l.to_ascii_lowercase()
+                .strip_prefix("content-length:")
+                .map(str::to_string)
+        })
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while request.len() < head_end + 4 + length {
+        let n = client.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..n]);
+    }
+    counter.fetch_add(1, Ordering::SeqCst);
+
+    // Drop the client's connection header in favour of our own
+    let mut lines = head.split("\r\n");
+    let mut rewritten = format!(
+        "{}\r\nConnection: close\r\n",
+        lines.next().unwrap_or_default()
+    );
+    for line in lines.filter(|l| !l.to_ascii_lowercase().starts_with("connection:")) {
+        rewritten.push_str(line);
+        rewritten.push_str("\r\n");
+    }
+    rewritten.push_str("\r\n");
+
+    let mut server = TcpStream::connect(upstream).await?;
+    server.write_all(rewritten.as_bytes()).await?;
+    server.write_all(&request[head_end + 4..]).await?;
+    let mut response = Vec::new();
+    server.read_to_end(&mut response).await?;
+    client.write_all(&response).await?;
+    client.shutdown().await
+}
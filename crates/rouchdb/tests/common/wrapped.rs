@@ -0,0 +1,245 @@
+//! An adapter that forwards every call to another one, counting calls per
+//! method and failing the ones a test picks, so tests can watch or break
+//! what a `Database` asks of its adapter.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rouchdb::{
+    Adapter, AllDocsOptions, AllDocsResponse, BulkDocsOptions, BulkGetItem, BulkGetResponse,
+    ChangesOptions, ChangesResponse, DbInfo, DocResult, Document, GetAttachmentOptions, GetOptions,
+    Result, RevsDiffResponse, RouchError,
+};
+use rouchdb_core::adapter::AttachmentReader;
+use rouchdb_core::document::{
+    CompactOptions, OpenRevs, PurgeResponse, Revision, SecurityDocument, StoredViewIndex,
+    ViewIndexUpdate,
+};
+
+/// Picks the calls to fail: given the method name and how many calls to
+/// it came before, returns the error to answer with.
+type FailFn = dyn Fn(&str, usize) -> Option<RouchError> + Send + Sync;
+
+pub struct WrappedAdapter {
+    inner: Arc<dyn Adapter>,
+    fail: Box<FailFn>,
+    calls: Mutex<HashMap<&'static str, usize>>,
+    rows_read: AtomicUsize,
+}
+
+impl WrappedAdapter {
+    pub fn new(inner: impl Adapter + 'static) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            fail: Box::new(|_, _| None),
+            calls: Mutex::new(HashMap::new()),
+            rows_read: AtomicUsize::new(0),
+        }
+    }
+
+    /// Answer the calls `fail` picks with its error instead of forwarding
+    /// them.
+    pub fn failing(
+        mut self,
+        fail: impl Fn(&str, usize) -> Option<RouchError> + Send + Sync + 'static,
+    ) -> Self {
+        self.fail = Box::new(fail);
+        self
+    }
+
+    /// Calls made to `method` so far, failed ones included.
+    pub fn calls(&self, method: &str) -> usize {
+        self.calls.lock().unwrap().get(method).copied().unwrap_or(0)
+    }
+
+    /// Rows returned by `all_docs` since the last reset.
+    pub fn rows_read(&self) -> usize {
+        self.rows_read.load(Ordering::SeqCst)
+    }
+
+    pub fn reset_rows_read(&self) {
+        self.rows_read.store(0, Ordering::SeqCst);
+    }
+
+    fn enter(&self, method: &'static str) -> Result<()> {
+        let before = {
+            let mut calls = self.calls.lock().unwrap();
+            let count = calls.entry(method).or_insert(0);
+            *count += 1;
+            *count - 1
+        };
+        match (self.fail)(method, before) {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Everything but `all_docs_stream` is forwarded. That one keeps the
+/// default, which pages through `all_docs`, so its rows are counted.
+#[async_trait::async_trait]
+impl Adapter for WrappedAdapter {
+    async fn info(&self) -> Result<DbInfo> {
+        self.enter("info")?;
+        self.inner.info().await
+    }
+    async fn get(&self, id: &str, opts: GetOptions) -> Result<Document> {
+        self.enter("get")?;
+        self.inner.get(id, opts).await
+    }
+    async fn exists(&self, id: &str) -> Result<bool> {
+        self.enter("exists")?;
+        self.inner.exists(id).await
+    }
+    async fn winning_rev(&self, id: &str) -> Result<Revision> {
+        self.enter("winning_rev")?;
+        self.inner.winning_rev(id).await
+    }
+    async fn get_open_revs(&self, id: &str, open_revs: OpenRevs) -> Result<Vec<Document>> {
+        self.enter("get_open_revs")?;
+        self.inner.get_open_revs(id, open_revs).await
+    }
+    async fn bulk_docs(
+        &self,
+        docs: Vec<Document>,
+        opts: BulkDocsOptions,
+    ) -> Result<Vec<DocResult>> {
+        self.enter("bulk_docs")?;
+        self.inner.bulk_docs(docs, opts).await
+    }
+    async fn all_docs(&self, opts: AllDocsOptions) -> Result<AllDocsResponse> {
+        self.enter("all_docs")?;
+        let response = self.inner.all_docs(opts).await?;
+        self.rows_read
+            .fetch_add(response.rows.len(), Ordering::SeqCst);
+        Ok(response)
+    }
+    async fn changes(&self, opts: ChangesOptions) -> Result<ChangesResponse> {
+        self.enter("changes")?;
+        self.inner.changes(opts).await
+    }
+    async fn revs_diff(&self, revs: HashMap<String, Vec<String>>) -> Result<RevsDiffResponse> {
+        self.enter("revs_diff")?;
+        self.inner.revs_diff(revs).await
+    }
+    async fn bulk_get(&self, docs: Vec<BulkGetItem>) -> Result<BulkGetResponse> {
+        self.enter("bulk_get")?;
+        self.inner.bulk_get(docs).await
+    }
+    async fn put_attachment(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        rev: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<DocResult> {
+        self.enter("put_attachment")?;
+        self.inner
+            .put_attachment(doc_id, att_id, rev, data, content_type)
+            .await
+    }
+    async fn get_attachment(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        opts: GetAttachmentOptions,
+    ) -> Result<Vec<u8>> {
+        self.enter("get_attachment")?;
+        self.inner.get_attachment(doc_id, att_id, opts).await
+    }
+    async fn put_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        rev: &str,
+        content_type: &str,
+        reader: AttachmentReader,
+    ) -> Result<DocResult> {
+        self.enter("put_attachment_stream")?;
+        self.inner
+            .put_attachment_stream(doc_id, att_id, rev, content_type, reader)
+            .await
+    }
+    async fn get_attachment_stream(
+        &self,
+        doc_id: &str,
+        att_id: &str,
+        opts: GetAttachmentOptions,
+    ) -> Result<AttachmentReader> {
+        self.enter("get_attachment_stream")?;
+        self.inner.get_attachment_stream(doc_id, att_id, opts).await
+    }
+    async fn remove_attachment(&self, doc_id: &str, att_id: &str, rev: &str) -> Result<DocResult> {
+        self.enter("remove_attachment")?;
+        self.inner.remove_attachment(doc_id, att_id, rev).await
+    }
+    async fn get_local(&self, id: &str) -> Result<serde_json::Value> {
+        self.enter("get_local")?;
+        self.inner.get_local(id).await
+    }
+    async fn put_local(&self, id: &str, doc: serde_json::Value) -> Result<()> {
+        self.enter("put_local")?;
+        self.inner.put_local(id, doc).await
+    }
+    async fn remove_local(&self, id: &str) -> Result<()> {
+        self.enter("remove_local")?;
+        self.inner.remove_local(id).await
+    }
+    async fn compact(&self) -> Result<()> {
+        self.enter("compact")?;
+        self.inner.compact().await
+    }
+    async fn compact_with_opts(&self, opts: CompactOptions) -> Result<()> {
+        self.enter("compact_with_opts")?;
+        self.inner.compact_with_opts(opts).await
+    }
+    async fn needs_compaction(&self) -> Result<bool> {
+        self.enter("needs_compaction")?;
+        self.inner.needs_compaction().await
+    }
+    async fn backup_to(&self, path: &Path) -> Result<()> {
+        self.enter("backup_to")?;
+        self.inner.backup_to(path).await
+    }
+    async fn destroy(&self) -> Result<()> {
+        self.enter("destroy")?;
+        self.inner.destroy().await
+    }
+    async fn close(&self) -> Result<()> {
+        self.enter("close")?;
+        self.inner.close().await
+    }
+    async fn purge(&self, req: HashMap<String, Vec<String>>) -> Result<PurgeResponse> {
+        self.enter("purge")?;
+        self.inner.purge(req).await
+    }
+    async fn get_security(&self) -> Result<SecurityDocument> {
+        self.enter("get_security")?;
+        self.inner.get_security().await
+    }
+    async fn put_security(&self, doc: SecurityDocument) -> Result<()> {
+        self.enter("put_security")?;
+        self.inner.put_security(doc).await
+    }
+    async fn get_view_index(&self, name: &str) -> Result<Option<StoredViewIndex>> {
+        self.enter("get_view_index")?;
+        self.inner.get_view_index(name).await
+    }
+    async fn update_view_index(&self, name: &str, update: ViewIndexUpdate) -> Result<()> {
+        self.enter("update_view_index")?;
+        self.inner.update_view_index(name, update).await
+    }
+    async fn query_design_view(
+        &self,
+        ddoc: &str,
+        view: &str,
+        query: serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        self.enter("query_design_view")?;
+        self.inner.query_design_view(ddoc, view, query).await
+    }
+}
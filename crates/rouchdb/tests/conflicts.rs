@@ -39,7 +39,7 @@ async fn conflict_both_sides_modify_same_doc() {
         .await
         .unwrap();
 
-    let (push, pull) = local.sync(&remote).await.unwrap();
+    let (push, pull) = local.sync(&remote).await.into_result().unwrap();
     assert!(push.ok);
     assert!(pull.ok);
 
@@ -103,7 +103,7 @@ async fn conflict_local_delete_remote_update() {
         .await
         .unwrap();
 
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     // The non-deleted version should win
     let remote_doc = remote.get("doc1").await.unwrap();
@@ -138,7 +138,7 @@ async fn conflict_remote_delete_local_update() {
         .await
         .unwrap();
 
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let local_doc = local.get("doc1").await.unwrap();
     assert_eq!(local_doc.data["v"], 2);
@@ -178,9 +178,9 @@ async fn conflict_three_way() {
         .await
         .unwrap();
 
-    local1.sync(&remote).await.unwrap();
-    local2.sync(&remote).await.unwrap();
-    local1.sync(&remote).await.unwrap();
+    local1.sync(&remote).await.into_result().unwrap();
+    local2.sync(&remote).await.into_result().unwrap();
+    local1.sync(&remote).await.into_result().unwrap();
 
     let d1 = local1.get("doc1").await.unwrap();
     let d2 = local2.get("doc1").await.unwrap();
@@ -222,7 +222,7 @@ async fn conflict_resolve_by_update() {
         .await
         .unwrap();
 
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let doc = local
         .get_with_opts(
@@ -247,7 +247,7 @@ async fn conflict_resolve_by_update() {
         .await
         .unwrap();
 
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let local_doc = local.get("doc1").await.unwrap();
     let remote_doc = remote.get("doc1").await.unwrap();
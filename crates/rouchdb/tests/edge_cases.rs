@@ -337,6 +337,7 @@ async fn index_returns_correct_results_after_delete() {
         name: String::new(),
         fields: vec![SortField::Simple("age".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -379,6 +380,7 @@ async fn index_updates_on_field_value_change() {
         name: String::new(),
         fields: vec![SortField::Simple("status".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -718,6 +720,7 @@ async fn explain_with_multiple_indexes() {
         name: String::new(),
         fields: vec![SortField::Simple("name".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -726,6 +729,7 @@ async fn explain_with_multiple_indexes() {
         name: String::new(),
         fields: vec![SortField::Simple("age".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -734,6 +738,7 @@ async fn explain_with_multiple_indexes() {
         name: String::new(),
         fields: vec![SortField::Simple("city".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -766,7 +771,7 @@ async fn sync_three_times_no_duplication() {
         .unwrap();
 
     for _ in 0..3 {
-        let (push, pull) = a.sync(&b).await.unwrap();
+        let (push, pull) = a.sync(&b).await.into_result().unwrap();
         assert!(push.ok);
         assert!(pull.ok);
     }
@@ -25,10 +25,19 @@ async fn error_update_wrong_rev() {
 
     db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
 
+    // Well-formed but never written, so CouchDB reports a conflict rather
+    // than rejecting the rev
     let result = db
-        .update("doc1", "1-bogusrevisionhash", serde_json::json!({"v": 2}))
-        .await;
-    assert!(result.is_err() || !result.unwrap().ok);
+        .update(
+            "doc1",
+            "1-00000000000000000000000000000000",
+            serde_json::json!({"v": 2}),
+        )
+        .await
+        .unwrap();
+    // `_bulk_docs` reports the conflict per document, not as an HTTP error
+    assert!(!result.ok);
+    assert_eq!(result.error.as_deref(), Some("conflict"));
 
     delete_remote_db(&url).await;
 }
@@ -41,8 +50,12 @@ async fn error_delete_wrong_rev() {
 
     db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
 
-    let result = db.remove("doc1", "1-bogusrevisionhash").await;
-    assert!(result.is_err() || !result.unwrap().ok);
+    let result = db
+        .remove("doc1", "1-00000000000000000000000000000000")
+        .await
+        .unwrap();
+    assert!(!result.ok);
+    assert_eq!(result.error.as_deref(), Some("conflict"));
 
     delete_remote_db(&url).await;
 }
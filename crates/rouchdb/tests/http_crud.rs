@@ -3,7 +3,7 @@
 mod common;
 
 use common::{delete_remote_db, fresh_remote_db};
-use rouchdb::{AllDocsOptions, ChangesOptions, Database};
+use rouchdb::{AllDocsOptions, ChangesOptions, Database, ViewQueryOptions};
 
 #[tokio::test]
 #[ignore]
@@ -62,6 +62,27 @@ async fn http_delete_document() {
     delete_remote_db(&url).await;
 }
 
+#[tokio::test]
+#[ignore]
+async fn http_exists() {
+    let url = fresh_remote_db("http_exists").await;
+    let db = Database::http(&url);
+
+    assert!(!db.exists("doc1").await.unwrap());
+    let rev = db
+        .put("doc1", serde_json::json!({}))
+        .await
+        .unwrap()
+        .rev
+        .unwrap();
+    assert!(db.exists("doc1").await.unwrap());
+
+    db.remove("doc1", &rev).await.unwrap();
+    assert!(!db.exists("doc1").await.unwrap());
+
+    delete_remote_db(&url).await;
+}
+
 #[tokio::test]
 #[ignore]
 async fn http_all_docs() {
@@ -98,3 +119,58 @@ async fn http_changes_feed() {
 
     delete_remote_db(&url).await;
 }
+
+#[tokio::test]
+#[ignore]
+async fn http_query_design_view() {
+    let url = fresh_remote_db("http_view").await;
+    let db = Database::http(&url);
+
+    for (id, n) in [("a", 1), ("b", 2), ("c", 3)] {
+        db.put(id, serde_json::json!({"n": n})).await.unwrap();
+    }
+    db.put(
+        "_design/app",
+        serde_json::json!({
+            "views": {
+                "by_n": {"map": "function (doc) { emit(doc.n, null); }", "reduce": "_count"}
+            }
+        }),
+    )
+    .await
+    .unwrap();
+
+    let result = db
+        .query(
+            "app",
+            "by_n",
+            ViewQueryOptions {
+                start_key: Some(serde_json::json!(2)),
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+    let ids: Vec<_> = result
+        .rows
+        .iter()
+        .map(|r| r.id.as_deref().unwrap())
+        .collect();
+    assert_eq!(ids, ["b", "c"]);
+    assert_eq!(result.total_rows, 3);
+
+    let reduced = db
+        .query(
+            "app",
+            "by_n",
+            ViewQueryOptions {
+                reduce: true,
+                ..ViewQueryOptions::new()
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(reduced.rows[0].value, 3);
+
+    delete_remote_db(&url).await;
+}
@@ -2,10 +2,8 @@
 
 mod common;
 
-use std::collections::HashMap;
-
 use common::{delete_remote_db, fresh_remote_db};
-use rouchdb::{Database, FindOptions, IndexDefinition, SortField};
+use rouchdb::{Database, FindOptions, IndexDefinition, SortDirection, SortField};
 
 #[tokio::test]
 #[ignore]
@@ -458,6 +456,7 @@ async fn mango_sort_skip_limit_projection() {
         .find(FindOptions {
             selector: serde_json::json!({}),
             sort: Some(vec![SortField::Simple("age".into())]),
+            allow_fallback: true,
             ..Default::default()
         })
         .await
@@ -466,12 +465,14 @@ async fn mango_sort_skip_limit_projection() {
     assert_eq!(result.docs[3]["name"], "Charlie");
 
     // Sort descending
-    let mut dir = HashMap::new();
-    dir.insert("age".to_string(), "desc".to_string());
     let result = local
         .find(FindOptions {
             selector: serde_json::json!({}),
-            sort: Some(vec![SortField::WithDirection(dir)]),
+            sort: Some(vec![SortField::WithDirection(
+                "age".into(),
+                SortDirection::Desc,
+            )]),
+            allow_fallback: true,
             ..Default::default()
         })
         .await
@@ -485,6 +486,7 @@ async fn mango_sort_skip_limit_projection() {
             sort: Some(vec![SortField::Simple("age".into())]),
             skip: Some(1),
             limit: Some(2),
+            allow_fallback: true,
             ..Default::default()
         })
         .await
@@ -570,6 +572,7 @@ async fn mango_create_index_and_query() {
             name: String::new(),
             fields: vec![SortField::Simple("age".into())],
             ddoc: None,
+            partial_filter_selector: None,
         })
         .await
         .unwrap();
@@ -597,6 +600,7 @@ async fn mango_create_index_and_query() {
             name: "idx-age".into(),
             fields: vec![SortField::Simple("age".into())],
             ddoc: None,
+            partial_filter_selector: None,
         })
         .await
         .unwrap();
@@ -638,6 +642,7 @@ async fn mango_index_with_sort_and_limit() {
             name: String::new(),
             fields: vec![SortField::Simple("score".into())],
             ddoc: None,
+            partial_filter_selector: None,
         })
         .await
         .unwrap();
@@ -703,6 +708,7 @@ async fn mango_multi_field_index() {
                 SortField::Simple("amount".into()),
             ],
             ddoc: None,
+            partial_filter_selector: None,
         })
         .await
         .unwrap();
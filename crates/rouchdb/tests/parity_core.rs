@@ -129,6 +129,11 @@ async fn close_redb_db() {
     let db = Database::open(&path, "test_close").unwrap();
     db.put("doc1", serde_json::json!({"v": 1})).await.unwrap();
     db.close().await.unwrap();
+
+    // Closing releases the file lock, so the path reopens in-process
+    let reopened = Database::open(&path, "test_close").unwrap();
+    let doc = reopened.get("doc1").await.unwrap();
+    assert_eq!(doc.data["v"], 1);
 }
 
 // =========================================================================
@@ -303,6 +308,7 @@ async fn explain_with_index() {
         name: String::new(),
         fields: vec![SortField::Simple("age".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
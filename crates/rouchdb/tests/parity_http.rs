@@ -67,6 +67,7 @@ async fn explain_on_http_with_index() {
         name: String::new(),
         fields: vec![SortField::Simple("age".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
@@ -372,6 +373,7 @@ async fn mango_find_with_index_http() {
         name: String::new(),
         fields: vec![SortField::Simple("age".into())],
         ddoc: None,
+        partial_filter_selector: None,
     })
     .await
     .unwrap();
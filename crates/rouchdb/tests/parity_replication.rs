@@ -1,5 +1,5 @@
 //! Tests for replication parity features:
-//! - ReplicationOptions::since (override starting point)
+//! - ReplicationOptions::since (override starting point, resume from last_seq)
 //! - ReplicationOptions::checkpoint (disable checkpointing)
 //! - Replication with events
 //! - Live replication
@@ -58,6 +58,47 @@ async fn replication_with_since_override() {
     assert!(target_info.doc_count < 3, "since should skip some docs");
 }
 
+#[tokio::test]
+async fn replication_resumes_from_returned_last_seq() {
+    let source = Database::memory("source");
+    let target = Database::memory("target");
+    let no_checkpoint = |since| ReplicationOptions {
+        since,
+        checkpoint: false,
+        batch_size: 2,
+        ..Default::default()
+    };
+
+    for i in 0..2 {
+        source
+            .put(&format!("doc{}", i), serde_json::json!({"v": i}))
+            .await
+            .unwrap();
+    }
+    let first = source
+        .replicate_to_with_opts(&target, no_checkpoint(None))
+        .await
+        .unwrap();
+    assert_eq!(first.docs_written, 2);
+    assert_eq!(first.last_seq, source.info().await.unwrap().update_seq);
+
+    for i in 2..5 {
+        source
+            .put(&format!("doc{}", i), serde_json::json!({"v": i}))
+            .await
+            .unwrap();
+    }
+    // Passing the returned seq back picks up only what came after it
+    let rest = source
+        .replicate_to_with_opts(&target, no_checkpoint(Some(first.last_seq)))
+        .await
+        .unwrap();
+    assert!(rest.ok);
+    assert_eq!(rest.docs_read, 3);
+    assert_eq!(rest.docs_written, 3);
+    assert_eq!(target.info().await.unwrap().doc_count, 5);
+}
+
 // =========================================================================
 // ReplicationOptions::checkpoint = false
 // =========================================================================
@@ -256,11 +297,9 @@ async fn live_replication_picks_up_new_docs() {
                         initial_done = true;
                         break;
                     }
-                    Some(ReplicationEvent::Paused) => {
-                        if target.get("doc1").await.is_ok() {
-                            initial_done = true;
-                            break;
-                        }
+                    Some(ReplicationEvent::Paused) if target.get("doc1").await.is_ok() => {
+                        initial_done = true;
+                        break;
                     }
                     None => break,
                     _ => {}
@@ -292,7 +331,7 @@ async fn sync_merges_both_directions() {
         .await
         .unwrap();
 
-    let (push, pull) = local.sync(&remote).await.unwrap();
+    let (push, pull) = local.sync(&remote).await.into_result().unwrap();
     assert!(push.ok);
     assert!(pull.ok);
 
@@ -307,7 +346,7 @@ async fn sync_with_no_changes() {
     let remote = Database::memory("remote");
 
     // No docs — sync should still succeed
-    let (push, pull) = local.sync(&remote).await.unwrap();
+    let (push, pull) = local.sync(&remote).await.into_result().unwrap();
     assert!(push.ok);
     assert!(pull.ok);
 }
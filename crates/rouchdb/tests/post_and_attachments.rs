@@ -91,7 +91,7 @@ async fn remove_attachment_from_couchdb() {
         .get_attachment(
             "doc1",
             "hello.txt",
-            rouchdb::GetAttachmentOptions { rev: None },
+            rouchdb::GetAttachmentOptions::default(),
         )
         .await
         .unwrap();
@@ -110,7 +110,7 @@ async fn remove_attachment_from_couchdb() {
         .get_attachment(
             "doc1",
             "hello.txt",
-            rouchdb::GetAttachmentOptions { rev: None },
+            rouchdb::GetAttachmentOptions::default(),
         )
         .await;
     assert!(err.is_err());
@@ -2,7 +2,10 @@
 
 mod common;
 
-use common::{delete_remote_db, fresh_remote_db};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+
+use common::{counting_proxy, delete_remote_db, fresh_remote_db};
 use rouchdb::{ChangesOptions, Database, ReplicationEvent, ReplicationFilter, ReplicationOptions};
 
 // =========================================================================
@@ -84,7 +87,7 @@ async fn bidirectional_sync_with_couchdb() {
         .await
         .unwrap();
 
-    let (push, pull) = local.sync(&remote).await.unwrap();
+    let (push, pull) = local.sync(&remote).await.into_result().unwrap();
     assert!(push.ok);
     assert!(pull.ok);
 
@@ -326,14 +329,14 @@ async fn multiple_sync_rounds() {
         .put("doc1", serde_json::json!({"round": 1}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     // Round 2: remote creates, syncs
     remote
         .put("doc2", serde_json::json!({"round": 2}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     // Round 3: both create, sync
     local
@@ -344,7 +347,7 @@ async fn multiple_sync_rounds() {
         .put("doc4", serde_json::json!({"round": 4}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let local_info = local.info().await.unwrap();
     let remote_info = remote.info().await.unwrap();
@@ -494,7 +497,7 @@ async fn replicate_redb_bidirectional_memory() {
         .await
         .unwrap();
 
-    let (push, pull) = redb.sync(&memory).await.unwrap();
+    let (push, pull) = redb.sync(&memory).await.into_result().unwrap();
     assert!(push.ok);
     assert!(pull.ok);
 
@@ -600,6 +603,65 @@ async fn replicate_large_batch() {
 // Pull updates and deletes back from CouchDB
 // =========================================================================
 
+#[tokio::test]
+#[ignore]
+async fn pull_from_couchdb_fetches_docs_in_bulk() {
+    let url = fresh_remote_db("bulk_get_pull").await;
+    let remote = Database::http(&url);
+    let mut first_revs = Vec::new();
+    for i in 0..50 {
+        let rev = remote
+            .put(&format!("doc{:03}", i), serde_json::json!({"i": i}))
+            .await
+            .unwrap()
+            .rev
+            .unwrap();
+        remote
+            .update(
+                &format!("doc{:03}", i),
+                &rev,
+                serde_json::json!({"i": i * 2}),
+            )
+            .await
+            .unwrap();
+        first_revs.push(rev);
+    }
+
+    let (proxied, requests) = counting_proxy(&url).await;
+    let source = Database::http(&proxied);
+    let local = Database::memory("local");
+    let result = source
+        .replicate_to_with_opts(
+            &local,
+            ReplicationOptions {
+                batch_size: 20,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    assert!(result.ok);
+    assert_eq!(result.docs_written, 50);
+    // A few changes/_bulk_get/checkpoint calls per batch, not one per doc
+    assert!(requests.load(Ordering::SeqCst) < 25);
+
+    // _bulk_get?revs=true brought each doc's ancestry along
+    let doc = local.get("doc007").await.unwrap();
+    assert_eq!(doc.data["i"], 14);
+    assert_eq!(doc.rev.unwrap().pos, 2);
+    let diff = local
+        .revs_diff(HashMap::from([(
+            "doc007".to_string(),
+            vec![first_revs[7].clone()],
+        )]))
+        .await
+        .unwrap();
+    assert!(diff.results.is_empty());
+
+    delete_remote_db(&url).await;
+}
+
 #[tokio::test]
 #[ignore]
 async fn replicate_remote_updates_back_to_local() {
@@ -664,13 +726,13 @@ async fn sync_interleaved_updates() {
         .put("doc1", serde_json::json!({"v": 1}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let _r2 = local
         .update("doc1", &r1.rev.unwrap(), serde_json::json!({"v": 2}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let remote_doc = remote.get("doc1").await.unwrap();
     let remote_rev = remote_doc.rev.unwrap().to_string();
@@ -678,7 +740,7 @@ async fn sync_interleaved_updates() {
         .update("doc1", &remote_rev, serde_json::json!({"v": 3}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let local_doc = local.get("doc1").await.unwrap();
     let local_rev = local_doc.rev.unwrap().to_string();
@@ -686,7 +748,7 @@ async fn sync_interleaved_updates() {
         .update("doc1", &local_rev, serde_json::json!({"v": 4}))
         .await
         .unwrap();
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let final_local = local.get("doc1").await.unwrap();
     let final_remote = remote.get("doc1").await.unwrap();
@@ -715,7 +777,7 @@ async fn sync_many_docs_diverse_operations() {
             .unwrap();
         revs.push(r.rev.unwrap());
     }
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     // Update even-numbered docs
     for i in (0..10).step_by(2) {
@@ -737,7 +799,7 @@ async fn sync_many_docs_diverse_operations() {
             .unwrap();
     }
 
-    local.sync(&remote).await.unwrap();
+    local.sync(&remote).await.into_result().unwrap();
 
     let remote_info = remote.info().await.unwrap();
     assert_eq!(remote_info.doc_count, 5);
@@ -996,11 +1058,7 @@ async fn live_replicate_to_couchdb() {
             event = rx.recv() => {
                 match event {
                     Some(ReplicationEvent::Complete(r)) if r.docs_written > 0 => break,
-                    Some(ReplicationEvent::Paused) => {
-                        if remote.get("doc1").await.is_ok() {
-                            break;
-                        }
-                    }
+                    Some(ReplicationEvent::Paused) if remote.get("doc1").await.is_ok() => break,
                     None => break,
                     _ => {}
                 }
@@ -1075,11 +1133,9 @@ async fn live_replicate_picks_up_new_docs() {
                         replicated = true;
                         break;
                     }
-                    Some(ReplicationEvent::Paused) => {
-                        if remote.get("late_doc").await.is_ok() {
-                            replicated = true;
-                            break;
-                        }
+                    Some(ReplicationEvent::Paused) if remote.get("late_doc").await.is_ok() => {
+                        replicated = true;
+                        break;
                     }
                     None => break,
                     _ => {}